@@ -0,0 +1,9 @@
+// Generates the gRPC server in `grpc.rs` from proto/demo.proto
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Vendored, so building needs no protoc install
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/demo.proto"], &["proto"])?;
+    Ok(())
+}
@@ -95,7 +95,8 @@ while true; do
                 ;;
             4)
                 # Create order
-                ORDER_DATA='{"user_id":"user-'"$RANDOM"'","items":[{"product_id":"prod-001","quantity":2,"price":29.99}]}'
+                ORDER_USER_ID=$(uuidgen | tr '[:upper:]' '[:lower:]')
+                ORDER_DATA='{"user_id":"'"$ORDER_USER_ID"'","items":[{"product_id":"prod-001","quantity":2,"price":"29.99"}]}'
                 make_request "POST" "/api/orders" "$ORDER_DATA" "Create Order"
                 ;;
             5)
@@ -40,20 +40,23 @@ echo ""
 
 # Test 5: Create order
 echo "5. Creating an order..."
-curl -s -X POST "${API_URL}/api/orders" \
+ORDER_RESPONSE=$(curl -s -X POST "${API_URL}/api/orders" \
     -H "Content-Type: application/json" \
     -d '{
         "user_id": "'${USER_ID}'",
+        "currency": "USD",
         "items": [
-            {"product_id": "prod-001", "quantity": 2, "price": 29.99},
-            {"product_id": "prod-002", "quantity": 1, "price": 49.99}
+            {"product_id": "prod-001", "quantity": 2, "price": "29.99"},
+            {"product_id": "prod-002", "quantity": 1, "price": "49.99"}
         ]
-    }' | jq .
+    }')
+echo $ORDER_RESPONSE | jq .
+ORDER_ID=$(echo $ORDER_RESPONSE | jq -r '.order_id')
 echo ""
 
 # Test 6: Get order
 echo "6. Getting order by ID..."
-curl -s "${API_URL}/api/orders/order-123" | jq .
+curl -s "${API_URL}/api/orders/${ORDER_ID}" | jq .
 echo ""
 
 # Test 7: Slow operation
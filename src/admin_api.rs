@@ -0,0 +1,327 @@
+use crate::ids::{IdPath, JobId, UserId};
+use crate::negotiation::ResponseFormat;
+use crate::{config, dependency_health, events, job_tracker, search, AppState};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use rust_datadog_otel::error::{AppError, ErrorResponse};
+use rust_datadog_otel::info_trace;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// Users deleted per batch by a purge job
+const PURGE_BATCH_SIZE: usize = 50;
+
+/// Which users a purge deletes; at least one criterion is required
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PurgeRequest {
+    /// Delete users created before this RFC 3339 time
+    created_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Delete these users
+    #[serde(default)]
+    user_ids: Vec<UserId>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobAccepted {
+    job_id: JobId,
+    /// Users selected for deletion
+    total: u64,
+    /// Poll this for progress
+    status_url: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/protocols",
+    tag = "admin",
+    responses((status = 200, description = "Requests served per HTTP protocol version", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn protocol_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(serde_json::json!({
+        "requests_by_protocol": state.protocols.snapshot(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/queue-time",
+    tag = "admin",
+    responses((status = 200, description = "Histogram of upstream queue time from X-Request-Start/X-Queue-Start", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn queue_time_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(serde_json::json!({
+        "queue_time_ms": state.queue_times.snapshot(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/regions",
+    tag = "admin",
+    responses((status = 200, description = "Region latency profiles, with requests and added downstream latency per region", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn region_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(state.regions.snapshot())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/priority",
+    tag = "admin",
+    responses((status = 200, description = "Concurrency limits and timeouts per priority class, with admitted, shed and timed-out counts", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn priority_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(state.priorities.snapshot())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/costs",
+    tag = "admin",
+    responses((status = 200, description = "Cost unit weights and cost totals per endpoint", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn cost_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(state.costs.snapshot())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/disconnects",
+    tag = "admin",
+    responses((status = 200, description = "Requests abandoned by the client before the response was ready, per endpoint", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn disconnect_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(state.disconnects.snapshot())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/dependencies",
+    tag = "admin",
+    responses((status = 200, description = "Rolling success rate, p95 latency, state and state transitions per downstream dependency", body = serde_json::Value))
+)]
+#[instrument]
+pub async fn dependency_stats(format: ResponseFormat) -> impl IntoResponse {
+    format.body(dependency_health::snapshot())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/config",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Effective configuration with the source of each value (env, file, default or unset); secrets redacted", body = serde_json::Value),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the admin role", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state))]
+pub async fn effective_config(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(config::effective(&state.secrets, &state.config))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/rate-limit",
+    tag = "admin",
+    responses((status = 200, description = "Rate limit settings and store, with allowed, limited and store error counts", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn rate_limit_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(state.rate_limiter.snapshot())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/experiments",
+    tag = "admin",
+    responses((status = 200, description = "Experiment definitions and allocations per variant", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn experiment_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(serde_json::json!({
+        "experiments": state.experiments.snapshot(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/jobs",
+    tag = "admin",
+    responses((status = 200, description = "Scheduled job leadership and run counts", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn job_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    let scheduler = &state.scheduler;
+    format.body(serde_json::json!({
+        "owner": scheduler.owner(),
+        "lock_backend": scheduler.backend(),
+        "jobs.leader": scheduler.leader_gauge(),
+        "jobs": scheduler.job_statuses(),
+        "reports.generated": state.reports.generated(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/jobs/{id}",
+    tag = "admin",
+    params(("id" = String, Path, format = Uuid, description = "Job ID from the request that started it")),
+    responses(
+        (status = 200, description = "Job state and progress", body = job_tracker::TrackedJob),
+        (status = 400, description = "Malformed job ID", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the admin role", body = ErrorResponse),
+        (status = 404, description = "Unknown or expired job", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, format), fields(job.id = %id))]
+pub async fn job_status(
+    State(state): State<Arc<AppState>>,
+    IdPath(id): IdPath<JobId>,
+    format: ResponseFormat,
+) -> impl IntoResponse {
+    match state.job_tracker.get(id) {
+        Some(job) => format.body(job).into_response(),
+        None => AppError::NotFound("Job not found".to_string()).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/purge",
+    tag = "admin",
+    request_body = PurgeRequest,
+    responses(
+        (status = 202, description = "Purge job started", body = JobAccepted,
+            headers(("Location" = String, description = "Job status URL"))),
+        (status = 400, description = "No criteria given", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the admin role", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, format, payload), fields(job.id = tracing::field::Empty))]
+pub async fn purge_users(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    Json(payload): Json<PurgeRequest>,
+) -> impl IntoResponse {
+    if payload.created_before.is_none() && payload.user_ids.is_empty() {
+        return AppError::BadRequest("Give created_before and/or user_ids".to_string()).into_response();
+    }
+
+    let mut ids = payload.user_ids;
+    if let Some(cutoff) = payload.created_before {
+        ids.extend(state.users.created_before(cutoff).await);
+    }
+    ids.sort();
+    ids.dedup();
+
+    let total = ids.len() as u64;
+    let users = state.users.clone();
+    let search = state.search.clone();
+    let cache = state.cache.clone();
+    let job_id = state.job_tracker.spawn("users.purge", total, move |progress| async move {
+        for batch in ids.chunks(PURGE_BATCH_SIZE) {
+            let deleted = users.delete_many(batch).await;
+            for id in batch {
+                cache.invalidate("user", &id.to_string()).await;
+            }
+            search
+                .remove(search::DocumentKind::User, batch.iter().map(UserId::to_string).collect())
+                .await;
+            progress.advance(deleted as u64);
+            // Let request handlers at the user store between batches
+            tokio::task::yield_now().await;
+        }
+        Ok(())
+    });
+    tracing::Span::current().record("job.id", tracing::field::display(job_id));
+    info_trace!(job.id = %job_id, purge.total = total, "User purge started");
+
+    let status_url = format!("/admin/jobs/{}", job_id);
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, status_url.clone())],
+        format.body(JobAccepted {
+            job_id,
+            total,
+            status_url,
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/auth",
+    tag = "admin",
+    responses((status = 200, description = "Login attempts, failures by reason and failure rate", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn auth_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(serde_json::json!({
+        "login": state.auth.login_stats.snapshot(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/email",
+    tag = "admin",
+    responses((status = 200, description = "Email provider, send attempts, failures and failure rate", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn email_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    let mut stats = state.notifier.stats.snapshot();
+    stats["provider"] = state.notifier.provider().into();
+    format.body(stats)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/events",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Domain events published, with the most recent envelopes", body = serde_json::Value),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the admin role", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state))]
+pub async fn event_log(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(state.events.snapshot())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/events/schema",
+    tag = "admin",
+    responses((status = 200, description = "Avro schema of the domain event envelope", body = serde_json::Value))
+)]
+#[instrument]
+pub async fn event_schema(format: ResponseFormat) -> impl IntoResponse {
+    format.body(events::avro_schema())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/concurrency",
+    tag = "admin",
+    responses((status = 200, description = "Versioned updates and If-Match conflicts by entity", body = serde_json::Value))
+)]
+#[instrument(skip(state))]
+pub async fn concurrency_stats(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    format.body(state.concurrency.snapshot())
+}
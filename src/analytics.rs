@@ -0,0 +1,181 @@
+use crate::ids::ProductId;
+use crate::money::Currency;
+use crate::orders::{self, OrderRecord, OrderRepository};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// Products listed in `top_products` per currency
+const TOP_PRODUCTS: usize = 5;
+
+/// Longest window accepted, so a typo can't scan the whole history
+pub const MAX_WINDOW_DAYS: i64 = 30;
+
+/// Parse a window like `30m`, `1h` or `7d`
+pub fn parse_window(window: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid window '{}', expected e.g. 30m, 1h or 7d", window);
+    let (split, _) = window.char_indices().last().ok_or_else(invalid)?;
+    let (amount, unit) = window.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        _ => return Err(invalid()),
+    };
+
+    match duration {
+        Some(duration) if amount > 0 && duration <= Duration::days(MAX_WINDOW_DAYS) => Ok(duration),
+        _ => Err(format!("window must be between 1m and {}d", MAX_WINDOW_DAYS)),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TopProduct {
+    pub product_id: ProductId,
+    pub currency: Currency,
+    pub units: u64,
+    pub revenue: Decimal,
+}
+
+/// Revenue in one currency; amounts in different currencies are never added together
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CurrencyRevenue {
+    pub currency: Currency,
+    pub order_count: usize,
+    pub total_revenue: Decimal,
+    /// Rounded half to even to the currency's minor unit
+    pub average_order_value: Decimal,
+}
+
+/// Result of `GET /analytics/orders`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OrderAnalytics {
+    pub window: String,
+    pub window_start: String,
+    pub order_count: usize,
+    pub units: u64,
+    /// One entry per currency ordered in, by currency code
+    pub revenue: Vec<CurrencyRevenue>,
+    /// Best-selling products by revenue, per currency
+    pub top_products: Vec<TopProduct>,
+}
+
+/// Totals accumulated by the aggregate stage
+#[derive(Debug, Default)]
+struct Aggregate {
+    order_count: usize,
+    units: u64,
+    revenue: BTreeMap<Currency, (usize, Decimal)>,
+    products: HashMap<(ProductId, Currency), (u64, Decimal)>,
+}
+
+/// Stage 1: load the orders in the window
+#[instrument(name = "analytics.fetch", skip(orders), fields(analytics.rows = tracing::field::Empty))]
+async fn fetch(orders: &OrderRepository, since: DateTime<Utc>) -> Vec<OrderRecord> {
+    let rows = orders.created_since(since).await;
+    tracing::Span::current().record("analytics.rows", rows.len());
+    rows
+}
+
+/// Stage 2: fold orders, except cancelled ones, into totals and per-product sums
+#[instrument(
+    name = "analytics.aggregate",
+    skip_all,
+    fields(
+        analytics.rows = rows.len(),
+        analytics.products = tracing::field::Empty,
+        analytics.currencies = tracing::field::Empty,
+    )
+)]
+fn aggregate(rows: &[OrderRecord]) -> Aggregate {
+    // Saturating, so a pathological history can't panic the summary
+    let mut totals = Aggregate::default();
+    for order in rows.iter().filter(|order| order.status != orders::CANCELLED) {
+        totals.order_count += 1;
+        let revenue = totals.revenue.entry(order.currency).or_default();
+        revenue.0 += 1;
+        revenue.1 = revenue.1.saturating_add(order.total_amount);
+        for line in &order.lines {
+            let product = totals
+                .products
+                .entry((line.product_id.clone(), order.currency))
+                .or_default();
+            product.0 += line.quantity as u64;
+            product.1 = product
+                .1
+                .saturating_add(line.price.saturating_mul(Decimal::from(line.quantity)));
+            totals.units += line.quantity as u64;
+        }
+    }
+    let span = tracing::Span::current();
+    span.record("analytics.products", totals.products.len());
+    span.record("analytics.currencies", totals.revenue.len());
+    totals
+}
+
+/// Stage 3: rank products and shape the response
+#[instrument(
+    name = "analytics.format",
+    skip_all,
+    fields(analytics.rows = totals.order_count, analytics.top_products = tracing::field::Empty)
+)]
+fn format(totals: Aggregate, window: &str, since: DateTime<Utc>) -> OrderAnalytics {
+    let mut top_products: Vec<TopProduct> = totals
+        .products
+        .into_iter()
+        .map(|((product_id, currency), (units, revenue))| TopProduct {
+            product_id,
+            currency,
+            units,
+            revenue,
+        })
+        .collect();
+    // Revenue is only comparable within a currency, so rank each one separately
+    top_products.sort_by(|a, b| {
+        a.currency
+            .cmp(&b.currency)
+            .then_with(|| b.revenue.cmp(&a.revenue))
+            .then_with(|| a.product_id.cmp(&b.product_id))
+    });
+    let mut ranked: HashMap<Currency, usize> = HashMap::new();
+    top_products.retain(|product| {
+        let rank = ranked.entry(product.currency).or_default();
+        *rank += 1;
+        *rank <= TOP_PRODUCTS
+    });
+    tracing::Span::current().record("analytics.top_products", top_products.len());
+
+    OrderAnalytics {
+        window: window.to_string(),
+        window_start: since.to_rfc3339(),
+        order_count: totals.order_count,
+        units: totals.units,
+        revenue: totals
+            .revenue
+            .into_iter()
+            .map(|(currency, (order_count, total_revenue))| CurrencyRevenue {
+                currency,
+                order_count,
+                total_revenue,
+                average_order_value: currency.round(total_revenue / Decimal::from(order_count)),
+            })
+            .collect(),
+        top_products,
+    }
+}
+
+/// Run the fetch → aggregate → format pipeline over the last `window`
+pub async fn order_analytics(
+    orders: &OrderRepository,
+    window: &str,
+    duration: Duration,
+) -> OrderAnalytics {
+    let since = Utc::now() - duration;
+    let rows = fetch(orders, since).await;
+    let totals = aggregate(&rows);
+    format(totals, window, since)
+}
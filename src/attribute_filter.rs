@@ -0,0 +1,290 @@
+use crate::cardinality_guard::CardinalityGuard;
+use crate::propagation::BaggageAttributes;
+use crate::sampling;
+use crate::span_rules::SpanRules;
+use opentelemetry::trace::{Span, SpanBuilder, SpanContext, Status, Tracer};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::trace::ShouldSample;
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Attribute keys Datadog needs to map spans; never stripped
+const RESERVED_KEYS: &[&str] = &["operation.name", "resource.name", "service.name", "span.type"];
+
+/// Export-time span attribute filtering
+///
+/// Configured via environment variables (comma-separated keys, `prefix.*` wildcards allowed):
+/// - `SPAN_ATTRIBUTE_ALLOWLIST`: only these keys may leave the process (empty = allow all)
+/// - `SPAN_ATTRIBUTE_DENYLIST`: these keys are always stripped (takes precedence)
+///
+/// Applies to span, event, and link attributes.
+#[derive(Debug, Default)]
+pub struct AttributeFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl AttributeFilter {
+    pub fn from_env() -> Self {
+        Self {
+            allow: parse_key_list("SPAN_ATTRIBUTE_ALLOWLIST"),
+            deny: parse_key_list("SPAN_ATTRIBUTE_DENYLIST"),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Whether an attribute key may be exported
+    pub fn permits(&self, key: &str) -> bool {
+        if RESERVED_KEYS.contains(&key) || key.starts_with("_dd") {
+            return true;
+        }
+        if self.deny.iter().any(|pattern| matches_pattern(pattern, key)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|pattern| matches_pattern(pattern, key))
+    }
+
+    fn retain(&self, attributes: &mut Vec<KeyValue>) {
+        attributes.retain(|kv| self.permits(kv.key.as_str()));
+    }
+}
+
+/// Operator span rules, the attribute filter and the cardinality guard, applied in that order
+#[derive(Debug)]
+struct Rules {
+    span_rules: Arc<SpanRules>,
+    filter: AttributeFilter,
+    guard: Arc<CardinalityGuard>,
+}
+
+impl Rules {
+    /// Rewrite the attributes of span `span`, as named in code
+    fn apply(&self, span: &str, attributes: &mut Vec<KeyValue>) {
+        self.span_rules.attributes(span, attributes);
+        self.filter.retain(attributes);
+        self.guard.attributes(attributes);
+    }
+}
+
+fn parse_key_list(var: &str) -> Vec<String> {
+    std::env::var(var)
+        .map(|raw| {
+            raw.split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn matches_pattern(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => pattern == key,
+    }
+}
+
+/// Tracer wrapper enforcing [`SpanRules`], an [`AttributeFilter`] and a [`CardinalityGuard`] on every span it creates
+///
+/// Filtering happens here rather than in a `SpanProcessor` because the SDK hands
+/// each processor its own copy of the span data: a filtering processor cannot
+/// change what the Datadog processor exports. A sampler set with
+/// [`FilteringTracer::with_sampler`] runs here too, for the same reason: the
+/// SDK's own sampler is Datadog's. Allow-listed baggage set with
+/// [`FilteringTracer::with_baggage_attributes`] is added to each span here,
+/// before the rules run, since only span start sees the parent context.
+#[derive(Debug)]
+pub struct FilteringTracer<T> {
+    inner: T,
+    rules: Arc<Rules>,
+    sampler: Option<Box<dyn ShouldSample>>,
+    baggage: BaggageAttributes,
+}
+
+impl<T> FilteringTracer<T> {
+    pub fn new(inner: T, span_rules: Arc<SpanRules>, filter: AttributeFilter, guard: Arc<CardinalityGuard>) -> Self {
+        Self {
+            inner,
+            rules: Arc::new(Rules { span_rules, filter, guard }),
+            sampler: None,
+            baggage: BaggageAttributes::default(),
+        }
+    }
+
+    /// Copy baggage entries listed in `baggage` onto every span as attributes
+    pub fn with_baggage_attributes(mut self, baggage: BaggageAttributes) -> Self {
+        self.baggage = baggage;
+        self
+    }
+
+    /// Sample spans with `sampler`, by their exported name and attributes, before the Datadog SDK does
+    pub fn with_sampler(mut self, sampler: Box<dyn ShouldSample>) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+}
+
+impl<T: Tracer> Tracer for FilteringTracer<T> {
+    type Span = FilteredSpan<T::Span>;
+
+    fn build_with_context(&self, mut builder: SpanBuilder, parent_cx: &Context) -> Self::Span {
+        let name = builder.name.clone();
+        builder.name = self.rules.guard.span_name(self.rules.span_rules.span_name(builder.name));
+        let baggage = self.baggage.attributes(parent_cx);
+        if !baggage.is_empty() {
+            let attributes = builder.attributes.get_or_insert_with(Vec::new);
+            for attribute in baggage {
+                if !attributes.iter().any(|existing| existing.key == attribute.key) {
+                    attributes.push(attribute);
+                }
+            }
+        }
+        if let Some(attributes) = builder.attributes.as_mut() {
+            self.rules.apply(&name, attributes);
+        }
+        if let Some(events) = builder.events.as_mut() {
+            for event in events.iter_mut() {
+                self.rules.apply(&name, &mut event.attributes);
+            }
+        }
+        if let Some(links) = builder.links.as_mut() {
+            for link in links.iter_mut() {
+                self.rules.apply(&name, &mut link.attributes);
+            }
+        }
+        if let Some(sampler) = &self.sampler {
+            sampling::presample(sampler.as_ref(), &mut builder, parent_cx);
+        }
+
+        FilteredSpan {
+            inner: self.inner.build_with_context(builder, parent_cx),
+            name,
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+/// Span wrapper filtering and guarding attributes recorded after span start
+#[derive(Debug)]
+pub struct FilteredSpan<S> {
+    inner: S,
+    /// The name given in code, which span rules match
+    name: Cow<'static, str>,
+    rules: Arc<Rules>,
+}
+
+impl<S: Span> Span for FilteredSpan<S> {
+    fn add_event_with_timestamp<T>(
+        &mut self,
+        name: T,
+        timestamp: SystemTime,
+        mut attributes: Vec<KeyValue>,
+    ) where
+        T: Into<Cow<'static, str>>,
+    {
+        self.rules.apply(&self.name, &mut attributes);
+        self.inner.add_event_with_timestamp(name, timestamp, attributes);
+    }
+
+    fn span_context(&self) -> &SpanContext {
+        self.inner.span_context()
+    }
+
+    fn is_recording(&self) -> bool {
+        self.inner.is_recording()
+    }
+
+    fn set_attribute(&mut self, attribute: KeyValue) {
+        let mut attributes = vec![attribute];
+        self.rules.apply(&self.name, &mut attributes);
+        for attribute in attributes {
+            self.inner.set_attribute(attribute);
+        }
+    }
+
+    fn set_status(&mut self, status: Status) {
+        self.inner.set_status(status);
+    }
+
+    fn update_name<T>(&mut self, new_name: T)
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        self.name = new_name.into();
+        let renamed = self.rules.span_rules.span_name(self.name.clone());
+        self.inner.update_name(self.rules.guard.span_name(renamed));
+    }
+
+    fn add_link(&mut self, span_context: SpanContext, mut attributes: Vec<KeyValue>) {
+        self.rules.apply(&self.name, &mut attributes);
+        self.inner.add_link(span_context, attributes);
+    }
+
+    fn end_with_timestamp(&mut self, timestamp: SystemTime) {
+        self.inner.end_with_timestamp(timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanKind, TracerProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+
+    fn filter(allow: &[&str], deny: &[&str]) -> AttributeFilter {
+        AttributeFilter {
+            allow: allow.iter().map(|key| key.to_string()).collect(),
+            deny: deny.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    /// One span through a `FilteringTracer` over an in-memory exporter, with
+    /// `secret` and `kept` set every way a span takes attributes
+    fn export(filter: AttributeFilter) -> SpanData {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let guard = Arc::new(CardinalityGuard::from_env().unwrap());
+        let tracer = FilteringTracer::new(provider.tracer("test"), Arc::new(SpanRules::default()), filter, guard);
+
+        let linked = tracer.start("linked").span_context().clone();
+        let mut span = tracer
+            .span_builder("checkout")
+            .with_kind(SpanKind::Server)
+            .with_attributes([KeyValue::new("secret", "builder"), KeyValue::new("kept", "builder")])
+            .start(&tracer);
+        span.set_attribute(KeyValue::new("secret", "set"));
+        span.set_attribute(KeyValue::new("kept.later", "set"));
+        span.add_event("charged", vec![KeyValue::new("secret", "event"), KeyValue::new("kept", "event")]);
+        span.add_link(linked, vec![KeyValue::new("secret", "link"), KeyValue::new("kept", "link")]);
+        span.end();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        spans.into_iter().find(|span| span.name == "checkout").unwrap()
+    }
+
+    fn keys(attributes: &[KeyValue]) -> Vec<&str> {
+        attributes.iter().map(|kv| kv.key.as_str()).collect()
+    }
+
+    #[test]
+    fn denied_keys_are_stripped_everywhere() {
+        let span = export(filter(&[], &["secret"]));
+        assert_eq!(keys(&span.attributes), ["kept", "kept.later"]);
+        assert_eq!(keys(&span.events[0].attributes), ["kept"]);
+        assert_eq!(keys(&span.links[0].attributes), ["kept"]);
+    }
+
+    #[test]
+    fn allowlist_drops_unlisted_keys() {
+        let span = export(filter(&["kept.*"], &[]));
+        assert_eq!(keys(&span.attributes), ["kept.later"]);
+        assert!(span.events[0].attributes.is_empty());
+        assert!(span.links[0].attributes.is_empty());
+        assert!(filter(&["kept.*"], &[]).permits("resource.name"));
+    }
+}
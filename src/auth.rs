@@ -0,0 +1,405 @@
+use crate::ids::UserId;
+use crate::secrets::{SecretString, Secrets};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::future::BoxFuture;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::warn_trace;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const DEFAULT_TOKEN_TTL_SECS: u64 = 60 * 60;
+const DEFAULT_ISSUER: &str = "rust-datadog-otel";
+
+/// Shortest password accepted at registration
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+pub type AuthError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Authorization role carried in tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Whether this role grants `required`; admins can do anything users can
+    pub fn grants(self, required: Role) -> bool {
+        self == Role::Admin || self == required
+    }
+}
+
+/// JWT claims issued at login
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// A token whose subject isn't a user ID fails verification
+    pub sub: UserId,
+    /// Tokens issued before roles existed carry none and are treated as `user`
+    #[serde(default)]
+    pub role: Role,
+    pub iss: String,
+    pub iat: u64,
+    pub exp: u64,
+}
+
+/// The authenticated caller, inserted by [`authenticate`]
+///
+/// Also usable as an extractor; it rejects with 401 when the request carried
+/// no valid token.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user_id: UserId,
+    pub role: Role,
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Principal {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Principal>()
+            .cloned()
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".into()).into_response())
+    }
+}
+
+/// Hash a password with argon2id and a random salt (PHC string format)
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| format!("password hashing failed: {}", e))?
+        .to_string())
+}
+
+/// Check a password against a stored PHC hash
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+/// Verify against a fixed hash when the user doesn't exist, so unknown emails
+/// take as long as wrong passwords and can't be enumerated by timing
+pub fn verify_dummy_password(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| {
+        hash_password("dummy-password").expect("hashing a constant password succeeds")
+    });
+    verify_password(password, hash);
+}
+
+/// Login attempts and failures by reason, for `/admin/auth`
+#[derive(Debug, Default)]
+pub struct LoginStats {
+    attempts: AtomicU64,
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl LoginStats {
+    pub fn record_success(&self) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self, reason: &'static str) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        *self
+            .failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let attempts = self.attempts.load(Ordering::Relaxed);
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let failed: u64 = failures.values().sum();
+        let failure_rate = if attempts == 0 {
+            0.0
+        } else {
+            failed as f64 / attempts as f64
+        };
+        serde_json::json!({
+            "attempts": attempts,
+            "failures": failed,
+            "failures_by_reason": failures,
+            "failure_rate": failure_rate,
+        })
+    }
+}
+
+/// JWT issuing and verification
+///
+/// Configuration:
+/// - `JWT_SECRET`: HS256 signing key, read through [`Secrets`]
+///   (ephemeral when unset, which invalidates tokens on restart)
+/// - `JWT_TTL_SECS`: token lifetime (default 1 hour)
+/// - `JWT_ISSUER`: `iss` claim issued and required (default `rust-datadog-otel`)
+pub struct Auth {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    validation: Validation,
+    issuer: String,
+    ttl: Duration,
+    pub login_stats: LoginStats,
+}
+
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Auth")
+            .field("issuer", &self.issuer)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Auth {
+    pub fn from_env(secrets: &Secrets) -> Result<Self, Box<dyn std::error::Error>> {
+        let secret = match secrets.get("JWT_SECRET")? {
+            Some(secret) => secret.expose_secret().as_bytes().to_vec(),
+            None => {
+                warn_trace!("JWT_SECRET not set, using an ephemeral token signing key");
+                let mut key = vec![0u8; 32];
+                OsRng.fill_bytes(&mut key);
+                key
+            }
+        };
+        let ttl = std::env::var("JWT_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TOKEN_TTL_SECS);
+        let issuer = std::env::var("JWT_ISSUER").unwrap_or_else(|_| DEFAULT_ISSUER.to_string());
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[&issuer]);
+
+        Ok(Self {
+            encoding_key: EncodingKey::from_secret(&secret),
+            decoding_key: DecodingKey::from_secret(&secret),
+            validation,
+            issuer,
+            ttl: Duration::from_secs(ttl),
+            login_stats: LoginStats::default(),
+        })
+    }
+
+    pub fn token_ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Sign a token for `user_id`
+    pub fn issue(&self, user_id: UserId, role: Role) -> Result<String, AuthError> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let claims = Claims {
+            sub: user_id,
+            role,
+            iss: self.issuer.clone(),
+            iat: now,
+            exp: now + self.ttl.as_secs(),
+        };
+        Ok(jsonwebtoken::encode(&Header::default(), &claims, &self.encoding_key)?)
+    }
+
+    /// Validate signature, expiry and issuer
+    pub fn verify(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        jsonwebtoken::decode::<Claims>(token, &self.decoding_key, &self.validation)
+            .map(|data| data.claims)
+    }
+
+    /// The caller behind an `Authorization: Bearer` value, for HTTP headers and gRPC metadata alike
+    ///
+    /// Records the outcome and the caller's role on the current span; a
+    /// malformed value or an invalid or expired token is a 401.
+    pub fn principal(&self, authorization: &str) -> Result<Principal, AppError> {
+        let Some(token) = authorization.strip_prefix("Bearer ") else {
+            record_outcome("jwt", Err("malformed_header"));
+            warn_trace!(auth.failure_reason = "malformed_header", "Authentication failed");
+            return Err(AppError::Unauthorized("Malformed Authorization header".into()));
+        };
+        match self.verify(token.trim()) {
+            Ok(claims) => {
+                record_outcome("jwt", Ok(&claims.sub.to_string()));
+                tracing::Span::current().set_attribute("usr.role", claims.role.as_str());
+                Ok(Principal {
+                    user_id: claims.sub,
+                    role: claims.role,
+                })
+            }
+            Err(e) => {
+                let reason = match e.kind() {
+                    jsonwebtoken::errors::ErrorKind::ExpiredSignature => "expired_token",
+                    _ => "invalid_token",
+                };
+                record_outcome("jwt", Err(reason));
+                warn_trace!(auth.failure_reason = reason, "Authentication failed");
+                Err(AppError::Unauthorized("Invalid or expired token".into()))
+            }
+        }
+    }
+}
+
+/// Operator-provisioned admin account, created at startup if its email is free
+///
+/// Self-registration always grants [`Role::User`]; this is the only way to
+/// get an `admin` account, so the role can't be claimed by registering first.
+///
+/// Configuration:
+/// - `AUTH_ADMIN_EMAIL`: email of the admin account; none is created when unset
+/// - `AUTH_ADMIN_PASSWORD`: its password, read through [`Secrets`]; required with the email
+#[derive(Debug)]
+pub struct AdminAccount {
+    pub email: String,
+    pub password: SecretString,
+}
+
+impl AdminAccount {
+    pub fn from_env(secrets: &Secrets) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let Some(email) = std::env::var("AUTH_ADMIN_EMAIL").ok().filter(|email| !email.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let password = secrets
+            .get("AUTH_ADMIN_PASSWORD")?
+            .ok_or("AUTH_ADMIN_EMAIL is set but AUTH_ADMIN_PASSWORD is not")?;
+        if password.expose_secret().chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(format!("AUTH_ADMIN_PASSWORD must be at least {} characters", MIN_PASSWORD_LENGTH).into());
+        }
+        Ok(Some(Self {
+            email: email.trim().to_string(),
+            password,
+        }))
+    }
+}
+
+/// Record an authentication outcome on the current span
+///
+/// Only the outcome and a failure reason are recorded; credentials and
+/// tokens never reach telemetry.
+pub fn record_outcome(method: &'static str, outcome: Result<&str, &'static str>) {
+    let span = tracing::Span::current();
+    span.set_attribute("auth.method", method);
+    match outcome {
+        Ok(user_id) => {
+            span.set_attribute("auth.outcome", "success");
+            span.set_attribute("usr.id", user_id.to_string());
+        }
+        Err(reason) => {
+            span.set_attribute("auth.outcome", "failure");
+            span.set_attribute("auth.failure_reason", reason);
+        }
+    }
+}
+
+/// Middleware validating `Authorization: Bearer` tokens
+///
+/// Valid tokens attach a [`Principal`]; requests without a token pass through
+/// anonymously, and handlers that need a caller extract [`Principal`]. An
+/// invalid or expired token is rejected with 401 rather than downgraded to
+/// anonymous.
+pub async fn authenticate(
+    State(auth): State<Arc<Auth>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(authorization) = request.headers().get(header::AUTHORIZATION) else {
+        return next.run(request).await;
+    };
+    match auth.principal(authorization.to_str().unwrap_or_default()) {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Route-level guard requiring `role`, for use with `middleware::from_fn`
+///
+/// Anonymous requests get 401; callers without the role get 403. Either way
+/// the request span is tagged `authz.decision` and `authz.required_role`.
+pub fn require_role(
+    role: Role,
+) -> impl Fn(Request, Next) -> BoxFuture<'static, Response> + Clone + Send + Sync + 'static {
+    move |request: Request, next: Next| {
+        Box::pin(async move {
+            let principal = request.extensions().get::<Principal>().cloned();
+            let span = tracing::Span::current();
+            span.set_attribute("authz.required_role", role.as_str());
+
+            let Some(principal) = principal else {
+                span.set_attribute("authz.decision", "deny");
+                warn_trace!(authz.decision = "deny", authz.required_role = role.as_str(), "Authorization denied: anonymous");
+                return AppError::Unauthorized("Authentication required".into()).into_response();
+            };
+
+            if !principal.role.grants(role) {
+                span.set_attribute("authz.decision", "deny");
+                warn_trace!(
+                    authz.decision = "deny",
+                    authz.required_role = role.as_str(),
+                    usr.id = %principal.user_id,
+                    usr.role = principal.role.as_str(),
+                    "Authorization denied"
+                );
+                return AppError::Forbidden(format!("Requires the {} role", role.as_str()))
+                    .into_response();
+            }
+
+            span.set_attribute("authz.decision", "allow");
+            next.run(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn require_role_allows_denies_and_challenges() {
+        let app = Router::new()
+            .route("/admin", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn(require_role(Role::Admin)));
+        let status = |principal: Option<Role>| {
+            let mut request = axum::http::Request::get("/admin").body(Body::empty()).unwrap();
+            if let Some(role) = principal {
+                request.extensions_mut().insert(Principal {
+                    user_id: UserId::generate(),
+                    role,
+                });
+            }
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(status(Some(Role::Admin)).await, StatusCode::OK);
+        assert_eq!(status(Some(Role::User)).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(None).await, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,352 @@
+use crate::ids::UserId;
+use crate::negotiation::ResponseFormat;
+use crate::user_api::{register_user, CreateUserRequest, User};
+use crate::{auth, compute, session, AppState};
+use axum::{
+    extract::{Extension, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use rust_datadog_otel::error::{AppError, ErrorResponse};
+use rust_datadog_otel::{
+    error_trace, info_trace, warn_trace,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+/// No `Debug`, so the password can't end up in a log line
+#[derive(Deserialize, ToSchema)]
+pub struct RegisterRequest {
+    name: String,
+    email: String,
+    password: String,
+}
+
+/// No `Debug`, so the password can't end up in a log line
+#[derive(Deserialize, ToSchema)]
+pub struct Credentials {
+    email: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenResponse {
+    user_id: UserId,
+    role: auth::Role,
+    access_token: String,
+    token_type: &'static str,
+    /// Seconds until the token expires
+    expires_in: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionResponse {
+    user_id: UserId,
+    created_at: String,
+}
+
+impl From<session::SessionData> for SessionResponse {
+    fn from(data: session::SessionData) -> Self {
+        Self {
+            user_id: data.user_id,
+            created_at: data.created_at,
+        }
+    }
+}
+
+/// Respond with a freshly signed token for `user_id`, or 500 if signing fails
+fn token_response(
+    state: &AppState,
+    format: ResponseFormat,
+    status: StatusCode,
+    user_id: UserId,
+    role: auth::Role,
+) -> axum::response::Response {
+    match state.auth.issue(user_id, role) {
+        Ok(access_token) => {
+            let token = TokenResponse {
+                user_id,
+                role,
+                access_token,
+                token_type: "Bearer",
+                expires_in: state.auth.token_ttl().as_secs(),
+            };
+            (status, format.body(token)).into_response()
+        }
+        Err(e) => {
+            error_trace!(error = %e, "Failed to sign token");
+            AppError::Internal("Failed to issue token".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 201, description = "User registered; returns an access token", body = TokenResponse),
+        (status = 400, description = "Invalid user or password too short", body = ErrorResponse),
+        (status = 409, description = "Email already registered", body = ErrorResponse),
+        (status = 500, description = "Storage or hashing failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip_all)]
+pub async fn register(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    Json(payload): Json<RegisterRequest>,
+) -> impl IntoResponse {
+    if payload.password.chars().count() < auth::MIN_PASSWORD_LENGTH {
+        warn_trace!("Registration failed: password too short");
+        return AppError::BadRequest(format!(
+            "Password must be at least {} characters",
+            auth::MIN_PASSWORD_LENGTH
+        ))
+        .into_response();
+    }
+
+    // argon2 is deliberately slow; keep it off the async workers
+    let password = payload.password;
+    let password_hash =
+        match compute::compute("password.hash", move || auth::hash_password(&password)).await {
+            Ok(Ok(hash)) => hash,
+            Ok(Err(e)) => {
+                error_trace!(error = %e, "Failed to hash password");
+                return AppError::Internal("Failed to register user".to_string()).into_response();
+            }
+            Err(e) => {
+                error_trace!(error = %e, "Password hashing task failed");
+                return AppError::Internal("Failed to register user".to_string()).into_response();
+            }
+        };
+
+    // Admin accounts are provisioned by the operator, never self-registered
+    let role = auth::Role::User;
+    let request = CreateUserRequest {
+        name: payload.name,
+        email: payload.email,
+    };
+    let user = match register_user(&state, request, Some(password_hash), role).await {
+        Ok(user) => user,
+        Err(e) => {
+            e.log();
+            return AppError::from(e).into_response();
+        }
+    };
+
+    info_trace!(user_id = %user.id, role = role.as_str(), "User registered");
+    token_response(&state, format, StatusCode::CREATED, user.id, role)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = Credentials,
+    responses(
+        (status = 200, description = "Logged in; returns an access token", body = TokenResponse),
+        (status = 401, description = "Invalid email or password", body = ErrorResponse),
+        (status = 500, description = "Token signing failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip_all)]
+pub async fn auth_login(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    Json(payload): Json<Credentials>,
+) -> impl IntoResponse {
+    let credentials = state.users.credentials(&payload.email).await;
+
+    let password = payload.password;
+    let verified = compute::compute("password.verify", move || match credentials {
+        Some(credentials) => auth::verify_password(&password, &credentials.password_hash)
+            .then_some((credentials.user_id, credentials.role))
+            .ok_or("invalid_password"),
+        None => {
+            auth::verify_dummy_password(&password);
+            Err("unknown_user")
+        }
+    })
+    .await
+    .unwrap_or(Err("verification_error"));
+
+    match verified {
+        Ok((user_id, role)) => {
+            auth::record_outcome("password", Ok(&user_id.to_string()));
+            state.auth.login_stats.record_success();
+            info_trace!(user_id = %user_id, auth.outcome = "success", "Login succeeded");
+            token_response(&state, format, StatusCode::OK, user_id, role)
+        }
+        Err(reason) => {
+            auth::record_outcome("password", Err(reason));
+            state.auth.login_stats.record_failure(reason);
+            warn_trace!(auth.outcome = "failure", auth.failure_reason = reason, "Login failed");
+            // One message for both reasons, so the response doesn't reveal which emails exist
+            AppError::Unauthorized("Invalid email or password".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    tag = "auth",
+    responses(
+        (status = 200, description = "The user the bearer token was issued to", body = User),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 404, description = "User no longer exists", body = ErrorResponse)
+    )
+)]
+#[instrument(skip_all, fields(user_id = %principal.user_id))]
+pub async fn current_user(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    principal: auth::Principal,
+) -> impl IntoResponse {
+    match state.users.find_by_id(principal.user_id).await {
+        Ok(Some(record)) => format.body(User::from(record)).into_response(),
+        Ok(None) => AppError::NotFound("User not found".to_string()).into_response(),
+        Err(e) => {
+            error_trace!(error = %e, "Failed to load user");
+            AppError::Internal("Failed to load user".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/session/login",
+    tag = "sessions",
+    responses(
+        (status = 200, description = "Session started for the token's user; the session cookie is set", body = SessionResponse),
+        (status = 401, description = "Missing, invalid or expired token, or its user no longer exists", body = ErrorResponse),
+        (status = 500, description = "Session storage failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip_all, fields(user_id = %principal.user_id))]
+pub async fn login(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    principal: auth::Principal,
+) -> impl IntoResponse {
+    // A bearer token proves who the caller is; the session carries that identity in a cookie
+    match state.users.find_by_id(principal.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            warn_trace!(user_id = %principal.user_id, "Login failed: unknown user");
+            return AppError::Unauthorized("Unknown user".to_string()).into_response();
+        }
+        Err(e) => {
+            error_trace!(error = %e, "Failed to load user for login");
+            return AppError::Internal("Failed to load user".to_string()).into_response();
+        }
+    }
+
+    match state.sessions.create(principal.user_id).await {
+        Ok((session, cookie)) => {
+            info_trace!(session.id_hash = %session.id_hash(), user_id = %principal.user_id, "Session started");
+            (
+                [(header::SET_COOKIE, cookie)],
+                format.body(SessionResponse::from(session.data)),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error_trace!(error = %e, "Failed to store session");
+            AppError::Internal("Failed to start session".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/session/logout",
+    tag = "sessions",
+    responses(
+        (status = 204, description = "Session ended and cookie cleared"),
+        (status = 500, description = "Session storage failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip_all)]
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    session: Option<Extension<session::Session>>,
+) -> impl IntoResponse {
+    let Some(Extension(session)) = session else {
+        return StatusCode::NO_CONTENT.into_response();
+    };
+
+    match state.sessions.destroy(&session).await {
+        Ok(cookie) => {
+            info_trace!(session.id_hash = %session.id_hash(), "Session ended");
+            (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response()
+        }
+        Err(e) => {
+            error_trace!(error = %e, "Failed to delete session");
+            AppError::Internal("Failed to end session".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/session",
+    tag = "sessions",
+    responses(
+        (status = 200, description = "Current session", body = SessionResponse),
+        (status = 401, description = "No active session", body = ErrorResponse)
+    )
+)]
+#[instrument(skip_all)]
+pub async fn current_session(
+    format: ResponseFormat,
+    session: Option<Extension<session::Session>>,
+) -> impl IntoResponse {
+    match session {
+        Some(Extension(session)) => {
+            format.body(SessionResponse::from(session.data)).into_response()
+        }
+        None => AppError::Unauthorized("No active session".to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_app;
+    use axum::body::Body;
+
+    #[tokio::test]
+    async fn sessions_start_only_for_authenticated_users() {
+        use tower::ServiceExt;
+
+        let (state, app) = test_app().await;
+        let user = CreateUserRequest {
+            name: "Ada".to_string(),
+            email: "ada@example.com".to_string(),
+        };
+        let user = register_user(&state, user, None, auth::Role::User).await.unwrap();
+        let login = |token: Option<String>| {
+            let mut request = axum::http::Request::post("/api/v1/session/login")
+                .header(header::CONTENT_TYPE, "application/json");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            // A user ID alone, as the route used to accept, is not enough
+            let body = serde_json::json!({ "user_id": user.id }).to_string();
+            app.clone().oneshot(request.body(Body::from(body)).unwrap())
+        };
+
+        assert_eq!(login(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let unknown = state.auth.issue(UserId::generate(), auth::Role::User).unwrap();
+        assert_eq!(login(Some(unknown)).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let token = state.auth.issue(user.id, auth::Role::User).unwrap();
+        let response = login(Some(token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(header::SET_COOKIE));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::dependency_health;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use rust_datadog_otel::{dogstatsd, info_trace, warn_trace};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_TTL_SECS: u64 = 60;
+
+/// Longest wait for the cache before going to the database instead
+const STORE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Key-value storage for cached responses
+#[async_trait::async_trait]
+pub trait CacheStore: Send + Sync + Debug {
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError>;
+    async fn set(&self, key: &str, value: String, ttl: Duration) -> Result<(), StoreError>;
+    async fn delete(&self, key: &str) -> Result<(), StoreError>;
+}
+
+/// Redis-backed cache entries, shared between replicas
+pub struct RedisCacheStore {
+    connection: ConnectionManager,
+}
+
+impl Debug for RedisCacheStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisCacheStore").finish_non_exhaustive()
+    }
+}
+
+impl RedisCacheStore {
+    pub fn new(connection: ConnectionManager) -> Self {
+        Self { connection }
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for RedisCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        Ok(dependency_health::observe("redis", self.connection.clone().get(key)).await?)
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Duration) -> Result<(), StoreError> {
+        let mut connection = self.connection.clone();
+        let _: () = dependency_health::observe("redis", connection.set_ex(key, value, ttl.as_secs())).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        let _: () = dependency_health::observe("redis", self.connection.clone().del(key)).await?;
+        Ok(())
+    }
+}
+
+/// Read-through cache in front of the database for single-entity reads
+///
+/// Entries are JSON under `cache:<entity>:<id>` and expire after the TTL;
+/// writes through the API invalidate them. Each lookup runs in a `cache.get`
+/// span (`cache.set`, `cache.delete` for writes) tagged `cache.entity` and
+/// `cache.hit`, and `cache.hit` is also set on the calling span so a trace
+/// shows whether the request was served from cache. Lookups count the
+/// `cache.hits` and `cache.misses` metrics, tagged `entity`. The cache fails
+/// open: a slow or unreachable Redis reads as a miss.
+///
+/// Configuration:
+/// - `REDIS_URL`: the cache is off without Redis
+/// - `CACHE_TTL_SECS`: entry lifetime in seconds (default 60); 0 turns the cache off
+#[derive(Debug, Clone)]
+pub struct Cache {
+    store: Option<Arc<dyn CacheStore>>,
+    ttl: Duration,
+}
+
+impl Cache {
+    pub fn from_env(store: Option<Arc<dyn CacheStore>>) -> Result<Self, Box<dyn std::error::Error>> {
+        let ttl_secs = match std::env::var("CACHE_TTL_SECS") {
+            Ok(value) => value.parse::<u64>().map_err(|e| format!("CACHE_TTL_SECS: {}", e))?,
+            Err(_) => DEFAULT_TTL_SECS,
+        };
+        let store = store.filter(|_| ttl_secs > 0);
+        info_trace!(enabled = store.is_some(), ttl_secs, "Response cache configured");
+        Ok(Self {
+            store,
+            ttl: Duration::from_secs(ttl_secs),
+        })
+    }
+
+    /// The cached `entity` with this ID, if any
+    pub async fn get<T: DeserializeOwned>(&self, entity: &'static str, id: &str) -> Option<T> {
+        let store = self.store.as_ref()?;
+        let span = cache_span("cache.get", entity);
+        let raw = match tokio::time::timeout(STORE_TIMEOUT, store.get(&key(entity, id)).instrument(span.clone())).await {
+            Ok(Ok(raw)) => raw,
+            Ok(Err(e)) => {
+                warn_trace!(cache.entity = entity, error = %e, "Cache read failed");
+                None
+            }
+            Err(_) => {
+                warn_trace!(cache.entity = entity, "Cache read timed out");
+                None
+            }
+        };
+        let value = raw.and_then(|raw| match serde_json::from_str(&raw) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn_trace!(cache.entity = entity, error = %e, "Ignoring unreadable cache entry");
+                None
+            }
+        });
+
+        let hit = value.is_some();
+        span.set_attribute("cache.hit", hit);
+        Span::current().set_attribute("cache.hit", hit);
+        let metric = if hit { "cache.hits" } else { "cache.misses" };
+        dogstatsd::count(metric, 1, &[format!("entity:{}", entity)]);
+        value
+    }
+
+    /// Cache `value` as the `entity` with this ID
+    pub async fn set<T: Serialize>(&self, entity: &'static str, id: &str, value: &T) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let raw = match serde_json::to_string(value) {
+            Ok(raw) => raw,
+            Err(e) => {
+                warn_trace!(cache.entity = entity, error = %e, "Failed to serialize cache entry");
+                return;
+            }
+        };
+        let key = key(entity, id);
+        let write = store.set(&key, raw, self.ttl).instrument(cache_span("cache.set", entity));
+        if let Ok(Err(e)) = tokio::time::timeout(STORE_TIMEOUT, write).await {
+            warn_trace!(cache.entity = entity, error = %e, "Cache write failed");
+        }
+    }
+
+    /// Drop the cached `entity` with this ID after it changed
+    pub async fn invalidate(&self, entity: &'static str, id: &str) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let key = key(entity, id);
+        let delete = store.delete(&key).instrument(cache_span("cache.delete", entity));
+        if let Ok(Err(e)) = tokio::time::timeout(STORE_TIMEOUT, delete).await {
+            warn_trace!(cache.entity = entity, error = %e, "Cache invalidation failed");
+        }
+    }
+}
+
+fn key(entity: &str, id: &str) -> String {
+    format!("cache:{}:{}", entity, id)
+}
+
+fn cache_span(operation: &'static str, entity: &'static str) -> Span {
+    tracing::info_span!(
+        "cache",
+        otel.name = operation,
+        otel.kind = "client",
+        db.system = "redis",
+        peer.service = "redis",
+        cache.entity = entity,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct MapStore(Mutex<HashMap<String, String>>);
+
+    #[async_trait::async_trait]
+    impl CacheStore for MapStore {
+        async fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        async fn set(&self, key: &str, value: String, _ttl: Duration) -> Result<(), StoreError> {
+            self.0.lock().unwrap().insert(key.to_string(), value);
+            Ok(())
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), StoreError> {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_through_and_invalidates() {
+        let cache = Cache {
+            store: Some(Arc::new(MapStore::default())),
+            ttl: Duration::from_secs(60),
+        };
+        assert_eq!(cache.get::<Vec<u32>>("order", "1").await, None);
+        cache.set("order", "1", &vec![1, 2]).await;
+        assert_eq!(cache.get::<Vec<u32>>("order", "1").await, Some(vec![1, 2]));
+        cache.invalidate("order", "1").await;
+        assert_eq!(cache.get::<Vec<u32>>("order", "1").await, None);
+
+        let disabled = Cache { store: None, ttl: Duration::ZERO };
+        disabled.set("order", "1", &vec![1]).await;
+        assert_eq!(disabled.get::<Vec<u32>>("order", "1").await, None);
+    }
+}
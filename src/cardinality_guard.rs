@@ -0,0 +1,302 @@
+use crate::attribute_filter::matches_pattern;
+use crate::dogstatsd;
+use opentelemetry::{KeyValue, StringValue, Value};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+const DEFAULT_MAX_VALUE_LENGTH: usize = 1024;
+const DEFAULT_MAX_UNIQUE_VALUES: usize = 1000;
+const DEFAULT_GUARDED_KEYS: &str = "resource.name";
+
+/// Replaces values of a guarded key once it has seen too many distinct ones
+const OVERFLOW_VALUE: &str = "other";
+
+/// Key span names are tracked under, for uniqueness and the warning metric
+const SPAN_NAME: &str = "span.name";
+
+/// Warning metric sent, through DogStatsD, for each rewritten value
+const METRIC: &str = "otel.cardinality_guard.rewrites";
+
+/// Why a value was rewritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Reason {
+    /// An ID-like path segment in a span or resource name
+    IdSegment,
+    /// Longer than the length limit
+    Truncated,
+    /// A new value of a key past its distinct-value limit
+    Overflow,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Reason::IdSegment => "id_segment",
+            Reason::Truncated => "truncated",
+            Reason::Overflow => "overflow",
+        }
+    }
+}
+
+/// Export-time protection against high-cardinality span data
+///
+/// Instrumentation mistakes, like a raw `/orders/5f0c…` path as a span name, a
+/// full SQL statement or unbounded user input in an attribute, multiply what
+/// Datadog indexes and bills. The guard rewrites such values before export:
+/// - UUID, numeric and long hex path segments in span names and `resource.name` become `?`
+/// - string values over the length limit are cut, keeping a hash of the full value
+///   (`SELECT …#1a2b3c4d`), so distinct values still tell apart
+/// - span names and guarded keys past the distinct-value limit report `other`
+///
+/// Every rewrite increments `otel.cardinality_guard.rewrites` (tagged `reason`
+/// and `attribute`) and the counts on `/debug/telemetry`. `_dd` keys are never touched.
+///
+/// Configuration:
+/// - `CARDINALITY_MAX_VALUE_LENGTH`: longest string value kept as is (default 1024, 0 = no limit)
+/// - `CARDINALITY_MAX_UNIQUE_VALUES`: distinct span names, and values per guarded key (default 1000, 0 = no limit)
+/// - `CARDINALITY_GUARDED_KEYS`: keys whose distinct values are limited (default `resource.name`, `prefix.*` wildcards)
+#[derive(Debug)]
+pub struct CardinalityGuard {
+    max_value_length: usize,
+    max_unique_values: usize,
+    guarded_keys: Vec<String>,
+    seen: Mutex<HashMap<String, HashSet<u64>>>,
+    rewrites: Mutex<HashMap<(String, Reason), u64>>,
+    total: AtomicU64,
+}
+
+impl CardinalityGuard {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let limit = |name: &str, default: usize| -> Result<usize, Box<dyn std::error::Error>> {
+            match std::env::var(name) {
+                Ok(value) => Ok(value.parse().map_err(|e| format!("{}: {}", name, e))?),
+                Err(_) => Ok(default),
+            }
+        };
+        let guarded_keys = std::env::var("CARDINALITY_GUARDED_KEYS")
+            .unwrap_or_else(|_| DEFAULT_GUARDED_KEYS.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(Self::new(
+            limit("CARDINALITY_MAX_VALUE_LENGTH", DEFAULT_MAX_VALUE_LENGTH)?,
+            limit("CARDINALITY_MAX_UNIQUE_VALUES", DEFAULT_MAX_UNIQUE_VALUES)?,
+            guarded_keys,
+        ))
+    }
+
+    fn new(max_value_length: usize, max_unique_values: usize, guarded_keys: Vec<String>) -> Self {
+        Self {
+            max_value_length,
+            max_unique_values,
+            guarded_keys,
+            seen: Mutex::new(HashMap::new()),
+            rewrites: Mutex::new(HashMap::new()),
+            total: AtomicU64::new(0),
+        }
+    }
+
+    /// `name` with ID segments replaced, cut to length, and limited in distinct values
+    pub fn span_name(&self, name: Cow<'static, str>) -> Cow<'static, str> {
+        match self.guard_str(SPAN_NAME, &name, true, true) {
+            Some(guarded) => Cow::Owned(guarded),
+            None => name,
+        }
+    }
+
+    /// Rewrite `attribute`'s value in place if it is a risky string
+    pub fn attribute(&self, attribute: &mut KeyValue) {
+        let key = attribute.key.as_str();
+        if key.starts_with("_dd") {
+            return;
+        }
+        let Value::String(value) = &attribute.value else {
+            return;
+        };
+        let is_name = key == "resource.name";
+        let limit_unique = self.guarded_keys.iter().any(|pattern| matches_pattern(pattern, key));
+        if let Some(guarded) = self.guard_str(key, value.as_str(), is_name, limit_unique) {
+            attribute.value = Value::String(StringValue::from(guarded));
+        }
+    }
+
+    pub fn attributes(&self, attributes: &mut [KeyValue]) {
+        for attribute in attributes {
+            self.attribute(attribute);
+        }
+    }
+
+    /// The rewritten value, or `None` when `value` passes unchanged
+    fn guard_str(&self, key: &str, value: &str, is_name: bool, limit_unique: bool) -> Option<String> {
+        let mut guarded = Cow::Borrowed(value);
+        if is_name {
+            if let Some(normalized) = replace_id_segments(value) {
+                self.record(key, Reason::IdSegment);
+                guarded = Cow::Owned(normalized);
+            }
+        }
+        if self.max_value_length > 0 && guarded.chars().count() > self.max_value_length {
+            self.record(key, Reason::Truncated);
+            guarded = Cow::Owned(truncate(&guarded, self.max_value_length));
+        }
+        if limit_unique && !self.admit(key, &guarded) {
+            self.record(key, Reason::Overflow);
+            guarded = Cow::Borrowed(OVERFLOW_VALUE);
+        }
+        match guarded {
+            Cow::Owned(guarded) => Some(guarded),
+            Cow::Borrowed(guarded) if guarded != value => Some(guarded.to_string()),
+            Cow::Borrowed(_) => None,
+        }
+    }
+
+    /// Whether `value` is known for `key`, or there is room to remember it
+    fn admit(&self, key: &str, value: &str) -> bool {
+        if self.max_unique_values == 0 {
+            return true;
+        }
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let values = seen.entry(key.to_string()).or_default();
+        if values.contains(&hash) {
+            return true;
+        }
+        if values.len() >= self.max_unique_values {
+            return false;
+        }
+        values.insert(hash);
+        true
+    }
+
+    fn record(&self, key: &str, reason: Reason) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        *self
+            .rewrites
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry((key.to_string(), reason))
+            .or_default() += 1;
+        dogstatsd::count(
+            METRIC,
+            1,
+            &[format!("reason:{}", reason.as_str()), format!("attribute:{}", key)],
+        );
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let rewrites = self.rewrites.lock().unwrap_or_else(|e| e.into_inner());
+        let mut by_key: Vec<_> = rewrites
+            .iter()
+            .map(|((key, reason), count)| {
+                serde_json::json!({ "attribute": key, "reason": reason.as_str(), "count": count })
+            })
+            .collect();
+        by_key.sort_by(|a, b| b["count"].as_u64().cmp(&a["count"].as_u64()));
+        serde_json::json!({
+            "max_value_length": self.max_value_length,
+            "max_unique_values": self.max_unique_values,
+            "guarded_keys": self.guarded_keys,
+            "rewrites": self.total.load(Ordering::Relaxed),
+            "by_attribute": by_key,
+        })
+    }
+}
+
+/// `name` with ID-like path segments replaced by `?`, or `None` if it has none
+///
+/// Segments are split on `/`, so `GET /orders/42` becomes `GET /orders/?`.
+fn replace_id_segments(name: &str) -> Option<String> {
+    if !name.split('/').skip(1).any(is_id_segment) {
+        return None;
+    }
+    let mut segments = name.split('/');
+    let mut normalized = segments.next().unwrap_or_default().to_string();
+    for segment in segments {
+        normalized.push('/');
+        normalized.push_str(if is_id_segment(segment) { "?" } else { segment });
+    }
+    Some(normalized)
+}
+
+/// UUIDs, numbers and long hex strings, e.g. object IDs and hashes
+fn is_id_segment(segment: &str) -> bool {
+    let segment = segment.split(['?', '#']).next().unwrap_or_default();
+    if segment.is_empty() {
+        return false;
+    }
+    let hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+    let is_uuid = segment.len() == 36
+        && segment
+            .split('-')
+            .map(str::len)
+            .eq([8, 4, 4, 4, 12])
+        && hex(&segment.replace('-', ""));
+    is_uuid
+        || segment.chars().all(|c| c.is_ascii_digit())
+        || (segment.len() >= 16 && hex(segment) && segment.chars().any(|c| c.is_ascii_digit()))
+}
+
+/// The first `max` characters of `value`, then `…#` and a hash of the whole value
+fn truncate(value: &str, max: usize) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    let mut truncated: String = value.chars().take(max).collect();
+    truncated.push_str("…#");
+    for byte in &digest[..4] {
+        truncated.push_str(&format!("{:02x}", byte));
+    }
+    truncated
+}
+
+static GUARD: OnceLock<Arc<CardinalityGuard>> = OnceLock::new();
+
+/// Report `guard` in [`snapshot`]; returns it for the tracer to use
+pub fn install(guard: CardinalityGuard) -> Arc<CardinalityGuard> {
+    GUARD.get_or_init(|| Arc::new(guard)).clone()
+}
+
+/// Guard settings and rewrite counts for `/debug/telemetry`
+pub fn snapshot() -> serde_json::Value {
+    match GUARD.get() {
+        Some(guard) => guard.snapshot(),
+        None => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites_high_cardinality_values() {
+        let guard = CardinalityGuard::new(24, 2, vec!["user.*".to_string()]);
+
+        assert_eq!(
+            guard.span_name("GET /orders/5f0c8b9e-1d2a-4c3b-9e8f-0a1b2c3d4e5f/items/42".into()),
+            "GET /orders/?/items/?"
+        );
+        assert_eq!(guard.span_name("GET /orders/v2".into()), "GET /orders/v2");
+
+        let mut statement = KeyValue::new("db.statement", "SELECT * FROM orders WHERE id = 1");
+        guard.attribute(&mut statement);
+        assert!(statement.value.as_str().starts_with("SELECT * FROM orders WHE…#"));
+
+        let mut ids = ["a", "b", "a", "c"].map(|id| KeyValue::new("user.id", id));
+        guard.attributes(&mut ids);
+        let values: Vec<_> = ids.iter().map(|kv| kv.value.as_str().into_owned()).collect();
+        assert_eq!(values, ["a", "b", "a", "other"]);
+
+        let mut internal = KeyValue::new("_dd.p.dm", "-4".repeat(20));
+        guard.attribute(&mut internal);
+        assert_eq!(internal.value.as_str().len(), 40);
+        assert_eq!(guard.snapshot()["rewrites"], 3);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::config::ServiceConfig;
+use clap::{Parser, Subcommand};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Datadog APM demo service on OpenTelemetry
+///
+/// Options override the matching environment variables and config file keys,
+/// so a local run needs no `DD_*` exports.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Address to listen on [default: from BIND_ADDRESS, else 0.0.0.0]
+    #[arg(long)]
+    pub host: Option<IpAddr>,
+    /// Port to listen on [default: from BIND_ADDRESS, else 8080]
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Log filter directives, e.g. `debug` or `info,rust_datadog_otel=trace` [default: RUST_LOG]
+    #[arg(long)]
+    pub log_level: Option<String>,
+    /// Datadog Agent host for traces [default: DD_AGENT_HOST, else HOST_IP, else localhost]
+    #[arg(long)]
+    pub agent_host: Option<String>,
+    /// Service config file [default: config.toml, when it exists]
+    #[arg(long, env = "APP_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Tools that run and exit instead of serving
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check event schema changes against the golden copy in schemas/
+    CheckSchemas {
+        /// Refresh the golden copy once the change is compatible
+        #[arg(long)]
+        update: bool,
+    },
+    /// Print the JSON Schema of the deployment config
+    ConfigSchema,
+    /// Lint a deployment config file (.env, JSON or TOML)
+    ValidateConfig { file: PathBuf },
+    /// Run a frontend proxy and the API behind it, for multi-service traces
+    Demo {
+        /// Port the frontend listens on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Loopback port of the backend API
+        #[arg(long, default_value_t = 8081)]
+        backend_port: u16,
+    },
+}
+
+impl Cli {
+    /// Apply the server options to `config`
+    pub fn apply(&self, config: &mut ServiceConfig) {
+        if let Some(host) = self.host {
+            config.bind_address.set_ip(host);
+        }
+        if let Some(port) = self.port {
+            config.bind_address.set_port(port);
+        }
+        if let Some(log_level) = &self.log_level {
+            config.log_level = log_level.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn parses_server_options_and_tools() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["rust-datadog-otel", "--port", "9000", "--agent-host", "agent.local"]).unwrap();
+        assert_eq!(cli.port, Some(9000));
+        assert_eq!(cli.agent_host.as_deref(), Some("agent.local"));
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from(["rust-datadog-otel", "check-schemas", "--update"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::CheckSchemas { update: true })));
+
+        let cli = Cli::try_parse_from(["rust-datadog-otel", "demo", "--port", "3000"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Demo { port: 3000, backend_port: 8081 })));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::http_client::HttpClient;
+use reqwest::{header, Method, RequestBuilder, StatusCode};
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+/// `peer.service` of client spans unless set with [`ApiClient::with_peer_service`]
+const DEFAULT_PEER_SERVICE: &str = "rust-datadog-otel";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Health {
+    pub status: String,
+    pub version: String,
+    pub timestamp: String,
+    /// Where the service exports spans: `agent`, `collector`, `otlp`, `console`, or `none`
+    pub exporter: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewUser {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewOrder {
+    pub user_id: Uuid,
+    /// ISO 4217 code of every item price: `USD` (the default), `EUR`, `GBP` or `JPY`
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub items: Vec<OrderLine>,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderLine {
+    /// Product SKU such as `prod-001`
+    pub product_id: String,
+    pub quantity: u32,
+    /// Unit price in the order's currency
+    pub price: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Order {
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub total_amount: Decimal,
+    pub currency: String,
+    pub status: String,
+    pub created_at: String,
+}
+
+/// One page of a user's orders, oldest first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderPage {
+    pub orders: Vec<Order>,
+    /// Pass to [`ApiClient::list_user_orders`] for the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// Why an API call failed
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request never got a response, or the body didn't decode
+    Http(reqwest::Error),
+    /// The API answered with an error status
+    Api { status: StatusCode, message: String },
+}
+
+impl ClientError {
+    /// The API's status code, if it answered
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Http(e) => e.status(),
+            ClientError::Api { status, .. } => Some(*status),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "request failed: {}", e),
+            ClientError::Api { status, message } => write!(f, "{}: {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+/// Typed client for the users and orders API
+///
+/// Calls go through [`HttpClient`], so each one is a client span in the
+/// caller's trace, with the trace context injected: a service calling the API
+/// through this client shows up in the same Datadog trace as the API's own spans.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use rust_datadog_otel::client::{ApiClient, NewUser};
+///
+/// let api = ApiClient::new("http://localhost:8080")?.with_api_version("v2");
+/// let user = api
+///     .create_user(&NewUser { name: "Ada".into(), email: "ada@example.com".into() })
+///     .await?;
+/// let orders = api.list_user_orders(user.id, Some(10), None).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    http: HttpClient,
+    base_url: reqwest::Url,
+    api_prefix: String,
+}
+
+impl ApiClient {
+    /// Client for the service at `base_url`, e.g. `http://localhost:8080`, on the unversioned `/api`
+    pub fn new(base_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let base_url = reqwest::Url::parse(base_url).map_err(|e| format!("API base URL '{}': {}", base_url, e))?;
+        Ok(Self {
+            http: HttpClient::new().with_peer_service(DEFAULT_PEER_SERVICE),
+            base_url,
+            api_prefix: "/api".to_string(),
+        })
+    }
+
+    /// Call a versioned API, `v1` or `v2`
+    pub fn with_api_version(mut self, version: &str) -> Self {
+        self.api_prefix = format!("/api/{}", version);
+        self
+    }
+
+    /// Name the API's service in client spans, when it isn't deployed as `rust-datadog-otel`
+    pub fn with_peer_service(mut self, service: impl Into<String>) -> Self {
+        self.http = self.http.with_peer_service(service);
+        self
+    }
+
+    pub async fn health(&self) -> Result<Health, ClientError> {
+        self.call(self.request(Method::GET, "/health")).await
+    }
+
+    pub async fn create_user(&self, user: &NewUser) -> Result<User, ClientError> {
+        self.call(self.api(Method::POST, "/users").json(user)).await
+    }
+
+    pub async fn get_user(&self, id: Uuid) -> Result<User, ClientError> {
+        self.call(self.api(Method::GET, &format!("/users/{}", id))).await
+    }
+
+    /// A page of at most `limit` of the user's orders, after `cursor`
+    pub async fn list_user_orders(
+        &self,
+        user_id: Uuid,
+        limit: Option<usize>,
+        cursor: Option<&str>,
+    ) -> Result<OrderPage, ClientError> {
+        let mut request = self.api(Method::GET, &format!("/users/{}/orders", user_id));
+        if let Some(limit) = limit {
+            request = request.query(&[("limit", limit)]);
+        }
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+        self.call(request).await
+    }
+
+    pub async fn create_order(&self, order: &NewOrder) -> Result<Order, ClientError> {
+        self.call(self.api(Method::POST, "/orders").json(order)).await
+    }
+
+    pub async fn get_order(&self, id: Uuid) -> Result<Order, ClientError> {
+        self.call(self.api(Method::GET, &format!("/orders/{}", id))).await
+    }
+
+    pub async fn cancel_order(&self, id: Uuid) -> Result<Order, ClientError> {
+        self.call(self.api(Method::POST, &format!("/orders/{}/cancel", id))).await
+    }
+
+    fn api(&self, method: Method, path: &str) -> RequestBuilder {
+        self.request(method, &format!("{}{}", self.api_prefix, path))
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let mut url = self.base_url.clone();
+        url.set_path(&format!("{}{}", self.base_url.path().trim_end_matches('/'), path));
+        self.http
+            .request(method, url)
+            .header(header::ACCEPT, "application/json")
+    }
+
+    /// Send `request` and decode the JSON body, or the API's `{"error": …}`
+    async fn call<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, ClientError> {
+        let response = self.http.send(request).await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+        let message = match response.json::<ErrorBody>().await {
+            Ok(body) => body.error,
+            Err(_) => status.canonical_reason().unwrap_or_default().to_string(),
+        };
+        Err(ClientError::Api { status, message })
+    }
+}
@@ -0,0 +1,98 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// Client address resolved after trusted-proxy handling
+///
+/// Inserted into request extensions by [`resolve_client_ip`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Proxies allowed to set `X-Forwarded-For`
+///
+/// Configured via `TRUSTED_PROXIES` (comma-separated CIDRs). Defaults to
+/// loopback and RFC1918 ranges, which covers the GKE load balancer and
+/// `kubectl port-forward`.
+#[derive(Debug, Clone)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn from_env() -> Result<Self, ipnet::AddrParseError> {
+        let raw = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_else(|_| "127.0.0.0/8,::1/128,10.0.0.0/8,172.16.0.0/12,192.168.0.0/16".to_string());
+        Ok(Self {
+            networks: parse_cidrs(&raw)?,
+        })
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Resolve the originating client address
+    ///
+    /// Walks `X-Forwarded-For` right to left starting from the socket peer,
+    /// skipping hops that are trusted proxies. The first untrusted hop is the
+    /// client; a spoofed header from an untrusted peer is ignored.
+    pub fn resolve(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        let mut client = peer?;
+        if !self.is_trusted(client) {
+            return Some(client);
+        }
+
+        let forwarded: Vec<IpAddr> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+
+        for hop in forwarded.into_iter().rev() {
+            client = hop;
+            if !self.is_trusted(hop) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Parse a comma-separated CIDR list; bare addresses are treated as host routes
+pub fn parse_cidrs(raw: &str) -> Result<Vec<IpNet>, ipnet::AddrParseError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|e| entry.parse::<IpAddr>().map(IpNet::from).map_err(|_| e))
+        })
+        .collect()
+}
+
+/// Middleware storing the resolved [`ClientIp`] in request extensions
+pub async fn resolve_client_ip(
+    State(proxies): State<Arc<TrustedProxies>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    if let Some(ip) = proxies.resolve(request.headers(), peer) {
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+
+    next.run(request).await
+}
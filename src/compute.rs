@@ -0,0 +1,37 @@
+use std::time::Instant;
+use tokio::task::JoinError;
+use tracing::Instrument;
+
+/// Run CPU-bound `work` on the blocking thread pool without stalling the async workers
+///
+/// The work runs inside a `compute` span that is a child of the caller's
+/// span, so the trace stays connected across the thread hop. Time spent
+/// waiting for a pool thread (`compute.queue_wait_ms`) is recorded separately
+/// from time spent working (`compute.duration_ms`), which tells pool
+/// saturation apart from slow work.
+pub async fn compute<F, T>(name: &'static str, work: F) -> Result<T, JoinError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let span = tracing::info_span!(
+        "compute",
+        compute.name = name,
+        compute.queue_wait_ms = tracing::field::Empty,
+        compute.duration_ms = tracing::field::Empty,
+    );
+
+    let queued = Instant::now();
+    let worker_span = span.clone();
+    tokio::task::spawn_blocking(move || {
+        let _guard = worker_span.enter();
+        worker_span.record("compute.queue_wait_ms", queued.elapsed().as_secs_f64() * 1000.0);
+
+        let started = Instant::now();
+        let output = work();
+        worker_span.record("compute.duration_ms", started.elapsed().as_secs_f64() * 1000.0);
+        output
+    })
+    .instrument(span)
+    .await
+}
@@ -0,0 +1,130 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue},
+};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::warn_trace;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+/// Strong entity tag for a record version, e.g. `"3"`
+pub fn etag(version: u64) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{}\"", version)).expect("quoted digits are a valid header")
+}
+
+/// `If-Match` precondition of a write request
+///
+/// Without the header a write is unconditional. Tags are compared strongly,
+/// so weak (`W/`) tags never match, as RFC 9110 requires for `If-Match`.
+#[derive(Debug, Default)]
+pub struct IfMatch(Option<Vec<String>>);
+
+impl IfMatch {
+    /// Whether a record at `version` satisfies the precondition
+    pub fn matches(&self, version: u64) -> bool {
+        let Some(tags) = &self.0 else {
+            return true;
+        };
+        let current = format!("\"{}\"", version);
+        tags.iter().any(|tag| tag == "*" || *tag == current)
+    }
+
+    /// Header value as sent, for logs
+    pub fn as_header(&self) -> String {
+        self.0.as_ref().map(|tags| tags.join(", ")).unwrap_or_default()
+    }
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let values: Vec<&HeaderValue> = parts.headers.get_all(header::IF_MATCH).iter().collect();
+        if values.is_empty() {
+            return Ok(Self(None));
+        }
+        // An unreadable header matches nothing rather than being ignored
+        let tags = values
+            .into_iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        Ok(Self(Some(tags)))
+    }
+}
+
+/// Result of a versioned update
+#[derive(Debug)]
+pub enum UpdateOutcome<T> {
+    Updated(T),
+    /// `If-Match` named an older version than the stored one
+    Stale { current_version: u64 },
+    NotFound,
+    /// The record is in a state that doesn't allow this update
+    Rejected(String),
+}
+
+/// Versioned update and conflict counters by entity, for `/admin/concurrency`
+#[derive(Debug, Default)]
+pub struct ConcurrencyStats {
+    counts: Mutex<BTreeMap<&'static str, EntityCounts>>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct EntityCounts {
+    updates: u64,
+    conflicts: u64,
+}
+
+impl ConcurrencyStats {
+    pub fn record_update(&self, entity: &'static str) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.entry(entity).or_default().updates += 1;
+    }
+
+    /// Count a lost race and note it on the current span; returns the 412 to send
+    pub fn record_conflict(&self, entity: &'static str, if_match: &IfMatch, current_version: u64) -> AppError {
+        {
+            let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+            counts.entry(entity).or_default().conflicts += 1;
+        }
+        warn_trace!(
+            concurrency.entity = entity,
+            concurrency.if_match = %if_match.as_header(),
+            concurrency.current_version = current_version,
+            "concurrency.conflict"
+        );
+        AppError::PreconditionFailed(format!(
+            "The {} was modified by another request; fetch it again and retry",
+            entity
+        ))
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let entities: serde_json::Map<String, serde_json::Value> = counts
+            .iter()
+            .map(|(entity, counts)| {
+                let attempts = counts.updates + counts.conflicts;
+                let conflict_rate = if attempts == 0 {
+                    0.0
+                } else {
+                    counts.conflicts as f64 / attempts as f64
+                };
+                (
+                    entity.to_string(),
+                    serde_json::json!({
+                        "updates": counts.updates,
+                        "conflicts": counts.conflicts,
+                        "conflict_rate": conflict_rate,
+                    }),
+                )
+            })
+            .collect();
+        serde_json::json!({ "entities": entities })
+    }
+}
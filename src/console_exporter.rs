@@ -0,0 +1,95 @@
+use crate::warn_trace;
+use axum::{response::IntoResponse, Json, Router};
+use opentelemetry::trace::{SpanId, Status};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Prints finished spans to stdout in a readable form
+///
+/// Each span is a header line with its name, duration, IDs, parent (`root`
+/// for a trace's first span) and any error, then one indented line per
+/// attribute:
+///
+/// ```text
+/// check_inventory 81.2ms trace=6f1c… span=3a9e… parent=b40d…
+///     order.items = 2
+///     inventory.products = 2
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleExporter;
+
+impl SpanExporter for ConsoleExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        for span in &batch {
+            println!("{}", format_span(span));
+        }
+        Ok(())
+    }
+}
+
+/// `span` as [`ConsoleExporter`] prints it
+pub fn format_span(span: &SpanData) -> String {
+    let duration = span.end_time.duration_since(span.start_time).unwrap_or_default();
+    let parent = if span.parent_span_id == SpanId::INVALID {
+        "root".to_string()
+    } else {
+        span.parent_span_id.to_string()
+    };
+    let mut text = format!(
+        "{} {:.1}ms trace={} span={} parent={}",
+        span.name,
+        duration.as_secs_f64() * 1000.0,
+        span.span_context.trace_id(),
+        span.span_context.span_id(),
+        parent
+    );
+    if let Status::Error { description } = &span.status {
+        text.push_str(&format!(" error=\"{}\"", description));
+    }
+    for attribute in &span.attributes {
+        text.push_str(&format!("\n    {} = {}", attribute.key, attribute.value));
+    }
+    text
+}
+
+/// Whether `DD_TRACE_DEBUG_EXPORTER=console` asks for spans on stdout instead of the Agent
+pub fn from_env() -> Result<bool, Box<dyn std::error::Error>> {
+    match std::env::var("DD_TRACE_DEBUG_EXPORTER").as_deref() {
+        Err(_) | Ok("") => Ok(false),
+        Ok("console") => Ok(true),
+        Ok(other) => Err(format!("DD_TRACE_DEBUG_EXPORTER: expected console, got '{}'", other).into()),
+    }
+}
+
+/// Record that spans go to the console, for `/health`
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Serve a stand-in Agent on a loopback port that accepts and drops everything; returns its URL
+///
+/// The Datadog SDK still needs somewhere to send traces, and with no Agent
+/// running it would log an error for every failed export.
+///
+/// Must be called from within the Tokio runtime.
+pub fn start_null_agent() -> Result<String, Box<dyn std::error::Error>> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+
+    let app = Router::new().fallback(|| async { Json(serde_json::json!({ "rate_by_service": {} })).into_response() });
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn_trace!(error = %e, "Null Agent stopped");
+        }
+    });
+    Ok(url)
+}
@@ -0,0 +1,172 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use rust_datadog_otel::dogstatsd;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Cost units charged per database (or index) call
+const DB_CALL_UNITS: u64 = 1;
+/// Cost units charged per call to an external service
+const EXTERNAL_CALL_UNITS: u64 = 5;
+/// Bytes moved per cost unit, rounded up
+const BYTES_PER_UNIT: u64 = 1024;
+
+/// Work done while serving one request
+///
+/// Shared between the request's extensions and a task-local, so repositories
+/// and clients can charge the current request without it being passed down.
+#[derive(Debug, Default)]
+pub struct RequestCost {
+    db_calls: AtomicU64,
+    external_calls: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl RequestCost {
+    /// Abstract cost: calls weighted by kind, plus bytes moved
+    pub fn units(&self) -> u64 {
+        self.db_calls.load(Ordering::Relaxed) * DB_CALL_UNITS
+            + self.external_calls.load(Ordering::Relaxed) * EXTERNAL_CALL_UNITS
+            + self.bytes.load(Ordering::Relaxed).div_ceil(BYTES_PER_UNIT)
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Arc<RequestCost>;
+}
+
+fn charge(apply: impl FnOnce(&RequestCost)) {
+    // Work outside a request (scheduled jobs, background tasks) isn't charged
+    let _ = CURRENT.try_with(|cost| apply(cost));
+}
+
+/// Charge work in `future` to the current request, for work spawned off the request task
+pub fn in_current_request<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let current = CURRENT.try_with(Arc::clone).ok();
+    async move {
+        match current {
+            Some(current) => CURRENT.scope(current, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Charge the current request for a repository or index call
+pub fn record_db_call() {
+    charge(|cost| {
+        cost.db_calls.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// Charge the current request for a call to another service
+pub fn record_external_call() {
+    charge(|cost| {
+        cost.external_calls.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// Charge the current request for bytes sent or received
+pub fn record_bytes(bytes: usize) {
+    charge(|cost| {
+        cost.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    });
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+struct EndpointCost {
+    requests: u64,
+    units: u64,
+    db_calls: u64,
+    external_calls: u64,
+    bytes: u64,
+}
+
+/// Cost totals per endpoint, for `/admin/costs`
+#[derive(Debug, Default)]
+pub struct CostStats {
+    endpoints: Mutex<BTreeMap<String, EndpointCost>>,
+}
+
+impl CostStats {
+    fn record(&self, endpoint: String, cost: &RequestCost) {
+        let mut endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        let totals = endpoints.entry(endpoint).or_default();
+        totals.requests += 1;
+        totals.units += cost.units();
+        totals.db_calls += cost.db_calls.load(Ordering::Relaxed);
+        totals.external_calls += cost.external_calls.load(Ordering::Relaxed);
+        totals.bytes += cost.bytes.load(Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        let endpoints: serde_json::Map<String, serde_json::Value> = endpoints
+            .iter()
+            .map(|(endpoint, totals)| {
+                let mut value = serde_json::json!(totals);
+                value["average_units"] = serde_json::json!(totals.units as f64 / totals.requests as f64);
+                (endpoint.clone(), value)
+            })
+            .collect();
+        serde_json::json!({
+            "weights": {
+                "db_call": DB_CALL_UNITS,
+                "external_call": EXTERNAL_CALL_UNITS,
+                "bytes_per_unit": BYTES_PER_UNIT,
+            },
+            "endpoints": endpoints,
+        })
+    }
+}
+
+/// Size of a body whose length is known up front
+fn known_size(body: &Body) -> usize {
+    body.size_hint().exact().unwrap_or(0) as usize
+}
+
+/// Route layer metering the cost of each request
+///
+/// Request and response bodies count when their length is known up front;
+/// streamed bodies are charged only for the calls made to produce them.
+/// The total is set on the request span as `cost.units` (with the
+/// `cost.db_calls`, `cost.external_calls` and `cost.bytes` breakdown),
+/// added to the endpoint's totals and sent as the DogStatsD distribution
+/// `request.cost_units`, tagged by endpoint.
+pub async fn meter_cost(State(stats): State<Arc<CostStats>>, mut request: Request, next: Next) -> Response {
+    let endpoint = format!(
+        "{} {}",
+        request.method(),
+        request
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| request.uri().path(), MatchedPath::as_str)
+    );
+    let cost = Arc::new(RequestCost::default());
+    request.extensions_mut().insert(cost.clone());
+
+    let response = CURRENT
+        .scope(cost.clone(), async {
+            record_bytes(known_size(request.body()));
+            let response = next.run(request).await;
+            record_bytes(known_size(response.body()));
+            response
+        })
+        .await;
+
+    let span = tracing::Span::current();
+    span.set_attribute("cost.units", cost.units() as i64);
+    span.set_attribute("cost.db_calls", cost.db_calls.load(Ordering::Relaxed) as i64);
+    span.set_attribute("cost.external_calls", cost.external_calls.load(Ordering::Relaxed) as i64);
+    span.set_attribute("cost.bytes", cost.bytes.load(Ordering::Relaxed) as i64);
+    dogstatsd::distribution("request.cost_units", cost.units() as f64, &[format!("endpoint:{}", endpoint)]);
+    stats.record(endpoint, &cost);
+    response
+}
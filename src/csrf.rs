@@ -0,0 +1,160 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::warn_trace;
+use std::sync::Arc;
+
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Double-submit-cookie CSRF protection
+///
+/// Configuration:
+/// - `CSRF_PROTECTION`: `true` to enable (default `false`)
+/// - `CSRF_PROTECTED_PREFIXES`: comma-separated route groups to protect (default `/api`)
+/// - `CSRF_COOKIE_SECURE`: set the `Secure` cookie attribute (default `true`)
+///
+/// Only browser-originated requests (those carrying `Origin` or `Referer`) are
+/// checked, so service-to-service clients and curl are unaffected.
+#[derive(Debug, Clone)]
+pub struct CsrfConfig {
+    enabled: bool,
+    protected_prefixes: Vec<String>,
+    secure_cookie: bool,
+}
+
+impl CsrfConfig {
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("CSRF_PROTECTION")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let protected_prefixes = std::env::var("CSRF_PROTECTED_PREFIXES")
+            .unwrap_or_else(|_| "/api".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|prefix| !prefix.is_empty())
+            .map(str::to_string)
+            .collect();
+        let secure_cookie = std::env::var("CSRF_COOKIE_SECURE")
+            .map(|v| !v.eq_ignore_ascii_case("false"))
+            .unwrap_or(true);
+
+        Self {
+            enabled,
+            protected_prefixes,
+            secure_cookie,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn protects(&self, path: &str) -> bool {
+        self.protected_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    fn cookie(&self, token: &str) -> String {
+        let secure = if self.secure_cookie { "; Secure" } else { "" };
+        format!("{}={}; Path=/; SameSite=Strict{}", CSRF_COOKIE, token, secure)
+    }
+}
+
+fn is_state_changing(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+fn is_browser_request(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::ORIGIN) || headers.contains_key(header::REFERER)
+}
+
+fn cookie_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == CSRF_COOKIE)
+        .map(|(_, value)| value)
+}
+
+/// Compare tokens without short-circuiting on the first mismatch
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a
+            .bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn new_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Middleware enforcing [`CsrfConfig`]
+///
+/// Safe requests without a token cookie receive one; browser-originated
+/// state-changing requests must echo it in the `X-CSRF-Token` header.
+pub async fn enforce_csrf(
+    State(config): State<Arc<CsrfConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !config.protects(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers();
+    let cookie = cookie_token(headers).map(str::to_string);
+
+    if is_state_changing(request.method()) && is_browser_request(headers) {
+        let header_token = headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok());
+
+        let valid = matches!(
+            (cookie.as_deref(), header_token),
+            (Some(cookie), Some(header)) if tokens_match(cookie, header)
+        );
+
+        if !valid {
+            let reason = match (cookie.is_some(), header_token.is_some()) {
+                (false, _) => "missing_cookie",
+                (true, false) => "missing_header",
+                (true, true) => "token_mismatch",
+            };
+            warn_trace!(
+                csrf.reason = reason,
+                http.method = %request.method(),
+                path = %request.uri().path(),
+                "CSRF validation failed"
+            );
+            return AppError::Forbidden("CSRF token missing or invalid".to_string()).into_response();
+        }
+    }
+
+    let mut response = next.run(request).await;
+
+    if cookie.is_none() {
+        if let Ok(value) = HeaderValue::from_str(&config.cookie(&new_token())) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+
+    response
+}
@@ -0,0 +1,170 @@
+use crate::{debug_trace, dogstatsd, info_trace};
+use opentelemetry::trace::TraceContextExt;
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Severity shown on the event, and its color on the timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertType {
+    Success,
+    Error,
+}
+
+impl AlertType {
+    fn as_str(self) -> &'static str {
+        match self {
+            AlertType::Success => "success",
+            AlertType::Error => "error",
+        }
+    }
+}
+
+/// One Datadog event, e.g. a deploy or an incident
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub title: String,
+    pub text: String,
+    pub alert_type: AlertType,
+    /// Groups related events, e.g. every transition of one dependency
+    pub aggregation_key: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl Event {
+    pub fn new(title: impl Into<String>, text: impl Into<String>, alert_type: AlertType) -> Self {
+        Self {
+            title: title.into(),
+            text: text.into(),
+            alert_type,
+            aggregation_key: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn aggregation_key(mut self, key: impl Into<String>) -> Self {
+        self.aggregation_key = Some(key.into());
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: impl std::fmt::Display) -> Self {
+        self.tags.push(format!("{}:{}", key, value));
+        self
+    }
+}
+
+/// DogStatsD's `|` and newline framing, escaped as the Agent expects
+fn escape(text: &str) -> String {
+    text.replace('\n', "\\n").replace('|', "/")
+}
+
+/// DogStatsD event datagram: `_e{title_len,text_len}:title|text|t:..|k:..|#tags`
+fn datagram(event: &Event, tags: &[String]) -> String {
+    let title = escape(&event.title);
+    let text = escape(&event.text);
+    let mut datagram = format!(
+        "_e{{{},{}}}:{}|{}|t:{}|s:rust-datadog-otel",
+        title.len(),
+        text.len(),
+        title,
+        text,
+        event.alert_type.as_str()
+    );
+    if let Some(key) = &event.aggregation_key {
+        datagram.push_str("|k:");
+        datagram.push_str(&escape(key));
+    }
+    let tags: Vec<String> = tags
+        .iter()
+        .chain(&event.tags)
+        .map(|tag| escape(&tag.replace(',', "_")))
+        .collect();
+    if !tags.is_empty() {
+        datagram.push_str("|#");
+        datagram.push_str(&tags.join(","));
+    }
+    datagram
+}
+
+/// Sends Datadog events through the installed [`dogstatsd`] client
+///
+/// Events are tagged with the service, env and version, and, when emitted
+/// inside a traced request, with its trace ID and a link to the trace, so a
+/// timeline annotation leads back to the request that caused it. Sending is
+/// best effort: a missing Agent only loses the event.
+///
+/// Configuration:
+/// - `DD_EVENTS_ENABLED`: send events (default true)
+#[derive(Debug)]
+pub struct EventClient {
+    enabled: bool,
+    site: String,
+}
+
+impl EventClient {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let enabled = match std::env::var("DD_EVENTS_ENABLED") {
+            Ok(value) => value
+                .parse::<bool>()
+                .map_err(|e| format!("DD_EVENTS_ENABLED: {}", e))?,
+            Err(_) => true,
+        };
+        Ok(Self {
+            enabled,
+            site: std::env::var("DD_SITE").unwrap_or_else(|_| "datadoghq.com".to_string()),
+        })
+    }
+
+    /// Send `event`, adding the current trace when there is one
+    pub fn emit(&self, mut event: Event) {
+        let Some(statsd) = dogstatsd::client().filter(|_| self.enabled) else {
+            return;
+        };
+        let span_context = tracing::Span::current().context().span().span_context().clone();
+        if span_context.is_valid() {
+            let trace_id = span_context.trace_id();
+            event.text.push_str(&format!("\n\nTrace: https://app.{}/apm/trace/{}", self.site, trace_id));
+            event = event
+                .tag("trace_id", trace_id)
+                .tag("span_id", span_context.span_id());
+        }
+
+        match statsd.send(&datagram(&event, statsd.tags())) {
+            Ok(_) => info_trace!(
+                event.title = %event.title,
+                event.alert_type = event.alert_type.as_str(),
+                "Datadog event sent"
+            ),
+            Err(e) => debug_trace!(event.title = %event.title, error = %e, "Datadog event not sent"),
+        }
+    }
+}
+
+static CLIENT: OnceLock<EventClient> = OnceLock::new();
+
+/// Use `client` for `emit` from now on; call once at startup
+pub fn install(client: EventClient) {
+    let _ = CLIENT.set(client);
+}
+
+/// Send `event` with the installed client; a no-op before `install`
+pub fn emit(event: Event) {
+    if let Some(client) = CLIENT.get() {
+        client.emit(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dogstatsd_events() {
+        let event = Event::new("Deployed v1|2", "line one\nline two", AlertType::Error)
+            .aggregation_key("deploy")
+            .tag("dependency", "redis");
+        assert_eq!(
+            datagram(&event, &["service:api".to_string()]),
+            "_e{13,18}:Deployed v1/2|line one\\nline two|t:error|s:rust-datadog-otel|k:deploy|#service:api,dependency:redis"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use crate::compute;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::warn_trace;
+use std::io::Read;
+use std::sync::Arc;
+use tracing::Instrument;
+
+const DEFAULT_MAX_COMPRESSED_BYTES: usize = 2 * 1024 * 1024;
+const DEFAULT_MAX_DECOMPRESSED_BYTES: usize = 10 * 1024 * 1024;
+
+/// Advertised in `Accept-Encoding` on 415 responses (RFC 7694)
+const SUPPORTED_ENCODINGS: &str = "gzip, deflate, zstd";
+
+/// Content codings accepted on request bodies
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    Gzip,
+    Deflate,
+    Zstd,
+}
+
+impl Encoding {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Decode `input`, reading at most `limit + 1` bytes so bombs are cut off early
+    fn decode(self, input: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+        let reader: Box<dyn Read + '_> = match self {
+            Encoding::Gzip => Box::new(flate2::read::GzDecoder::new(input)),
+            // HTTP "deflate" is zlib-wrapped
+            Encoding::Deflate => Box::new(flate2::read::ZlibDecoder::new(input)),
+            Encoding::Zstd => Box::new(zstd::stream::read::Decoder::new(input)?),
+        };
+        let mut output = Vec::new();
+        reader.take(limit as u64 + 1).read_to_end(&mut output)?;
+        Ok(output)
+    }
+}
+
+/// Size limits for compressed request bodies
+///
+/// Configuration:
+/// - `REQUEST_MAX_COMPRESSED_BYTES`: largest accepted body on the wire (default 2 MiB)
+/// - `REQUEST_MAX_DECOMPRESSED_BYTES`: largest body after decoding (default 10 MiB)
+#[derive(Debug, Clone)]
+pub struct DecompressionConfig {
+    max_compressed_bytes: usize,
+    max_decompressed_bytes: usize,
+}
+
+impl DecompressionConfig {
+    pub fn from_env() -> Self {
+        let limit = |var: &str, default: usize| {
+            std::env::var(var)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_compressed_bytes: limit("REQUEST_MAX_COMPRESSED_BYTES", DEFAULT_MAX_COMPRESSED_BYTES),
+            max_decompressed_bytes: limit(
+                "REQUEST_MAX_DECOMPRESSED_BYTES",
+                DEFAULT_MAX_DECOMPRESSED_BYTES,
+            ),
+        }
+    }
+}
+
+fn reject(error: AppError) -> Response {
+    let unsupported = matches!(error, AppError::UnsupportedMediaType(_));
+    let mut response = error.into_response();
+    if unsupported {
+        response.headers_mut().insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static(SUPPORTED_ENCODINGS),
+        );
+    }
+    response
+}
+
+/// Middleware decoding `Content-Encoding: gzip/deflate/zstd` request bodies
+///
+/// Unsupported codings get a 415 and bodies over either size limit a 413.
+/// Decoding happens in an `http.request.decompress` span recording the wire
+/// and decoded sizes; handlers then see a plain body with `Content-Encoding` removed.
+pub async fn decompress_request(
+    State(config): State<Arc<DecompressionConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(content_encoding) = request.headers().get(header::CONTENT_ENCODING) else {
+        return next.run(request).await;
+    };
+    // No coding has a non-ASCII name, so this can't be one we support
+    let Ok(content_encoding) = content_encoding.to_str().map(str::to_string) else {
+        warn_trace!("Non-ASCII request Content-Encoding");
+        return reject(AppError::UnsupportedMediaType("Unsupported Content-Encoding".to_string()));
+    };
+
+    // Codings are listed in the order they were applied
+    let mut encodings = Vec::new();
+    for coding in content_encoding.split(',').filter(|c| !c.trim().is_empty()) {
+        if coding.trim().eq_ignore_ascii_case("identity") {
+            continue;
+        }
+        match Encoding::parse(coding) {
+            Some(encoding) => encodings.push(encoding),
+            None => {
+                warn_trace!(content_encoding = %content_encoding, "Unsupported request Content-Encoding");
+                return reject(AppError::UnsupportedMediaType(format!(
+                    "Unsupported Content-Encoding '{}'",
+                    coding.trim()
+                )));
+            }
+        }
+    }
+
+    let (mut parts, body) = request.into_parts();
+    parts.headers.remove(header::CONTENT_ENCODING);
+    if encodings.is_empty() {
+        return next.run(Request::from_parts(parts, body)).await;
+    }
+
+    let span = tracing::info_span!(
+        "http.request.decompress",
+        http.request.content_encoding = %content_encoding,
+        http.request.body.size = tracing::field::Empty,
+        http.request.body.decompressed_size = tracing::field::Empty,
+    );
+
+    let decoded = match decode_body(body, &encodings, &config)
+        .instrument(span)
+        .await
+    {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(decoded.len()));
+    next.run(Request::from_parts(parts, Body::from(decoded))).await
+}
+
+/// Why a body couldn't be decoded
+enum DecodeError {
+    Malformed(std::io::Error),
+    /// Over the decompressed size limit
+    TooLarge,
+}
+
+/// Undo `encodings`, last applied first, stopping once the output passes `limit`
+fn decode_all(compressed: &[u8], encodings: &[Encoding], limit: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut decoded = compressed.to_vec();
+    for encoding in encodings.iter().rev() {
+        decoded = encoding.decode(&decoded, limit).map_err(DecodeError::Malformed)?;
+        if decoded.len() > limit {
+            return Err(DecodeError::TooLarge);
+        }
+    }
+    Ok(decoded)
+}
+
+/// Buffer and decode a body, recording both sizes on the current span
+async fn decode_body(
+    body: Body,
+    encodings: &[Encoding],
+    config: &DecompressionConfig,
+) -> Result<Bytes, Response> {
+    let span = tracing::Span::current();
+
+    let compressed = to_bytes(body, config.max_compressed_bytes)
+        .await
+        .map_err(|_| {
+            warn_trace!(limit = config.max_compressed_bytes, "Compressed request body too large");
+            reject(AppError::PayloadTooLarge(format!(
+                "Request body exceeds {} bytes",
+                config.max_compressed_bytes
+            )))
+        })?;
+    span.record("http.request.body.size", compressed.len());
+
+    // Inflating is CPU-bound, so it runs off the async workers
+    let encodings = encodings.to_vec();
+    let limit = config.max_decompressed_bytes;
+    let decoded = compute::compute("request.decompress", move || decode_all(&compressed, &encodings, limit))
+        .await
+        .map_err(|e| {
+            warn_trace!(error = %e, "Request decompression task failed");
+            reject(AppError::Internal("Request body could not be decompressed".to_string()))
+        })?;
+    let decoded = match decoded {
+        Ok(decoded) => decoded,
+        Err(DecodeError::Malformed(e)) => {
+            warn_trace!(error = %e, "Malformed compressed request body");
+            return Err(reject(AppError::BadRequest(
+                "Request body could not be decompressed".to_string(),
+            )));
+        }
+        Err(DecodeError::TooLarge) => {
+            warn_trace!(limit = limit, "Decompressed request body too large");
+            return Err(reject(AppError::PayloadTooLarge(format!(
+                "Decompressed request body exceeds {} bytes",
+                limit
+            ))));
+        }
+    };
+    span.record("http.request.body.decompressed_size", decoded.len());
+
+    Ok(Bytes::from(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, middleware, routing::post, Router};
+    use std::io::Write;
+    use tower::ServiceExt;
+
+    fn app(max_compressed_bytes: usize, max_decompressed_bytes: usize) -> Router {
+        let config = Arc::new(DecompressionConfig {
+            max_compressed_bytes,
+            max_decompressed_bytes,
+        });
+        Router::new()
+            .route("/echo", post(|body: Bytes| async move { body }))
+            .layer(middleware::from_fn_with_state(config, decompress_request))
+    }
+
+    async fn send(app: Router, content_encoding: &str, body: Vec<u8>) -> (StatusCode, Option<HeaderValue>, Bytes) {
+        let request = axum::http::Request::post("/echo")
+            .header(header::CONTENT_ENCODING, content_encoding)
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let accept_encoding = response.headers().get(header::ACCEPT_ENCODING).cloned();
+        (status, accept_encoding, to_bytes(response.into_body(), usize::MAX).await.unwrap())
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn decodes_gzip_deflate_and_zstd_bodies() {
+        let payload = br#"{"name":"widget","quantity":3}"#.repeat(50);
+        let cases = [
+            ("gzip", gzip(&payload)),
+            ("deflate", deflate(&payload)),
+            ("zstd", zstd::encode_all(&payload[..], 0).unwrap()),
+            ("gzip, zstd", zstd::encode_all(&gzip(&payload)[..], 0).unwrap()),
+        ];
+        for (content_encoding, body) in cases {
+            let (status, _, echoed) = send(app(1024 * 1024, 1024 * 1024), content_encoding, body).await;
+            assert_eq!(status, StatusCode::OK, "{}", content_encoding);
+            assert_eq!(&echoed[..], &payload[..], "{}", content_encoding);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_over_either_limit() {
+        let bomb = gzip(&vec![0u8; 64 * 1024]);
+        let (status, _, _) = send(app(1024 * 1024, 1024), "gzip", bomb.clone()).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let (status, _, _) = send(app(bomb.len() - 1, 1024 * 1024), "gzip", bomb).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_encodings() {
+        let (status, accept_encoding, _) = send(app(1024, 1024), "br", b"data".to_vec()).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(accept_encoding.unwrap(), SUPPORTED_ENCODINGS);
+
+        let request = axum::http::Request::post("/echo")
+            .header(header::CONTENT_ENCODING, HeaderValue::from_bytes(b"gz\xffip").unwrap())
+            .body(Body::from("data"))
+            .unwrap();
+        let response = app(1024, 1024).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::ids::UserId;
+use crate::negotiation::ResponseFormat;
+use crate::{auth, compute, dependency_health, money, orders, region, report, reports, repository, search, AppState};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use rust_datadog_otel::error::{AppError, ErrorResponse};
+use rust_datadog_otel::{
+    debug_trace, error_trace, info_trace, measure, warn_trace,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ErrorSimulationQuery {
+    /// One of `generic`, `server`, `database`, `timeout`
+    #[serde(default)]
+    error_type: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// Synthetic orders to aggregate (default 100000)
+    rows: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Search terms; each must match a word or word prefix
+    q: String,
+    /// Only return `user` or `product` hits
+    kind: Option<search::DocumentKind>,
+    /// Hits to return, 1-50 (default 10)
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResults {
+    hits: Vec<search::SearchHit>,
+    /// Backend the query ran on, `embedded` or `meilisearch`
+    backend: &'static str,
+}
+
+/// Longest upload name accepted, matching common filesystem limits
+const MAX_UPLOAD_NAME_LEN: usize = 255;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UploadResponse {
+    key: String,
+    size: usize,
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching users and products, best first", body = SearchResults),
+        (status = 400, description = "Invalid limit or kind", body = ErrorResponse),
+        (status = 502, description = "Search backend unavailable", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, query, format), fields(search.kind = query.kind.map(search::DocumentKind::as_str)))]
+pub async fn search_entities(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+    format: ResponseFormat,
+) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(10);
+    if !(1..=search::MAX_LIMIT).contains(&limit) {
+        return AppError::BadRequest(format!("limit must be between 1 and {}", search::MAX_LIMIT)).into_response();
+    }
+
+    match state.search.search(&query.q, query.kind, limit).await {
+        Ok(hits) => format
+            .body(SearchResults {
+                hits,
+                backend: state.search.backend(),
+            })
+            .into_response(),
+        Err(e) => {
+            error_trace!(error = %e, search.backend = state.search.backend(), "Search failed");
+            AppError::Upstream("Search is unavailable".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/uploads/{name}",
+    tag = "uploads",
+    params(("name" = String, Path, description = "File name, stored under the caller's prefix")),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 201, description = "File stored", body = UploadResponse),
+        (status = 400, description = "Invalid file name", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 500, description = "Object storage failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, format, principal, body), fields(user_id = %principal.user_id, upload.size = body.len()))]
+pub async fn upload_file(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    format: ResponseFormat,
+    principal: auth::Principal,
+    body: axum::body::Bytes,
+) -> impl IntoResponse {
+    // One path segment, so a name can't escape the caller's prefix
+    let valid = !name.is_empty()
+        && name.len() <= MAX_UPLOAD_NAME_LEN
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\']);
+    if !valid {
+        return AppError::BadRequest("Invalid file name".to_string()).into_response();
+    }
+
+    let key = format!("uploads/{}/{}", principal.user_id, name);
+    let size = body.len();
+    match state.objects.put(&key, body.to_vec()).await {
+        Ok(()) => {
+            info_trace!(upload.key = %key, upload.size = size, "File uploaded");
+            (StatusCode::CREATED, format.body(UploadResponse { key, size })).into_response()
+        }
+        Err(e) => {
+            error_trace!(error = %e, upload.key = %key, "Failed to store upload");
+            AppError::Internal("Failed to store file".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports/latest",
+    tag = "orders",
+    responses(
+        (status = 200, description = "Most recent scheduled orders summary", body = reports::ReportArtifact),
+        (status = 404, description = "No report generated yet", body = ErrorResponse),
+        (status = 500, description = "Artifact storage failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip_all)]
+pub async fn latest_report(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    match state.reports.latest().await {
+        Ok(Some(report)) => format.body(report).into_response(),
+        Ok(None) => AppError::NotFound("No report generated yet".to_string()).into_response(),
+        Err(e) => {
+            error_trace!(error = %e, "Failed to load latest report");
+            AppError::Internal("Failed to load report".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/simulate-error",
+    tag = "simulation",
+    params(ErrorSimulationQuery),
+    responses(
+        (status = 400, description = "Generic error", body = ErrorResponse),
+        (status = 408, description = "Timeout error", body = ErrorResponse),
+        (status = 500, description = "Server error", body = ErrorResponse),
+        (status = 503, description = "Database error", body = ErrorResponse)
+    )
+)]
+#[instrument]
+pub async fn simulate_error(
+    Query(params): Query<ErrorSimulationQuery>,
+    format: ResponseFormat,
+) -> impl IntoResponse {
+    let error_type = if params.error_type.is_empty() {
+        "generic"
+    } else {
+        &params.error_type
+    };
+
+    error_trace!(error_type = %error_type, "Simulating error");
+
+    match error_type {
+        "timeout" => {
+            warn_trace!("Simulating timeout error");
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            AppError::RequestTimeout("Request timeout".to_string())
+        }
+        "server" => {
+            error_trace!("Simulating internal server error");
+            AppError::Internal("Internal server error".to_string())
+        }
+        "database" => {
+            error_trace!("Simulating database connection error");
+            dependency_health::record("database", false, Duration::ZERO);
+            AppError::ServiceUnavailable("Database connection failed".to_string())
+        }
+        _ => {
+            error_trace!("Simulating generic error");
+            AppError::BadRequest("Bad request".to_string())
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/slow-operation",
+    tag = "simulation",
+    responses((status = 200, description = "Completed after ~1 second", body = serde_json::Value))
+)]
+#[instrument]
+pub async fn slow_operation(format: ResponseFormat) -> impl IntoResponse {
+    info_trace!("Starting slow operation");
+
+    // Simulate multiple slow steps
+    for i in 1..=5 {
+        measure!("slow_operation.step", "step" = i, async {
+            debug_trace!(step = i, "Processing step");
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await;
+    }
+
+    info_trace!("Slow operation completed");
+
+    format.body(serde_json::json!({
+        "message": "Slow operation completed",
+        "duration_ms": 1000
+    }))
+}
+
+/// Users listed by `/database-query`, busiest first
+const DATABASE_QUERY_TOP_USERS: usize = 50;
+
+/// One row of the `/database-query` report
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserOrderSummary {
+    user_id: UserId,
+    name: String,
+    /// Orders placed, cancelled ones included
+    orders: usize,
+    /// Amount of the orders not cancelled, per currency, as decimal strings
+    #[schema(value_type = HashMap<String, String>)]
+    totals: BTreeMap<money::Currency, rust_decimal::Decimal>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DatabaseQueryResponse {
+    message: String,
+    /// Users with at least one order
+    results: usize,
+    users: Vec<UserOrderSummary>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/database-query",
+    tag = "simulation",
+    responses((status = 200, description = "Orders and order totals per user, busiest users first", body = DatabaseQueryResponse))
+)]
+#[instrument(skip(state))]
+pub async fn database_query(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    info_trace!("Executing database query");
+
+    // Both tables are read at once, so their spans run side by side under this one
+    let (names, orders) = measure!("database_query.read", async {
+        tokio::join!(query_users_table(&state.users), query_orders_table(&state.orders))
+    })
+    .await;
+    let users = join_user_orders(names, orders);
+
+    info_trace!(results = users.len(), "Database query completed");
+
+    format.body(DatabaseQueryResponse {
+        message: "Database query completed".to_string(),
+        results: users.len(),
+        users: users.into_iter().take(DATABASE_QUERY_TOP_USERS).collect(),
+    })
+}
+
+/// Default and largest `rows` accepted by `/report`
+const REPORT_DEFAULT_ROWS: usize = 100_000;
+const REPORT_MAX_ROWS: usize = 2_000_000;
+
+#[utoipa::path(
+    get,
+    path = "/report",
+    tag = "simulation",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Aggregated report over synthetic orders", body = report::Report),
+        (status = 400, description = "Too many rows requested", body = ErrorResponse),
+        (status = 500, description = "Report computation failed", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(format))]
+pub async fn generate_report(Query(query): Query<ReportQuery>, format: ResponseFormat) -> impl IntoResponse {
+    let rows = query.rows.unwrap_or(REPORT_DEFAULT_ROWS);
+    if rows > REPORT_MAX_ROWS {
+        warn_trace!(rows, "Report request too large");
+        return AppError::BadRequest(format!("rows must be at most {}", REPORT_MAX_ROWS)).into_response();
+    }
+
+    info_trace!(rows, "Generating report");
+    match compute::compute("report.generate", move || report::generate(rows)).await {
+        Ok(report) => {
+            info_trace!(rows, digest = %report.digest, "Report generated");
+            format.body(report).into_response()
+        }
+        Err(e) => {
+            error_trace!(error = %e, "Report computation failed");
+            AppError::Internal("Failed to generate report".to_string()).into_response()
+        }
+    }
+}
+
+#[instrument(skip_all)]
+async fn query_users_table(users: &repository::UserRepository) -> HashMap<UserId, String> {
+    debug_trace!("Querying users table");
+    region::simulate_downstream("database").await;
+    users.names().await
+}
+
+#[instrument(skip_all)]
+async fn query_orders_table(orders: &orders::OrderRepository) -> Vec<orders::OrderRecord> {
+    debug_trace!("Querying orders table");
+    region::simulate_downstream("database").await;
+    orders.all().await
+}
+
+/// Order counts and totals per user with orders, most orders first
+#[instrument(skip_all, fields(users = names.len(), orders = orders.len()))]
+fn join_user_orders(names: HashMap<UserId, String>, orders: Vec<orders::OrderRecord>) -> Vec<UserOrderSummary> {
+    debug_trace!("Joining user and order data");
+    let mut summaries: HashMap<UserId, UserOrderSummary> = HashMap::new();
+    for order in orders {
+        // Orders of deleted users have no one to report them under
+        let Some(name) = names.get(&order.user_id) else {
+            continue;
+        };
+        let summary = summaries.entry(order.user_id).or_insert_with(|| UserOrderSummary {
+            user_id: order.user_id,
+            name: name.clone(),
+            orders: 0,
+            totals: BTreeMap::new(),
+        });
+        summary.orders += 1;
+        if order.status != orders::CANCELLED {
+            *summary.totals.entry(order.currency).or_default() += order.total_amount;
+        }
+    }
+    let mut summaries: Vec<UserOrderSummary> = summaries.into_values().collect();
+    summaries.sort_by(|a, b| b.orders.cmp(&a.orders).then_with(|| a.user_id.cmp(&b.user_id)));
+    summaries
+}
@@ -0,0 +1,236 @@
+use rust_datadog_otel::datadog_events::{self, AlertType, Event};
+use rust_datadog_otel::{info_trace, warn_trace};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Calls per dependency that its score is computed over
+const WINDOW: usize = 100;
+/// Calls needed before a dependency can leave `healthy`
+const MIN_SAMPLES: usize = 5;
+
+/// How a dependency has been doing over its recent calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyState {
+    Healthy,
+    Degraded,
+    Down,
+}
+
+impl DependencyState {
+    fn as_str(self) -> &'static str {
+        match self {
+            DependencyState::Healthy => "healthy",
+            DependencyState::Degraded => "degraded",
+            DependencyState::Down => "down",
+        }
+    }
+}
+
+/// Score thresholds, shared by every dependency
+///
+/// Configuration:
+/// - `DEPENDENCY_DEGRADED_SUCCESS_RATE`: success rate below which a dependency is degraded (default 0.95)
+/// - `DEPENDENCY_DOWN_SUCCESS_RATE`: success rate below which it is down (default 0.5)
+/// - `DEPENDENCY_SLOW_MS`: p95 latency above which it is degraded (default 1000)
+#[derive(Debug, Clone, Copy)]
+struct Thresholds {
+    degraded_success_rate: f64,
+    down_success_rate: f64,
+    slow: Duration,
+}
+
+impl Thresholds {
+    fn from_env() -> Self {
+        let setting = |name: &str, default: f64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            degraded_success_rate: setting("DEPENDENCY_DEGRADED_SUCCESS_RATE", 0.95),
+            down_success_rate: setting("DEPENDENCY_DOWN_SUCCESS_RATE", 0.5),
+            slow: Duration::from_millis(setting("DEPENDENCY_SLOW_MS", 1000.0) as u64),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Tracker {
+    calls: VecDeque<(bool, Duration)>,
+    state: DependencyState,
+    since: Instant,
+    transitions: BTreeMap<String, u64>,
+}
+
+impl Tracker {
+    fn new() -> Self {
+        Self {
+            calls: VecDeque::with_capacity(WINDOW),
+            state: DependencyState::Healthy,
+            since: Instant::now(),
+            transitions: BTreeMap::new(),
+        }
+    }
+
+    fn success_rate(&self) -> f64 {
+        if self.calls.is_empty() {
+            return 1.0;
+        }
+        self.calls.iter().filter(|(ok, _)| *ok).count() as f64 / self.calls.len() as f64
+    }
+
+    fn p95(&self) -> Duration {
+        let mut latencies: Vec<Duration> = self.calls.iter().map(|(_, latency)| *latency).collect();
+        latencies.sort();
+        latencies
+            .get((latencies.len() * 95).div_ceil(100).saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn score(&self, thresholds: &Thresholds) -> DependencyState {
+        if self.calls.len() < MIN_SAMPLES {
+            return DependencyState::Healthy;
+        }
+        let success_rate = self.success_rate();
+        if success_rate < thresholds.down_success_rate {
+            DependencyState::Down
+        } else if success_rate < thresholds.degraded_success_rate || self.p95() > thresholds.slow {
+            DependencyState::Degraded
+        } else {
+            DependencyState::Healthy
+        }
+    }
+}
+
+/// Rolling success rate and latency of each downstream the service calls
+///
+/// Dependencies appear once first called. Each one is scored over its last
+/// calls as healthy, degraded or down; changes are logged and counted, and a
+/// dependency that is down fails the readiness probe.
+#[derive(Debug)]
+struct Registry {
+    thresholds: Thresholds,
+    dependencies: Mutex<BTreeMap<&'static str, Tracker>>,
+}
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| Registry {
+        thresholds: Thresholds::from_env(),
+        dependencies: Mutex::new(BTreeMap::new()),
+    })
+}
+
+/// Record one call to `dependency`
+pub fn record(dependency: &'static str, ok: bool, latency: Duration) {
+    let registry = registry();
+    let mut dependencies = registry.dependencies.lock().unwrap_or_else(|e| e.into_inner());
+    let tracker = dependencies.entry(dependency).or_insert_with(Tracker::new);
+    if tracker.calls.len() == WINDOW {
+        tracker.calls.pop_front();
+    }
+    tracker.calls.push_back((ok, latency));
+
+    let state = tracker.score(&registry.thresholds);
+    if state == tracker.state {
+        return;
+    }
+    let previous = std::mem::replace(&mut tracker.state, state);
+    tracker.since = Instant::now();
+    *tracker
+        .transitions
+        .entry(format!("{}->{}", previous.as_str(), state.as_str()))
+        .or_default() += 1;
+    let success_rate = tracker.success_rate();
+    let p95_ms = tracker.p95().as_millis() as u64;
+    drop(dependencies);
+
+    if state == DependencyState::Down {
+        datadog_events::emit(
+            Event::new(
+                format!("{} is down", dependency),
+                format!(
+                    "{} success rate {:.0}%, p95 {}ms over recent calls; /ready now reports not ready",
+                    dependency,
+                    success_rate * 100.0,
+                    p95_ms
+                ),
+                AlertType::Error,
+            )
+            .aggregation_key(format!("dependency:{}", dependency))
+            .tag("dependency", dependency),
+        );
+    }
+    if state > previous {
+        warn_trace!(
+            dependency = dependency,
+            dependency.previous_state = previous.as_str(),
+            dependency.state = state.as_str(),
+            dependency.success_rate = success_rate,
+            dependency.p95_ms = p95_ms,
+            "Dependency health changed"
+        );
+    } else {
+        info_trace!(
+            dependency = dependency,
+            dependency.previous_state = previous.as_str(),
+            dependency.state = state.as_str(),
+            "Dependency health changed"
+        );
+    }
+}
+
+/// Run a call to `dependency`, recording its outcome and latency
+pub async fn observe<T, E>(dependency: &'static str, call: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let started = Instant::now();
+    let result = call.await;
+    record(dependency, result.is_ok(), started.elapsed());
+    result
+}
+
+/// Dependencies currently down; the service isn't ready while any are
+pub fn down() -> Vec<&'static str> {
+    let dependencies = registry().dependencies.lock().unwrap_or_else(|e| e.into_inner());
+    dependencies
+        .iter()
+        .filter(|(_, tracker)| tracker.state == DependencyState::Down)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+pub fn snapshot() -> serde_json::Value {
+    let registry = registry();
+    let dependencies = registry.dependencies.lock().unwrap_or_else(|e| e.into_inner());
+    let dependencies: serde_json::Map<String, serde_json::Value> = dependencies
+        .iter()
+        .map(|(name, tracker)| {
+            (
+                name.to_string(),
+                serde_json::json!({
+                    "state": tracker.state,
+                    "state_for_secs": tracker.since.elapsed().as_secs(),
+                    "calls": tracker.calls.len(),
+                    "success_rate": tracker.success_rate(),
+                    "p95_ms": tracker.p95().as_secs_f64() * 1000.0,
+                    "transitions": tracker.transitions,
+                }),
+            )
+        })
+        .collect();
+    serde_json::json!({
+        "thresholds": {
+            "degraded_success_rate": registry.thresholds.degraded_success_rate,
+            "down_success_rate": registry.thresholds.down_success_rate,
+            "slow_ms": registry.thresholds.slow.as_millis() as u64,
+            "window": WINDOW,
+            "min_samples": MIN_SAMPLES,
+        },
+        "dependencies": dependencies,
+    })
+}
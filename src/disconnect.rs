@@ -0,0 +1,107 @@
+use crate::priority::Deadline;
+use crate::{cost, region};
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use rust_datadog_otel::{dogstatsd, warn_trace};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::time::Instant;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Requests abandoned by their client per endpoint, for `/admin/disconnects`
+#[derive(Debug, Default)]
+pub struct DisconnectStats {
+    endpoints: Mutex<BTreeMap<String, u64>>,
+}
+
+impl DisconnectStats {
+    fn record(&self, endpoint: &str) {
+        let mut endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        *endpoints.entry(endpoint.to_string()).or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::json!({
+            "total": endpoints.values().sum::<u64>(),
+            "endpoints": *endpoints,
+        })
+    }
+}
+
+/// Notices the request being dropped before its response was produced
+struct Watch {
+    stats: Arc<DisconnectStats>,
+    endpoint: String,
+    span: tracing::Span,
+    started: Instant,
+    deadline: Option<Instant>,
+    finished: bool,
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        // Past the deadline, the priority class timeout dropped the request, not the client
+        if self.finished || self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return;
+        }
+        self.stats.record(&self.endpoint);
+        dogstatsd::count("request.client_disconnected", 1, &[format!("endpoint:{}", self.endpoint)]);
+        self.span.set_attribute("http.client_disconnected", true);
+        let elapsed_ms = self.started.elapsed().as_millis() as u64;
+        self.span.in_scope(|| {
+            warn_trace!(endpoint = %self.endpoint, elapsed_ms = elapsed_ms, "Client disconnected before the response was ready");
+        });
+    }
+}
+
+/// Route layer marking requests whose client went away mid-request
+///
+/// Hyper drops the request's future when the connection closes, which
+/// cancels the handler and any downstream calls at their next await; work
+/// that must not stop half-way runs under [`shield`]. The request span gets
+/// `http.client_disconnected=true`, so abandoned requests (what proxies log
+/// as 499) are told apart from slow ones, and the endpoint's disconnect
+/// count and the DogStatsD count `request.client_disconnected` go up.
+pub async fn detect_disconnect(State(stats): State<Arc<DisconnectStats>>, request: Request, next: Next) -> Response {
+    let endpoint = format!(
+        "{} {}",
+        request.method(),
+        request
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| request.uri().path(), MatchedPath::as_str)
+    );
+    let mut watch = Watch {
+        stats,
+        endpoint,
+        span: tracing::Span::current(),
+        started: Instant::now(),
+        deadline: request.extensions().get::<Deadline>().map(|deadline| deadline.0),
+        finished: false,
+    };
+    let response = next.run(request).await;
+    watch.finished = true;
+    response
+}
+
+/// Run `work` to completion even if the client disconnects
+///
+/// For side effects that must not be abandoned half-way, such as recording
+/// an order once payment was taken. The work keeps the current span, region
+/// and cost meter.
+pub async fn shield<F>(work: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let work = cost::in_current_request(region::in_current_region(work.in_current_span()));
+    tokio::spawn(work)
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
@@ -0,0 +1,130 @@
+use crate::dependency_health;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub type LockError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Lease-based lock shared between replicas
+///
+/// Locks expire after their TTL, so a crashed holder can't block others
+/// forever; holders renew by calling `acquire` again before it lapses.
+#[async_trait::async_trait]
+pub trait LockStore: Send + Sync + Debug {
+    /// Take `name` for `owner`, or extend the lease if `owner` already holds it
+    async fn acquire(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, LockError>;
+
+    /// Release `name` if `owner` still holds it
+    async fn release(&self, name: &str, owner: &str) -> Result<(), LockError>;
+
+    /// Backend name recorded on lock spans
+    fn backend(&self) -> &'static str;
+}
+
+/// Acquire-or-renew in one round trip: renew if we own it, else `SET NX PX`
+const ACQUIRE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+    return 1
+end
+return 0
+"#;
+
+/// Delete only if we still own it, so an expired lease taken over by another
+/// replica isn't released from under it
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Redis-backed locks, for deployments with more than one replica
+pub struct RedisLockStore {
+    connection: ConnectionManager,
+}
+
+impl Debug for RedisLockStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisLockStore").finish_non_exhaustive()
+    }
+}
+
+impl RedisLockStore {
+    pub fn new(connection: ConnectionManager) -> Self {
+        Self { connection }
+    }
+
+    fn key(name: &str) -> String {
+        format!("lock:{}", name)
+    }
+}
+
+#[async_trait::async_trait]
+impl LockStore for RedisLockStore {
+    async fn acquire(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, LockError> {
+        let acquired: i64 = dependency_health::observe("redis", async {
+            redis::Script::new(ACQUIRE_SCRIPT)
+                .key(Self::key(name))
+                .arg(owner)
+                .arg(ttl.as_millis() as u64)
+                .invoke_async(&mut self.connection.clone())
+                .await
+        })
+        .await?;
+        Ok(acquired == 1)
+    }
+
+    async fn release(&self, name: &str, owner: &str) -> Result<(), LockError> {
+        let _: i64 = dependency_health::observe("redis", async {
+            redis::Script::new(RELEASE_SCRIPT)
+                .key(Self::key(name))
+                .arg(owner)
+                .invoke_async(&mut self.connection.clone())
+                .await
+        })
+        .await?;
+        Ok(())
+    }
+
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+}
+
+/// Process-local locks, used when no Redis is configured (single replica)
+#[derive(Debug, Default)]
+pub struct InMemoryLockStore {
+    leases: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+#[async_trait::async_trait]
+impl LockStore for InMemoryLockStore {
+    async fn acquire(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, LockError> {
+        let mut leases = self.leases.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        match leases.get(name) {
+            Some((holder, expires)) if holder != owner && *expires > now => Ok(false),
+            _ => {
+                leases.insert(name.to_string(), (owner.to_string(), now + ttl));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release(&self, name: &str, owner: &str) -> Result<(), LockError> {
+        let mut leases = self.leases.lock().unwrap_or_else(|e| e.into_inner());
+        if leases.get(name).is_some_and(|(holder, _)| holder == owner) {
+            leases.remove(name);
+        }
+        Ok(())
+    }
+
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{telemetry, warn_trace};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::sync::OnceLock;
+
+const DEFAULT_DOGSTATSD_PORT: u16 = 8125;
+
+/// DogStatsD metric type, the letter after the value in a datagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Count,
+    Gauge,
+    Histogram,
+    Distribution,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Count => "c",
+            Kind::Gauge => "g",
+            Kind::Histogram => "h",
+            Kind::Distribution => "d",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Socket {
+    Udp(UdpSocket),
+    Unix(UnixDatagram),
+}
+
+impl Socket {
+    fn send(&self, datagram: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Socket::Udp(socket) => socket.send(datagram),
+            Socket::Unix(socket) => socket.send(datagram),
+        }
+    }
+}
+
+/// Tag values may not hold the `,` separator or DogStatsD's `|` and newline framing
+fn sanitize_tag(tag: &str) -> String {
+    tag.replace(',', "_").replace('|', "/").replace('\n', " ")
+}
+
+/// DogStatsD metric datagram: `metric:value|type|#tags`
+fn datagram(metric: &str, value: f64, kind: Kind, tags: &[String], extra_tags: &[String]) -> String {
+    let tags: Vec<String> = tags.iter().chain(extra_tags).map(|tag| sanitize_tag(tag)).collect();
+    let mut datagram = format!("{}:{}|{}", metric, value, kind.as_str());
+    if !tags.is_empty() {
+        datagram.push_str("|#");
+        datagram.push_str(&tags.join(","));
+    }
+    datagram
+}
+
+/// Sends custom metrics to the Datadog Agent's DogStatsD server
+///
+/// Every metric carries the unified service tags (`service`, `env`, `version`),
+/// so business metrics line up with the service's traces. Sending is best
+/// effort and never blocks: a missing Agent only loses the metric.
+///
+/// Configuration:
+/// - `DD_DOGSTATSD_SOCKET`: Agent DogStatsD Unix socket; UDP when unset
+/// - `DD_DOGSTATSD_PORT`: Agent DogStatsD UDP port (default 8125), on the telemetry Agent host
+#[derive(Debug)]
+pub struct Client {
+    socket: Option<Socket>,
+    tags: Vec<String>,
+}
+
+impl Client {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let port = match std::env::var("DD_DOGSTATSD_PORT") {
+            Ok(value) => value
+                .parse::<u16>()
+                .map_err(|e| format!("DD_DOGSTATSD_PORT: {}", e))?,
+            Err(_) => DEFAULT_DOGSTATSD_PORT,
+        };
+        let identity = telemetry::config();
+
+        let socket = match std::env::var("DD_DOGSTATSD_SOCKET") {
+            Ok(path) => Self::connect_unix(&path).map_err(|e| (path, e)),
+            Err(_) => Self::connect_udp(&identity.agent_host, port).map_err(|e| (identity.agent_host.clone(), e)),
+        };
+        let socket = match socket {
+            Ok(socket) => Some(socket),
+            Err((target, e)) => {
+                warn_trace!(dogstatsd.target = %target, error = %e, "DogStatsD disabled, Agent unreachable");
+                None
+            }
+        };
+
+        Ok(Self {
+            socket,
+            tags: vec![
+                format!("service:{}", identity.service),
+                format!("env:{}", identity.env),
+                format!("version:{}", identity.version),
+            ],
+        })
+    }
+
+    fn connect_udp(host: &str, port: u16) -> std::io::Result<Socket> {
+        let addr = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::other("no address for the Agent host"))?;
+        let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        socket.connect(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Socket::Udp(socket))
+    }
+
+    fn connect_unix(path: &str) -> std::io::Result<Socket> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        socket.set_nonblocking(true)?;
+        Ok(Socket::Unix(socket))
+    }
+
+    /// Unified service tags added to everything sent
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Send a raw datagram, e.g. an event; errors when the Agent is unreachable
+    pub fn send(&self, datagram: &str) -> std::io::Result<()> {
+        match &self.socket {
+            Some(socket) => socket.send(datagram.as_bytes()).map(|_| ()),
+            None => Err(std::io::Error::from(std::io::ErrorKind::NotConnected)),
+        }
+    }
+
+    fn metric(&self, metric: &str, value: f64, kind: Kind, tags: &[String]) {
+        if let Some(socket) = &self.socket {
+            let _ = socket.send(datagram(metric, value, kind, &self.tags, tags).as_bytes());
+        }
+    }
+
+    /// Add `value` to the count `metric`
+    pub fn count(&self, metric: &str, value: i64, tags: &[String]) {
+        self.metric(metric, value as f64, Kind::Count, tags);
+    }
+
+    /// Set the gauge `metric`; the Agent keeps the last value per flush
+    pub fn gauge(&self, metric: &str, value: f64, tags: &[String]) {
+        self.metric(metric, value, Kind::Gauge, tags);
+    }
+
+    /// Record `value` in `metric`, aggregated into percentiles by the Agent
+    pub fn histogram(&self, metric: &str, value: f64, tags: &[String]) {
+        self.metric(metric, value, Kind::Histogram, tags);
+    }
+
+    /// Record `value` in `metric`, aggregated globally across hosts by Datadog
+    pub fn distribution(&self, metric: &str, value: f64, tags: &[String]) {
+        self.metric(metric, value, Kind::Distribution, tags);
+    }
+}
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Use `client` for the metric functions and Datadog events; call once at startup
+pub fn install(client: Client) {
+    let _ = CLIENT.set(client);
+}
+
+/// The installed client, if any
+pub fn client() -> Option<&'static Client> {
+    CLIENT.get()
+}
+
+/// Add to a count metric with the installed client; a no-op before `install`
+pub fn count(metric: &str, value: i64, tags: &[String]) {
+    if let Some(client) = CLIENT.get() {
+        client.count(metric, value, tags);
+    }
+}
+
+/// Set a gauge with the installed client; a no-op before `install`
+pub fn gauge(metric: &str, value: f64, tags: &[String]) {
+    if let Some(client) = CLIENT.get() {
+        client.gauge(metric, value, tags);
+    }
+}
+
+/// Record a histogram value with the installed client; a no-op before `install`
+pub fn histogram(metric: &str, value: f64, tags: &[String]) {
+    if let Some(client) = CLIENT.get() {
+        client.histogram(metric, value, tags);
+    }
+}
+
+/// Record a distribution value with the installed client; a no-op before `install`
+pub fn distribution(metric: &str, value: f64, tags: &[String]) {
+    if let Some(client) = CLIENT.get() {
+        client.distribution(metric, value, tags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_metric_datagrams() {
+        let service = ["service:api".to_string()];
+        assert_eq!(
+            datagram("rewrites", 1.0, Kind::Count, &service, &["reason:a|b".to_string()]),
+            "rewrites:1|c|#service:api,reason:a/b"
+        );
+        assert_eq!(
+            datagram("orders.amount", 12.5, Kind::Distribution, &service, &["currency:usd,eur".to_string()]),
+            "orders.amount:12.5|d|#service:api,currency:usd_eur"
+        );
+        assert_eq!(datagram("queue.depth", 3.0, Kind::Gauge, &[], &[]), "queue.depth:3|g");
+    }
+
+    #[test]
+    fn sends_over_a_unix_socket() {
+        let dir = std::env::temp_dir().join(format!("dogstatsd-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dsd.socket");
+        let _ = std::fs::remove_file(&path);
+        let agent = UnixDatagram::bind(&path).unwrap();
+
+        let client = Client {
+            socket: Some(Client::connect_unix(path.to_str().unwrap()).unwrap()),
+            tags: vec!["env:test".to_string()],
+        };
+        client.histogram("payments.duration", 50.0, &["gateway:v2".to_string()]);
+        let mut buf = [0; 128];
+        let len = agent.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"payments.duration:50|h|#env:test,gateway:v2");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,22 @@
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Name the service a client span calls
+///
+/// Datadog draws `peer.service` as the downstream's node in the service map
+/// and computes inferred service metrics from it.
+pub fn set_peer_service(span: &Span, service: impl Into<String>) {
+    span.set_attribute("peer.service", service.into());
+}
+
+/// Report a client span under the downstream's own service
+///
+/// For proxy-style calls to arbitrary hosts, which otherwise all show up as
+/// one node for the shared client code. Sets `peer.service` and the span's
+/// own `service.name`, which the Datadog exporter prefers over the process
+/// service; the span stays in the same trace under the same parent.
+pub fn override_service(span: &Span, service: impl Into<String>) {
+    let service = service.into();
+    set_peer_service(span, service.clone());
+    span.set_attribute("service.name", service);
+}
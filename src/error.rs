@@ -0,0 +1,231 @@
+use crate::{error_trace, trace_context};
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use opentelemetry::trace::Status;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use utoipa::ToSchema;
+
+/// JSON body of error responses
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Datadog trace ID of the failed request, for support tickets and trace search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+/// Errors returned by handlers and middleware as JSON [`ErrorResponse`]s
+///
+/// Each response records the error on the current span as `error.type` and
+/// `error.message`, and carries the request's trace ID. Server errors (5xx)
+/// also mark the span as failed and log an error with the trace IDs; client
+/// errors don't, as they are the caller's to fix.
+#[derive(Debug)]
+pub enum AppError {
+    /// Malformed or invalid request input (400)
+    BadRequest(String),
+    /// No valid credentials (401, with a `WWW-Authenticate: Bearer` challenge)
+    Unauthorized(String),
+    /// Payment declined (402)
+    PaymentRequired(String),
+    /// Authenticated but not allowed (403)
+    Forbidden(String),
+    /// The resource doesn't exist (404)
+    NotFound(String),
+    /// The request took too long (408)
+    RequestTimeout(String),
+    /// Conflicts with the current state, e.g. not enough stock (409)
+    Conflict(String),
+    /// `If-Match` no longer matches the stored version (412)
+    PreconditionFailed(String),
+    /// The request body is over a size limit (413)
+    PayloadTooLarge(String),
+    /// The request body's media type or content coding isn't supported (415)
+    UnsupportedMediaType(String),
+    /// Over a rate limit (429; the limiter adds `Retry-After`)
+    TooManyRequests(String),
+    /// Unexpected failure in the service itself (500)
+    Internal(String),
+    /// A service or backend we depend on failed (502)
+    Upstream(String),
+    /// Shed or timed out under load (503, with `Retry-After`)
+    ServiceUnavailable(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::PaymentRequired(_) => StatusCode::PAYMENT_REQUIRED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::RequestTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Short name recorded as the span's `error.type`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::PaymentRequired(_) => "payment_required",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::RequestTimeout(_) => "request_timeout",
+            AppError::Conflict(_) => "conflict",
+            AppError::PreconditionFailed(_) => "precondition_failed",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::UnsupportedMediaType(_) => "unsupported_media_type",
+            AppError::TooManyRequests(_) => "too_many_requests",
+            AppError::Internal(_) => "internal",
+            AppError::Upstream(_) => "upstream",
+            AppError::ServiceUnavailable(_) => "service_unavailable",
+        }
+    }
+
+    /// The error for a `status` answered by another service, e.g. the API behind the frontend
+    ///
+    /// Statuses without a variant of their own become 400 or 502.
+    pub fn from_status(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => AppError::Unauthorized(message),
+            StatusCode::PAYMENT_REQUIRED => AppError::PaymentRequired(message),
+            StatusCode::FORBIDDEN => AppError::Forbidden(message),
+            StatusCode::NOT_FOUND => AppError::NotFound(message),
+            StatusCode::REQUEST_TIMEOUT => AppError::RequestTimeout(message),
+            StatusCode::CONFLICT => AppError::Conflict(message),
+            StatusCode::PRECONDITION_FAILED => AppError::PreconditionFailed(message),
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(message),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => AppError::UnsupportedMediaType(message),
+            StatusCode::TOO_MANY_REQUESTS => AppError::TooManyRequests(message),
+            StatusCode::INTERNAL_SERVER_ERROR => AppError::Internal(message),
+            StatusCode::SERVICE_UNAVAILABLE => AppError::ServiceUnavailable(message),
+            status if status.is_client_error() => AppError::BadRequest(message),
+            _ => AppError::Upstream(message),
+        }
+    }
+
+    /// Record the error on the current span, and log it when it's the service's fault
+    fn record(&self) {
+        let span = tracing::Span::current();
+        span.set_attribute("error.type", self.kind());
+        span.set_attribute("error.message", self.to_string());
+        if self.status().is_server_error() {
+            span.set_status(Status::error(self.to_string()));
+            error_trace!(
+                http.status_code = self.status().as_u16(),
+                error.type = self.kind(),
+                error.message = %self,
+                "Request failed"
+            );
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::Unauthorized(message)
+            | AppError::PaymentRequired(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::RequestTimeout(message)
+            | AppError::Conflict(message)
+            | AppError::PreconditionFailed(message)
+            | AppError::PayloadTooLarge(message)
+            | AppError::UnsupportedMediaType(message)
+            | AppError::TooManyRequests(message)
+            | AppError::Internal(message)
+            | AppError::Upstream(message)
+            | AppError::ServiceUnavailable(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        self.record();
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            trace_id: trace_context::current_trace_context().map(|ids| ids.trace_id),
+        });
+        match self {
+            AppError::Unauthorized(_) => (
+                self.status(),
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                body,
+            )
+                .into_response(),
+            AppError::ServiceUnavailable(_) => {
+                (self.status(), [(header::RETRY_AFTER, "1")], body).into_response()
+            }
+            AppError::BadRequest(_)
+            | AppError::PaymentRequired(_)
+            | AppError::Forbidden(_)
+            | AppError::NotFound(_)
+            | AppError::RequestTimeout(_)
+            | AppError::Conflict(_)
+            | AppError::PreconditionFailed(_)
+            | AppError::PayloadTooLarge(_)
+            | AppError::UnsupportedMediaType(_)
+            | AppError::TooManyRequests(_)
+            | AppError::Internal(_)
+            | AppError::Upstream(_) => {
+                (self.status(), body).into_response()
+            }
+        }
+    }
+}
+
+/// The gRPC API's view of the same errors, recorded on the span as for HTTP
+impl From<AppError> for tonic::Status {
+    fn from(e: AppError) -> Self {
+        e.record();
+        let code = match e {
+            AppError::BadRequest(_) | AppError::UnsupportedMediaType(_) => tonic::Code::InvalidArgument,
+            AppError::Unauthorized(_) => tonic::Code::Unauthenticated,
+            AppError::PaymentRequired(_) | AppError::PreconditionFailed(_) => tonic::Code::FailedPrecondition,
+            AppError::Forbidden(_) => tonic::Code::PermissionDenied,
+            AppError::NotFound(_) => tonic::Code::NotFound,
+            AppError::RequestTimeout(_) => tonic::Code::DeadlineExceeded,
+            AppError::Conflict(_) => tonic::Code::Aborted,
+            AppError::TooManyRequests(_) | AppError::PayloadTooLarge(_) => tonic::Code::ResourceExhausted,
+            AppError::Internal(_) => tonic::Code::Internal,
+            AppError::Upstream(_) | AppError::ServiceUnavailable(_) => tonic::Code::Unavailable,
+        };
+        tonic::Status::new(code, e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn responds_with_status_and_error_body() {
+        let response = AppError::NotFound("User not found".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        // Outside a traced request there is no trace ID to include
+        assert_eq!(&body[..], br#"{"error":"User not found"}"#);
+
+        let upstream = AppError::Upstream("Search is unavailable".to_string());
+        assert_eq!((upstream.status(), upstream.kind()), (StatusCode::BAD_GATEWAY, "upstream"));
+    }
+}
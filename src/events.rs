@@ -0,0 +1,165 @@
+use crate::ids::{OrderId, UserId};
+use crate::money::Currency;
+use chrono::{DateTime, Utc};
+use rust_datadog_otel::{info_trace, trace_context};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Version of the envelope and payload layout; bump on incompatible changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Events kept for `/admin/events`
+const RECENT_EVENTS: usize = 100;
+
+/// Something that happened in the domain, serialized as `event_type` plus `data`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event_type", content = "data")]
+pub enum DomainEvent {
+    #[serde(rename = "user.created")]
+    UserCreated { user_id: UserId },
+    #[serde(rename = "order.confirmed")]
+    OrderConfirmed {
+        order_id: OrderId,
+        user_id: UserId,
+        total_amount: Decimal,
+        currency: Currency,
+        item_count: usize,
+    },
+    #[serde(rename = "order.cancelled")]
+    OrderCancelled { order_id: OrderId, user_id: UserId },
+}
+
+impl DomainEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DomainEvent::UserCreated { .. } => "user.created",
+            DomainEvent::OrderConfirmed { .. } => "order.confirmed",
+            DomainEvent::OrderCancelled { .. } => "order.cancelled",
+        }
+    }
+
+    /// ID of the entity the event is about, so a transport can keep its events in order
+    pub fn partition_key(&self) -> String {
+        match self {
+            DomainEvent::UserCreated { user_id } => user_id.to_string(),
+            DomainEvent::OrderConfirmed { order_id, .. } | DomainEvent::OrderCancelled { order_id, .. } => {
+                order_id.to_string()
+            }
+        }
+    }
+}
+
+/// A [`DomainEvent`] with its identity, time and the trace that produced it
+///
+/// This is the record written to every transport, so consumers can dedupe on
+/// `event_id` and link their own spans back to `trace_id`/`span_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub event_id: Uuid,
+    pub schema_version: u32,
+    pub occurred_at: DateTime<Utc>,
+    /// Hex trace and span IDs of the producing span, when it was sampled
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    #[serde(flatten)]
+    pub event: DomainEvent,
+}
+
+impl EventEnvelope {
+    /// Wrap `event`, correlated with the current span
+    pub fn new(event: DomainEvent) -> Self {
+        let ids = trace_context::current_trace_context();
+        Self {
+            event_id: Uuid::new_v4(),
+            schema_version: SCHEMA_VERSION,
+            occurred_at: Utc::now(),
+            trace_id: ids.as_ref().map(|ids| ids.trace_id_hex.clone()),
+            span_id: ids.map(|ids| ids.span_id_hex),
+            event,
+        }
+    }
+}
+
+/// Avro schema of [`EventEnvelope`], for registries and non-Rust consumers
+///
+/// Amounts are decimal strings and IDs are UUID strings, matching the JSON form.
+/// Avro enum symbols can't contain dots, so `order.confirmed` is `order_confirmed` here.
+pub fn avro_schema() -> serde_json::Value {
+    let uuid = serde_json::json!({"type": "string", "logicalType": "uuid"});
+    serde_json::json!({
+        "type": "record",
+        "name": "EventEnvelope",
+        "namespace": "com.example.rust_datadog_otel.events",
+        "fields": [
+            {"name": "event_id", "type": uuid},
+            {"name": "schema_version", "type": "int"},
+            {"name": "occurred_at", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+            {"name": "trace_id", "type": ["null", "string"], "default": null},
+            {"name": "span_id", "type": ["null", "string"], "default": null},
+            {"name": "event_type", "type": {
+                "type": "enum",
+                "name": "EventType",
+                "symbols": ["user_created", "order_confirmed", "order_cancelled"]
+            }},
+            {"name": "data", "type": [
+                {"type": "record", "name": "UserCreated", "fields": [
+                    {"name": "user_id", "type": uuid}
+                ]},
+                {"type": "record", "name": "OrderConfirmed", "fields": [
+                    {"name": "order_id", "type": uuid},
+                    {"name": "user_id", "type": uuid},
+                    {"name": "total_amount", "type": "string"},
+                    {"name": "currency", "type": "string"},
+                    {"name": "item_count", "type": "int"}
+                ]},
+                {"type": "record", "name": "OrderCancelled", "fields": [
+                    {"name": "order_id", "type": uuid},
+                    {"name": "user_id", "type": uuid}
+                ]}
+            ]}
+        ]
+    })
+}
+
+/// In-process event log that transports (Kafka, webhooks, an outbox) read from
+#[derive(Debug, Default)]
+pub struct EventLog {
+    recent: Mutex<VecDeque<EventEnvelope>>,
+    published: AtomicU64,
+}
+
+impl EventLog {
+    /// Record `event`, tagging the current span with its type and ID
+    ///
+    /// Returns the envelope for transports that forward it, such as Kafka.
+    pub fn publish(&self, event: DomainEvent) -> EventEnvelope {
+        let envelope = EventEnvelope::new(event);
+        info_trace!(
+            event.id = %envelope.event_id,
+            "event.type" = envelope.event.event_type(),
+            event.schema_version = envelope.schema_version,
+            "Domain event published"
+        );
+
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if recent.len() == RECENT_EVENTS {
+            recent.pop_front();
+        }
+        recent.push_back(envelope.clone());
+        self.published.fetch_add(1, Ordering::Relaxed);
+        envelope
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::json!({
+            "schema_version": SCHEMA_VERSION,
+            "published": self.published.load(Ordering::Relaxed),
+            "recent": recent.iter().rev().collect::<Vec<_>>(),
+        })
+    }
+}
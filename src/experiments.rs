@@ -0,0 +1,157 @@
+use crate::feature_flags::{bucket, FeatureFlags};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use rust_datadog_otel::info_trace;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Used when `EXPERIMENTS` is not set: split users on the new checkout path
+/// evenly between the legacy and v2 payment gateways
+const DEFAULT_EXPERIMENTS: &str =
+    r#"{"checkout_gateway": {"flag": "new_checkout", "variants": {"control": 50, "treatment": 50}}}"#;
+
+/// Header identifying the user for requests without a user ID in the payload
+const USER_ID_HEADER: &str = "x-user-id";
+
+/// An experiment: an optional gating flag plus weighted variants
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExperimentDefinition {
+    /// Users for whom this flag is off are not enrolled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flag: Option<String>,
+    variants: BTreeMap<String, u32>,
+}
+
+impl ExperimentDefinition {
+    /// Variant for a bucket in `0..100`, proportional to the variant weights
+    fn variant_for(&self, bucket: u8) -> Option<&str> {
+        let total: u32 = self.variants.values().sum();
+        if total == 0 {
+            return None;
+        }
+        let point = bucket as u32 * total / 100;
+        let mut cumulative = 0;
+        self.variants.iter().find_map(|(name, weight)| {
+            cumulative += weight;
+            (point < cumulative).then_some(name.as_str())
+        })
+    }
+}
+
+/// A/B experiment allocator layered on [`FeatureFlags`]
+///
+/// Configuration:
+/// - `EXPERIMENTS`: JSON object of experiments, e.g.
+///   `{"checkout_gateway": {"flag": "new_checkout", "variants": {"control": 50, "treatment": 50}}}`
+///
+/// Users are assigned by stable hash of their ID, so a user always sees the
+/// same variant. Allocations tag the current span with `experiment.name`,
+/// `experiment.variant` and `experiment.<name>`, and are counted per variant.
+#[derive(Debug)]
+pub struct Experiments {
+    flags: Arc<FeatureFlags>,
+    experiments: BTreeMap<String, ExperimentDefinition>,
+    allocations: Mutex<HashMap<String, BTreeMap<String, u64>>>,
+}
+
+impl Experiments {
+    pub fn from_env(flags: Arc<FeatureFlags>) -> Result<Self, Box<dyn std::error::Error>> {
+        let raw =
+            std::env::var("EXPERIMENTS").unwrap_or_else(|_| DEFAULT_EXPERIMENTS.to_string());
+        let experiments = serde_json::from_str(&raw)
+            .map_err(|e| format!("invalid EXPERIMENTS: {}", e))?;
+
+        Ok(Self {
+            flags,
+            experiments,
+            allocations: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Variant of `experiment` for `user_id`, or `None` if the user isn't enrolled
+    pub async fn allocate(&self, experiment: &str, user_id: &str) -> Option<String> {
+        let definition = self.experiments.get(experiment)?;
+
+        if let Some(flag) = &definition.flag {
+            if !self.flags.is_enabled(flag, user_id).await {
+                return None;
+            }
+        }
+
+        let variant = definition
+            .variant_for(bucket(experiment, user_id))?
+            .to_string();
+
+        *self
+            .allocations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(experiment.to_string())
+            .or_default()
+            .entry(variant.clone())
+            .or_default() += 1;
+
+        let span = tracing::Span::current();
+        span.set_attribute("experiment.name", experiment.to_string());
+        span.set_attribute("experiment.variant", variant.clone());
+        span.set_attribute(format!("experiment.{}", experiment), variant.clone());
+        info_trace!(
+            experiment.name = experiment,
+            experiment.variant = %variant,
+            "Experiment allocated"
+        );
+
+        Some(variant)
+    }
+
+    /// Allocate every experiment for a user
+    pub async fn allocate_all(&self, user_id: &str) {
+        for name in self.experiments.keys() {
+            self.allocate(name, user_id).await;
+        }
+    }
+
+    /// Definitions and per-variant allocation counts, for `/admin/experiments`
+    pub fn snapshot(&self) -> serde_json::Value {
+        let allocations = self.allocations.lock().unwrap_or_else(|e| e.into_inner());
+        let experiments: serde_json::Map<String, serde_json::Value> = self
+            .experiments
+            .iter()
+            .map(|(name, definition)| {
+                (
+                    name.clone(),
+                    serde_json::json!({
+                        "flag": definition.flag,
+                        "variants": definition.variants,
+                        "allocations": allocations.get(name).cloned().unwrap_or_default(),
+                    }),
+                )
+            })
+            .collect();
+        serde_json::Value::Object(experiments)
+    }
+}
+
+/// Middleware allocating experiments for requests carrying `X-User-Id`
+///
+/// Runs inside the `api.request` span so the experiment tags land on the
+/// request span itself.
+pub async fn tag_experiments(
+    State(experiments): State<Arc<Experiments>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(user_id) = request
+        .headers()
+        .get(USER_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        experiments.allocate_all(user_id).await;
+    }
+    next.run(request).await
+}
@@ -0,0 +1,307 @@
+use crate::ids::{OrderId, UserId};
+use crate::orders::OrderRecord;
+use axum::body::Bytes;
+use futures_util::Stream;
+use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use rust_datadog_otel::{error_trace, info_trace};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Orders encoded per chunk; each chunk is one CSV write or one parquet row group
+const CHUNK_ROWS: usize = 1000;
+
+/// Scale of the parquet `total_amount` column; covers every supported currency
+const PARQUET_AMOUNT_SCALE: u32 = 2;
+
+const PARQUET_SCHEMA: &str = "
+message order {
+    REQUIRED BYTE_ARRAY order_id (UTF8);
+    REQUIRED BYTE_ARRAY user_id (UTF8);
+    REQUIRED BYTE_ARRAY status (UTF8);
+    REQUIRED INT32 item_count;
+    REQUIRED INT64 total_amount (DECIMAL(18, 2));
+    REQUIRED BYTE_ARRAY currency (UTF8);
+    REQUIRED INT64 created_at (TIMESTAMP(MILLIS, true));
+}
+";
+
+pub type ExportError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+/// One exported order, as a CSV record
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    order_id: OrderId,
+    user_id: UserId,
+    status: &'a str,
+    item_count: usize,
+    total_amount: Decimal,
+    currency: &'a str,
+    created_at: String,
+}
+
+/// `Write` target shared with the parquet writer, so finished row groups can
+/// be drained into the response while the file is still being written
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Unscaled value of `amount` in the parquet `DECIMAL(18, 2)` column
+fn decimal_unscaled(amount: Decimal) -> Result<i64, ExportError> {
+    let mut amount = amount;
+    amount.rescale(PARQUET_AMOUNT_SCALE);
+    i64::try_from(amount.mantissa())
+        .ok()
+        .filter(|unscaled| unscaled.unsigned_abs() < 10u64.pow(18))
+        .ok_or_else(|| format!("amount {} does not fit DECIMAL(18, 2)", amount).into())
+}
+
+enum Encoder {
+    Csv {
+        wrote_header: bool,
+    },
+    Parquet {
+        writer: Box<SerializedFileWriter<SharedBuffer>>,
+        buffer: SharedBuffer,
+    },
+}
+
+impl Encoder {
+    fn new(format: ExportFormat) -> Result<Self, ExportError> {
+        Ok(match format {
+            ExportFormat::Csv => Encoder::Csv {
+                wrote_header: false,
+            },
+            ExportFormat::Parquet => {
+                let buffer = SharedBuffer::default();
+                let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+                let properties = Arc::new(WriterProperties::builder().build());
+                Encoder::Parquet {
+                    writer: Box::new(SerializedFileWriter::new(buffer.clone(), schema, properties)?),
+                    buffer,
+                }
+            }
+        })
+    }
+
+    /// Encode a chunk of orders, returning the bytes ready to send
+    fn encode(&mut self, orders: &[OrderRecord]) -> Result<Vec<u8>, ExportError> {
+        match self {
+            Encoder::Csv { wrote_header } => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(!*wrote_header)
+                    .from_writer(Vec::new());
+                for order in orders {
+                    writer.serialize(CsvRow {
+                        order_id: order.order_id,
+                        user_id: order.user_id,
+                        status: &order.status,
+                        item_count: order.lines.len(),
+                        total_amount: order.total_amount,
+                        currency: order.currency.as_str(),
+                        created_at: order.created_at.to_rfc3339(),
+                    })?;
+                }
+                *wrote_header = true;
+                Ok(writer.into_inner().map_err(|e| e.into_error())?)
+            }
+            Encoder::Parquet { writer, buffer } => {
+                let strings = |field: fn(&OrderRecord) -> String| -> Vec<ByteArray> {
+                    orders.iter().map(|order| field(order).into_bytes().into()).collect()
+                };
+
+                let mut row_group = writer.next_row_group()?;
+                let mut index = 0;
+                while let Some(mut column) = row_group.next_column()? {
+                    match index {
+                        0 => column.typed::<ByteArrayType>().write_batch(
+                            &strings(|order| order.order_id.to_string()),
+                            None,
+                            None,
+                        )?,
+                        1 => column.typed::<ByteArrayType>().write_batch(
+                            &strings(|order| order.user_id.to_string()),
+                            None,
+                            None,
+                        )?,
+                        2 => column.typed::<ByteArrayType>().write_batch(
+                            &strings(|order| order.status.clone()),
+                            None,
+                            None,
+                        )?,
+                        3 => column.typed::<Int32Type>().write_batch(
+                            &orders
+                                .iter()
+                                .map(|order| order.lines.len() as i32)
+                                .collect::<Vec<_>>(),
+                            None,
+                            None,
+                        )?,
+                        4 => column.typed::<Int64Type>().write_batch(
+                            &orders
+                                .iter()
+                                .map(|order| decimal_unscaled(order.total_amount))
+                                .collect::<Result<Vec<_>, _>>()?,
+                            None,
+                            None,
+                        )?,
+                        5 => column.typed::<ByteArrayType>().write_batch(
+                            &strings(|order| order.currency.as_str().to_string()),
+                            None,
+                            None,
+                        )?,
+                        _ => column.typed::<Int64Type>().write_batch(
+                            &orders
+                                .iter()
+                                .map(|order| order.created_at.timestamp_millis())
+                                .collect::<Vec<_>>(),
+                            None,
+                            None,
+                        )?,
+                    };
+                    column.close()?;
+                    index += 1;
+                }
+                row_group.close()?;
+                Ok(buffer.take())
+            }
+        }
+    }
+
+    /// Write any trailer (the parquet footer) and return the remaining bytes
+    fn finish(self) -> Result<Vec<u8>, ExportError> {
+        match self {
+            Encoder::Csv { .. } => Ok(Vec::new()),
+            Encoder::Parquet { writer, buffer } => {
+                writer.close()?;
+                Ok(buffer.take())
+            }
+        }
+    }
+}
+
+struct ExportState {
+    orders: Vec<OrderRecord>,
+    position: usize,
+    encoder: Option<Encoder>,
+    bytes: usize,
+    span: tracing::Span,
+}
+
+impl ExportState {
+    /// Next chunk of output, or `None` once the trailer has been sent
+    fn next_chunk(&mut self) -> Option<Result<Vec<u8>, ExportError>> {
+        let _guard = self.span.clone().entered();
+
+        if self.position < self.orders.len() {
+            let end = (self.position + CHUNK_ROWS).min(self.orders.len());
+            let chunk = self.encoder.as_mut()?.encode(&self.orders[self.position..end]);
+            self.position = end;
+            match &chunk {
+                Ok(bytes) => {
+                    self.bytes += bytes.len();
+                    info_trace!(
+                        export.rows = self.position,
+                        export.total_rows = self.orders.len(),
+                        export.bytes = self.bytes,
+                        "Export progress"
+                    );
+                }
+                // The body is cut short; no point encoding the rest
+                Err(e) => {
+                    error_trace!(error = %e, export.rows = self.position, "Export failed");
+                    self.encoder = None;
+                }
+            }
+            return Some(chunk);
+        }
+
+        let trailer = self.encoder.take()?.finish();
+        if let Ok(bytes) = &trailer {
+            self.bytes += bytes.len();
+            self.span.record("export.rows", self.orders.len());
+            self.span.record("export.bytes", self.bytes);
+            info_trace!(
+                export.rows = self.orders.len(),
+                export.bytes = self.bytes,
+                "Export completed"
+            );
+        }
+        Some(trailer)
+    }
+}
+
+/// Stream `orders` encoded as `format`, chunk by chunk
+///
+/// Runs in an `orders.export` span that lives as long as the response body,
+/// with a progress event per chunk and `export.rows`/`export.bytes` recorded
+/// once the last byte is produced.
+pub fn export_stream(
+    orders: Vec<OrderRecord>,
+    format: ExportFormat,
+) -> Result<impl Stream<Item = Result<Bytes, ExportError>>, ExportError> {
+    let span = tracing::info_span!(
+        "orders.export",
+        export.format = format.as_str(),
+        export.rows = tracing::field::Empty,
+        export.bytes = tracing::field::Empty,
+    );
+    let state = ExportState {
+        orders,
+        position: 0,
+        encoder: Some(Encoder::new(format)?),
+        bytes: 0,
+        span,
+    };
+
+    Ok(futures_util::stream::unfold(state, |mut state| async move {
+        let chunk = state.next_chunk()?.map(Bytes::from);
+        Some((chunk, state))
+    }))
+}
@@ -0,0 +1,442 @@
+use crate::{dogstatsd, info_trace, tail_sampling, telemetry, warn_trace};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+const DEFAULT_PROBE_SECS: u64 = 10;
+
+/// Consecutive failed exports before switching to the secondary target
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// Longest wait for one export before counting it as failed
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Count metric sent, through DogStatsD, each time the Agent connection is rebuilt
+const RECONNECT_METRIC: &str = "telemetry.reconnects";
+
+/// Hop-by-hop headers, which the relay must not pass on
+const HOP_BY_HOP: [header::HeaderName; 4] = [
+    header::HOST,
+    header::CONNECTION,
+    header::CONTENT_LENGTH,
+    header::TRANSFER_ENCODING,
+];
+
+/// An endpoint speaking the Datadog trace Agent API
+#[derive(Debug)]
+struct Target {
+    name: &'static str,
+    url: String,
+    exports: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Target {
+    fn new(name: &'static str, url: String) -> Self {
+        Self {
+            name,
+            url: url.trim_end_matches('/').to_string(),
+            exports: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Primary/secondary export targets with automatic failover
+///
+/// The SDK exports to a loopback relay, which forwards each payload to the
+/// active target: the Datadog Agent first, an OpenTelemetry Collector running
+/// the `datadog` receiver second. After a few consecutive failures the relay
+/// switches to the collector, resending the failed payload there, and probes
+/// the Agent's `/info` until it answers to switch back. The relay itself is
+/// not traced, so exports never produce spans of their own.
+///
+/// With no collector the relay only watches the Agent, so `export_fallback`
+/// can tell when exports stop getting through.
+///
+/// Every few consecutive Agent failures the relay also drops its connections
+/// and reconnects, so exports resume once a restarted Agent is back, even at
+/// a new address behind the same host name, without restarting the process.
+///
+/// Configuration:
+/// - `TELEMETRY_FAILOVER_URL`: secondary (collector) URL; failover is off when unset
+/// - `TELEMETRY_FAILOVER_PROBE_SECS`: how often to probe the Agent while failed over (default 10)
+/// - `TELEMETRY_RECONNECT`: reconnect to the Agent after repeated failures (default true)
+/// - The primary is the Agent telemetry was configured with (see `telemetry::TelemetryBuilder`)
+#[derive(Debug)]
+pub struct ExportFailover {
+    primary: Target,
+    secondary: Option<Target>,
+    on_secondary: AtomicBool,
+    consecutive_failures: AtomicU32,
+    /// Consecutive exports no target accepted
+    undelivered: AtomicU32,
+    failovers: AtomicU64,
+    recoveries: AtomicU64,
+    reconnect: bool,
+    reconnects: AtomicU64,
+    last_error: Mutex<Option<String>>,
+    probe_interval: Duration,
+    /// Replaced on reconnect, dropping pooled connections to the old Agent
+    client: Mutex<reqwest::Client>,
+}
+
+impl ExportFailover {
+    /// The relay to the Agent at `agent_url`, when failover or reconnecting is on
+    pub fn from_env(agent_url: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let secondary = match std::env::var("TELEMETRY_FAILOVER_URL") {
+            Ok(secondary) => {
+                reqwest::Url::parse(&secondary).map_err(|e| format!("TELEMETRY_FAILOVER_URL: {}", e))?;
+                Some(secondary)
+            }
+            Err(_) => None,
+        };
+        let failover = Self::new(agent_url.to_string(), secondary)?;
+        if failover.secondary.is_none() && !failover.reconnect {
+            return Ok(None);
+        }
+        Ok(Some(failover))
+    }
+
+    /// A relay to the Agent at `agent_url` alone, watching whether exports get through
+    pub fn agent_only(agent_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(agent_url.to_string(), None)
+    }
+
+    fn new(agent_url: String, secondary: Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let probe_secs = match std::env::var("TELEMETRY_FAILOVER_PROBE_SECS") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("TELEMETRY_FAILOVER_PROBE_SECS: {}", e))?,
+            Err(_) => DEFAULT_PROBE_SECS,
+        };
+        let reconnect = match std::env::var("TELEMETRY_RECONNECT") {
+            Ok(value) => value.parse().map_err(|e| format!("TELEMETRY_RECONNECT: {}", e))?,
+            Err(_) => true,
+        };
+        Ok(Self {
+            primary: Target::new("agent", agent_url),
+            secondary: secondary.map(|url| Target::new("collector", url)),
+            on_secondary: AtomicBool::new(false),
+            consecutive_failures: AtomicU32::new(0),
+            undelivered: AtomicU32::new(0),
+            failovers: AtomicU64::new(0),
+            recoveries: AtomicU64::new(0),
+            reconnect,
+            reconnects: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+            probe_interval: Duration::from_secs(probe_secs.max(1)),
+            client: Mutex::new(export_client()?),
+        })
+    }
+
+    fn client(&self) -> reqwest::Client {
+        self.client.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Serve the relay on a loopback port and start probing; returns the URL to export to
+    ///
+    /// Must be called from within the Tokio runtime.
+    pub fn start(self: Arc<Self>) -> Result<String, Box<dyn std::error::Error>> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let relay_url = format!("http://{}", listener.local_addr()?);
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+
+        let app = Router::new().fallback(relay).with_state(self.clone());
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                warn_trace!(error = %e, "Telemetry export relay stopped");
+            }
+        });
+        tokio::spawn(self.probe());
+        Ok(relay_url)
+    }
+
+    /// The target to export to, and the one to retry on
+    fn active(&self) -> (&Target, Option<&Target>) {
+        match &self.secondary {
+            Some(secondary) if self.on_secondary.load(Ordering::Relaxed) => (secondary, Some(&self.primary)),
+            secondary => (&self.primary, secondary.as_ref()),
+        }
+    }
+
+    /// Whether the last few exports reached no target
+    pub fn is_failing(&self) -> bool {
+        self.undelivered.load(Ordering::Relaxed) >= FAILURE_THRESHOLD
+    }
+
+    async fn forward(
+        &self,
+        target: &Target,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Result<Response, String> {
+        target.exports.fetch_add(1, Ordering::Relaxed);
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let mut headers = headers.clone();
+        for name in HOP_BY_HOP {
+            headers.remove(name);
+        }
+        let result = self
+            .client()
+            .request(method.clone(), format!("{}{}", target.url, path))
+            .headers(headers)
+            .body(body.clone())
+            .send()
+            .await;
+
+        let error = match result {
+            Ok(response) if !response.status().is_server_error() => {
+                let status = response.status();
+                let mut headers = response.headers().clone();
+                for name in HOP_BY_HOP {
+                    headers.remove(name);
+                }
+                match response.bytes().await {
+                    Ok(body) => return Ok((status, headers, body).into_response()),
+                    Err(e) => e.to_string(),
+                }
+            }
+            Ok(response) => format!("{} answered {}", target.name, response.status()),
+            Err(e) => e.to_string(),
+        };
+        target.errors.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(error.clone());
+        Err(error)
+    }
+
+    /// Reconnect, and switch to the secondary, after repeated primary failures
+    fn record_primary_failure(&self, error: &str) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.reconnect && failures % FAILURE_THRESHOLD == 0 {
+            self.reconnect_primary(failures, error);
+        }
+        let Some(secondary) = &self.secondary else {
+            return;
+        };
+        if failures >= FAILURE_THRESHOLD && !self.on_secondary.swap(true, Ordering::Relaxed) {
+            self.failovers.fetch_add(1, Ordering::Relaxed);
+            warn_trace!(
+                telemetry.export_target = secondary.name,
+                telemetry.export_url = %secondary.url,
+                error = %error,
+                "Telemetry export failing over to the collector"
+            );
+        }
+    }
+
+    /// The Agent answered: reset the failure count
+    fn record_primary_success(&self) {
+        let failures = self.consecutive_failures.swap(0, Ordering::Relaxed);
+        if failures >= FAILURE_THRESHOLD && self.reconnect {
+            info_trace!(
+                telemetry.export_url = %self.primary.url,
+                telemetry.reconnects = self.reconnects.load(Ordering::Relaxed),
+                failures,
+                "Telemetry export reconnected to the Agent"
+            );
+        }
+    }
+
+    /// Drop every pooled connection, so the next export connects (and resolves the host) afresh
+    fn reconnect_primary(&self, failures: u32, error: &str) {
+        let client = match export_client() {
+            Ok(client) => client,
+            Err(e) => {
+                warn_trace!(error = %e, "Telemetry export client rebuild failed");
+                return;
+            }
+        };
+        *self.client.lock().unwrap_or_else(|e| e.into_inner()) = client;
+        let reconnects = self.reconnects.fetch_add(1, Ordering::Relaxed) + 1;
+        dogstatsd::count(RECONNECT_METRIC, 1, &[format!("target:{}", self.primary.name)]);
+        warn_trace!(
+            telemetry.export_url = %self.primary.url,
+            telemetry.reconnects = reconnects,
+            failures,
+            error = %error,
+            "Telemetry export reconnecting to the Agent"
+        );
+    }
+
+    /// While failed over, switch back once the Agent answers `/info`
+    async fn probe(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(self.probe_interval);
+        loop {
+            ticker.tick().await;
+            if !self.on_secondary.load(Ordering::Relaxed) {
+                continue;
+            }
+            // A failed probe counts toward reconnecting, as a failed export would
+            let error = match self.client().get(format!("{}/info", self.primary.url)).send().await {
+                Ok(response) if response.status().is_success() => None,
+                Ok(response) => Some(format!("{} answered {}", self.primary.name, response.status())),
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(error) = error {
+                self.record_primary_failure(&error);
+                continue;
+            }
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            self.on_secondary.store(false, Ordering::Relaxed);
+            self.recoveries.fetch_add(1, Ordering::Relaxed);
+            info_trace!(
+                telemetry.export_target = self.primary.name,
+                telemetry.export_url = %self.primary.url,
+                "Telemetry export recovered to the Agent"
+            );
+        }
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let target = |target: &Target| {
+            serde_json::json!({
+                "name": target.name,
+                "url": target.url,
+                "exports": target.exports.load(Ordering::Relaxed),
+                "errors": target.errors.load(Ordering::Relaxed),
+            })
+        };
+        let targets: Vec<_> = std::iter::once(&self.primary).chain(&self.secondary).map(target).collect();
+        serde_json::json!({
+            "failover": self.secondary.is_some(),
+            "active": self.active().0.name,
+            "delivering": !self.is_failing(),
+            "targets": targets,
+            "failovers": self.failovers.load(Ordering::Relaxed),
+            "recoveries": self.recoveries.load(Ordering::Relaxed),
+            "reconnect": self.reconnect,
+            "reconnects": self.reconnects.load(Ordering::Relaxed),
+            "last_error": *self.last_error.lock().unwrap_or_else(|e| e.into_inner()),
+        })
+    }
+}
+
+/// Forward one SDK request to the active target, falling back to the other
+async fn relay(
+    State(failover): State<Arc<ExportFailover>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let body = tail_sampling::apply(uri.path(), body);
+    let (active, standby) = failover.active();
+    let mut error = match failover.forward(active, &method, &uri, &headers, &body).await {
+        Ok(response) => {
+            if active.name == failover.primary.name {
+                failover.record_primary_success();
+            }
+            failover.undelivered.store(0, Ordering::Relaxed);
+            return response;
+        }
+        Err(error) => error,
+    };
+    if active.name == failover.primary.name {
+        failover.record_primary_failure(&error);
+    }
+    if let Some(standby) = standby {
+        match failover.forward(standby, &method, &uri, &headers, &body).await {
+            Ok(response) => {
+                failover.undelivered.store(0, Ordering::Relaxed);
+                return response;
+            }
+            Err(e) => error = e,
+        }
+    }
+    failover.undelivered.fetch_add(1, Ordering::Relaxed);
+    (StatusCode::BAD_GATEWAY, error).into_response()
+}
+
+fn export_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().timeout(EXPORT_TIMEOUT).build()
+}
+
+static FAILOVER: OnceLock<Arc<ExportFailover>> = OnceLock::new();
+
+/// Start `failover` and report it in [`snapshot`]; returns the URL the SDK should export to
+pub fn install(failover: ExportFailover) -> Result<String, Box<dyn std::error::Error>> {
+    let failover = Arc::new(failover);
+    let relay_url = failover.clone().start()?;
+    let _ = FAILOVER.set(failover);
+    Ok(relay_url)
+}
+
+/// Whether trace exports are failing on every target; `false` without a relay
+pub fn is_failing() -> bool {
+    FAILOVER.get().is_some_and(|failover| failover.is_failing())
+}
+
+/// Name of the target exports go to, `agent` or `collector`
+pub fn active_target() -> &'static str {
+    FAILOVER.get().map_or("agent", |failover| failover.active().0.name)
+}
+
+/// Export target status for `/debug/telemetry`
+pub fn snapshot() -> serde_json::Value {
+    match FAILOVER.get() {
+        Some(failover) => failover.snapshot(),
+        None => serde_json::json!({
+            "failover": false,
+            "active": "agent",
+            "targets": [{ "name": "agent", "url": telemetry::config().agent_url }],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_exports_no_target_accepted() {
+        // A port nothing listens on
+        let agent_url = format!("http://{}", std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+        let failover = Arc::new(ExportFailover::new(agent_url, None).unwrap());
+        let relay_url = failover.clone().start().unwrap();
+
+        let client = reqwest::Client::new();
+        for _ in 0..FAILURE_THRESHOLD {
+            assert!(!failover.is_failing());
+            let response = client.put(format!("{}/v0.4/traces", relay_url)).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        }
+        assert!(failover.is_failing());
+        assert_eq!(failover.snapshot()["delivering"], false);
+        assert_eq!(failover.active().0.name, "agent");
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_repeated_failures_and_recovers() {
+        // The Agent is down: nothing listens on its port yet
+        let agent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let agent_addr = agent.local_addr().unwrap();
+        drop(agent);
+        let failover = Arc::new(ExportFailover::new(format!("http://{}", agent_addr), None).unwrap());
+        let relay_url = failover.clone().start().unwrap();
+
+        let client = reqwest::Client::new();
+        let export = || client.put(format!("{}/v0.4/traces", relay_url)).send();
+        for _ in 0..FAILURE_THRESHOLD {
+            assert_eq!(export().await.unwrap().status(), StatusCode::BAD_GATEWAY);
+        }
+        assert_eq!(failover.snapshot()["reconnects"], 1);
+
+        // The Agent is back on the same address
+        let agent = tokio::net::TcpListener::bind(agent_addr).await.unwrap();
+        tokio::spawn(async move { axum::serve(agent, Router::new().fallback(|| async { "{}" })).await });
+        assert_eq!(export().await.unwrap().status(), StatusCode::OK);
+        assert!(!failover.is_failing());
+        assert_eq!(failover.consecutive_failures.load(Ordering::Relaxed), 0);
+    }
+}
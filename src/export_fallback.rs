@@ -0,0 +1,183 @@
+use crate::console_exporter::{self, ConsoleExporter};
+use crate::{export_failover, info_trace, warn_trace};
+use opentelemetry::Context;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{BatchSpanProcessor, Span, SpanData, SpanProcessor};
+use opentelemetry_sdk::Resource;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4318";
+
+/// Where spans go while Datadog exports fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FallbackTarget {
+    /// OTLP/HTTP, to this traces URL
+    Otlp(String),
+    /// Readable spans on stdout, see `console_exporter`
+    Console,
+}
+
+impl FallbackTarget {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FallbackTarget::Otlp(_) => "otlp",
+            FallbackTarget::Console => "console",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FallbackStatus {
+    target: FallbackTarget,
+    engaged: AtomicBool,
+    engagements: AtomicU64,
+    exported: AtomicU64,
+}
+
+/// Span processor exporting to a generic backend while the Datadog Agent is unreachable
+///
+/// Without it, spans silently disappear when no Agent is running. The SDK's
+/// exports go through `export_failover`'s relay, which counts the ones no
+/// target accepted; after 3 in a row, finished spans are also sent here until
+/// an export gets through again. Spans from the exports that tripped it are lost.
+///
+/// Configuration:
+/// - `TELEMETRY_FALLBACK_EXPORTER`: `otlp` or `console`; fallback is off when unset
+/// - `OTEL_EXPORTER_OTLP_ENDPOINT`: OTLP/HTTP collector for `otlp` (default `http://localhost:4318`)
+#[derive(Debug)]
+pub struct ExportFallback {
+    processor: BatchSpanProcessor,
+    status: Arc<FallbackStatus>,
+}
+
+impl ExportFallback {
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let target = match std::env::var("TELEMETRY_FALLBACK_EXPORTER").as_deref() {
+            Err(_) | Ok("") => return Ok(None),
+            Ok("otlp") => FallbackTarget::Otlp(otlp_traces_url()),
+            Ok("console") => FallbackTarget::Console,
+            Ok(other) => {
+                return Err(format!("TELEMETRY_FALLBACK_EXPORTER: expected otlp or console, got '{}'", other).into())
+            }
+        };
+        Ok(Some(Self::new(target)?))
+    }
+
+    pub fn new(target: FallbackTarget) -> Result<Self, Box<dyn std::error::Error>> {
+        let processor = match &target {
+            FallbackTarget::Otlp(url) => BatchSpanProcessor::builder(otlp_exporter(url)?).build(),
+            FallbackTarget::Console => BatchSpanProcessor::builder(ConsoleExporter).build(),
+        };
+        Ok(Self {
+            processor,
+            status: Arc::new(FallbackStatus {
+                target,
+                engaged: AtomicBool::new(false),
+                engagements: AtomicU64::new(0),
+                exported: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    pub fn target(&self) -> &FallbackTarget {
+        &self.status.target
+    }
+}
+
+impl SpanProcessor for ExportFallback {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        let status = &self.status;
+        let failing = export_failover::is_failing();
+        if failing != status.engaged.swap(failing, Ordering::Relaxed) {
+            if failing {
+                status.engagements.fetch_add(1, Ordering::Relaxed);
+                warn_trace!(
+                    telemetry.export_target = status.target.name(),
+                    "Datadog trace exports failing, sending spans to the fallback exporter"
+                );
+            } else {
+                info_trace!("Datadog trace exports recovered, fallback exporter idle");
+            }
+        }
+        if failing {
+            status.exported.fetch_add(1, Ordering::Relaxed);
+            self.processor.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.processor.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.processor.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.processor.set_resource(resource);
+    }
+}
+
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`'s traces URL
+pub fn otlp_traces_url() -> String {
+    let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or_else(|_| DEFAULT_OTLP_ENDPOINT.to_string());
+    traces_url(&endpoint)
+}
+
+/// The traces URL of the OTLP/HTTP collector at `endpoint`
+pub fn traces_url(endpoint: &str) -> String {
+    format!("{}/v1/traces", endpoint.trim_end_matches('/'))
+}
+
+/// OTLP/HTTP (protobuf) span exporter posting to `url`
+pub fn otlp_exporter(url: &str) -> Result<opentelemetry_otlp::SpanExporter, Box<dyn std::error::Error>> {
+    Ok(opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(url)
+        .build()?)
+}
+
+static STATUS: OnceLock<Arc<FallbackStatus>> = OnceLock::new();
+
+/// Report `fallback` in [`snapshot`]; returns it for the tracer provider
+pub fn install(fallback: ExportFallback) -> ExportFallback {
+    let _ = STATUS.set(fallback.status.clone());
+    fallback
+}
+
+/// The exporter spans currently reach
+///
+/// `agent` or `collector` while Datadog exports get through, else the
+/// fallback's name, or `none` when spans are being dropped. `console` when
+/// `DD_TRACE_DEBUG_EXPORTER` replaces the Agent.
+pub fn active_exporter() -> &'static str {
+    if console_exporter::is_enabled() {
+        return "console";
+    }
+    if !export_failover::is_failing() {
+        return export_failover::active_target();
+    }
+    STATUS.get().map_or("none", |status| status.target.name())
+}
+
+/// Fallback settings and counts for `/debug/telemetry`
+pub fn snapshot() -> serde_json::Value {
+    match STATUS.get() {
+        Some(status) => serde_json::json!({
+            "exporter": status.target.name(),
+            "endpoint": match &status.target {
+                FallbackTarget::Otlp(url) => Some(url),
+                FallbackTarget::Console => None,
+            },
+            "engaged": status.engaged.load(Ordering::Relaxed),
+            "engagements": status.engagements.load(Ordering::Relaxed),
+            "exported": status.exported.load(Ordering::Relaxed),
+        }),
+        None => serde_json::Value::Null,
+    }
+}
@@ -0,0 +1,151 @@
+use crate::export_fallback;
+use opentelemetry::Context;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{BatchSpanProcessor, Span, SpanData, SpanExporter, SpanProcessor};
+use opentelemetry_sdk::Resource;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+#[derive(Debug)]
+struct MirrorStatus {
+    url: String,
+    batches: AtomicU64,
+    spans: AtomicU64,
+    errors: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+/// OTLP exporter counting what it delivers, for [`snapshot`]
+#[derive(Debug)]
+struct CountingExporter {
+    inner: opentelemetry_otlp::SpanExporter,
+    status: Arc<MirrorStatus>,
+}
+
+impl SpanExporter for CountingExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let spans = batch.len() as u64;
+        let result = self.inner.export(batch).await;
+        let status = &self.status;
+        status.batches.fetch_add(1, Ordering::Relaxed);
+        match &result {
+            Ok(()) => {
+                status.spans.fetch_add(spans, Ordering::Relaxed);
+            }
+            Err(e) => {
+                status.errors.fetch_add(1, Ordering::Relaxed);
+                *status.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Span processor sending every span to an OTLP collector as well as Datadog
+///
+/// For evaluating a collector without giving up Datadog. The copy has its own
+/// queue and export thread, so a collector that is down or slow only drops
+/// its own spans: the Datadog export path never waits on it, and the other way
+/// round.
+///
+/// Configuration:
+/// - `TELEMETRY_OTLP_MIRROR`: `true` to mirror spans (default false)
+/// - `OTEL_EXPORTER_OTLP_ENDPOINT`: OTLP/HTTP collector (default `http://localhost:4318`)
+#[derive(Debug)]
+pub struct ExportMirror {
+    processor: BatchSpanProcessor,
+    status: Arc<MirrorStatus>,
+}
+
+impl ExportMirror {
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let enabled = match std::env::var("TELEMETRY_OTLP_MIRROR") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("TELEMETRY_OTLP_MIRROR: {}", e))?,
+            Err(_) => false,
+        };
+        if !enabled {
+            return Ok(None);
+        }
+        Ok(Some(Self::new(export_fallback::otlp_traces_url())?))
+    }
+
+    /// Mirror spans to the OTLP/HTTP traces URL `url`
+    pub fn new(url: String) -> Result<Self, Box<dyn std::error::Error>> {
+        let status = Arc::new(MirrorStatus {
+            url,
+            batches: AtomicU64::new(0),
+            spans: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        });
+        let exporter = CountingExporter {
+            inner: export_fallback::otlp_exporter(&status.url)?,
+            status: status.clone(),
+        };
+        Ok(Self {
+            processor: BatchSpanProcessor::builder(exporter).build(),
+            status,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.status.url
+    }
+}
+
+impl SpanProcessor for ExportMirror {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        self.processor.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.processor.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.processor.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.processor.set_resource(resource);
+    }
+}
+
+static STATUS: OnceLock<Arc<MirrorStatus>> = OnceLock::new();
+
+/// Report `mirror` in [`snapshot`]; returns it for the tracer provider
+pub fn install(mirror: ExportMirror) -> ExportMirror {
+    let _ = STATUS.set(mirror.status.clone());
+    mirror
+}
+
+/// Mirror target and delivery counts for `/debug/telemetry`
+pub fn snapshot() -> serde_json::Value {
+    match STATUS.get() {
+        Some(status) => serde_json::json!({
+            "url": status.url,
+            "batches": status.batches.load(Ordering::Relaxed),
+            "spans": status.spans.load(Ordering::Relaxed),
+            "errors": status.errors.load(Ordering::Relaxed),
+            "last_error": *status.last_error.lock().unwrap_or_else(|e| e.into_inner()),
+        }),
+        None => serde_json::Value::Null,
+    }
+}
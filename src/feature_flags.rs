@@ -0,0 +1,298 @@
+use open_feature::provider::{FeatureProvider, ProviderMetadata, ResolutionDetails};
+use open_feature::{
+    Client, EvaluationContext, EvaluationError, EvaluationErrorCode, EvaluationReason,
+    EvaluationResult, OpenFeature, StructValue,
+};
+use rust_datadog_otel::http_client::HttpClient;
+use rust_datadog_otel::{info_trace, warn_trace};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Flags used when neither `FEATURE_FLAGS` nor `FEATURE_FLAGS_URL` is set
+const DEFAULT_FLAGS: &str = r#"{"new_checkout": {"enabled": true, "rollout_percentage": 10}}"#;
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 30;
+
+/// A single flag: off, on for everyone, or on for a stable percentage of keys
+#[derive(Debug, Clone, Deserialize)]
+pub struct FlagDefinition {
+    enabled: bool,
+    #[serde(default = "full_rollout")]
+    rollout_percentage: u8,
+}
+
+fn full_rollout() -> u8 {
+    100
+}
+
+/// Stable bucket in `0..100` for a key, so a user keeps their variant across requests
+pub fn bucket(salt: &str, key: &str) -> u8 {
+    // FNV-1a: stable across processes and releases, unlike `DefaultHasher`
+    let hash = salt
+        .bytes()
+        .chain(std::iter::once(b':'))
+        .chain(key.bytes())
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    (hash % 100) as u8
+}
+
+/// Built-in OpenFeature provider: static config, optionally refreshed from a remote JSON document
+///
+/// Configuration:
+/// - `FEATURE_FLAGS`: JSON object of flags, e.g.
+///   `{"new_checkout": {"enabled": true, "rollout_percentage": 10}}`
+/// - `FEATURE_FLAGS_URL`: URL polled for the same JSON document
+/// - `FEATURE_FLAGS_POLL_INTERVAL_SECS`: polling interval (default 30)
+///
+/// Only boolean flags are supported; other types resolve to a type mismatch.
+#[derive(Debug, Clone)]
+pub struct FlagStore {
+    flags: Arc<RwLock<HashMap<String, FlagDefinition>>>,
+    metadata: ProviderMetadata,
+}
+
+impl FlagStore {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let raw = std::env::var("FEATURE_FLAGS").unwrap_or_else(|_| DEFAULT_FLAGS.to_string());
+        let flags: HashMap<String, FlagDefinition> = serde_json::from_str(&raw)
+            .map_err(|e| format!("invalid FEATURE_FLAGS: {}", e))?;
+
+        let name = if std::env::var("FEATURE_FLAGS_URL").is_ok() {
+            "remote"
+        } else {
+            "static"
+        };
+
+        Ok(Self {
+            flags: Arc::new(RwLock::new(flags)),
+            metadata: ProviderMetadata::new(name),
+        })
+    }
+
+    fn replace(&self, flags: HashMap<String, FlagDefinition>) {
+        *self.flags.write().unwrap_or_else(|e| e.into_inner()) = flags;
+    }
+
+    /// Start polling `FEATURE_FLAGS_URL`, if set
+    ///
+    /// A failed fetch keeps the last known flags.
+    pub fn spawn_polling(&self) {
+        let Ok(url) = std::env::var("FEATURE_FLAGS_URL") else {
+            return;
+        };
+        let interval = std::env::var("FEATURE_FLAGS_POLL_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+
+        let store = self.clone();
+        tokio::spawn(async move {
+            // Flag servers are often third-party; each host shows up as its own service
+            let client = HttpClient::new().with_service_per_host();
+            let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+            loop {
+                ticker.tick().await;
+                let span = tracing::info_span!("feature_flags.refresh", http.url = %url);
+                let fetched = async {
+                    client
+                        .send(client.get(&url))
+                        .await?
+                        .error_for_status()?
+                        .json::<HashMap<String, FlagDefinition>>()
+                        .await
+                }
+                .instrument(span)
+                .await;
+
+                match fetched {
+                    Ok(update) => {
+                        info_trace!(flag_count = update.len(), "Feature flags refreshed");
+                        store.replace(update);
+                    }
+                    Err(e) => warn_trace!(error = %e, url = %url, "Failed to refresh feature flags"),
+                }
+            }
+        });
+    }
+}
+
+fn type_mismatch<T>(flag_key: &str) -> EvaluationResult<ResolutionDetails<T>> {
+    Err(EvaluationError::builder()
+        .code(EvaluationErrorCode::TypeMismatch)
+        .message(format!("flag '{}' is boolean", flag_key))
+        .build())
+}
+
+#[async_trait::async_trait]
+impl FeatureProvider for FlagStore {
+    fn metadata(&self) -> &ProviderMetadata {
+        &self.metadata
+    }
+
+    async fn resolve_bool_value(
+        &self,
+        flag_key: &str,
+        evaluation_context: &EvaluationContext,
+    ) -> EvaluationResult<ResolutionDetails<bool>> {
+        let Some(definition) = self
+            .flags
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(flag_key)
+            .cloned()
+        else {
+            return Err(EvaluationError::builder()
+                .code(EvaluationErrorCode::FlagNotFound)
+                .message(format!("flag '{}' is not defined", flag_key))
+                .build());
+        };
+
+        let targeting_key = evaluation_context.targeting_key.as_deref().unwrap_or_default();
+        let (value, reason) = if !definition.enabled || definition.rollout_percentage == 0 {
+            (false, EvaluationReason::Disabled)
+        } else if definition.rollout_percentage >= 100 {
+            (true, EvaluationReason::Static)
+        } else {
+            (
+                bucket(flag_key, targeting_key) < definition.rollout_percentage,
+                EvaluationReason::Split,
+            )
+        };
+
+        Ok(ResolutionDetails {
+            value,
+            variant: Some(variant(value).to_string()),
+            reason: Some(reason),
+            flag_metadata: None,
+        })
+    }
+
+    async fn resolve_int_value(
+        &self,
+        flag_key: &str,
+        _evaluation_context: &EvaluationContext,
+    ) -> EvaluationResult<ResolutionDetails<i64>> {
+        type_mismatch(flag_key)
+    }
+
+    async fn resolve_float_value(
+        &self,
+        flag_key: &str,
+        _evaluation_context: &EvaluationContext,
+    ) -> EvaluationResult<ResolutionDetails<f64>> {
+        type_mismatch(flag_key)
+    }
+
+    async fn resolve_string_value(
+        &self,
+        flag_key: &str,
+        _evaluation_context: &EvaluationContext,
+    ) -> EvaluationResult<ResolutionDetails<String>> {
+        type_mismatch(flag_key)
+    }
+
+    async fn resolve_struct_value(
+        &self,
+        flag_key: &str,
+        _evaluation_context: &EvaluationContext,
+    ) -> EvaluationResult<ResolutionDetails<StructValue>> {
+        type_mismatch(flag_key)
+    }
+}
+
+/// `feature_flag.evaluation.reason` value for an OpenFeature reason
+fn reason_label(reason: EvaluationReason) -> String {
+    match reason {
+        EvaluationReason::Static => "static".to_string(),
+        EvaluationReason::Default => "default".to_string(),
+        EvaluationReason::TargetingMatch => "targeting_match".to_string(),
+        EvaluationReason::Split => "split".to_string(),
+        EvaluationReason::Cached => "cached".to_string(),
+        EvaluationReason::Disabled => "disabled".to_string(),
+        EvaluationReason::Unknown => "unknown".to_string(),
+        EvaluationReason::Error => "error".to_string(),
+        EvaluationReason::Other(other) => other,
+    }
+}
+
+fn variant(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Flag evaluation through the OpenFeature API
+///
+/// Any OpenFeature provider can back this (the built-in [`FlagStore`], or a
+/// LaunchDarkly/Flagsmith provider crate); evaluations are traced the same
+/// way regardless, as a `feature_flag.<key>` attribute on the current span
+/// and a `feature_flag` event.
+pub struct FeatureFlags {
+    client: Client,
+    provider_name: String,
+}
+
+impl std::fmt::Debug for FeatureFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FeatureFlags")
+            .field("provider_name", &self.provider_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FeatureFlags {
+    /// Install `provider` as the global OpenFeature provider
+    pub async fn with_provider(provider: impl FeatureProvider) -> Self {
+        let provider_name = provider.metadata().name.clone();
+
+        let mut api = OpenFeature::singleton_mut().await;
+        api.set_provider(provider).await;
+        let client = api.create_client();
+
+        Self {
+            client,
+            provider_name,
+        }
+    }
+
+    /// Evaluate a boolean flag for `targeting_key` (usually a user ID)
+    ///
+    /// Provider errors (unknown flag, type mismatch) evaluate to `false`.
+    pub async fn is_enabled(&self, flag: &str, targeting_key: &str) -> bool {
+        let context = EvaluationContext::default().with_targeting_key(targeting_key);
+        let (enabled, reason) = match self
+            .client
+            .get_bool_details(flag, Some(&context), None)
+            .await
+        {
+            Ok(details) => (
+                details.value,
+                details.reason.map_or_else(|| "unknown".to_string(), reason_label),
+            ),
+            Err(e) => {
+                warn_trace!(feature_flag.key = flag, error = ?e.code, "Feature flag evaluation failed");
+                (false, "error".to_string())
+            }
+        };
+
+        tracing::Span::current()
+            .set_attribute(format!("feature_flag.{}", flag), variant(enabled));
+        info_trace!(
+            feature_flag.key = flag,
+            feature_flag.provider_name = %self.provider_name,
+            feature_flag.result.variant = variant(enabled),
+            feature_flag.evaluation.reason = %reason,
+            "feature_flag"
+        );
+
+        enabled
+    }
+}
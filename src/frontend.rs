@@ -0,0 +1,136 @@
+use crate::config::ServiceConfig;
+use crate::server;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use rust_datadog_otel::client::{ApiClient, ClientError, NewOrder, NewUser};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::{info_trace, layers, propagation, request_span, warn_trace};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Frontend role: a thin proxy in front of the API, forwarding through the typed client
+///
+/// Every call it forwards is a client span with the trace context injected, so
+/// a request through the frontend is one Datadog trace across two services.
+/// Serves `/health` and the users and orders routes of the unversioned `/api`.
+pub async fn serve(config: &ServiceConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let api = Arc::new(ApiClient::new(&config.backend_url)?);
+    let trace_headers = Arc::new(propagation::ResponseHeaders::from_env()?);
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/api/users", post(create_user))
+        .route("/api/users/:id", get(get_user))
+        .route("/api/users/:id/orders", get(list_user_orders))
+        .route("/api/orders", post(create_order))
+        .route("/api/orders/:id", get(get_order))
+        .route("/api/orders/:id/cancel", post(cancel_order))
+        .layer(middleware::from_fn_with_state(
+            trace_headers.clone(),
+            propagation::add_response_headers,
+        ))
+        .layer(layers::cors(&config.cors_origins, &trace_headers.header_names())?)
+        .layer(request_span::layer())
+        // Outermost, so requests continue the caller's trace
+        .layer(middleware::from_fn(propagation::extract_trace_context))
+        .with_state(api);
+
+    let listener = tokio::net::TcpListener::bind(config.bind_address).await?;
+    info_trace!(backend = %config.backend_url, "Frontend listening on {}", config.bind_address);
+    server::serve(listener, app, server::ServerConfig::from_env(), crate::shutdown_signal()).await?;
+    Ok(())
+}
+
+/// The API's error status and message, or 502 when it didn't answer
+fn forward_error(error: ClientError) -> Response {
+    let status = match &error {
+        ClientError::Api { status, .. } => *status,
+        ClientError::Http(_) => StatusCode::BAD_GATEWAY,
+    };
+    if status.is_server_error() {
+        warn_trace!(error = %error, "Backend call failed");
+    }
+    let message = match error {
+        ClientError::Api { message, .. } => message,
+        ClientError::Http(e) => format!("backend unavailable: {}", e),
+    };
+    AppError::from_status(status, message).into_response()
+}
+
+#[instrument(name = "frontend.health", skip(api))]
+async fn health(State(api): State<Arc<ApiClient>>) -> Response {
+    match api.health().await {
+        Ok(backend) => Json(serde_json::json!({
+            "status": "healthy",
+            "role": "frontend",
+            "backend": backend,
+        }))
+        .into_response(),
+        Err(e) => forward_error(e),
+    }
+}
+
+#[instrument(name = "frontend.create_user", skip_all)]
+async fn create_user(State(api): State<Arc<ApiClient>>, Json(user): Json<NewUser>) -> Response {
+    match api.create_user(&user).await {
+        Ok(user) => (StatusCode::CREATED, Json(user)).into_response(),
+        Err(e) => forward_error(e),
+    }
+}
+
+#[instrument(name = "frontend.get_user", skip(api))]
+async fn get_user(State(api): State<Arc<ApiClient>>, Path(id): Path<Uuid>) -> Response {
+    match api.get_user(id).await {
+        Ok(user) => Json(user).into_response(),
+        Err(e) => forward_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+#[instrument(name = "frontend.list_user_orders", skip(api, query))]
+async fn list_user_orders(
+    State(api): State<Arc<ApiClient>>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    match api.list_user_orders(id, query.limit, query.cursor.as_deref()).await {
+        Ok(page) => Json(page).into_response(),
+        Err(e) => forward_error(e),
+    }
+}
+
+#[instrument(name = "frontend.create_order", skip_all)]
+async fn create_order(State(api): State<Arc<ApiClient>>, Json(order): Json<NewOrder>) -> Response {
+    match api.create_order(&order).await {
+        Ok(order) => (StatusCode::CREATED, Json(order)).into_response(),
+        Err(e) => forward_error(e),
+    }
+}
+
+#[instrument(name = "frontend.get_order", skip(api))]
+async fn get_order(State(api): State<Arc<ApiClient>>, Path(id): Path<Uuid>) -> Response {
+    match api.get_order(id).await {
+        Ok(order) => Json(order).into_response(),
+        Err(e) => forward_error(e),
+    }
+}
+
+#[instrument(name = "frontend.cancel_order", skip(api))]
+async fn cancel_order(State(api): State<Arc<ApiClient>>, Path(id): Path<Uuid>) -> Response {
+    match api.cancel_order(id).await {
+        Ok(order) => Json(order).into_response(),
+        Err(e) => forward_error(e),
+    }
+}
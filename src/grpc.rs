@@ -0,0 +1,298 @@
+use crate::ids::{OrderId, ProductId, UserId};
+use crate::order_api::{self, find_order, OrderItem, OrderRequest, OrderResponse};
+use crate::user_api::{self, find_user, CreateUserRequest, User};
+use crate::{auth, money, AppState};
+use axum::http::header;
+use proto::order_service_server::{OrderService, OrderServiceServer};
+use proto::user_service_server::{UserService, UserServiceServer};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::{error_trace, info_trace, propagation};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tonic::server::NamedService;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+use tracing::{field::Empty, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Types and service traits generated from `proto/demo.proto`
+pub mod proto {
+    tonic::include_proto!("demo.v1");
+}
+
+/// Name of the server span opened for every call
+const SPAN_NAME: &str = "grpc.server";
+
+/// Serve the user and order API over gRPC until `shutdown` resolves
+///
+/// `demo.v1.UserService` and `demo.v1.OrderService` run the same code as
+/// their HTTP routes, against the same state, so both protocols share
+/// storage, caching, events and telemetry. Each call opens a `grpc.server`
+/// span continuing the trace in the call's metadata (any
+/// `DD_TRACE_PROPAGATION_STYLE_EXTRACT` format, as for HTTP headers), with
+/// `rpc.system`, `rpc.service`, `rpc.method`, `rpc.grpc.status_code` and the
+/// full method path as its resource. Failed calls are recorded on it like
+/// failed HTTP requests.
+///
+/// The standard `grpc.health.v1.Health` service runs alongside, reporting the
+/// server and both services as not serving once shutdown begins.
+///
+/// Configuration:
+/// - `GRPC_PORT`: port to serve on over HTTP/2 cleartext, on the host of
+///   `BIND_ADDRESS`; off when unset
+pub fn spawn(
+    state: Arc<AppState>,
+    host: IpAddr,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<Option<JoinHandle<()>>, Box<dyn std::error::Error>> {
+    let port = match std::env::var("GRPC_PORT") {
+        Ok(value) => value.parse::<u16>().map_err(|e| format!("GRPC_PORT: {}", e))?,
+        Err(_) => return Ok(None),
+    };
+    let addr = SocketAddr::new(host, port);
+    let incoming = TcpIncoming::new(addr, true, None).map_err(|e| format!("GRPC_PORT: {}", e))?;
+    info_trace!(address = %addr, "gRPC API listening");
+
+    let health = state.health.grpc_service(&[
+        <UserServiceServer<Api> as NamedService>::NAME,
+        <OrderServiceServer<Api> as NamedService>::NAME,
+    ]);
+    let api = Api { state };
+    let server = tonic::transport::Server::builder()
+        .trace_fn(call_span)
+        .add_service(health)
+        .add_service(UserServiceServer::new(api.clone()))
+        .add_service(OrderServiceServer::new(api))
+        .serve_with_incoming_shutdown(incoming, shutdown);
+    Ok(Some(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error_trace!(error = %e, "gRPC server failed");
+        }
+    })))
+}
+
+/// Opens the `grpc.server` span, in the caller's trace
+fn call_span(request: &axum::http::Request<()>) -> Span {
+    let path = request.uri().path();
+    let (service, method) = path.trim_start_matches('/').split_once('/').unwrap_or((path, ""));
+    let span = tracing::info_span!(
+        SPAN_NAME,
+        otel.kind = "server",
+        resource.name = path,
+        span.type = "grpc",
+        rpc.system = "grpc",
+        rpc.service = service,
+        rpc.method = method,
+        rpc.grpc.status_code = Empty,
+    );
+    if let Some(parent) = propagation::extract(request.headers()) {
+        let _ = span.set_parent(parent);
+    }
+    span
+}
+
+/// Run one call, recording its outcome on its span, and convert the result for tonic
+///
+/// Errors are recorded by their conversion to [`Status`], as for HTTP responses.
+async fn reply<T, M: From<T>>(call: impl Future<Output = Result<T, AppError>>) -> Result<Response<M>, Status> {
+    let result = call.await.map(|value| Response::new(M::from(value))).map_err(Status::from);
+    let code = result.as_ref().map_or_else(Status::code, |_| tonic::Code::Ok);
+    Span::current().record("rpc.grpc.status_code", code as i32);
+    result
+}
+
+#[derive(Debug, Clone)]
+struct Api {
+    state: Arc<AppState>,
+}
+
+#[tonic::async_trait]
+impl UserService for Api {
+    async fn create_user(&self, request: Request<proto::CreateUserRequest>) -> Result<Response<proto::User>, Status> {
+        reply(async {
+            let request = request.into_inner();
+            info_trace!(user_name = %request.name, "Creating new user over gRPC");
+            let payload = CreateUserRequest {
+                name: request.name,
+                email: request.email,
+            };
+            user_api::register_user(&self.state, payload, None, auth::Role::User).await.map_err(|e| {
+                e.log();
+                AppError::from(e)
+            })
+        })
+        .await
+    }
+
+    async fn get_user(&self, request: Request<proto::GetUserRequest>) -> Result<Response<proto::User>, Status> {
+        reply(async {
+            let id = parse_id::<UserId>(&request.get_ref().id)?;
+            match find_user(&self.state, id).await {
+                Ok(Some(record)) => Ok(User::from(record)),
+                Ok(None) => Err(AppError::NotFound("User not found".to_string())),
+                Err(e) => {
+                    error_trace!(user_id = %id, error = %e, "Failed to decrypt stored user");
+                    Err(AppError::Internal("Failed to load user".to_string()))
+                }
+            }
+        })
+        .await
+    }
+}
+
+#[tonic::async_trait]
+impl OrderService for Api {
+    async fn create_order(&self, request: Request<proto::CreateOrderRequest>) -> Result<Response<proto::Order>, Status> {
+        reply(async {
+            let payload = OrderRequest::try_from(request.into_inner())?;
+            order_api::place_order(self.state.clone(), payload).await
+        })
+        .await
+    }
+
+    async fn get_order(&self, request: Request<proto::GetOrderRequest>) -> Result<Response<proto::Order>, Status> {
+        reply(async {
+            let id = parse_id::<OrderId>(&request.get_ref().order_id)?;
+            match find_order(&self.state, id).await {
+                Some(found) => Ok(found.order),
+                None => Err(AppError::NotFound("Order not found".to_string())),
+            }
+        })
+        .await
+    }
+
+    async fn cancel_order(&self, request: Request<proto::CancelOrderRequest>) -> Result<Response<proto::Order>, Status> {
+        reply(async {
+            let principal = self.principal(&request)?;
+            let id = parse_id::<OrderId>(&request.get_ref().order_id)?;
+            order_api::cancel_stored_order(&self.state, id, &principal).await
+        })
+        .await
+    }
+}
+
+impl Api {
+    /// The caller named by the call's `authorization: Bearer` metadata, as for HTTP
+    fn principal<T>(&self, request: &Request<T>) -> Result<auth::Principal, AppError> {
+        let authorization = request
+            .metadata()
+            .get(header::AUTHORIZATION.as_str())
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".into()))?;
+        self.state.auth.principal(authorization.to_str().unwrap_or_default())
+    }
+}
+
+fn parse_id<T: FromStr<Err = String>>(value: &str) -> Result<T, AppError> {
+    value.parse().map_err(AppError::BadRequest)
+}
+
+impl TryFrom<proto::CreateOrderRequest> for OrderRequest {
+    type Error = AppError;
+
+    fn try_from(request: proto::CreateOrderRequest) -> Result<Self, Self::Error> {
+        let currency = match request.currency.as_str() {
+            "" => money::Currency::default(),
+            code => code.parse().map_err(AppError::BadRequest)?,
+        };
+        let items = request
+            .items
+            .into_iter()
+            .map(|item| {
+                Ok(OrderItem {
+                    product_id: ProductId::try_from(item.product_id).map_err(AppError::BadRequest)?,
+                    quantity: item.quantity,
+                    price: rust_decimal::Decimal::from_str(&item.price)
+                        .map_err(|e| AppError::BadRequest(format!("invalid price '{}': {}", item.price, e)))?,
+                })
+            })
+            .collect::<Result<_, AppError>>()?;
+        Ok(Self {
+            user_id: parse_id(&request.user_id)?,
+            currency,
+            items,
+        })
+    }
+}
+
+impl From<User> for proto::User {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id.to_string(),
+            name: user.name,
+            email: user.email,
+            created_at: user.created_at,
+        }
+    }
+}
+
+impl From<OrderResponse> for proto::Order {
+    fn from(order: OrderResponse) -> Self {
+        Self {
+            order_id: order.order_id.to_string(),
+            user_id: order.user_id.to_string(),
+            total_amount: order.total_amount.to_string(),
+            currency: order.currency.as_str().to_string(),
+            status: order.status,
+            created_at: order.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::metadata::MetadataValue;
+
+    #[test]
+    fn converts_order_requests_and_errors() {
+        let request = proto::CreateOrderRequest {
+            user_id: UserId::generate().to_string(),
+            currency: String::new(),
+            items: vec![proto::OrderItem {
+                product_id: "prod-001".to_string(),
+                quantity: 2,
+                price: "19.99".to_string(),
+            }],
+        };
+        let order = OrderRequest::try_from(request.clone()).unwrap();
+        assert_eq!(order.currency, money::Currency::Usd);
+        assert_eq!(order.items[0].price, rust_decimal::Decimal::new(1999, 2));
+
+        let bad_price = proto::CreateOrderRequest {
+            items: vec![proto::OrderItem {
+                price: "cheap".to_string(),
+                ..request.items[0].clone()
+            }],
+            ..request
+        };
+        let status = Status::from(OrderRequest::try_from(bad_price).unwrap_err());
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(Status::from(AppError::Conflict("Order already cancelled".to_string())).code(), tonic::Code::Aborted);
+    }
+
+    #[tokio::test]
+    async fn cancel_order_requires_the_owner() {
+        let (state, _) = crate::tests::test_app().await;
+        let order_id = crate::tests::seed_order(&state, UserId::generate()).await;
+        let api = Api { state: state.clone() };
+        let cancel = |token: Option<String>| {
+            let mut request = Request::new(proto::CancelOrderRequest {
+                order_id: order_id.to_string(),
+            });
+            if let Some(token) = token {
+                let value = MetadataValue::try_from(format!("Bearer {}", token)).unwrap();
+                request.metadata_mut().insert("authorization", value);
+            }
+            api.cancel_order(request)
+        };
+
+        assert_eq!(cancel(None).await.unwrap_err().code(), tonic::Code::Unauthenticated);
+        let stranger = state.auth.issue(UserId::generate(), auth::Role::User).unwrap();
+        assert_eq!(cancel(Some(stranger)).await.unwrap_err().code(), tonic::Code::PermissionDenied);
+        let admin = state.auth.issue(UserId::generate(), auth::Role::Admin).unwrap();
+        assert_eq!(cancel(Some(admin)).await.unwrap().into_inner().status, "cancelled");
+    }
+}
@@ -0,0 +1,154 @@
+use rust_datadog_otel::{debug_trace, info_trace, warn_trace};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tonic_health::pb::health_server::{Health as HealthCheck, HealthServer};
+use tonic_health::ServingStatus;
+
+/// Whether the process is taking traffic, as reported by the gRPC and TCP health probes
+///
+/// Starts out serving and flips to not serving once shutdown begins, so load
+/// balancers stop routing new connections while in-flight requests drain.
+#[derive(Debug, Clone)]
+pub struct Health {
+    serving: Arc<watch::Sender<bool>>,
+}
+
+impl Health {
+    pub fn serving() -> Self {
+        Self {
+            serving: Arc::new(watch::Sender::new(true)),
+        }
+    }
+
+    pub fn is_serving(&self) -> bool {
+        *self.serving.borrow()
+    }
+
+    pub fn set_serving(&self, serving: bool) {
+        if self.serving.send_replace(serving) != serving {
+            info_trace!(serving = serving, "Health status changed");
+        }
+    }
+
+    /// The standard `grpc.health.v1.Health` service (`Check` and `Watch`), following this state
+    ///
+    /// Reports the overall server (`""`) and each of `services` as serving or not serving.
+    pub fn grpc_service(&self, services: &'static [&'static str]) -> HealthServer<impl HealthCheck> {
+        let (mut reporter, service) = tonic_health::server::health_reporter();
+        let mut serving = self.serving.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let status = match *serving.borrow_and_update() {
+                    true => ServingStatus::Serving,
+                    false => ServingStatus::NotServing,
+                };
+                for name in std::iter::once("").chain(services.iter().copied()) {
+                    reporter.set_service_status(name, status).await;
+                }
+                if serving.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+        service
+    }
+}
+
+/// Port for TCP health probes, disabled unless set
+///
+/// The gRPC health service is served alongside the gRPC API; see `grpc::spawn`.
+///
+/// Configuration:
+/// - `TCP_HEALTH_PORT`: accept (and immediately close) TCP connections on
+///   this port, on the host of `BIND_ADDRESS`, while serving; the port closes
+///   once shutdown begins
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeConfig {
+    tcp_port: Option<u16>,
+}
+
+impl ProbeConfig {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let port = |name: &str| -> Result<Option<u16>, Box<dyn std::error::Error>> {
+            match std::env::var(name) {
+                Ok(value) => Ok(Some(value.parse().map_err(|e| format!("{}: {}", name, e))?)),
+                Err(_) => Ok(None),
+            }
+        };
+        Ok(Self {
+            tcp_port: port("TCP_HEALTH_PORT")?,
+        })
+    }
+}
+
+/// Bind the configured probe port on `host` and serve it in the background
+pub async fn spawn_probes(config: ProbeConfig, host: IpAddr, health: Health) -> std::io::Result<()> {
+    if let Some(port) = config.tcp_port {
+        let addr = SocketAddr::new(host, port);
+        let listener = TcpListener::bind(addr).await?;
+        info_trace!(address = %addr, "TCP health port listening");
+        tokio::spawn(serve_tcp(listener, health));
+    }
+    Ok(())
+}
+
+/// Accept probe connections until the server stops serving, then close the port
+async fn serve_tcp(listener: TcpListener, health: Health) {
+    let mut serving = health.serving.subscribe();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                // A completed handshake is the whole check
+                Ok((_, peer)) => debug_trace!(client = %peer, "TCP health probe"),
+                Err(e) => {
+                    warn_trace!(error = %e, "Failed to accept TCP health probe");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            _ = async { serving.wait_for(|serving| !serving).await.is_ok() } => break,
+        }
+    }
+    info_trace!("TCP health port closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic_health::pb::health_check_response::ServingStatus as Status;
+    use tonic_health::pb::health_client::HealthClient;
+    use tonic_health::pb::HealthCheckRequest;
+
+    #[tokio::test]
+    async fn grpc_service_follows_serving_state() {
+        let health = Health::serving();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+        let server = tonic::transport::Server::builder()
+            .add_service(health.grpc_service(&["demo.v1.OrderService"]))
+            .serve_with_incoming(incoming);
+        tokio::spawn(server);
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = HealthClient::new(channel);
+        let check = |service: &str| HealthCheckRequest {
+            service: service.to_string(),
+        };
+        let mut watch = client.watch(check("demo.v1.OrderService")).await.unwrap().into_inner();
+        assert_eq!(watch.message().await.unwrap().unwrap().status(), Status::Serving);
+        assert_eq!(client.check(check("")).await.unwrap().into_inner().status(), Status::Serving);
+        let unknown = client.check(check("demo.v1.Nope")).await.unwrap_err();
+        assert_eq!(unknown.code(), tonic::Code::NotFound);
+
+        health.set_serving(false);
+        assert_eq!(watch.message().await.unwrap().unwrap().status(), Status::NotServing);
+        assert_eq!(client.check(check("")).await.unwrap().into_inner().status(), Status::NotServing);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{downstream, propagation};
+use reqwest::{Method, RequestBuilder, Response};
+use tracing::Instrument;
+
+/// How client spans name the service on the other end
+#[derive(Debug, Clone)]
+enum PeerService {
+    /// Every call goes to one downstream, e.g. `meilisearch`
+    Named(String),
+    /// Calls go to arbitrary hosts; each is reported as its own service
+    PerHost,
+}
+
+/// Outbound HTTP client whose calls show up as client spans in the caller's trace
+///
+/// Each request runs in an `http.client.request` span carrying the OpenTelemetry
+/// `http.*` semantic attributes (`http.request.method`, `url.full`,
+/// `server.address`, `server.port`, `http.response.status_code`, and
+/// `error.type` on failure), with the current trace context injected into the
+/// request headers in the configured propagation styles. Build requests with
+/// [`HttpClient::request`] (or `get`) and send them with
+/// [`HttpClient::send`]. Callers still charge the request's cost themselves.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    peer_service: Option<PeerService>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            peer_service: None,
+        }
+    }
+
+    /// Name the downstream every call goes to, as `peer.service`
+    pub fn with_peer_service(mut self, service: impl Into<String>) -> Self {
+        self.peer_service = Some(PeerService::Named(service.into()));
+        self
+    }
+
+    /// Report each call under its host's own service, for proxy-style clients
+    pub fn with_service_per_host(mut self) -> Self {
+        self.peer_service = Some(PeerService::PerHost);
+        self
+    }
+
+    pub fn request(&self, method: Method, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    pub fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    /// Send `request` in a client span, injecting the trace context
+    ///
+    /// Like `reqwest`, any response counts as success here; 4xx and 5xx
+    /// responses still mark the span as an error.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let mut request = request.build()?;
+        let url = request.url();
+        let span = tracing::info_span!(
+            "http.client.request",
+            otel.name = %request.method(),
+            otel.kind = "client",
+            otel.status_code = tracing::field::Empty,
+            http.request.method = %request.method(),
+            url.full = %redacted(url),
+            server.address = url.host_str().unwrap_or_default(),
+            server.port = tracing::field::Empty,
+            http.response.status_code = tracing::field::Empty,
+            error.type = tracing::field::Empty,
+        );
+        if let Some(port) = url.port_or_known_default() {
+            span.record("server.port", port);
+        }
+        match &self.peer_service {
+            Some(PeerService::Named(service)) => downstream::set_peer_service(&span, service.clone()),
+            Some(PeerService::PerHost) => {
+                if let Some(host) = url.host_str() {
+                    downstream::override_service(&span, host);
+                }
+            }
+            None => {}
+        }
+        span.in_scope(|| propagation::inject_current(request.headers_mut()));
+
+        let result = self.client.execute(request).instrument(span.clone()).await;
+        match &result {
+            Ok(response) => {
+                let status = response.status();
+                span.record("http.response.status_code", status.as_u16());
+                if status.is_client_error() || status.is_server_error() {
+                    span.record("otel.status_code", "error");
+                    span.record("error.type", status.as_str());
+                }
+            }
+            Err(e) => {
+                span.record("otel.status_code", "error");
+                span.record("error.type", error_type(e));
+            }
+        }
+        result
+    }
+}
+
+/// `url` without credentials, which must not reach span attributes
+fn redacted(url: &reqwest::Url) -> reqwest::Url {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url
+}
+
+fn error_type(error: &reqwest::Error) -> &'static str {
+    if error.is_timeout() {
+        "timeout"
+    } else if error.is_connect() {
+        "connect"
+    } else if error.is_decode() {
+        "decode"
+    } else {
+        "request"
+    }
+}
@@ -0,0 +1,153 @@
+use axum::{
+    extract::{FromRequestParts, Path},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::warn_trace;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Longest accepted product ID
+const MAX_PRODUCT_ID_LEN: usize = 64;
+
+/// Define a UUID-backed entity ID, serialized as the hyphenated UUID string
+macro_rules! uuid_id {
+    ($(#[$meta:meta])* $name:ident, $entity:literal) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema, sqlx::Type)]
+        #[serde(transparent)]
+        #[sqlx(transparent)]
+        pub struct $name(Uuid);
+
+        impl $name {
+            /// A new random (v4) ID
+            pub fn generate() -> Self {
+                Self(Uuid::new_v4())
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.hyphenated().fmt(f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                Uuid::parse_str(value)
+                    .map(Self)
+                    .map_err(|_| format!("invalid {} id '{}', expected a UUID", $entity, value))
+            }
+        }
+    };
+}
+
+uuid_id!(
+    /// ID of a registered user
+    UserId,
+    "user"
+);
+
+uuid_id!(
+    /// ID of a placed order
+    OrderId,
+    "order"
+);
+
+uuid_id!(
+    /// ID of a background job started through the API
+    JobId,
+    "job"
+);
+
+/// Product SKU such as `prod-001`: 1-64 ASCII letters, digits, `-` or `_`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(try_from = "String")]
+pub struct ProductId(String);
+
+impl TryFrom<String> for ProductId {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let valid = !value.is_empty()
+            && value.len() <= MAX_PRODUCT_ID_LEN
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if valid {
+            Ok(Self(value))
+        } else {
+            Err(format!(
+                "invalid product id '{}', expected 1-{} letters, digits, '-' or '_'",
+                value, MAX_PRODUCT_ID_LEN
+            ))
+        }
+    }
+}
+
+impl fmt::Display for ProductId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Path extractor for a route's single typed ID segment
+///
+/// A malformed ID is rejected with 400 before the handler runs, rather than
+/// being looked up and reported as not found.
+#[derive(Debug)]
+pub struct IdPath<T>(pub T);
+
+#[axum::async_trait]
+impl<S, T> FromRequestParts<S> for IdPath<T>
+where
+    S: Send + Sync,
+    T: FromStr<Err = String>,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(raw) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::BadRequest(e.body_text()).into_response())?;
+        raw.parse().map(IdPath).map_err(|e: String| {
+            warn_trace!(error = %e, "Rejected malformed id");
+            AppError::BadRequest(e).into_response()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_uuid_ids_and_rejects_anything_else() {
+        let id: UserId = "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap();
+        assert_eq!(id.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+        // Any UUID spelling is accepted, and displayed hyphenated and lowercase
+        let id: OrderId = "67E5504410B1426F9247BB680E5FE0C8".parse().unwrap();
+        assert_eq!(id.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+
+        for malformed in ["", "42", "not-a-uuid", "67e55044-10b1-426f-9247-bb680e5fe0c", "67e55044-10b1-426f-9247-bb680e5fe0c8x"] {
+            let error = malformed.parse::<UserId>().unwrap_err();
+            assert_eq!(error, format!("invalid user id '{}', expected a UUID", malformed));
+        }
+        assert!("not-a-uuid".parse::<OrderId>().unwrap_err().starts_with("invalid order id"));
+    }
+
+    #[test]
+    fn validates_product_ids() {
+        assert!(ProductId::try_from("prod-001".to_string()).is_ok());
+        assert!(ProductId::try_from("SKU_42".to_string()).is_ok());
+        for invalid in [String::new(), "prod 001".to_string(), "prod/001".to_string(), "p".repeat(MAX_PRODUCT_ID_LEN + 1)] {
+            assert!(ProductId::try_from(invalid).is_err());
+        }
+    }
+}
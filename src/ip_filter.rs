@@ -0,0 +1,133 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use rust_datadog_otel::client_ip::{parse_cidrs, ClientIp};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::warn_trace;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Default networks allowed to reach `/admin` and `/debug`: loopback and RFC1918
+const DEFAULT_RESTRICTED_PATHS: &str = "/admin=127.0.0.0/8,::1/128,10.0.0.0/8,172.16.0.0/12,192.168.0.0/16;\
+    /debug=127.0.0.0/8,::1/128,10.0.0.0/8,172.16.0.0/12,192.168.0.0/16";
+
+/// Path prefix restricted to a set of networks
+#[derive(Debug)]
+struct PathRule {
+    prefix: String,
+    allow: Vec<IpNet>,
+}
+
+/// CIDR-based access policy for the public listener
+///
+/// Configuration:
+/// - `IP_DENYLIST`: CIDRs rejected on every route
+/// - `IP_ALLOWLIST`: if set, only these CIDRs may reach any route
+/// - `IP_RESTRICTED_PATHS`: `prefix=cidr,cidr;prefix=cidr` rules
+///   (default restricts `/admin` and `/debug` to loopback and RFC1918)
+///
+/// Rules are evaluated against the [`ClientIp`] resolved from trusted proxies.
+#[derive(Debug)]
+pub struct IpPolicy {
+    deny: Vec<IpNet>,
+    allow: Vec<IpNet>,
+    paths: Vec<PathRule>,
+    blocked: AtomicU64,
+}
+
+impl IpPolicy {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let deny = parse_cidrs(&std::env::var("IP_DENYLIST").unwrap_or_default())?;
+        let allow = parse_cidrs(&std::env::var("IP_ALLOWLIST").unwrap_or_default())?;
+
+        let raw_paths = std::env::var("IP_RESTRICTED_PATHS")
+            .unwrap_or_else(|_| DEFAULT_RESTRICTED_PATHS.to_string());
+        let paths = raw_paths
+            .split(';')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| {
+                let (prefix, cidrs) = rule
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid IP_RESTRICTED_PATHS rule '{}'", rule))?;
+                Ok(PathRule {
+                    prefix: prefix.trim().to_string(),
+                    allow: parse_cidrs(cidrs)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+        Ok(Self {
+            deny,
+            allow,
+            paths,
+            blocked: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the name of the rule that blocks this request, if any
+    fn evaluate(&self, ip: Option<ClientIp>, path: &str) -> Option<&'static str> {
+        let Some(ClientIp(ip)) = ip else {
+            // Unknown client address: only unrestricted routes are reachable
+            let restricted = !self.allow.is_empty()
+                || self.paths.iter().any(|rule| path.starts_with(&rule.prefix));
+            return restricted.then_some("unresolved_client");
+        };
+
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return Some("denylist");
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|net| net.contains(&ip)) {
+            return Some("allowlist");
+        }
+        let path_blocked = self
+            .paths
+            .iter()
+            .filter(|rule| path.starts_with(&rule.prefix))
+            .any(|rule| !rule.allow.iter().any(|net| net.contains(&ip)));
+        path_blocked.then_some("restricted_path")
+    }
+}
+
+/// Middleware enforcing [`IpPolicy`]
+///
+/// Blocked requests get a 403 (picked up by the security audit middleware),
+/// are recorded in an `ip_filter.blocked` span tagged with the matching rule,
+/// and increment a blocked-request counter.
+pub async fn enforce_ip_policy(
+    State(policy): State<Arc<IpPolicy>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client_ip = request.extensions().get::<ClientIp>().copied();
+    let path = request.uri().path();
+
+    let Some(rule) = policy.evaluate(client_ip, path) else {
+        return next.run(request).await;
+    };
+
+    let blocked_total = policy.blocked.fetch_add(1, Ordering::Relaxed) + 1;
+    let ip = client_ip.map_or_else(|| "unknown".to_string(), |ClientIp(ip)| ip.to_string());
+
+    // Blocked requests never reach a handler span, so give them their own
+    let span = tracing::info_span!(
+        "ip_filter.blocked",
+        ip_filter.rule = rule,
+        network.client.ip = %ip,
+        http.target = %path,
+    );
+    let _guard = span.enter();
+
+    warn_trace!(
+        network.client.ip = %ip,
+        ip_filter.rule = rule,
+        ip_filter.blocked_total = blocked_total,
+        path = %path,
+        "Request blocked by IP policy"
+    );
+
+    AppError::Forbidden("Access denied".to_string()).into_response()
+}
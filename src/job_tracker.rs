@@ -0,0 +1,168 @@
+use crate::ids::JobId;
+use chrono::{DateTime, Utc};
+use opentelemetry::trace::TraceContextExt;
+use rust_datadog_otel::{info_trace, trace_context, warn_trace};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use utoipa::ToSchema;
+
+/// Finished jobs kept for `/admin/jobs/:id`; the oldest are dropped first
+const MAX_FINISHED_JOBS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Progress of a background job, as served by `/admin/jobs/:id`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TrackedJob {
+    pub id: JobId,
+    pub kind: &'static str,
+    pub state: JobState,
+    /// Items the job set out to process
+    pub total: u64,
+    /// Items processed so far, updated as the job runs
+    pub processed: u64,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    /// Trace of the job's own span, which links back to the initiating request
+    pub trace_id: Option<String>,
+}
+
+/// Handle a running job uses to report progress
+#[derive(Debug, Clone)]
+pub struct JobProgress {
+    id: JobId,
+    tracker: Arc<JobTracker>,
+}
+
+impl JobProgress {
+    /// Count `count` more items as processed, adding a `job.progress` span event
+    pub fn advance(&self, count: u64) {
+        let processed = self.tracker.update(self.id, |job| {
+            job.processed += count;
+            job.processed
+        });
+        info_trace!(job.id = %self.id, job.processed = processed, "job.progress");
+    }
+}
+
+/// Registry of asynchronous jobs started by API requests
+///
+/// Each job runs in its own trace rather than as a child of the request that
+/// started it, which would otherwise stay open for the job's whole lifetime;
+/// a span link ties the two together instead.
+#[derive(Debug, Default)]
+pub struct JobTracker {
+    jobs: Mutex<HashMap<JobId, TrackedJob>>,
+}
+
+impl JobTracker {
+    pub fn get(&self, id: JobId) -> Option<TrackedJob> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).get(&id).cloned()
+    }
+
+    fn running(&self) -> usize {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.values().filter(|job| job.state == JobState::Running).count()
+    }
+
+    /// Wait for running jobs to finish, for shutdown
+    pub async fn wait_idle(&self) {
+        while self.running() > 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    fn update<R>(&self, id: JobId, apply: impl FnOnce(&mut TrackedJob) -> R) -> R {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        apply(jobs.get_mut(&id).expect("tracked jobs are only removed once finished"))
+    }
+
+    fn insert(&self, job: TrackedJob) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut finished: Vec<(DateTime<Utc>, JobId)> = jobs
+            .values()
+            .filter_map(|job| job.finished_at.map(|at| (at, job.id)))
+            .collect();
+        if finished.len() >= MAX_FINISHED_JOBS {
+            finished.sort();
+            for (_, id) in &finished[..=finished.len() - MAX_FINISHED_JOBS] {
+                jobs.remove(id);
+            }
+        }
+        jobs.insert(job.id, job);
+    }
+
+    /// Run `work` in the background as a `kind` job over `total` items
+    ///
+    /// Returns immediately with the job's ID. The job span is named `kind` and
+    /// linked to the current span; the current span gets a `job.id` event.
+    pub fn spawn<F, Fut>(self: &Arc<Self>, kind: &'static str, total: u64, work: F) -> JobId
+    where
+        F: FnOnce(JobProgress) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let id = JobId::generate();
+        let span = tracing::info_span!(
+            parent: None,
+            "job.run",
+            otel.name = kind,
+            job.id = %id,
+            job.kind = kind,
+            job.total = total,
+        );
+        span.add_link(tracing::Span::current().context().span().span_context().clone());
+
+        self.insert(TrackedJob {
+            id,
+            kind,
+            state: JobState::Running,
+            total,
+            processed: 0,
+            started_at: Utc::now(),
+            finished_at: None,
+            error: None,
+            trace_id: trace_context::trace_ids(&span).map(|ids| ids.trace_id_hex),
+        });
+        info_trace!(job.id = %id, job.kind = kind, job.total = total, "Background job started");
+
+        let progress = JobProgress {
+            id,
+            tracker: self.clone(),
+        };
+        let tracker = self.clone();
+        tokio::spawn(
+            async move {
+                let result = work(progress).await;
+                let processed = tracker.update(id, |job| {
+                    job.finished_at = Some(Utc::now());
+                    match &result {
+                        Ok(()) => job.state = JobState::Completed,
+                        Err(e) => {
+                            job.state = JobState::Failed;
+                            job.error = Some(e.clone());
+                        }
+                    }
+                    job.processed
+                });
+                match result {
+                    Ok(()) => info_trace!(job.id = %id, job.processed = processed, "Background job completed"),
+                    Err(e) => warn_trace!(job.id = %id, job.processed = processed, error = %e, "Background job failed"),
+                }
+            }
+            .instrument(span),
+        );
+        id
+    }
+}
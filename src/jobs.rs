@@ -0,0 +1,202 @@
+use crate::distributed_lock::LockStore;
+use futures_util::future::BoxFuture;
+use rust_datadog_otel::{dogstatsd, info_trace, start_span, warn_trace};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Lock held by the replica that runs scheduled jobs
+const LEADER_LOCK: &str = "jobs:leader";
+
+const DEFAULT_LOCK_TTL_SECS: u64 = 30;
+/// The lease is renewed every third of it, so shorter leases would renew continuously
+const MIN_LOCK_TTL_SECS: u64 = 3;
+
+type JobFn = Box<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
+
+struct Job {
+    name: &'static str,
+    interval: Duration,
+    run: JobFn,
+    runs: AtomicU64,
+}
+
+/// Job status for `/admin/jobs`
+#[derive(Debug, Serialize)]
+pub struct JobStatus {
+    name: &'static str,
+    interval_secs: u64,
+    runs: u64,
+}
+
+/// Periodic jobs that run on exactly one replica
+///
+/// Replicas compete for a leader lease in the [`LockStore`]; only the holder
+/// runs jobs. Every acquisition attempt is traced as a `jobs.lock.acquire`
+/// span, and leadership is exposed as the `jobs.leader` gauge (1 or 0).
+///
+/// Configuration:
+/// - `JOBS_LOCK_TTL_SECS`: leader lease length (default 30, at least 3), renewed every third of it
+pub struct Scheduler {
+    lock: Arc<dyn LockStore>,
+    owner: String,
+    ttl: Duration,
+    leader: AtomicBool,
+    jobs: Vec<Job>,
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("owner", &self.owner)
+            .field("backend", &self.lock.backend())
+            .field("leader", &self.is_leader())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Scheduler {
+    pub fn new(lock: Arc<dyn LockStore>) -> Result<Self, Box<dyn std::error::Error>> {
+        let ttl = std::env::var("JOBS_LOCK_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_LOCK_TTL_SECS);
+        if ttl < MIN_LOCK_TTL_SECS {
+            return Err(format!("JOBS_LOCK_TTL_SECS must be at least {}, got {}", MIN_LOCK_TTL_SECS, ttl).into());
+        }
+
+        // Pod name in Kubernetes; the suffix keeps restarted pods distinct
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "local".to_string());
+        let owner = format!("{}-{}", host, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+
+        Ok(Self {
+            lock,
+            owner,
+            ttl: Duration::from_secs(ttl),
+            leader: AtomicBool::new(false),
+            jobs: Vec::new(),
+        })
+    }
+
+    /// Register a job to run every `interval` while this replica is leader
+    pub fn add_job<F>(mut self, name: &'static str, interval: Duration, run: F) -> Self
+    where
+        F: Fn() -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    {
+        self.jobs.push(Job {
+            name,
+            interval,
+            run: Box::new(run),
+            runs: AtomicU64::new(0),
+        });
+        self
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::Relaxed)
+    }
+
+    /// `jobs.leader` gauge value
+    pub fn leader_gauge(&self) -> u8 {
+        self.is_leader() as u8
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    pub fn backend(&self) -> &'static str {
+        self.lock.backend()
+    }
+
+    pub fn job_statuses(&self) -> Vec<JobStatus> {
+        self.jobs
+            .iter()
+            .map(|job| JobStatus {
+                name: job.name,
+                interval_secs: job.interval.as_secs(),
+                runs: job.runs.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    async fn try_lead(&self) {
+        let span = tracing::info_span!(
+            "jobs.lock.acquire",
+            lock.name = LEADER_LOCK,
+            lock.owner = %self.owner,
+            lock.backend = self.lock.backend(),
+            lock.acquired = tracing::field::Empty,
+            jobs.leader = tracing::field::Empty,
+        );
+
+        async {
+            let acquired = match self.lock.acquire(LEADER_LOCK, &self.owner, self.ttl).await {
+                Ok(acquired) => acquired,
+                Err(e) => {
+                    // Can't confirm the lease, so stop running jobs until we can
+                    warn_trace!(error = %e, "Leader lock acquisition failed");
+                    false
+                }
+            };
+
+            let was_leader = self.leader.swap(acquired, Ordering::Relaxed);
+            let span = tracing::Span::current();
+            span.record("lock.acquired", acquired);
+            span.record("jobs.leader", self.leader_gauge());
+            dogstatsd::gauge("jobs.leader", self.leader_gauge().into(), &[]);
+
+            if acquired != was_leader {
+                info_trace!(
+                    jobs.leader = self.leader_gauge(),
+                    owner = %self.owner,
+                    "Scheduled job leadership changed"
+                );
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Start leader election and one task per job
+    pub fn start(self: &Arc<Self>) {
+        let scheduler = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(scheduler.ttl / 3);
+            loop {
+                ticker.tick().await;
+                scheduler.try_lead().await;
+            }
+        });
+
+        for index in 0..self.jobs.len() {
+            let scheduler = Arc::clone(self);
+            tokio::spawn(async move {
+                let job = &scheduler.jobs[index];
+                let mut ticker = tokio::time::interval(job.interval);
+                // The first tick is immediate; wait a full interval before the first run
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    if !scheduler.is_leader() {
+                        continue;
+                    }
+                    let span = start_span!("jobs.run", job.name, "worker", job.name = job.name);
+                    (job.run)().instrument(span).await;
+                    job.runs.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    }
+
+    /// Give up leadership so another replica takes over without waiting for the lease to expire
+    pub async fn step_down(&self) {
+        if self.leader.swap(false, Ordering::Relaxed) {
+            if let Err(e) = self.lock.release(LEADER_LOCK, &self.owner).await {
+                warn_trace!(error = %e, "Failed to release leader lock");
+            }
+        }
+    }
+}
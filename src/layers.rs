@@ -0,0 +1,132 @@
+use crate::client_ip::{self, TrustedProxies};
+use crate::metrics::{self, HttpMetrics};
+use crate::protocol::{self, ProtocolStats};
+use crate::propagation::{self, ResponseHeaders};
+use crate::request_span;
+use crate::security_headers::{self, SecurityHeaders};
+use axum::{
+    http::{HeaderName, HeaderValue},
+    middleware, Router,
+};
+use std::sync::Arc;
+use tower_http::cors::CorsLayer;
+
+/// The service-agnostic outer middleware of an instrumented Axum service
+///
+/// [`ServiceLayers::apply`] wraps a router, innermost first, in: client IP
+/// resolution, protocol version counts, security headers, CORS, RED metrics,
+/// trace ID response headers, the `api.request` server span and trace context
+/// extraction. Layers a service adds itself go on the router before, so they
+/// see the resolved [`client_ip::ClientIp`] and run inside the request span.
+///
+/// Configuration: `TRUSTED_PROXIES`, the `SECURITY_HEADER_*` variables and the
+/// trace response header settings, as read by the types behind each layer.
+#[derive(Debug, Clone)]
+pub struct ServiceLayers {
+    trusted_proxies: Arc<TrustedProxies>,
+    protocols: Arc<ProtocolStats>,
+    security_headers: Arc<SecurityHeaders>,
+    metrics: Arc<HttpMetrics>,
+    response_headers: Arc<ResponseHeaders>,
+    cors: CorsLayer,
+}
+
+impl ServiceLayers {
+    /// Layers configured from the environment, allowing `cors_origins` (any when empty)
+    pub fn from_env(cors_origins: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let response_headers = Arc::new(ResponseHeaders::from_env()?);
+        Ok(Self {
+            trusted_proxies: Arc::new(TrustedProxies::from_env()?),
+            protocols: Arc::new(ProtocolStats::default()),
+            security_headers: Arc::new(SecurityHeaders::from_env()?),
+            metrics: Arc::new(HttpMetrics::default()),
+            cors: cors(cors_origins, &response_headers.header_names())?,
+            response_headers,
+        })
+    }
+
+    /// Count protocol versions into `protocols`, e.g. stats a handler also reports
+    pub fn with_protocols(mut self, protocols: Arc<ProtocolStats>) -> Self {
+        self.protocols = protocols;
+        self
+    }
+
+    /// The request metrics, for serving with [`metrics::routes`]
+    pub fn metrics(&self) -> Arc<HttpMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Wrap `router` in every layer
+    pub fn apply<S: Clone + Send + Sync + 'static>(self, router: Router<S>) -> Router<S> {
+        router
+            .layer(middleware::from_fn_with_state(
+                self.trusted_proxies,
+                client_ip::resolve_client_ip,
+            ))
+            .layer(middleware::from_fn_with_state(
+                self.protocols,
+                protocol::count_protocol,
+            ))
+            .layer(middleware::from_fn_with_state(
+                self.security_headers,
+                security_headers::apply_security_headers,
+            ))
+            .layer(self.cors)
+            // Over every route, so requests any middleware rejects are counted too
+            .layer(middleware::from_fn_with_state(self.metrics, metrics::record_request))
+            .layer(middleware::from_fn_with_state(
+                self.response_headers,
+                propagation::add_response_headers,
+            ))
+            // One api.request server span per request, in the caller's trace
+            .layer(request_span::layer())
+            // Outermost, so every span the request opens joins the caller's trace
+            .layer(middleware::from_fn(propagation::extract_trace_context))
+    }
+}
+
+/// CORS for `origins`, or any origin when none are configured
+///
+/// Browser scripts may read the `exposed` response headers, such as the trace ID.
+pub fn cors(origins: &[String], exposed: &[HeaderName]) -> Result<CorsLayer, Box<dyn std::error::Error>> {
+    if origins.is_empty() {
+        return Ok(CorsLayer::permissive());
+    }
+    let origins = origins
+        .iter()
+        .map(|origin| origin.parse().map_err(|e| format!("CORS origin '{}': {}", origin, e)))
+        .collect::<Result<Vec<HeaderValue>, _>>()?;
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(tower_http::cors::Any)
+        .allow_headers(tower_http::cors::Any)
+        .expose_headers(exposed.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn wraps_routes_in_the_service_layers() {
+        let layers = ServiceLayers::from_env(&[]).unwrap();
+        let metrics = layers.metrics();
+        let app: Router = layers.apply(Router::new().route("/users/:id", get(|| async { "ok" })));
+
+        let response = app
+            .oneshot(Request::get("/users/7").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(metrics
+            .render()
+            .contains(r#"http_requests_total{method="GET",route="/users/:id",status="200"} 1"#));
+    }
+}
@@ -0,0 +1,68 @@
+//! Datadog APM for Rust services on OpenTelemetry
+//!
+//! The reusable half of the demo service: tracer setup on the Datadog SDK,
+//! trace-correlated logging, trace context propagation, server middleware,
+//! outbound HTTP instrumentation, DogStatsD metrics and Datadog events, and a
+//! typed client for the demo API. The `rust-datadog-otel` binary is one
+//! consumer of it.
+//!
+//! ```no_run
+//! use axum::{routing::get, Router};
+//! use rust_datadog_otel::{info_trace, layers::ServiceLayers, telemetry};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! // Unset values fall back to DD_SERVICE, DD_VERSION, DD_AGENT_HOST and friends
+//! let tracer_provider = telemetry::TelemetryBuilder::new()
+//!     .service("checkout")
+//!     .sample_rate(0.5)
+//!     .init()?;
+//! info_trace!("Tracing ready");
+//!
+//! // Request spans, RED metrics, security headers and CORS, in the caller's trace
+//! let app: Router = ServiceLayers::from_env(&[])?.apply(Router::new().route("/", get(|| async { "ok" })));
+//! # let _ = app;
+//!
+//! telemetry::shutdown_telemetry(tracer_provider)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Most modules are configured from `DD_*` and related environment variables,
+//! listed on each type.
+
+pub mod attribute_filter;
+pub mod cardinality_guard;
+pub mod client;
+pub mod client_ip;
+pub mod console_exporter;
+pub mod datadog_events;
+pub mod dogstatsd;
+pub mod downstream;
+pub mod error;
+pub mod export_failover;
+pub mod export_fallback;
+pub mod export_mirror;
+pub mod http_client;
+pub mod layers;
+pub mod measure;
+pub mod metrics;
+pub mod propagation;
+pub mod protocol;
+pub mod request_span;
+pub mod runtime_metrics;
+pub mod sampling;
+pub mod security_headers;
+pub mod span_dedup;
+pub mod span_rules;
+pub mod span_tap;
+pub mod tail_sampling;
+pub mod telemetry;
+pub mod trace_context;
+
+/// Used by the logging macros, so callers don't need `tracing` in scope
+#[doc(hidden)]
+pub use tracing as __tracing;
+
+/// Used by `link_trace!`, so callers don't need `opentelemetry` in scope
+#[doc(hidden)]
+pub use opentelemetry as __opentelemetry;
@@ -0,0 +1,134 @@
+use futures_util::future::BoxFuture;
+use rust_datadog_otel::{info_trace, warn_trace};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+type HookFn = Box<dyn FnOnce() -> BoxFuture<'static, Result<(), HookError>> + Send>;
+
+/// Stage of shutdown a hook belongs to; stages run in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Stop taking on new work: consumers, schedulers, leader leases
+    Intake,
+    /// Let background work already started finish
+    Workers,
+    /// Flush telemetry; last, so every earlier hook is still traced
+    Telemetry,
+}
+
+impl Phase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Phase::Intake => "intake",
+            Phase::Workers => "workers",
+            Phase::Telemetry => "telemetry",
+        }
+    }
+}
+
+struct Hook {
+    name: &'static str,
+    phase: Phase,
+    timeout: Duration,
+    run: HookFn,
+}
+
+/// Shutdown hooks registered by subsystems, run once the server has drained
+///
+/// Hooks run one at a time, phase by phase, and within a phase in reverse
+/// registration order, so a subsystem stops before the ones it was built on.
+/// Each runs in a `shutdown.hook` span under its own timeout; a hook that fails
+/// or times out is logged and the rest still run.
+#[derive(Default)]
+pub struct Lifecycle {
+    hooks: Mutex<Vec<Hook>>,
+}
+
+impl std::fmt::Debug for Lifecycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hooks = self.hooks.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("Lifecycle")
+            .field("hooks", &hooks.iter().map(|hook| hook.name).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Lifecycle {
+    /// Register `hook` to run during `phase`, giving up on it after `timeout`
+    pub fn on_shutdown<F, Fut>(&self, name: &'static str, phase: Phase, timeout: Duration, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), HookError>> + Send + 'static,
+    {
+        self.hooks.lock().unwrap_or_else(|e| e.into_inner()).push(Hook {
+            name,
+            phase,
+            timeout,
+            run: Box::new(move || Box::pin(hook())),
+        });
+    }
+
+    /// Run every registered hook; hooks registered afterwards are not run
+    pub async fn shutdown(&self) {
+        let mut hooks = std::mem::take(&mut *self.hooks.lock().unwrap_or_else(|e| e.into_inner()));
+        // Stable sort, so the reversed registration order holds within a phase
+        hooks.reverse();
+        hooks.sort_by_key(|hook| hook.phase);
+
+        let started = Instant::now();
+        let count = hooks.len();
+        let mut failed = 0;
+        for hook in hooks {
+            let span = tracing::info_span!(
+                "shutdown.hook",
+                otel.name = format!("shutdown {}", hook.name),
+                shutdown.hook = hook.name,
+                shutdown.phase = hook.phase.as_str(),
+            );
+            let hook_started = Instant::now();
+            let outcome = tokio::time::timeout(hook.timeout, (hook.run)())
+                .instrument(span.clone())
+                .await;
+            let duration_ms = hook_started.elapsed().as_millis() as u64;
+
+            let _entered = span.enter();
+            match outcome {
+                Ok(Ok(())) => info_trace!(
+                    shutdown.hook = hook.name,
+                    shutdown.phase = hook.phase.as_str(),
+                    duration_ms = duration_ms,
+                    "Shutdown hook finished"
+                ),
+                Ok(Err(e)) => {
+                    failed += 1;
+                    warn_trace!(
+                        shutdown.hook = hook.name,
+                        shutdown.phase = hook.phase.as_str(),
+                        duration_ms = duration_ms,
+                        error = %e,
+                        "Shutdown hook failed"
+                    );
+                }
+                Err(_) => {
+                    failed += 1;
+                    warn_trace!(
+                        shutdown.hook = hook.name,
+                        shutdown.phase = hook.phase.as_str(),
+                        timeout_ms = hook.timeout.as_millis() as u64,
+                        "Shutdown hook timed out"
+                    );
+                }
+            }
+        }
+        info_trace!(
+            hooks = count,
+            failed = failed,
+            duration_ms = started.elapsed().as_millis() as u64,
+            "Shutdown hooks complete"
+        );
+    }
+}
@@ -1,114 +1,367 @@
-use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Json},
-    routing::{get, post},
-    Router,
+use clap::Parser;
+use rust_datadog_otel::{
+    datadog_events, dogstatsd, error_trace, info_trace, protocol, runtime_metrics, span_tap, telemetry,
 };
-use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Duration;
-use tower_http::cors::CorsLayer;
-use tracing::instrument;
 
-mod telemetry;
-mod trace_context;
+mod admin_api;
+mod analytics;
+mod auth;
+mod auth_api;
+mod cache;
+mod cli;
+mod compute;
+mod concurrency;
+mod config;
+mod cost;
+mod csrf;
+mod decompression;
+mod demo_api;
+mod dependency_health;
+mod disconnect;
+mod distributed_lock;
+mod email;
+mod events;
+mod experiments;
+mod export;
+mod feature_flags;
+mod frontend;
+mod grpc;
+mod health;
+mod ids;
+mod ip_filter;
+mod job_tracker;
+mod jobs;
+mod lifecycle;
+mod messaging;
+mod money;
+mod multi_service;
+mod ndjson;
+mod negotiation;
+mod object_store;
+mod openapi;
+mod order_api;
+mod orders;
+mod pagination;
+mod pii;
+mod postgres;
+mod priority;
+mod queue_time;
+mod rate_limit;
+mod region;
+mod report;
+mod reports;
+mod repository;
+mod request_context;
+mod routes;
+mod rum;
+mod schema_check;
+mod search;
+mod secrets;
+mod security;
+mod server;
+mod service_api;
+mod session;
+mod sql;
+mod sqlite;
+mod static_assets;
+mod user_api;
+mod versioning;
+
 
 // Application state
 #[derive(Debug, Clone)]
 struct AppState {
     version: String,
+    users: Arc<repository::UserRepository>,
+    orders: Arc<orders::OrderRepository>,
+    cursors: Arc<pagination::CursorCodec>,
+    reports: Arc<reports::Reports>,
+    objects: Arc<object_store::ObjectStorage>,
+    protocols: Arc<protocol::ProtocolStats>,
+    queue_times: Arc<queue_time::QueueTimeStats>,
+    regions: Arc<region::Regions>,
+    priorities: Arc<priority::PriorityClasses>,
+    costs: Arc<cost::CostStats>,
+    disconnects: Arc<disconnect::DisconnectStats>,
+    rate_limiter: Arc<rate_limit::RateLimiter>,
+    secrets: Arc<secrets::Secrets>,
+    health: health::Health,
+    span_tap: span_tap::SpanTap,
+    rum: rum::RumConfig,
+    experiments: Arc<experiments::Experiments>,
+    scheduler: Arc<jobs::Scheduler>,
+    job_tracker: Arc<job_tracker::JobTracker>,
+    sessions: Arc<session::SessionManager>,
+    auth: Arc<auth::Auth>,
+    notifier: Arc<email::Notifier>,
+    events: Arc<events::EventLog>,
+    messaging: Option<Arc<messaging::Messaging>>,
+    concurrency: Arc<concurrency::ConcurrencyStats>,
+    search: Arc<search::Search>,
+    cache: cache::Cache,
+    config: Arc<config::ServiceConfig>,
 }
 
-// API Models
-#[derive(Debug, Serialize, Deserialize)]
-struct HealthResponse {
-    status: String,
-    version: String,
-    timestamp: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct User {
-    id: String,
-    name: String,
-    email: String,
-    created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct CreateUserRequest {
-    name: String,
-    email: String,
-}
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = cli::Cli::parse();
+
+    // Tools run and exit: schema compatibility, the config JSON Schema, config linting, and the two-service demo
+    match &cli.command {
+        Some(cli::Command::CheckSchemas { update }) => std::process::exit(schema_check::run(*update)),
+        Some(cli::Command::ConfigSchema) => std::process::exit(config::run_schema()),
+        Some(cli::Command::ValidateConfig { file }) => std::process::exit(config::run_validate(file)),
+        Some(cli::Command::Demo { port, backend_port }) => std::process::exit(multi_service::run(&cli, *port, *backend_port).await?),
+        None => {}
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OrderRequest {
-    user_id: String,
-    items: Vec<OrderItem>,
-}
+    let mut service_config = config::ServiceConfig::load(cli.config.as_deref())?;
+    cli.apply(&mut service_config);
+    let service_config = Arc::new(service_config);
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OrderItem {
-    product_id: String,
-    quantity: u32,
-    price: f64,
-}
+    // Initialize OpenTelemetry and tracing
+    let span_tap = span_tap::SpanTap::new();
+    let mut telemetry = telemetry::TelemetryBuilder::new()
+        .span_tap(span_tap.clone())
+        .log_level(&service_config.log_level);
+    if let Some(rate) = service_config.sample_rate {
+        telemetry = telemetry.sample_rate(rate);
+    }
+    if let Some(rules) = &service_config.route_sample_rates {
+        telemetry = telemetry.route_sample_rates(rules);
+    }
+    if let Some(agent_host) = &cli.agent_host {
+        telemetry = telemetry.agent_host(agent_host);
+    }
+    let tracer_provider = telemetry.init()?;
+    dogstatsd::install(dogstatsd::Client::from_env()?);
+    datadog_events::install(datadog_events::EventClient::from_env()?);
+    runtime_metrics::RuntimeReporter::from_env()?.start();
+
+    // Subsystems register their shutdown here, to run once the server has drained
+    let lifecycle = lifecycle::Lifecycle::default();
+    lifecycle.on_shutdown("telemetry", lifecycle::Phase::Telemetry, Duration::from_secs(5), || async move {
+        // Flushing pending spans to the Agent blocks
+        tokio::task::spawn_blocking(move || telemetry::shutdown_telemetry(tracer_provider)).await??;
+        Ok(())
+    });
+
+    if service_config.role == config::Role::Frontend {
+        let result = frontend::serve(&service_config).await;
+        lifecycle.shutdown().await;
+        return result;
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OrderResponse {
-    order_id: String,
-    user_id: String,
-    total_amount: f64,
-    status: String,
-    created_at: String,
-}
+    info_trace!("Starting Rust Datadog OpenTelemetry Demo Application");
+    if let Some(path) = &service_config.path {
+        info_trace!(path = %path.display(), "Service config loaded");
+    }
 
-#[derive(Debug, Deserialize)]
-struct ErrorSimulationQuery {
-    #[serde(default)]
-    error_type: String,
-}
+    let secrets = Arc::new(secrets::Secrets::from_env());
+    let cipher = pii::FieldCipher::from_provider(&pii::SecretsKeyProvider::new(&secrets))?;
+    info_trace!(key_id = %cipher.active_key_id(), "PII field encryption enabled");
+
+    // Swap `FlagStore` for another OpenFeature provider to change flag backends
+    let flag_store = feature_flags::FlagStore::from_env()?;
+    flag_store.spawn_polling();
+    let flags = Arc::new(feature_flags::FeatureFlags::with_provider(flag_store).await);
+
+    // Postgres keeps users and orders across restarts and replicas, SQLite across restarts; without either they are process-local
+    let (user_store, order_store): (Arc<dyn repository::Repository>, Arc<dyn orders::OrderStore>) =
+        match secrets.get("DATABASE_URL")? {
+            Some(url) if sqlite::SqliteRepository::handles(url.expose_secret()) => {
+                let database = Arc::new(sqlite::SqliteRepository::connect(url.expose_secret()).await?);
+                (database.clone(), database)
+            }
+            Some(url) => {
+                let database = Arc::new(postgres::PostgresRepository::connect(url.expose_secret()).await?);
+                (database.clone(), database)
+            }
+            None => (
+                Arc::new(repository::MemoryRepository::default()),
+                Arc::new(orders::MemoryOrderStore::default()),
+            ),
+        };
+    let users = Arc::new(repository::UserRepository::new(cipher, user_store));
+    let orders = Arc::new(orders::OrderRepository::new(order_store));
+    info_trace!(backend = users.backend(), "User storage configured");
+    info_trace!(backend = orders.backend(), "Order storage configured");
+
+    // Redis shares locks, sessions and rate limits between replicas; without it they are process-local
+    let redis = match std::env::var("REDIS_URL") {
+        Ok(url) => Some(redis::aio::ConnectionManager::new(redis::Client::open(url)?).await?),
+        Err(_) => None,
+    };
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize OpenTelemetry and tracing
-    // Store the tracer provider to shutdown properly on exit
-    let tracer_provider = telemetry::init_telemetry()?;
+    let cache_store = redis
+        .clone()
+        .map(|connection| Arc::new(cache::RedisCacheStore::new(connection)) as Arc<dyn cache::CacheStore>);
+    let cache = cache::Cache::from_env(cache_store)?;
+
+    let (lock_store, session_store, rate_limit_store): (
+        Arc<dyn distributed_lock::LockStore>,
+        Arc<dyn session::SessionStore>,
+        Arc<dyn rate_limit::RateLimitStore>,
+    ) = match redis {
+        Some(connection) => (
+            Arc::new(distributed_lock::RedisLockStore::new(connection.clone())),
+            Arc::new(session::RedisSessionStore::new(connection.clone())),
+            Arc::new(rate_limit::RedisRateLimitStore::new(connection)),
+        ),
+        None => (
+            Arc::new(distributed_lock::InMemoryLockStore::default()),
+            Arc::new(session::MemorySessionStore::default()),
+            Arc::new(rate_limit::MemoryRateLimitStore::default()),
+        ),
+    };
+    let rate_limiter = Arc::new(rate_limit::RateLimiter::from_env(rate_limit_store)?);
+    info_trace!(store = rate_limiter.backend(), "Rate limiting configured");
+    let sessions = Arc::new(session::SessionManager::from_env(session_store, &secrets)?);
+    let auth = Arc::new(auth::Auth::from_env(&secrets)?);
+    let admin_account = auth::AdminAccount::from_env(&secrets)?;
+    let notifier = Arc::new(email::Notifier::from_env()?);
+    info_trace!(provider = notifier.provider(), "Email provider configured");
+
+    let search = Arc::new(search::Search::from_env(&secrets).await?);
+    info_trace!(backend = search.backend(), "Search backend configured");
+
+    // Order events go to Kafka or RabbitMQ when one is configured, and are consumed back in the background
+    let messaging = messaging::Messaging::from_env().await?.map(Arc::new);
+    if let Some(messaging) = &messaging {
+        messaging.spawn_consumer().await?;
+    }
+    let cursors = Arc::new(pagination::CursorCodec::from_env(&secrets)?);
+    let objects = Arc::new(object_store::ObjectStorage::from_env()?);
+    info_trace!(backend = objects.backend(), "Object storage configured");
+    let reports = Arc::new(reports::Reports::from_env(orders.clone(), objects.clone()));
+
+    // Scheduled jobs run on one replica at a time
+    let scheduler = Arc::new(
+        jobs::Scheduler::new(lock_store)?
+            .add_job("users.snapshot", Duration::from_secs(60), {
+                let users = users.clone();
+                move || {
+                    let users = users.clone();
+                    Box::pin(async move {
+                        let user_count = users.count().await;
+                        info_trace!(user_count, "User snapshot");
+                    })
+                }
+            })
+            .add_job("reports.orders_summary", reports.interval(), {
+                let reports = reports.clone();
+                move || {
+                    let reports = reports.clone();
+                    Box::pin(async move {
+                        if let Err(e) = reports.generate().await {
+                            error_trace!(error = %e, "Report generation failed");
+                        }
+                    })
+                }
+            }),
+    );
+    scheduler.start();
+    info_trace!(owner = %scheduler.owner(), lock_backend = scheduler.backend(), "Job scheduler started");
+    lifecycle.on_shutdown("jobs.scheduler", lifecycle::Phase::Intake, Duration::from_secs(2), {
+        let scheduler = scheduler.clone();
+        || async move {
+            scheduler.step_down().await;
+            Ok(())
+        }
+    });
+
+    let job_tracker = Arc::new(job_tracker::JobTracker::default());
+    lifecycle.on_shutdown("jobs.background", lifecycle::Phase::Workers, Duration::from_secs(10), {
+        let job_tracker = job_tracker.clone();
+        || async move {
+            job_tracker.wait_idle().await;
+            Ok(())
+        }
+    });
 
-    info_trace!("Starting Rust Datadog OpenTelemetry Demo Application");
+    let health = health::Health::serving();
 
-    let state = AppState {
+    let state = Arc::new(AppState {
         version: env!("CARGO_PKG_VERSION").to_string(),
-    };
+        users,
+        orders,
+        cursors,
+        reports,
+        objects,
+        protocols: Arc::new(protocol::ProtocolStats::default()),
+        queue_times: Arc::new(queue_time::QueueTimeStats::default()),
+        regions: Arc::new(region::Regions::from_env(service_config.region.clone())?),
+        priorities: Arc::new(priority::PriorityClasses::from_env()?),
+        costs: Arc::new(cost::CostStats::default()),
+        disconnects: Arc::new(disconnect::DisconnectStats::default()),
+        rate_limiter: rate_limiter.clone(),
+        secrets,
+        health: health.clone(),
+        span_tap,
+        rum: rum::RumConfig::from_env(),
+        experiments: Arc::new(experiments::Experiments::from_env(flags.clone())?),
+        scheduler: scheduler.clone(),
+        job_tracker,
+        sessions: sessions.clone(),
+        auth: auth.clone(),
+        notifier,
+        events: Arc::new(events::EventLog::default()),
+        messaging,
+        concurrency: Arc::new(concurrency::ConcurrencyStats::default()),
+        search,
+        cache,
+        config: service_config.clone(),
+    });
+    if let Some(account) = admin_account {
+        user_api::seed_admin(&state, account).await?;
+    }
+    info_trace!(rum_enabled = state.rum.enabled(), "Demo page available at /demo");
+    info_trace!(region = state.regions.home(), "Simulated region latency enabled");
 
-    // Build application with routes
-    let app = Router::new()
-        .route("/", get(root))
-        .route("/health", get(health))
-        .route("/api/users", post(create_user))
-        .route("/api/users/:id", get(get_user))
-        .route("/api/orders", post(create_order))
-        .route("/api/orders/:id", get(get_order))
-        .route("/api/simulate-error", get(simulate_error))
-        .route("/api/slow-operation", get(slow_operation))
-        .route("/api/database-query", get(database_query))
-        .layer(CorsLayer::permissive())
-        .with_state(Arc::new(state));
+    let app = routes::app(state.clone(), flags.clone())?;
 
     // Start server
-    let addr = "0.0.0.0:8080";
+    let addr = service_config.bind_address;
     info_trace!("Server listening on {}", addr);
     
     let listener = tokio::net::TcpListener::bind(addr).await?;
+
+    health::spawn_probes(health::ProbeConfig::from_env()?, addr.ip(), health.clone()).await?;
+    // The same user and order operations over gRPC, when a port is configured
+    let grpc_server = grpc::spawn(state.clone(), addr.ip(), shutdown_signal())?;
+
+    // Deploy marker for dashboards, linked to a startup trace
+    tracing::info_span!("service.startup").in_scope(|| {
+        let version = std::env::var("DD_VERSION").unwrap_or_else(|_| env!("CARGO_PKG_VERSION").to_string());
+        datadog_events::emit(
+            datadog_events::Event::new(
+                format!("rust-datadog-otel deployed v{}", version),
+                format!("Listening on {}", addr),
+                datadog_events::AlertType::Success,
+            )
+            .aggregation_key("deploy"),
+        );
+    });
     
     // Run server with graceful shutdown
-    let result = axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await;
+    let result = server::serve(
+        listener,
+        app,
+        server::ServerConfig::from_env(),
+        async {
+            shutdown_signal().await;
+            // Probes report not serving while open connections drain
+            health.set_serving(false);
+        },
+    )
+    .await;
+    if let Some(grpc_server) = grpc_server {
+        let _ = grpc_server.await;
+    }
 
-    // Shutdown telemetry to flush remaining spans
-    telemetry::shutdown_telemetry(tracer_provider);
+    lifecycle.shutdown().await;
 
     result?;
     Ok(())
@@ -122,286 +375,78 @@ async fn shutdown_signal() {
     info_trace!("Shutdown signal received, shutting down gracefully...");
 }
 
-#[instrument]
-async fn root() -> impl IntoResponse {
-    info_trace!("Root endpoint called");
-    Json(serde_json::json!({
-        "message": "Rust Datadog OpenTelemetry Demo API",
-        "version": env!("CARGO_PKG_VERSION"),
-        "endpoints": [
-            "GET /health",
-            "POST /api/users",
-            "GET /api/users/:id",
-            "POST /api/orders",
-            "GET /api/orders/:id",
-            "GET /api/simulate-error?error_type=<type>",
-            "GET /api/slow-operation",
-            "GET /api/database-query"
-        ]
-    }))
-}
-
-#[instrument]
-async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    info_trace!("Health check called");
-    
-    Json(HealthResponse {
-        status: "healthy".to_string(),
-        version: state.version.clone(),
-        timestamp: chrono::Utc::now().to_rfc3339(),
-    })
-}
-
-#[instrument(skip(_state))]
-async fn create_user(
-    State(_state): State<Arc<AppState>>,
-    Json(payload): Json<CreateUserRequest>,
-) -> impl IntoResponse {
-    info_trace!(
-        user_name = %payload.name,
-        user_email = %payload.email,
-        "Creating new user"
-    );
-
-    // Simulate validation
-    if payload.name.is_empty() {
-        warn_trace!("User creation failed: empty name");
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": "Name cannot be empty"})),
-        ).into_response();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ids::{OrderId, ProductId, UserId};
+
+    /// The full HTTP app over in-memory stores, and its state for seeding data and issuing tokens
+    pub(crate) async fn test_app() -> (Arc<AppState>, axum::Router) {
+        let config = Arc::new(config::ServiceConfig::load(None).unwrap());
+        let secrets = Arc::new(secrets::Secrets::from_env());
+        let cipher = pii::FieldCipher::from_provider(&pii::SecretsKeyProvider::new(&secrets)).unwrap();
+        let flags = Arc::new(feature_flags::FeatureFlags::with_provider(feature_flags::FlagStore::from_env().unwrap()).await);
+        let orders = Arc::new(orders::OrderRepository::new(Arc::new(orders::MemoryOrderStore::default())));
+        let objects = Arc::new(object_store::ObjectStorage::from_env().unwrap());
+        let state = Arc::new(AppState {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            users: Arc::new(repository::UserRepository::new(cipher, Arc::new(repository::MemoryRepository::default()))),
+            cursors: Arc::new(pagination::CursorCodec::from_env(&secrets).unwrap()),
+            reports: Arc::new(reports::Reports::from_env(orders.clone(), objects.clone())),
+            orders,
+            objects,
+            protocols: Arc::new(protocol::ProtocolStats::default()),
+            queue_times: Arc::new(queue_time::QueueTimeStats::default()),
+            regions: Arc::new(region::Regions::from_env(config.region.clone()).unwrap()),
+            priorities: Arc::new(priority::PriorityClasses::from_env().unwrap()),
+            costs: Arc::new(cost::CostStats::default()),
+            disconnects: Arc::new(disconnect::DisconnectStats::default()),
+            rate_limiter: Arc::new(
+                rate_limit::RateLimiter::from_env(Arc::new(rate_limit::MemoryRateLimitStore::default())).unwrap(),
+            ),
+            health: health::Health::serving(),
+            span_tap: span_tap::SpanTap::new(),
+            rum: rum::RumConfig::from_env(),
+            experiments: Arc::new(experiments::Experiments::from_env(flags.clone()).unwrap()),
+            scheduler: Arc::new(
+                jobs::Scheduler::new(Arc::new(distributed_lock::InMemoryLockStore::default())).unwrap(),
+            ),
+            job_tracker: Arc::new(job_tracker::JobTracker::default()),
+            sessions: Arc::new(
+                session::SessionManager::from_env(Arc::new(session::MemorySessionStore::default()), &secrets).unwrap(),
+            ),
+            auth: Arc::new(auth::Auth::from_env(&secrets).unwrap()),
+            notifier: Arc::new(email::Notifier::from_env().unwrap()),
+            events: Arc::new(events::EventLog::default()),
+            messaging: None,
+            concurrency: Arc::new(concurrency::ConcurrencyStats::default()),
+            search: Arc::new(search::Search::from_env(&secrets).await.unwrap()),
+            cache: cache::Cache::from_env(None).unwrap(),
+            secrets,
+            config,
+        });
+        let app = routes::app(state.clone(), flags).unwrap();
+        (state, app)
     }
 
-    // Simulate user creation
-    let user = User {
-        id: uuid::Uuid::new_v4().to_string(),
-        name: payload.name,
-        email: payload.email,
-        created_at: chrono::Utc::now().to_rfc3339(),
-    };
-
-    info_trace!(user_id = %user.id, "User created successfully");
-    
-    (StatusCode::CREATED, Json(user)).into_response()
-}
-
-#[instrument]
-async fn get_user(Path(id): Path<String>) -> impl IntoResponse {
-    info_trace!(user_id = %id, "Fetching user");
-
-    // Simulate database lookup with nested span
-    let user = fetch_user_from_database(&id).await;
-
-    match user {
-        Some(user) => {
-            debug_trace!(user_id = %id, "User found");
-            (StatusCode::OK, Json(user)).into_response()
-        }
-        None => {
-            warn_trace!(user_id = %id, "User not found");
-            (
-                StatusCode::NOT_FOUND,
-                Json(serde_json::json!({"error": "User not found"})),
-            )
-                .into_response()
-        }
+    /// Store a confirmed order for `user_id`, returning its ID
+    pub(crate) async fn seed_order(state: &AppState, user_id: UserId) -> OrderId {
+        let order_id = OrderId::generate();
+        let record = orders::OrderRecord {
+            order_id,
+            user_id,
+            lines: vec![orders::OrderLine {
+                product_id: ProductId::try_from("prod-001".to_string()).unwrap(),
+                quantity: 1,
+                price: rust_decimal::Decimal::TEN,
+            }],
+            total_amount: rust_decimal::Decimal::TEN,
+            currency: money::Currency::Usd,
+            status: "confirmed".to_string(),
+            created_at: chrono::Utc::now(),
+            version: 1,
+        };
+        state.orders.insert(record).await.unwrap();
+        order_id
     }
 }
-
-#[instrument]
-async fn fetch_user_from_database(id: &str) -> Option<User> {
-    // Simulate database query delay
-    tokio::time::sleep(Duration::from_millis(50)).await;
-    
-    debug_trace!(user_id = %id, "Querying database for user");
-
-    // Mock user data
-    Some(User {
-        id: id.to_string(),
-        name: "John Doe".to_string(),
-        email: "john.doe@example.com".to_string(),
-        created_at: chrono::Utc::now().to_rfc3339(),
-    })
-}
-
-#[instrument(skip(_state))]
-async fn create_order(
-    State(_state): State<Arc<AppState>>,
-    Json(payload): Json<OrderRequest>,
-) -> impl IntoResponse {
-    info_trace!(
-        user_id = %payload.user_id,
-        item_count = payload.items.len(),
-        "Creating new order"
-    );
-
-    // Validate order
-    if payload.items.is_empty() {
-        warn_trace!("Order creation failed: no items");
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": "Order must contain at least one item"})),
-        ).into_response();
-    }
-
-    // Calculate total
-    let total_amount: f64 = payload
-        .items
-        .iter()
-        .map(|item| item.price * item.quantity as f64)
-        .sum();
-
-    // Simulate payment processing
-    process_payment(&payload.user_id, total_amount).await;
-
-    // Simulate inventory check
-    check_inventory(&payload.items).await;
-
-    let order = OrderResponse {
-        order_id: uuid::Uuid::new_v4().to_string(),
-        user_id: payload.user_id,
-        total_amount,
-        status: "confirmed".to_string(),
-        created_at: chrono::Utc::now().to_rfc3339(),
-    };
-
-    info_trace!(order_id = %order.order_id, total_amount = %total_amount, "Order created successfully");
-
-    (StatusCode::CREATED, Json(order)).into_response()
-}
-
-#[instrument]
-async fn process_payment(user_id: &str, amount: f64) {
-    info_trace!(user_id = %user_id, amount = %amount, "Processing payment");
-    
-    // Simulate payment gateway call
-    tokio::time::sleep(Duration::from_millis(100)).await;
-    
-    debug_trace!("Payment processed successfully");
-}
-
-#[instrument]
-async fn check_inventory(items: &[OrderItem]) {
-    info_trace!(item_count = items.len(), "Checking inventory");
-    
-    // Simulate inventory check
-    tokio::time::sleep(Duration::from_millis(75)).await;
-    
-    debug_trace!("Inventory check completed");
-}
-
-#[instrument]
-async fn get_order(Path(id): Path<String>) -> impl IntoResponse {
-    info_trace!(order_id = %id, "Fetching order");
-
-    // Simulate database lookup
-    tokio::time::sleep(Duration::from_millis(50)).await;
-
-    let order = OrderResponse {
-        order_id: id.clone(),
-        user_id: "user-123".to_string(),
-        total_amount: 99.99,
-        status: "shipped".to_string(),
-        created_at: chrono::Utc::now().to_rfc3339(),
-    };
-
-    debug_trace!(order_id = %id, "Order found");
-    Json(order)
-}
-
-#[instrument]
-async fn simulate_error(Query(params): Query<ErrorSimulationQuery>) -> impl IntoResponse {
-    let error_type = if params.error_type.is_empty() {
-        "generic"
-    } else {
-        &params.error_type
-    };
-
-    error_trace!(error_type = %error_type, "Simulating error");
-
-    match error_type {
-        "timeout" => {
-            warn_trace!("Simulating timeout error");
-            tokio::time::sleep(Duration::from_secs(30)).await;
-            (
-                StatusCode::REQUEST_TIMEOUT,
-                Json(serde_json::json!({"error": "Request timeout"})),
-            )
-        }
-        "server" => {
-            error_trace!("Simulating internal server error");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": "Internal server error"})),
-            )
-        }
-        "database" => {
-            error_trace!("Simulating database connection error");
-            (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(serde_json::json!({"error": "Database connection failed"})),
-            )
-        }
-        _ => {
-            error_trace!("Simulating generic error");
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": "Bad request"})),
-            )
-        }
-    }
-}
-
-#[instrument]
-async fn slow_operation() -> impl IntoResponse {
-    info_trace!("Starting slow operation");
-
-    // Simulate multiple slow steps
-    for i in 1..=5 {
-        debug_trace!(step = i, "Processing step");
-        tokio::time::sleep(Duration::from_millis(200)).await;
-    }
-
-    info_trace!("Slow operation completed");
-
-    Json(serde_json::json!({
-        "message": "Slow operation completed",
-        "duration_ms": 1000
-    }))
-}
-
-#[instrument]
-async fn database_query() -> impl IntoResponse {
-    info_trace!("Executing database query");
-
-    // Simulate complex database query with multiple operations
-    query_users_table().await;
-    query_orders_table().await;
-    join_user_orders().await;
-
-    info_trace!("Database query completed");
-
-    Json(serde_json::json!({
-        "message": "Database query completed",
-        "results": 42
-    }))
-}
-
-#[instrument]
-async fn query_users_table() {
-    debug_trace!("Querying users table");
-    tokio::time::sleep(Duration::from_millis(80)).await;
-}
-
-#[instrument]
-async fn query_orders_table() {
-    debug_trace!("Querying orders table");
-    tokio::time::sleep(Duration::from_millis(120)).await;
-}
-
-#[instrument]
-async fn join_user_orders() {
-    debug_trace!("Joining user and order data");
-    tokio::time::sleep(Duration::from_millis(150)).await;
-}
-
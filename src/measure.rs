@@ -0,0 +1,88 @@
+use crate::dogstatsd;
+use std::time::Instant;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Wrap a block in a child span and time it as a histogram metric
+///
+/// The span is named after the first argument, and its duration is sent
+/// through [`dogstatsd`] as `<name>.duration_ms`. Tags given as
+/// `"key" = value` become both span attributes and metric tags, so the
+/// trace and the dashboard can be filtered the same way. Blocks may be
+/// plain or `async`; an `async` one yields a future to await:
+///
+/// ```
+/// use rust_datadog_otel::measure;
+///
+/// # async fn example() {
+/// let total = measure!("cart.total", "currency" = "usd", { 2 + 2 });
+/// measure!("cart.refresh", async { tokio::task::yield_now().await }).await;
+/// # }
+/// ```
+#[macro_export]
+macro_rules! measure {
+    ($name:literal $(, $key:literal = $value:expr)*, async $body:block) => {{
+        let span = $crate::__tracing::info_span!($name);
+        let tags = vec![$(($key, $value.to_string())),*];
+        let future = async $body;
+        $crate::__tracing::Instrument::instrument(
+            async move {
+                let _measurement = $crate::measure::Measurement::start(&$crate::__tracing::Span::current(), $name, tags);
+                future.await
+            },
+            span,
+        )
+    }};
+    ($name:literal $(, $key:literal = $value:expr)*, $body:block) => {{
+        let span = $crate::__tracing::info_span!($name);
+        let _entered = span.enter();
+        let _measurement = $crate::measure::Measurement::start(&span, $name, vec![$(($key, $value.to_string())),*]);
+        $body
+    }};
+}
+
+/// A running [`measure!`] block; sends its duration when dropped
+#[derive(Debug)]
+pub struct Measurement {
+    metric: String,
+    tags: Vec<String>,
+    started: Instant,
+}
+
+impl Measurement {
+    /// Tag `span` and start timing
+    pub fn start(span: &Span, name: &str, tags: Vec<(&'static str, String)>) -> Self {
+        for (key, value) in &tags {
+            span.set_attribute(*key, value.clone());
+        }
+        Self {
+            metric: format!("{}.duration_ms", name),
+            tags: tags.iter().map(|(key, value)| format!("{}:{}", key, value)).collect(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Measurement {
+    fn drop(&mut self) {
+        let elapsed_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        dogstatsd::histogram(&self.metric, elapsed_ms, &self.tags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn measures_plain_and_async_blocks() {
+        let step = 3;
+        let value = crate::measure!("work.sync", "step" = step, { step * 2 });
+        assert_eq!(value, 6);
+
+        let borrowed = String::from("kept");
+        let length = crate::measure!("work.async", "kind" = "test", async { borrowed.len() }).await;
+        assert_eq!((length, borrowed.as_str()), (4, "kept"));
+
+        let measurement = super::Measurement::start(&tracing::Span::none(), "work", vec![("step", step.to_string())]);
+        assert_eq!((measurement.metric.as_str(), measurement.tags.as_slice()), ("work.duration_ms", &["step:3".to_string()][..]));
+    }
+}
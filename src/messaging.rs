@@ -0,0 +1,449 @@
+use crate::events::EventEnvelope;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use futures_util::StreamExt;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+};
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind};
+use opentelemetry::KeyValue;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{Header, Headers, Message, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rust_datadog_otel::{dogstatsd, error_trace, info_trace, propagation, telemetry, warn_trace};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub type BrokerError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_DESTINATION: &str = "domain-events";
+
+/// Longest wait for room in the Kafka producer queue before giving up on a message
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An event on its way to the broker
+#[derive(Debug)]
+pub struct Outgoing<'a> {
+    pub event_type: &'static str,
+    /// The entity the event is about; messages with one key stay in order
+    pub key: &'a str,
+    pub message_id: String,
+    pub payload: Vec<u8>,
+    /// Trace context of the producer span
+    pub headers: HeaderMap,
+}
+
+/// A message broker that carries domain events
+#[async_trait::async_trait]
+pub trait Broker: Send + Sync + Debug {
+    /// `messaging.system` of its spans
+    fn system(&self) -> &'static str;
+
+    /// The topic or exchange events are sent to
+    fn destination(&self) -> &str;
+
+    /// Send `message`, returning broker-specific span attributes such as its offset
+    async fn send(&self, message: Outgoing<'_>) -> Result<Vec<KeyValue>, BrokerError>;
+
+    /// Consume events in the background, passing each to [`handle`], until the process exits
+    async fn spawn_consumer(&self) -> Result<(), BrokerError>;
+}
+
+/// Domain events over Kafka or RabbitMQ, with the producing trace carried in message headers
+///
+/// [`Messaging::publish`] sends an [`EventEnvelope`] from a `<system>.produce`
+/// producer span (`kafka.produce`, `rabbitmq.produce`), writing the span's
+/// context into the message headers in the formats of
+/// `DD_TRACE_PROPAGATION_STYLE_INJECT`. A background consumer reads the
+/// events back and handles each in a `<system>.consume` span whose parent is
+/// extracted from those headers, so the consumer shows up in the trace of
+/// the HTTP request that produced the event. Both spans carry
+/// `messaging.system`, `messaging.destination.name` and
+/// `messaging.message.id`; consumed messages count `messaging.consumed`.
+///
+/// Configuration, with at most one broker:
+/// - `KAFKA_BROKERS`: Kafka bootstrap servers, comma-separated
+/// - `KAFKA_TOPIC`: topic for domain events (default `domain-events`)
+/// - `KAFKA_CONSUMER_GROUP`: consumer group of the background consumer (default `<service>-events`)
+/// - `AMQP_URL`: RabbitMQ connection URL
+/// - `AMQP_EXCHANGE`: topic exchange for domain events, routed by event type (default `domain-events`)
+/// - `AMQP_QUEUE`: queue of the background consumer (default `<service>-events`)
+#[derive(Debug, Clone)]
+pub struct Messaging {
+    broker: Arc<dyn Broker>,
+}
+
+impl Messaging {
+    /// The configured broker, or `None` when neither `KAFKA_BROKERS` nor `AMQP_URL` is set
+    pub async fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let broker: Arc<dyn Broker> = match (std::env::var("KAFKA_BROKERS"), std::env::var("AMQP_URL")) {
+            (Ok(_), Ok(_)) => return Err("KAFKA_BROKERS and AMQP_URL are both set; pick one broker".into()),
+            (Ok(brokers), Err(_)) => Arc::new(KafkaBroker::new(brokers)?),
+            (Err(_), Ok(url)) => Arc::new(AmqpBroker::connect(&url).await?),
+            (Err(_), Err(_)) => return Ok(None),
+        };
+        info_trace!(messaging.system = broker.system(), destination = %broker.destination(), "Messaging configured");
+        Ok(Some(Self { broker }))
+    }
+
+    pub async fn spawn_consumer(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.broker.spawn_consumer().await.map_err(|e| -> Box<dyn std::error::Error> { e })
+    }
+
+    /// Send `envelope`, keyed by the entity it's about so its events stay in order
+    ///
+    /// Waits for the broker to acknowledge. Failures are logged rather than
+    /// returned: the event is already in the in-process log.
+    pub async fn publish(&self, envelope: &EventEnvelope) {
+        let system = self.broker.system();
+        let event_type = envelope.event.event_type();
+        let span = messaging_span(system, "publish", self.broker.destination(), event_type);
+        span.set_attribute("messaging.message.id", envelope.event_id.to_string());
+        let payload = match serde_json::to_vec(envelope) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error_trace!(event.id = %envelope.event_id, error = %e, "Failed to serialize event for messaging");
+                return;
+            }
+        };
+
+        let mut headers = HeaderMap::new();
+        span.in_scope(|| propagation::inject_current(&mut headers));
+        let key = envelope.event.partition_key();
+        let message = Outgoing {
+            event_type,
+            key: &key,
+            message_id: envelope.event_id.to_string(),
+            payload,
+            headers,
+        };
+        match self.broker.send(message).instrument(span.clone()).await {
+            Ok(attributes) => {
+                for attribute in attributes {
+                    span.set_attribute(attribute.key, attribute.value);
+                }
+            }
+            Err(e) => {
+                span.set_status(opentelemetry::trace::Status::error(e.to_string()));
+                span.in_scope(|| warn_trace!(event.id = %envelope.event_id, messaging.system = system, error = %e, "Failed to send event"));
+            }
+        }
+    }
+}
+
+fn messaging_span(system: &'static str, operation: &'static str, destination: &str, event_type: &str) -> Span {
+    let (suffix, kind) = match operation {
+        "publish" => ("produce", "producer"),
+        _ => ("consume", "consumer"),
+    };
+    tracing::info_span!(
+        "messaging",
+        otel.name = %format!("{}.{}", system, suffix),
+        otel.kind = kind,
+        resource.name = %format!("{} {}", operation, destination),
+        span.type = "queue",
+        messaging.system = system,
+        messaging.operation = operation,
+        messaging.destination.name = %destination,
+        peer.service = system,
+        "event.type" = %event_type,
+    )
+}
+
+/// Process one consumed event as a child of the span that produced it
+///
+/// `attributes` are the broker's own, such as the message's partition and offset.
+fn handle(system: &'static str, destination: &str, payload: Option<&[u8]>, headers: &HeaderMap, attributes: Vec<KeyValue>) {
+    let envelope = payload.map(serde_json::from_slice::<EventEnvelope>);
+    let event_type = match &envelope {
+        Some(Ok(envelope)) => envelope.event.event_type(),
+        _ => "unknown",
+    };
+    let span = messaging_span(system, "process", destination, event_type);
+    if let Some(parent) = propagation::extract(headers) {
+        let _ = span.set_parent(parent);
+    }
+    for attribute in attributes {
+        span.set_attribute(attribute.key, attribute.value);
+    }
+
+    let _entered = span.enter();
+    match envelope {
+        Some(Ok(envelope)) => {
+            span.set_attribute("messaging.message.id", envelope.event_id.to_string());
+            info_trace!(event.id = %envelope.event_id, "event.type" = event_type, "Domain event consumed");
+            let tags = [format!("event_type:{}", event_type), format!("messaging_system:{}", system)];
+            dogstatsd::count("messaging.consumed", 1, &tags);
+        }
+        Some(Err(e)) => {
+            span.set_status(opentelemetry::trace::Status::error(e.to_string()));
+            warn_trace!(messaging.system = system, error = %e, "Skipping unreadable message");
+        }
+        None => warn_trace!(messaging.system = system, "Skipping empty message"),
+    }
+}
+
+fn default_consumer_name() -> String {
+    format!("{}-events", telemetry::config().service)
+}
+
+/// Kafka through librdkafka, keyed by entity so each entity's events keep their order
+pub struct KafkaBroker {
+    producer: FutureProducer,
+    brokers: String,
+    topic: String,
+    group: String,
+}
+
+impl Debug for KafkaBroker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaBroker")
+            .field("brokers", &self.brokers)
+            .field("topic", &self.topic)
+            .field("group", &self.group)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KafkaBroker {
+    fn new(brokers: String) -> Result<Self, Box<dyn std::error::Error>> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .set("message.timeout.ms", "5000")
+            .create()
+            .map_err(|e| format!("KAFKA_BROKERS: {}", e))?;
+        Ok(Self {
+            producer,
+            brokers,
+            topic: std::env::var("KAFKA_TOPIC").unwrap_or_else(|_| DEFAULT_DESTINATION.to_string()),
+            group: std::env::var("KAFKA_CONSUMER_GROUP").unwrap_or_else(|_| default_consumer_name()),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Broker for KafkaBroker {
+    fn system(&self) -> &'static str {
+        "kafka"
+    }
+
+    fn destination(&self) -> &str {
+        &self.topic
+    }
+
+    async fn send(&self, message: Outgoing<'_>) -> Result<Vec<KeyValue>, BrokerError> {
+        let record = FutureRecord::to(&self.topic)
+            .key(message.key)
+            .payload(&message.payload)
+            .headers(kafka_headers(&message.headers));
+        let (partition, offset) = self.producer.send(record, QUEUE_TIMEOUT).await.map_err(|(e, _)| e)?;
+        Ok(vec![
+            KeyValue::new("messaging.kafka.destination.partition", partition as i64),
+            KeyValue::new("messaging.kafka.message.offset", offset),
+        ])
+    }
+
+    async fn spawn_consumer(&self) -> Result<(), BrokerError> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &self.brokers)
+            .set("group.id", &self.group)
+            .set("auto.offset.reset", "latest")
+            .create()?;
+        consumer.subscribe(&[&self.topic])?;
+        let topic = self.topic.clone();
+        tokio::spawn(async move {
+            loop {
+                match consumer.recv().await {
+                    Ok(message) => {
+                        let attributes = vec![
+                            KeyValue::new("messaging.kafka.destination.partition", message.partition() as i64),
+                            KeyValue::new("messaging.kafka.message.offset", message.offset()),
+                        ];
+                        let headers = kafka_trace_headers(message.headers());
+                        handle("kafka", &topic, message.payload(), &headers, attributes);
+                    }
+                    Err(e) => {
+                        warn_trace!(topic = %topic, error = %e, "Kafka receive failed");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+fn kafka_headers(trace_headers: &HeaderMap) -> OwnedHeaders {
+    trace_headers.iter().fold(OwnedHeaders::new(), |headers, (name, value)| {
+        headers.insert(Header {
+            key: name.as_str(),
+            value: Some(value.as_bytes()),
+        })
+    })
+}
+
+/// A Kafka message's headers as HTTP headers, for the propagators
+fn kafka_trace_headers(message_headers: Option<&impl Headers>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let Some(message_headers) = message_headers else {
+        return headers;
+    };
+    for header in message_headers.iter() {
+        if let Some(value) = header.value {
+            append_header(&mut headers, header.key.as_bytes(), value);
+        }
+    }
+    headers
+}
+
+/// RabbitMQ through lapin, publishing to a topic exchange with the event type as routing key
+pub struct AmqpBroker {
+    connection: Connection,
+    channel: Channel,
+    exchange: String,
+    queue: String,
+}
+
+impl Debug for AmqpBroker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AmqpBroker")
+            .field("exchange", &self.exchange)
+            .field("queue", &self.queue)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AmqpBroker {
+    async fn connect(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let connection = Connection::connect(url, ConnectionProperties::default())
+            .await
+            .map_err(|e| format!("AMQP_URL: {}", e))?;
+        let channel = connection.create_channel().await?;
+        let exchange = std::env::var("AMQP_EXCHANGE").unwrap_or_else(|_| DEFAULT_DESTINATION.to_string());
+        let options = ExchangeDeclareOptions {
+            durable: true,
+            ..Default::default()
+        };
+        channel
+            .exchange_declare(&exchange, ExchangeKind::Topic, options, FieldTable::default())
+            .await
+            .map_err(|e| format!("AMQP_EXCHANGE: {}", e))?;
+        Ok(Self {
+            connection,
+            channel,
+            exchange,
+            queue: std::env::var("AMQP_QUEUE").unwrap_or_else(|_| default_consumer_name()),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Broker for AmqpBroker {
+    fn system(&self) -> &'static str {
+        "rabbitmq"
+    }
+
+    fn destination(&self) -> &str {
+        &self.exchange
+    }
+
+    async fn send(&self, message: Outgoing<'_>) -> Result<Vec<KeyValue>, BrokerError> {
+        let properties = BasicProperties::default()
+            .with_content_type(ShortString::from("application/json"))
+            .with_message_id(ShortString::from(message.message_id))
+            .with_headers(amqp_headers(&message.headers));
+        let options = BasicPublishOptions::default();
+        // The first await hands the message to the channel, the second waits for the broker
+        self.channel
+            .basic_publish(&self.exchange, message.event_type, options, &message.payload, properties)
+            .await?
+            .await?;
+        Ok(vec![KeyValue::new("messaging.rabbitmq.destination.routing_key", message.event_type)])
+    }
+
+    async fn spawn_consumer(&self) -> Result<(), BrokerError> {
+        let channel = self.connection.create_channel().await?;
+        let options = QueueDeclareOptions {
+            durable: true,
+            ..Default::default()
+        };
+        channel.queue_declare(&self.queue, options, FieldTable::default()).await?;
+        channel
+            .queue_bind(&self.queue, &self.exchange, "#", QueueBindOptions::default(), FieldTable::default())
+            .await?;
+        let mut consumer = channel
+            .basic_consume(&self.queue, &self.queue, BasicConsumeOptions::default(), FieldTable::default())
+            .await?;
+        let exchange = self.exchange.clone();
+        tokio::spawn(async move {
+            while let Some(delivery) = consumer.next().await {
+                let delivery = match delivery {
+                    Ok(delivery) => delivery,
+                    Err(e) => {
+                        warn_trace!(exchange = %exchange, error = %e, "RabbitMQ receive failed");
+                        continue;
+                    }
+                };
+                let routing_key = delivery.routing_key.to_string();
+                let attributes = vec![KeyValue::new("messaging.rabbitmq.destination.routing_key", routing_key)];
+                let headers = delivery.properties.headers().as_ref().map(amqp_trace_headers).unwrap_or_default();
+                handle("rabbitmq", &exchange, Some(&delivery.data), &headers, attributes);
+                if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                    warn_trace!(exchange = %exchange, error = %e, "RabbitMQ ack failed");
+                }
+            }
+            warn_trace!(exchange = %exchange, "RabbitMQ consumer stopped");
+        });
+        Ok(())
+    }
+}
+
+fn amqp_headers(trace_headers: &HeaderMap) -> FieldTable {
+    let mut table = FieldTable::default();
+    for (name, value) in trace_headers {
+        table.insert(ShortString::from(name.as_str()), AMQPValue::LongString(LongString::from(value.as_bytes())));
+    }
+    table
+}
+
+/// An AMQP message's string headers as HTTP headers, for the propagators
+fn amqp_trace_headers(table: &FieldTable) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in table.inner() {
+        if let AMQPValue::LongString(value) = value {
+            append_header(&mut headers, name.as_str().as_bytes(), value.as_bytes());
+        }
+    }
+    headers
+}
+
+fn append_header(headers: &mut HeaderMap, name: &[u8], value: &[u8]) {
+    if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name), HeaderValue::from_bytes(value)) {
+        headers.append(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_headers_survive_the_message_round_trip() {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", HeaderValue::from_static("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"));
+        headers.insert("x-datadog-trace-id", HeaderValue::from_static("9532127138774266268"));
+
+        let kafka = kafka_headers(&headers);
+        assert_eq!(kafka.count(), 2);
+        assert_eq!(kafka_trace_headers(Some(&kafka)), headers);
+        assert!(kafka_trace_headers(None::<&OwnedHeaders>).is_empty());
+
+        let amqp = amqp_headers(&headers);
+        assert_eq!(amqp.inner().len(), 2);
+        assert_eq!(amqp_trace_headers(&amqp), headers);
+    }
+}
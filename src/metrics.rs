@@ -0,0 +1,260 @@
+use crate::dogstatsd;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Upper bounds of the latency histogram buckets, in seconds; `+Inf` follows
+const BUCKETS_SECONDS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// `route` label of requests no route matched, so unknown paths can't grow the label set
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// DogStatsD count of every request, tagged method, route and status class
+const REQUESTS_METRIC: &str = "http.server.requests";
+
+/// DogStatsD count of 5xx responses, with the same tags, for the error rate
+const ERRORS_METRIC: &str = "http.server.errors";
+
+/// DogStatsD distribution of request latency in seconds, with the same tags
+const DURATION_METRIC: &str = "http.server.duration";
+
+/// Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Debug, Default)]
+struct RouteStats {
+    by_status: BTreeMap<u16, u64>,
+    buckets: [u64; BUCKETS_SECONDS.len() + 1],
+    count: u64,
+    sum_seconds: f64,
+}
+
+/// Request counts and latency per route, for Prometheus to scrape
+///
+/// Routes are labelled by their template (`/api/users/:id`), never the raw
+/// path, so the series count stays bounded.
+#[derive(Debug)]
+pub struct HttpMetrics {
+    /// (method, route) to its stats
+    routes: Mutex<BTreeMap<(String, String), RouteStats>>,
+    in_flight: AtomicI64,
+    started: SystemTime,
+}
+
+impl Default for HttpMetrics {
+    fn default() -> Self {
+        Self {
+            routes: Mutex::default(),
+            in_flight: AtomicI64::new(0),
+            started: SystemTime::now(),
+        }
+    }
+}
+
+impl HttpMetrics {
+    fn record(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = routes.entry((method.to_string(), route.to_string())).or_default();
+        *stats.by_status.entry(status).or_default() += 1;
+        let index = BUCKETS_SECONDS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS_SECONDS.len());
+        stats.buckets[index] += 1;
+        stats.count += 1;
+        stats.sum_seconds += seconds;
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+
+        out.push_str("# HELP http_requests_total HTTP requests by method, route template and status.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for ((method, route), stats) in routes.iter() {
+            for (status, count) in &stats.by_status {
+                let _ = writeln!(
+                    out,
+                    "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                    method,
+                    escape(route),
+                    status,
+                    count
+                );
+            }
+        }
+
+        out.push_str("# HELP http_request_duration_seconds HTTP request latency by method and route template.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((method, route), stats) in routes.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+            let mut cumulative = 0;
+            let bounds = BUCKETS_SECONDS.iter().map(|bound| bound.to_string()).chain(["+Inf".to_string()]);
+            for (bound, count) in bounds.zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, cumulative);
+            }
+            let _ = writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, stats.sum_seconds);
+            let _ = writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, stats.count);
+        }
+        drop(routes);
+
+        out.push_str("# HELP http_requests_in_flight HTTP requests being served.\n");
+        out.push_str("# TYPE http_requests_in_flight gauge\n");
+        let _ = writeln!(out, "http_requests_in_flight {}", self.in_flight.load(Ordering::Relaxed));
+
+        let started = self.started.duration_since(UNIX_EPOCH).unwrap_or_default();
+        gauge(&mut out, "process_start_time_seconds", "Start time of the process since the Unix epoch.", started.as_secs_f64());
+        for (name, help, value) in process_stats() {
+            gauge(&mut out, name, help, value);
+        }
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value);
+}
+
+/// Label values may not hold raw quotes, backslashes or newlines
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Memory, thread, file descriptor and CPU figures from `/proc`; empty off Linux
+fn process_stats() -> Vec<(&'static str, &'static str, f64)> {
+    let mut stats = Vec::new();
+    if let Ok(status) = std::fs::read_to_string("/proc/self/status") {
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|rest| rest.split_whitespace().next()?.parse::<f64>().ok())
+        };
+        if let Some(kib) = field("VmRSS:") {
+            stats.push(("process_resident_memory_bytes", "Resident memory size in bytes.", kib * 1024.0));
+        }
+        if let Some(kib) = field("VmSize:") {
+            stats.push(("process_virtual_memory_bytes", "Virtual memory size in bytes.", kib * 1024.0));
+        }
+        if let Some(threads) = field("Threads:") {
+            stats.push(("process_threads", "OS threads in the process.", threads));
+        }
+    }
+    if let Ok(fds) = std::fs::read_dir("/proc/self/fd") {
+        stats.push(("process_open_fds", "Open file descriptors.", fds.count() as f64));
+    }
+    if let Ok(stat) = std::fs::read_to_string("/proc/self/stat") {
+        // Fields after the parenthesised command name; utime and stime are the 12th and 13th
+        let ticks: Option<f64> = stat.rsplit_once(')').and_then(|(_, rest)| {
+            let mut fields = rest.split_whitespace().skip(11);
+            Some(fields.next()?.parse::<f64>().ok()? + fields.next()?.parse::<f64>().ok()?)
+        });
+        if let Some(ticks) = ticks {
+            // USER_HZ is 100 on every mainstream Linux build
+            stats.push(("process_cpu_seconds_total", "User and system CPU time in seconds.", ticks / 100.0));
+        }
+    }
+    stats
+}
+
+/// Counts a request as in flight until dropped, even if the client goes away mid-request
+struct InFlight<'a>(&'a AtomicI64);
+
+impl<'a> InFlight<'a> {
+    fn enter(gauge: &'a AtomicI64) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// `2xx` for 200 to 299, and so on
+fn status_class(status: u16) -> String {
+    format!("{}xx", status / 100)
+}
+
+/// Middleware recording rate, errors and duration (RED metrics) by route template
+///
+/// Layered over the whole app, so every route is covered, including
+/// requests rejected by other middleware and ones no route matched. Each
+/// request feeds `/metrics` and the DogStatsD `http.server.*` metrics, tagged
+/// by method, route and status class; handlers don't count requests themselves.
+pub async fn record_request(State(metrics): State<Arc<HttpMetrics>>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED_ROUTE, MatchedPath::as_str)
+        .to_string();
+    let start = Instant::now();
+    let response = {
+        let _in_flight = InFlight::enter(&metrics.in_flight);
+        next.run(request).await
+    };
+    let status = response.status().as_u16();
+    let seconds = start.elapsed().as_secs_f64();
+    metrics.record(&method, &route, status, seconds);
+
+    let tags = [
+        format!("method:{}", method),
+        format!("route:{}", route),
+        format!("status_class:{}", status_class(status)),
+    ];
+    dogstatsd::count(REQUESTS_METRIC, 1, &tags);
+    if status >= 500 {
+        dogstatsd::count(ERRORS_METRIC, 1, &tags);
+    }
+    dogstatsd::distribution(DURATION_METRIC, seconds, &tags);
+    response
+}
+
+/// `GET /metrics` in the Prometheus text format, on its own router
+pub fn routes<S>(metrics: Arc<HttpMetrics>) -> Router<S> {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(metrics)
+}
+
+async fn render(State(metrics): State<Arc<HttpMetrics>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], metrics.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counts_and_cumulative_buckets() {
+        let metrics = HttpMetrics::default();
+        metrics.record("GET", "/api/users/:id", 200, 0.004);
+        metrics.record("GET", "/api/users/:id", 404, 0.2);
+        let text = metrics.render();
+
+        assert!(text.contains("http_requests_total{method=\"GET\",route=\"/api/users/:id\",status=\"200\"} 1\n"));
+        assert!(text.contains("http_requests_total{method=\"GET\",route=\"/api/users/:id\",status=\"404\"} 1\n"));
+        let labels = "method=\"GET\",route=\"/api/users/:id\"";
+        assert!(text.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"0.005\"}} 1\n", labels)));
+        assert!(text.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"0.1\"}} 1\n", labels)));
+        assert!(text.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"0.25\"}} 2\n", labels)));
+        assert!(text.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n", labels)));
+        assert!(text.contains(&format!("http_request_duration_seconds_count{{{}}} 2\n", labels)));
+        assert!(text.contains("# TYPE process_start_time_seconds gauge\n"));
+    }
+}
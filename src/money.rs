@@ -0,0 +1,122 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// ISO 4217 currencies accepted for orders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    #[default]
+    Usd,
+    Eur,
+    Gbp,
+    Jpy,
+}
+
+impl Currency {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Jpy => "JPY",
+        }
+    }
+
+    /// Decimal places of the currency's minor unit (cents, pence; none for yen)
+    pub fn minor_units(self) -> u32 {
+        match self {
+            Currency::Jpy => 0,
+            _ => 2,
+        }
+    }
+
+    /// Round to the minor unit, half to even, so repeated rounding doesn't drift
+    /// upwards; the result always has exactly that many decimal places
+    pub fn round(self, amount: Decimal) -> Decimal {
+        let mut rounded =
+            amount.round_dp_with_strategy(self.minor_units(), RoundingStrategy::MidpointNearestEven);
+        rounded.rescale(self.minor_units());
+        rounded
+    }
+
+    /// Check a unit price: positive and no finer than the minor unit
+    pub fn validate_price(self, price: Decimal) -> Result<(), String> {
+        if price <= Decimal::ZERO {
+            return Err(format!("price {} must be positive", price));
+        }
+        if price.normalize().scale() > self.minor_units() {
+            return Err(format!(
+                "price {} has more than {} decimal places for {}",
+                price,
+                self.minor_units(),
+                self.as_str()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Currency {
+    type Err = String;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        match code {
+            "USD" => Ok(Currency::Usd),
+            "EUR" => Ok(Currency::Eur),
+            "GBP" => Ok(Currency::Gbp),
+            "JPY" => Ok(Currency::Jpy),
+            other => Err(format!("unsupported currency '{}'", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `price × quantity`, or `None` on overflow
+pub fn line_total(price: Decimal, quantity: u32) -> Option<Decimal> {
+    price.checked_mul(Decimal::from(quantity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn rounds_half_to_even_at_the_minor_unit() {
+        assert_eq!(Currency::Usd.round(amount("2.345")).to_string(), "2.34");
+        assert_eq!(Currency::Usd.round(amount("2.355")).to_string(), "2.36");
+        assert_eq!(Currency::Usd.round(amount("2.3451")).to_string(), "2.35");
+        assert_eq!(Currency::Eur.round(amount("7")).to_string(), "7.00");
+        assert_eq!(Currency::Jpy.round(amount("12.5")).to_string(), "12");
+        assert_eq!(Currency::Jpy.round(amount("13.5")).to_string(), "14");
+        assert_eq!(Currency::Jpy.round(amount("99.00")).to_string(), "99");
+    }
+
+    #[test]
+    fn validates_prices_per_currency() {
+        assert!(Currency::Usd.validate_price(amount("19.99")).is_ok());
+        // Trailing zeros aren't extra precision
+        assert!(Currency::Usd.validate_price(amount("19.9900")).is_ok());
+        assert!(Currency::Jpy.validate_price(amount("1500.0")).is_ok());
+
+        assert!(Currency::Usd.validate_price(amount("19.999")).unwrap_err().contains("more than 2 decimal places"));
+        assert!(Currency::Jpy.validate_price(amount("1500.5")).unwrap_err().contains("more than 0 decimal places for JPY"));
+        assert!(Currency::Usd.validate_price(Decimal::ZERO).unwrap_err().contains("must be positive"));
+        assert!(Currency::Gbp.validate_price(amount("-0.01")).unwrap_err().contains("must be positive"));
+    }
+
+    #[test]
+    fn line_total_is_none_on_overflow() {
+        assert_eq!(line_total(amount("19.99"), 3), Some(amount("59.97")));
+        assert_eq!(line_total(Decimal::MAX, 2), None);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::cli::Cli;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// How long the backend gets to answer `/health` before the demo gives up
+const BACKEND_STARTUP: Duration = Duration::from_secs(30);
+
+/// How long each role gets to drain and flush its spans after Ctrl+C
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// `demo`: run this binary twice, a backend API and a frontend proxy in front of it
+///
+/// The frontend forwards to the backend through the instrumented client, so
+/// every request through it is one trace across two Datadog services:
+/// `<DD_SERVICE>-frontend` and `<DD_SERVICE>`. The global `--config`,
+/// `--log-level` and `--agent-host` options apply to both. Returns the exit code.
+pub async fn run(cli: &Cli, port: u16, backend_port: u16) -> Result<i32, Box<dyn std::error::Error>> {
+    let service = std::env::var("DD_SERVICE").unwrap_or_else(|_| "rust-datadog-otel".to_string());
+    let backend_url = format!("http://127.0.0.1:{}", backend_port);
+
+    let mut backend = role(cli, "api", &format!("127.0.0.1:{}", backend_port), &service)
+        .spawn()
+        .map_err(|e| format!("starting the backend: {}", e))?;
+    wait_until_healthy(&backend_url, &mut backend).await?;
+
+    let mut frontend = role(cli, "frontend", &format!("0.0.0.0:{}", port), &format!("{}-frontend", service))
+        .env("BACKEND_URL", &backend_url)
+        .spawn()
+        .map_err(|e| format!("starting the frontend: {}", e))?;
+    println!("Demo running, Ctrl+C to stop");
+    println!("  Frontend ({}-frontend): http://localhost:{}", service, port);
+    println!("  Backend ({}): {}", service, backend_url);
+    println!("  Try: curl -X POST localhost:{}/api/users -H 'content-type: application/json' -d '{{\"name\":\"Ada\",\"email\":\"ada@example.com\"}}'", port);
+
+    let failed = tokio::select! {
+        status = backend.wait() => Some(("backend", status?)),
+        status = frontend.wait() => Some(("frontend", status?)),
+        _ = tokio::signal::ctrl_c() => None,
+    };
+    if let Some((name, status)) = failed {
+        eprintln!("Demo {} exited ({}), stopping the other role", name, status);
+        // Both share the terminal, so only the survivor is still running
+        let _ = backend.start_kill();
+        let _ = frontend.start_kill();
+        return Ok(1);
+    }
+
+    // Ctrl+C reached both roles too; let them drain and flush their spans
+    for (name, child) in [("frontend", &mut frontend), ("backend", &mut backend)] {
+        if tokio::time::timeout(SHUTDOWN_GRACE, child.wait()).await.is_err() {
+            eprintln!("Demo {} did not stop in time, killing it", name);
+            let _ = child.start_kill();
+        }
+    }
+    Ok(0)
+}
+
+/// This binary as one demo role, listening on `bind_address` as Datadog service `service`
+fn role(cli: &Cli, role: &str, bind_address: &str, service: &str) -> Command {
+    let mut command = Command::new(std::env::current_exe().unwrap_or_else(|_| "rust-datadog-otel".into()));
+    command
+        .env("SERVICE_ROLE", role)
+        .env("BIND_ADDRESS", bind_address)
+        .env("DD_SERVICE", service)
+        .kill_on_drop(true);
+    if let Some(config) = &cli.config {
+        command.env("APP_CONFIG", config);
+    }
+    if let Some(log_level) = &cli.log_level {
+        command.env("RUST_LOG", log_level);
+    }
+    if let Some(agent_host) = &cli.agent_host {
+        command.env("DD_AGENT_HOST", agent_host);
+    }
+    command
+}
+
+async fn wait_until_healthy(url: &str, backend: &mut Child) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    let deadline = tokio::time::Instant::now() + BACKEND_STARTUP;
+    loop {
+        if let Some(status) = backend.try_wait()? {
+            return Err(format!("demo backend exited during startup ({})", status).into());
+        }
+        let healthy = client
+            .get(format!("{}/health", url))
+            .send()
+            .await
+            .is_ok_and(|response| response.status().is_success());
+        if healthy {
+            return Ok(());
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!("backend not healthy at {} after {:?}", url, BACKEND_STARTUP).into());
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
@@ -0,0 +1,114 @@
+/// One record produced by [`LineSplitter`]
+#[derive(Debug)]
+pub enum Line {
+    Complete(Vec<u8>),
+    /// The line exceeded the size limit; its bytes were discarded
+    TooLong,
+}
+
+/// Incrementally splits a byte stream into newline-delimited records
+///
+/// Memory stays bounded by `max_line_bytes` regardless of input: an overlong
+/// line is reported once as [`Line::TooLong`] and skipped up to its newline.
+/// Every input line yields exactly one [`Line`], so callers can count line numbers.
+/// Lines may end in `\n` or `\r\n`; neither is part of the line.
+#[derive(Debug)]
+pub struct LineSplitter {
+    buffer: Vec<u8>,
+    max_line_bytes: usize,
+    discarding: bool,
+}
+
+impl LineSplitter {
+    pub fn new(max_line_bytes: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_line_bytes,
+            discarding: false,
+        }
+    }
+
+    /// Feed a chunk, returning the lines it completed
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut rest = chunk;
+
+        while let Some(pos) = rest.iter().position(|&b| b == b'\n') {
+            let head = &rest[..pos];
+            rest = &rest[pos + 1..];
+
+            if self.discarding {
+                self.discarding = false;
+            } else if self.buffer.len() + head.len() > self.max_line_bytes {
+                self.buffer.clear();
+                lines.push(Line::TooLong);
+            } else {
+                self.buffer.extend_from_slice(head);
+                let mut line = std::mem::take(&mut self.buffer);
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                lines.push(Line::Complete(line));
+            }
+        }
+
+        if !self.discarding {
+            if self.buffer.len() + rest.len() > self.max_line_bytes {
+                self.buffer.clear();
+                self.discarding = true;
+                lines.push(Line::TooLong);
+            } else {
+                self.buffer.extend_from_slice(rest);
+            }
+        }
+        lines
+    }
+
+    /// Flush a final line that wasn't newline-terminated
+    pub fn finish(self) -> Option<Line> {
+        (!self.discarding && !self.buffer.is_empty()).then_some(Line::Complete(self.buffer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines of `chunks` fed in order, then the final unterminated one; `None` for a too-long line
+    fn split(max_line_bytes: usize, chunks: &[&str]) -> Vec<Option<String>> {
+        let mut splitter = LineSplitter::new(max_line_bytes);
+        let mut lines: Vec<Line> = chunks.iter().flat_map(|chunk| splitter.push(chunk.as_bytes())).collect();
+        lines.extend(splitter.finish());
+        lines
+            .into_iter()
+            .map(|line| match line {
+                Line::Complete(bytes) => Some(String::from_utf8(bytes).unwrap()),
+                Line::TooLong => None,
+            })
+            .collect()
+    }
+
+    fn lines(expected: &[&str]) -> Vec<Option<String>> {
+        expected.iter().map(|line| Some(line.to_string())).collect()
+    }
+
+    #[test]
+    fn joins_lines_split_across_chunks() {
+        assert_eq!(split(64, &["{\"a\":", "1}\n{\"b\"", ":2}\n"]), lines(&["{\"a\":1}", "{\"b\":2}"]));
+        assert_eq!(split(64, &["one\ntw", "o"]), lines(&["one", "two"]));
+        assert_eq!(split(64, &["one\n", "\n"]), lines(&["one", ""]));
+    }
+
+    #[test]
+    fn strips_crlf_endings() {
+        assert_eq!(split(64, &["one\r\ntwo\r", "\nthree"]), lines(&["one", "two", "three"]));
+    }
+
+    #[test]
+    fn skips_overlong_lines_once() {
+        assert_eq!(split(4, &["1234\n12345\nok\n"]), vec![Some("1234".to_string()), None, Some("ok".to_string())]);
+        // Over the limit mid-line: reported when it happens, then skipped up to its newline
+        assert_eq!(split(4, &["123", "45", "678\nok"]), vec![None, Some("ok".to_string())]);
+        assert_eq!(split(4, &["123456"]), vec![None]);
+    }
+}
@@ -0,0 +1,205 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_datadog_otel::error_trace;
+use serde::Serialize;
+use std::fmt;
+
+/// Response serialization formats selectable via the `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl ResponseFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ResponseFormat::Json => "application/json",
+            ResponseFormat::MessagePack => "application/msgpack",
+            ResponseFormat::Cbor => "application/cbor",
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(ResponseFormat::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(ResponseFormat::MessagePack)
+            }
+            "application/cbor" => Some(ResponseFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Pick the supported format with the highest `q` value
+    ///
+    /// Returns JSON when the header is absent, and `None` when the client only
+    /// accepts formats we can't produce.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let Some(accept) = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.trim().is_empty())
+        else {
+            return Some(ResponseFormat::Json);
+        };
+
+        let mut best: Option<(Self, f32)> = None;
+        for entry in accept.split(',') {
+            let mut parts = entry.split(';').map(str::trim);
+            let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if quality <= 0.0 {
+                continue;
+            }
+            if let Some(format) = Self::from_media_type(&media_type) {
+                if best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                    best = Some((format, quality));
+                }
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+
+    /// Wrap a body so it is serialized in this format
+    pub fn body<T: Serialize>(self, body: T) -> Negotiated<T> {
+        Negotiated { format: self, body }
+    }
+}
+
+impl fmt::Display for ResponseFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResponseFormat::Json => "json",
+            ResponseFormat::MessagePack => "msgpack",
+            ResponseFormat::Cbor => "cbor",
+        })
+    }
+}
+
+/// Extracts the negotiated format; rejects with 406 when nothing acceptable is offered
+///
+/// Handlers taking `format: ResponseFormat` get it recorded on their
+/// `#[instrument]` span automatically.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ResponseFormat {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        ResponseFormat::from_headers(&parts.headers).ok_or_else(|| {
+            (
+                StatusCode::NOT_ACCEPTABLE,
+                "Supported formats: application/json, application/msgpack, application/cbor",
+            )
+                .into_response()
+        })
+    }
+}
+
+/// Responder serializing `T` in the negotiated [`ResponseFormat`]
+#[derive(Debug)]
+pub struct Negotiated<T> {
+    format: ResponseFormat,
+    body: T,
+}
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let encoded = match self.format {
+            ResponseFormat::Json => serde_json::to_vec(&self.body).map_err(|e| e.to_string()),
+            ResponseFormat::MessagePack => {
+                rmp_serde::to_vec_named(&self.body).map_err(|e| e.to_string())
+            }
+            ResponseFormat::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::into_writer(&self.body, &mut buffer)
+                    .map(|_| buffer)
+                    .map_err(|e| e.to_string())
+            }
+        };
+
+        match encoded {
+            Ok(bytes) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(self.format.content_type()),
+                )],
+                bytes,
+            )
+                .into_response(),
+            Err(e) => {
+                error_trace!(format = %self.format, error = %e, "Failed to serialize response");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn negotiate(accept: &str) -> Option<ResponseFormat> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+        ResponseFormat::from_headers(&headers)
+    }
+
+    #[test]
+    fn picks_the_highest_quality_supported_format() {
+        assert_eq!(ResponseFormat::from_headers(&HeaderMap::new()), Some(ResponseFormat::Json));
+        assert_eq!(negotiate("application/cbor"), Some(ResponseFormat::Cbor));
+        assert_eq!(
+            negotiate("application/json;q=0.5, application/x-msgpack;q=0.9"),
+            Some(ResponseFormat::MessagePack)
+        );
+        assert_eq!(negotiate("text/html, application/cbor;q=0.1"), Some(ResponseFormat::Cbor));
+        // q=0 rules a format out, and ties go to the first listed
+        assert_eq!(negotiate("application/cbor;q=0, application/json;q=0.2"), Some(ResponseFormat::Json));
+        assert_eq!(negotiate("application/msgpack, application/cbor"), Some(ResponseFormat::MessagePack));
+    }
+
+    #[test]
+    fn wildcards_mean_json() {
+        assert_eq!(negotiate("*/*"), Some(ResponseFormat::Json));
+        assert_eq!(negotiate("text/html, application/*;q=0.8"), Some(ResponseFormat::Json));
+        assert_eq!(negotiate("*/*;q=0.1, application/cbor"), Some(ResponseFormat::Cbor));
+        assert_eq!(negotiate("text/html, image/*"), None);
+    }
+
+    #[tokio::test]
+    async fn serializes_in_the_negotiated_format_or_rejects_with_406() {
+        let app = Router::new().route(
+            "/",
+            get(|format: ResponseFormat| async move { format.body(serde_json::json!({ "id": 7 })) }),
+        );
+        let request = |accept: &str| Request::get("/").header(header::ACCEPT, accept).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(request("application/msgpack")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/msgpack");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(body["id"], 7);
+
+        let response = app.clone().oneshot(request("application/cbor")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/cbor");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = ciborium::from_reader(&bytes[..]).unwrap();
+        assert_eq!(body["id"], 7);
+
+        let response = app.oneshot(request("text/html")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::cost;
+use ::object_store::{
+    aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder, local::LocalFileSystem,
+    memory::InMemory, path::Path, prefix::PrefixStore, ObjectStore, RetryConfig,
+};
+use futures_util::TryStreamExt;
+use rust_datadog_otel::warn_trace;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Object storage client for S3, MinIO, GCS or a local directory
+///
+/// Configuration:
+/// - `OBJECT_STORE_URL`: `s3://bucket/prefix`, `gs://bucket/prefix`,
+///   `file:///absolute/dir` or `memory://` (default, process-local)
+/// - `OBJECT_STORE_MAX_RETRIES`: retries of failed requests (default 3)
+/// - credentials and endpoints from the provider's usual variables, e.g.
+///   `AWS_ACCESS_KEY_ID`, `AWS_ENDPOINT` and `AWS_ALLOW_HTTP` for MinIO, or
+///   `GOOGLE_SERVICE_ACCOUNT` for GCS
+///
+/// Every operation is an `object_store.<op>` client span with bucket, key,
+/// size and retry count. Retries happen here, with backoff, rather than
+/// inside the provider client, so each one is visible in the trace.
+#[derive(Debug)]
+pub struct ObjectStorage {
+    store: Arc<dyn ObjectStore>,
+    backend: &'static str,
+    bucket: String,
+    max_retries: u32,
+}
+
+impl ObjectStorage {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let url = std::env::var("OBJECT_STORE_URL").unwrap_or_else(|_| "memory://".to_string());
+        let max_retries = std::env::var("OBJECT_STORE_MAX_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_RETRIES);
+
+        // Provider-side retries off; `with_retries` does them instead
+        let no_retry = RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        };
+
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| format!("invalid OBJECT_STORE_URL '{}'", url))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+
+        let (store, backend): (Arc<dyn ObjectStore>, &'static str) = match scheme {
+            "s3" => (
+                Arc::new(
+                    AmazonS3Builder::from_env()
+                        .with_bucket_name(bucket)
+                        .with_retry(no_retry)
+                        .build()?,
+                ),
+                "s3",
+            ),
+            "gs" => (
+                Arc::new(
+                    GoogleCloudStorageBuilder::from_env()
+                        .with_bucket_name(bucket)
+                        .with_retry(no_retry)
+                        .build()?,
+                ),
+                "gcs",
+            ),
+            "file" => {
+                std::fs::create_dir_all(rest)?;
+                (Arc::new(LocalFileSystem::new_with_prefix(rest)?), "file")
+            }
+            "memory" => (Arc::new(InMemory::new()), "memory"),
+            other => return Err(format!("unsupported OBJECT_STORE_URL scheme '{}'", other).into()),
+        };
+
+        let store: Arc<dyn ObjectStore> = match scheme {
+            "s3" | "gs" if !prefix.is_empty() => Arc::new(PrefixStore::new(store, prefix)),
+            _ => store,
+        };
+
+        Ok(Self {
+            store,
+            backend,
+            bucket: match scheme {
+                "s3" | "gs" => bucket.to_string(),
+                _ => String::new(),
+            },
+            max_retries,
+        })
+    }
+
+    pub fn backend(&self) -> &'static str {
+        self.backend
+    }
+
+    fn span(&self, operation: &'static str, key: &str) -> tracing::Span {
+        tracing::info_span!(
+            "object_store.request",
+            otel.name = %format!("object_store.{}", operation),
+            otel.kind = "client",
+            object_store.operation = operation,
+            object_store.backend = self.backend,
+            object_store.bucket = %self.bucket,
+            object_store.key = %key,
+            object_store.size = tracing::field::Empty,
+            object_store.retries = 0u32,
+        )
+    }
+
+    /// Run `request`, retrying transient failures with exponential backoff
+    async fn with_retries<T, F, Fut>(&self, mut request: F) -> Result<T, ::object_store::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ::object_store::Error>>,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            cost::record_external_call();
+            match request().await {
+                // Only `Generic` covers network and server errors; the rest won't change on retry
+                Err(e @ ::object_store::Error::Generic { .. }) if attempt < self.max_retries => {
+                    attempt += 1;
+                    tracing::Span::current().record("object_store.retries", attempt);
+                    warn_trace!(error = %e, attempt, backoff_ms = backoff.as_millis() as u64, "Object store request failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), StorageError> {
+        let span = self.span("put", key);
+        span.record("object_store.size", body.len());
+        cost::record_bytes(body.len());
+        let path = Path::parse(key)?;
+        let payload = ::object_store::PutPayload::from(body);
+        self.with_retries(|| self.store.put(&path, payload.clone()))
+            .instrument(span)
+            .await?;
+        Ok(())
+    }
+
+    /// Object contents, or `None` if it doesn't exist
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let span = self.span("get", key);
+        let path = Path::parse(key)?;
+        let result = self
+            .with_retries(|| async { self.store.get(&path).await?.bytes().await })
+            .instrument(span.clone())
+            .await;
+
+        match result {
+            Ok(bytes) => {
+                span.record("object_store.size", bytes.len());
+                cost::record_bytes(bytes.len());
+                Ok(Some(bytes.to_vec()))
+            }
+            Err(::object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Keys under `prefix` (a directory-like path such as `reports`)
+    pub async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let span = self.span("list", prefix);
+        let path = Path::parse(prefix)?;
+        let objects: Vec<_> = self
+            .with_retries(|| self.store.list(Some(&path)).try_collect::<Vec<_>>())
+            .instrument(span)
+            .await?;
+        Ok(objects
+            .into_iter()
+            .map(|object| object.location.to_string())
+            .collect())
+    }
+}
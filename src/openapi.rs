@@ -0,0 +1,169 @@
+use utoipa::OpenApi;
+
+/// OpenAPI 3 document for the demo API
+///
+/// Served at `/api-docs/openapi.json` with Swagger UI at `/swagger-ui`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Rust Datadog OpenTelemetry Demo API",
+        description = "Demo service instrumented with the Datadog OpenTelemetry SDK"
+    ),
+    paths(
+        crate::service_api::root,
+        crate::service_api::health,
+        crate::service_api::ready,
+        crate::service_api::demo,
+        crate::service_api::demo_config,
+        crate::service_api::span_stream,
+        crate::service_api::telemetry_status,
+        crate::admin_api::protocol_stats,
+        crate::admin_api::queue_time_stats,
+        crate::admin_api::region_stats,
+        crate::admin_api::priority_stats,
+        crate::admin_api::cost_stats,
+        crate::admin_api::disconnect_stats,
+        crate::admin_api::rate_limit_stats,
+        crate::admin_api::dependency_stats,
+        crate::admin_api::effective_config,
+        crate::admin_api::experiment_stats,
+        crate::admin_api::job_stats,
+        crate::admin_api::job_status,
+        crate::admin_api::purge_users,
+        crate::admin_api::auth_stats,
+        crate::admin_api::email_stats,
+        crate::admin_api::event_log,
+        crate::admin_api::event_schema,
+        crate::admin_api::concurrency_stats
+    ),
+    nest(
+        (path = "/api/v1", api = VersionedApi),
+        (path = "/api/v2", api = VersionedApi),
+        (path = "/api", api = VersionedApi)
+    ),
+    components(schemas(
+        crate::service_api::HealthResponse,
+        rust_datadog_otel::error::ErrorResponse,
+        crate::admin_api::PurgeRequest,
+        crate::admin_api::JobAccepted,
+        crate::ids::JobId,
+        crate::job_tracker::TrackedJob,
+        crate::job_tracker::JobState,
+    )),
+    tags(
+        (name = "meta", description = "Service information"),
+        (name = "admin", description = "Operational endpoints, restricted to private networks by default"),
+        (name = "users", description = "User management"),
+        (name = "auth", description = "Password registration and login issuing JWT bearer tokens"),
+        (name = "sessions", description = "Cookie-based login sessions"),
+        (name = "orders", description = "Order processing"),
+        (name = "uploads", description = "File uploads to object storage"),
+        (name = "search", description = "Full-text search over users and products"),
+        (name = "simulation", description = "Error and latency simulation for APM demos")
+    )
+)]
+pub struct ApiDoc;
+
+/// Routes mounted under each API version prefix
+///
+/// `/api` is the original unversioned surface and behaves as v1.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::user_api::create_user,
+        crate::user_api::import_users,
+        crate::auth_api::register,
+        crate::auth_api::auth_login,
+        crate::auth_api::current_user,
+        crate::auth_api::login,
+        crate::auth_api::logout,
+        crate::auth_api::current_session,
+        crate::user_api::get_user,
+        crate::user_api::update_user,
+        crate::order_api::list_user_orders,
+        crate::order_api::create_order,
+        crate::order_api::get_order,
+        crate::order_api::update_order,
+        crate::order_api::cancel_order,
+        crate::order_api::order_analytics,
+        crate::demo_api::search_entities,
+        crate::order_api::export_orders,
+        crate::demo_api::latest_report,
+        crate::demo_api::upload_file,
+        crate::demo_api::simulate_error,
+        crate::demo_api::slow_operation,
+        crate::demo_api::database_query,
+        crate::demo_api::generate_report,
+    ),
+    components(schemas(
+        crate::user_api::User,
+        crate::user_api::CreateUserRequest,
+        crate::user_api::UpdateUserRequest,
+        crate::user_api::ImportSummary,
+        crate::user_api::ImportFailure,
+        crate::auth_api::RegisterRequest,
+        crate::auth_api::Credentials,
+        crate::auth_api::TokenResponse,
+        crate::auth::Role,
+        crate::auth_api::SessionResponse,
+        crate::order_api::OrderRequest,
+        crate::order_api::OrderItem,
+        crate::order_api::OrderResponse,
+        crate::order_api::UpdateOrderRequest,
+        crate::order_api::OrderPage,
+        crate::money::Currency,
+        rust_datadog_otel::error::ErrorResponse,
+        crate::export::ExportFormat,
+        crate::reports::ReportArtifact,
+        crate::demo_api::UploadResponse,
+        crate::demo_api::SearchResults,
+        crate::search::SearchHit,
+        crate::search::DocumentKind,
+        crate::analytics::OrderAnalytics,
+        crate::analytics::TopProduct,
+        crate::analytics::CurrencyRevenue,
+        crate::demo_api::DatabaseQueryResponse,
+        crate::demo_api::UserOrderSummary,
+        crate::report::Report,
+        crate::report::ProductSummary,
+    ))
+)]
+struct VersionedApi;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Convert an axum route (`/api/users/:id`) to OpenAPI form (`/api/users/{id}`)
+    fn openapi_path(route: &str) -> String {
+        route
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(param) => format!("{{{}}}", param),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    #[test]
+    fn every_registered_route_is_documented() {
+        let spec = ApiDoc::openapi();
+        let meta = crate::routes::meta_routes()
+            .into_iter()
+            .chain(crate::routes::admin_routes())
+            .map(|(route, _)| openapi_path(route));
+        let versioned = crate::routes::API_MOUNTS.into_iter().flat_map(|(prefix, _)| {
+            crate::routes::api_routes()
+                .into_iter()
+                .map(move |(route, _)| format!("{}{}", prefix, openapi_path(route)))
+        });
+
+        let missing: Vec<_> = meta
+            .chain(versioned)
+            .filter(|path| !spec.paths.paths.contains_key(path))
+            .collect();
+
+        assert!(missing.is_empty(), "routes missing from OpenAPI spec: {:?}", missing);
+    }
+}
@@ -0,0 +1,949 @@
+use crate::ids::{IdPath, OrderId, ProductId, UserId};
+use crate::negotiation::ResponseFormat;
+use crate::{
+    analytics, auth, concurrency, config, disconnect, events, export, money, orders, pagination, region, search, AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+use rust_datadog_otel::error::{AppError, ErrorResponse};
+use rust_datadog_otel::{
+    debug_trace, dogstatsd, error_trace, info_trace, warn_trace,
+};
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OrderRequest {
+    pub user_id: UserId,
+    /// Currency of every item price (default USD)
+    #[serde(default)]
+    pub currency: money::Currency,
+    pub items: Vec<OrderItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OrderItem {
+    pub product_id: ProductId,
+    pub quantity: u32,
+    /// Unit price as a decimal string, e.g. `"19.99"`
+    pub price: rust_decimal::Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OrderResponse {
+    pub order_id: OrderId,
+    pub user_id: UserId,
+    /// Decimal string in `currency`, e.g. `"39.98"`
+    pub total_amount: rust_decimal::Decimal,
+    pub currency: money::Currency,
+    pub status: String,
+    pub created_at: String,
+}
+
+/// `GET /orders/{id}` cache entry, with the version for its ETag
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedOrder {
+    pub order: OrderResponse,
+    version: u64,
+}
+
+impl From<orders::OrderRecord> for OrderResponse {
+    fn from(record: orders::OrderRecord) -> Self {
+        Self {
+            order_id: record.order_id,
+            user_id: record.user_id,
+            total_amount: record.total_amount,
+            currency: record.currency,
+            status: record.status,
+            created_at: record.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Statuses an order can be moved to with `PUT /orders/:id`
+const ORDER_STATUSES: [&str; 3] = ["confirmed", "shipped", "delivered"];
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateOrderRequest {
+    /// `confirmed`, `shipped` or `delivered`; cancel with `POST /orders/{id}/cancel`
+    status: String,
+}
+
+/// One page of a user's orders, oldest first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderPage {
+    orders: Vec<OrderResponse>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalyticsQuery {
+    /// Look-back window such as `30m`, `1h` or `7d` (default `1h`)
+    window: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderPageQuery {
+    /// Orders per page, 1-100 (default 20)
+    limit: Option<usize>,
+    /// `next_cursor` from the previous page
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// `csv` (default) or `parquet`
+    format: Option<export::ExportFormat>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}/orders",
+    tag = "orders",
+    params(("id" = String, Path, format = Uuid, description = "User ID"), OrderPageQuery),
+    responses(
+        (status = 200, description = "A page of the user's orders, oldest first", body = OrderPage),
+        (status = 400, description = "Malformed user ID, invalid limit or invalid cursor", body = ErrorResponse)
+    )
+)]
+#[instrument(
+    skip(state, query, format),
+    fields(user_id = %user_id, page.size = tracing::field::Empty, page.has_more = tracing::field::Empty)
+)]
+pub async fn list_user_orders(
+    State(state): State<Arc<AppState>>,
+    IdPath(user_id): IdPath<UserId>,
+    Query(query): Query<OrderPageQuery>,
+    format: ResponseFormat,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = query.limit.unwrap_or(pagination::DEFAULT_PAGE_SIZE);
+    if !(1..=pagination::MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            pagination::MAX_PAGE_SIZE
+        )));
+    }
+
+    let after = match query.cursor {
+        Some(token) => {
+            let cursor: pagination::OrderCursor = state.cursors.decode(&token)?;
+            // A cursor only continues the listing it was issued for
+            if cursor.user_id != user_id {
+                warn_trace!(cursor.user_id = %cursor.user_id, "Cursor issued for another user");
+                return Err(AppError::BadRequest("Invalid cursor".to_string()));
+            }
+            Some((cursor.created_at, cursor.order_id))
+        }
+        None => None,
+    };
+
+    let (records, has_more) = state.orders.page_for_user(user_id, after, limit).await;
+    let next_cursor = records.last().filter(|_| has_more).map(|last| {
+        state.cursors.encode(&pagination::OrderCursor {
+            user_id,
+            created_at: last.created_at,
+            order_id: last.order_id,
+        })
+    });
+
+    let span = tracing::Span::current();
+    span.record("page.size", records.len());
+    span.record("page.has_more", has_more);
+    debug_trace!(user_id = %user_id, page.size = records.len(), "Listed user orders");
+
+    Ok(format.body(OrderPage {
+        orders: records.into_iter().map(OrderResponse::from).collect(),
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders",
+    tag = "orders",
+    request_body = OrderRequest,
+    responses(
+        (status = 201, description = "Order created", body = OrderResponse),
+        (status = 400, description = "Invalid order", body = ErrorResponse),
+        (status = 402, description = "Payment declined", body = ErrorResponse),
+        (status = 409, description = "Not enough stock", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state), fields(order.currency = %payload.currency))]
+pub async fn create_order(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    Json(payload): Json<OrderRequest>,
+) -> impl IntoResponse {
+    match place_order(state, payload).await {
+        Ok(order) => (StatusCode::CREATED, format.body(order)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Validate, pay for and record an order; shared by HTTP and gRPC
+pub async fn place_order(state: Arc<AppState>, mut payload: OrderRequest) -> Result<OrderResponse, AppError> {
+    info_trace!(
+        user_id = %payload.user_id,
+        item_count = payload.items.len(),
+        "Creating new order"
+    );
+
+    // Validate order
+    if payload.items.is_empty() {
+        warn_trace!("Order creation failed: no items");
+        return Err(AppError::BadRequest("Order must contain at least one item".to_string()));
+    }
+
+    let currency = payload.currency;
+    if let Some(item) = payload.items.iter().find(|item| item.quantity == 0) {
+        warn_trace!(product_id = %item.product_id, "Order creation failed: zero quantity");
+        return Err(AppError::BadRequest("Item quantity must be at least 1".to_string()));
+    }
+    if let Err(e) = payload.items.iter().try_for_each(|item| currency.validate_price(item.price)) {
+        warn_trace!(error = %e, "Order creation failed: invalid price");
+        return Err(AppError::BadRequest(e));
+    }
+
+    // Prices are exact to the minor unit, so rounding only fixes the scale (`0.2` → `0.20`)
+    for item in &mut payload.items {
+        item.price = currency.round(item.price);
+    }
+    let Some(total_amount) = payload.items.iter().try_fold(rust_decimal::Decimal::ZERO, |total, item| {
+        total.checked_add(money::line_total(item.price, item.quantity)?)
+    }) else {
+        warn_trace!("Order creation failed: total overflows");
+        return Err(AppError::BadRequest("Order total is too large".to_string()));
+    };
+
+    // Users on the new checkout path are split between payment gateways
+    let gateway = match state
+        .experiments
+        .allocate("checkout_gateway", &payload.user_id.to_string())
+        .await
+        .as_deref()
+    {
+        Some("treatment") => PaymentGateway::V2,
+        _ => PaymentGateway::Legacy,
+    };
+
+    // Once payment is taken the order has to be recorded, even if the client goes away
+    let item_count = payload.items.len();
+    let order = match disconnect::shield(confirm_order(
+        state.clone(),
+        payload.user_id,
+        payload.items,
+        total_amount,
+        currency,
+        gateway,
+    ))
+    .await
+    {
+        Ok(order) => order,
+        Err(e) => {
+            warn_trace!(error = %e, "Order creation failed");
+            dogstatsd::count(
+                "orders.failed",
+                1,
+                &[format!("currency:{}", currency.as_str()), format!("status:{}", e.status().as_u16())],
+            );
+            return Err(e);
+        }
+    };
+
+    info_trace!(order_id = %order.order_id, total_amount = %total_amount, currency = %currency, "Order created successfully");
+    // Amounts are only comparable within a currency, so every series is tagged with it
+    let tags = [format!("currency:{}", currency.as_str()), format!("gateway:{}", gateway.as_str())];
+    dogstatsd::count("orders.created", 1, &tags);
+    dogstatsd::distribution("orders.amount", total_amount.to_f64().unwrap_or_default(), &tags);
+    dogstatsd::histogram("orders.items", item_count as f64, &tags);
+
+    Ok(order)
+}
+
+/// Take payment for an order and record it, returning the confirmed order
+async fn confirm_order(
+    state: Arc<AppState>,
+    user_id: UserId,
+    items: Vec<OrderItem>,
+    total_amount: rust_decimal::Decimal,
+    currency: money::Currency,
+    gateway: PaymentGateway,
+) -> Result<OrderResponse, AppError> {
+    pay_and_check_inventory(user_id, &items, total_amount, currency, gateway, &state.config).await?;
+
+    let record = orders::OrderRecord {
+        order_id: OrderId::generate(),
+        user_id,
+        lines: items
+            .into_iter()
+            .map(|item| orders::OrderLine {
+                product_id: item.product_id,
+                quantity: item.quantity,
+                price: item.price,
+            })
+            .collect(),
+        total_amount,
+        currency,
+        status: "confirmed".to_string(),
+        created_at: chrono::Utc::now(),
+        version: 1,
+    };
+    let order = OrderResponse {
+        order_id: record.order_id,
+        user_id: record.user_id,
+        total_amount,
+        currency,
+        status: record.status.clone(),
+        created_at: record.created_at.to_rfc3339(),
+    };
+    let item_count = record.lines.len();
+    let mut products: Vec<search::SearchDocument> = record
+        .lines
+        .iter()
+        .map(|line| search::SearchDocument {
+            kind: search::DocumentKind::Product,
+            id: line.product_id.to_string(),
+            title: line.product_id.to_string(),
+        })
+        .collect();
+    products.sort_by(|a, b| a.id.cmp(&b.id));
+    products.dedup_by(|a, b| a.id == b.id);
+    if let Err(e) = state.orders.insert(record).await {
+        error_trace!(order_id = %order.order_id, error = %e, "Failed to store order");
+        return Err(AppError::Internal("Failed to store order".to_string()));
+    }
+    state.search.index(products).await;
+    let envelope = state.events.publish(events::DomainEvent::OrderConfirmed {
+        order_id: order.order_id,
+        user_id: order.user_id,
+        total_amount,
+        currency,
+        item_count,
+    });
+    if let Some(messaging) = state.messaging.clone() {
+        // Sent in the background, still under this span, so a slow broker doesn't hold up the order
+        tokio::spawn(tracing::Instrument::in_current_span(async move { messaging.publish(&envelope).await }));
+    }
+
+    Ok(order)
+}
+
+/// Take payment and check stock at the same time; the first failure wins
+///
+/// The two calls don't depend on each other, so their spans overlap. When one
+/// fails the other is dropped mid-flight; payment is simulated, so there is
+/// nothing to refund.
+async fn pay_and_check_inventory(
+    user_id: UserId,
+    items: &[OrderItem],
+    total_amount: rust_decimal::Decimal,
+    currency: money::Currency,
+    gateway: PaymentGateway,
+    config: &config::ServiceConfig,
+) -> Result<(), AppError> {
+    tokio::try_join!(
+        process_payment(user_id, total_amount, currency, gateway, config),
+        check_inventory(items),
+    )?;
+    Ok(())
+}
+
+/// Payment backend selected by the `checkout_gateway` experiment
+#[derive(Debug, Clone, Copy)]
+enum PaymentGateway {
+    Legacy,
+    V2,
+}
+
+impl PaymentGateway {
+    fn as_str(self) -> &'static str {
+        match self {
+            PaymentGateway::Legacy => "legacy",
+            PaymentGateway::V2 => "v2",
+        }
+    }
+
+    /// Simulated gateway round trip
+    fn latency(self, config: &config::ServiceConfig) -> Duration {
+        match self {
+            PaymentGateway::Legacy => config.payment_legacy_latency,
+            PaymentGateway::V2 => config.payment_v2_latency,
+        }
+    }
+}
+
+/// Largest amount the simulated gateways approve, in any currency
+const PAYMENT_LIMIT: rust_decimal::Decimal = rust_decimal::Decimal::from_parts(100_000, 0, 0, false, 0);
+
+#[instrument(skip(config), err(Display), fields(user_id = %user_id, payment.currency = %currency))]
+async fn process_payment(
+    user_id: UserId,
+    amount: rust_decimal::Decimal,
+    currency: money::Currency,
+    gateway: PaymentGateway,
+    config: &config::ServiceConfig,
+) -> Result<(), AppError> {
+    info_trace!(user_id = %user_id, amount = %amount, currency = %currency, "Processing payment");
+    let start = Instant::now();
+    
+    // Simulate payment gateway call
+    region::simulate_downstream("payment-gateway").await;
+    tokio::time::sleep(gateway.latency(config)).await;
+    let approved = amount <= PAYMENT_LIMIT;
+    let tags = [
+        format!("gateway:{}", gateway.as_str()),
+        format!("currency:{}", currency.as_str()),
+        format!("outcome:{}", if approved { "approved" } else { "declined" }),
+    ];
+    dogstatsd::count("payments.processed", 1, &tags);
+    dogstatsd::histogram("payments.duration_ms", start.elapsed().as_secs_f64() * 1000.0, &tags);
+    if !approved {
+        return Err(AppError::PaymentRequired(format!(
+            "Payment declined: {} {} is over the {} limit",
+            amount, currency, PAYMENT_LIMIT
+        )));
+    }
+    
+    debug_trace!("Payment processed successfully");
+    Ok(())
+}
+
+/// Units of each product the simulated warehouse holds
+const INVENTORY_STOCK: u64 = 500;
+
+/// Most product stock lookups in flight at once
+const INVENTORY_CONCURRENCY: usize = 4;
+
+/// Check stock for every product in the order, a few products at a time
+///
+/// Each product is looked up in its own span; the result names every product
+/// that is short, not just the first.
+#[instrument(skip(items), err(Display), fields(order.items = items.len(), inventory.products = tracing::field::Empty))]
+pub async fn check_inventory(items: &[OrderItem]) -> Result<(), AppError> {
+    info_trace!(item_count = items.len(), "Checking inventory");
+
+    let mut wanted: BTreeMap<&ProductId, u64> = BTreeMap::new();
+    for item in items {
+        *wanted.entry(&item.product_id).or_default() += u64::from(item.quantity);
+    }
+    tracing::Span::current().record("inventory.products", wanted.len());
+
+    let mut pending = wanted.into_iter();
+    let mut checks: FuturesUnordered<_> = pending
+        .by_ref()
+        .take(INVENTORY_CONCURRENCY)
+        .map(|(product_id, quantity)| check_product_stock(product_id, quantity))
+        .collect();
+    let mut short = Vec::new();
+    while let Some(result) = checks.next().await {
+        if let Some((product_id, quantity)) = pending.next() {
+            checks.push(check_product_stock(product_id, quantity));
+        }
+        if let Err(product_id) = result {
+            short.push(product_id.to_string());
+        }
+    }
+    if !short.is_empty() {
+        short.sort();
+        return Err(AppError::Conflict(format!(
+            "Not enough stock for {}: at most {} of each available",
+            short.join(", "),
+            INVENTORY_STOCK
+        )));
+    }
+
+    debug_trace!("Inventory check completed");
+    Ok(())
+}
+
+/// Look up stock for one product; `Err` with the product when it is short
+#[instrument(
+    skip_all,
+    fields(
+        product.id = %product_id,
+        product.quantity = quantity,
+        product.available = INVENTORY_STOCK,
+        product.in_stock = tracing::field::Empty
+    )
+)]
+async fn check_product_stock(product_id: &ProductId, quantity: u64) -> Result<(), &ProductId> {
+    // Simulate an inventory service call
+    region::simulate_downstream("inventory").await;
+    tokio::time::sleep(Duration::from_millis(75)).await;
+
+    let in_stock = quantity <= INVENTORY_STOCK;
+    tracing::Span::current().record("product.in_stock", in_stock);
+    if in_stock {
+        Ok(())
+    } else {
+        Err(product_id)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/{id}",
+    tag = "orders",
+    params(("id" = String, Path, format = Uuid, description = "Order ID")),
+    responses(
+        (status = 200, description = "Order found (a simulated order for unknown IDs)", body = OrderResponse,
+            headers(("ETag" = String, description = "Version of a stored order, for `If-Match` on updates"))),
+        (status = 400, description = "Malformed order ID", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, format), fields(order_id = %id))]
+pub async fn get_order(
+    State(state): State<Arc<AppState>>,
+    IdPath(id): IdPath<OrderId>,
+    format: ResponseFormat,
+) -> impl IntoResponse {
+    info_trace!(order_id = %id, "Fetching order");
+
+    if let Some(found) = find_order(&state, id).await {
+        let etag = concurrency::etag(found.version);
+        return ([(header::ETAG, etag)], format.body(found.order)).into_response();
+    }
+
+    // Simulate database lookup
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let order = OrderResponse {
+        order_id: id,
+        user_id: UserId::generate(),
+        total_amount: rust_decimal::Decimal::new(9999, 2),
+        currency: money::Currency::Usd,
+        status: "shipped".to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+
+    debug_trace!(order_id = %id, "Order found");
+    format.body(order).into_response()
+}
+
+/// A stored order with its version, read through the cache
+pub async fn find_order(state: &AppState, id: OrderId) -> Option<CachedOrder> {
+    let key = id.to_string();
+    if let Some(cached) = state.cache.get::<CachedOrder>("order", &key).await {
+        debug_trace!(order_id = %id, "Cached order found");
+        return Some(cached);
+    }
+    let record = state.orders.find(id).await?;
+    debug_trace!(order_id = %id, "Stored order found");
+    let found = CachedOrder {
+        version: record.version,
+        order: OrderResponse::from(record),
+    };
+    state.cache.set("order", &key, &found).await;
+    Some(found)
+}
+
+#[utoipa::path(
+    put,
+    path = "/orders/{id}",
+    tag = "orders",
+    params(
+        ("id" = String, Path, format = Uuid, description = "Order ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag from a previous read; fails with 412 if the order changed since")
+    ),
+    request_body = UpdateOrderRequest,
+    responses(
+        (status = 200, description = "Order updated", body = OrderResponse,
+            headers(("ETag" = String, description = "New version of the order"))),
+        (status = 400, description = "Malformed order ID or unknown status", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Not the order's owner or an admin", body = ErrorResponse),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 409, description = "Order is cancelled", body = ErrorResponse),
+        (status = 412, description = "Order was modified since the `If-Match` version", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, principal, if_match, format, payload), fields(order_id = %id, order.status = %payload.status))]
+pub async fn update_order(
+    State(state): State<Arc<AppState>>,
+    IdPath(id): IdPath<OrderId>,
+    principal: auth::Principal,
+    if_match: concurrency::IfMatch,
+    format: ResponseFormat,
+    Json(payload): Json<UpdateOrderRequest>,
+) -> impl IntoResponse {
+    if !ORDER_STATUSES.contains(&payload.status.as_str()) {
+        return AppError::BadRequest(format!(
+            "status must be one of {}",
+            ORDER_STATUSES.join(", ")
+        ))
+        .into_response();
+    }
+
+    let not_found = || {
+        AppError::NotFound("Order not found".to_string()).into_response()
+    };
+    // Ownership never changes, so it can be checked before the versioned write
+    match state.orders.find(id).await {
+        Some(order) if order.user_id != principal.user_id && principal.role != auth::Role::Admin => {
+            return AppError::Forbidden("Orders can only be updated by their owner".to_string()).into_response();
+        }
+        Some(_) => {}
+        None => return not_found(),
+    }
+
+    match state.orders.update_status(id, &payload.status, &if_match).await {
+        Err(e) => {
+            error_trace!(order_id = %id, error = %e, "Failed to update order");
+            AppError::Internal("Failed to update order".to_string()).into_response()
+        }
+        Ok(concurrency::UpdateOutcome::Updated(record)) => {
+            state.concurrency.record_update("order");
+            state.cache.invalidate("order", &id.to_string()).await;
+            info_trace!(order_id = %id, version = record.version, "Order updated");
+            let etag = concurrency::etag(record.version);
+            ([(header::ETAG, etag)], format.body(OrderResponse::from(record))).into_response()
+        }
+        Ok(concurrency::UpdateOutcome::Stale { current_version }) => state
+            .concurrency
+            .record_conflict("order", &if_match, current_version)
+            .into_response(),
+        Ok(concurrency::UpdateOutcome::NotFound) => not_found(),
+        Ok(concurrency::UpdateOutcome::Rejected(message)) => {
+            warn_trace!(order_id = %id, reason = %message, "Order update rejected");
+            AppError::Conflict(message).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/cancel",
+    tag = "orders",
+    params(("id" = String, Path, format = Uuid, description = "Order ID")),
+    responses(
+        (status = 200, description = "Order cancelled", body = OrderResponse),
+        (status = 400, description = "Malformed order ID", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Not the order's owner or an admin", body = ErrorResponse),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 409, description = "Order already cancelled", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, principal, format), fields(order_id = %id))]
+pub async fn cancel_order(
+    State(state): State<Arc<AppState>>,
+    IdPath(id): IdPath<OrderId>,
+    principal: auth::Principal,
+    format: ResponseFormat,
+) -> impl IntoResponse {
+    match cancel_stored_order(&state, id, &principal).await {
+        Ok(order) => format.body(order).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Cancel an order for its owner or an admin, and announce it; shared by HTTP and gRPC
+pub async fn cancel_stored_order(
+    state: &AppState,
+    id: OrderId,
+    principal: &auth::Principal,
+) -> Result<OrderResponse, AppError> {
+    // Ownership never changes, so it can be checked before cancelling
+    match state.orders.find(id).await {
+        Some(order) if order.user_id != principal.user_id && principal.role != auth::Role::Admin => {
+            warn_trace!(order_id = %id, usr.id = %principal.user_id, "Order cancellation denied: not the owner");
+            return Err(AppError::Forbidden("Orders can only be cancelled by their owner".to_string()));
+        }
+        Some(_) => {}
+        None => {
+            warn_trace!(order_id = %id, "Order cancellation failed: not found");
+            return Err(AppError::NotFound("Order not found".to_string()));
+        }
+    }
+
+    match state.orders.cancel(id).await {
+        Err(e) => {
+            error_trace!(order_id = %id, error = %e, "Failed to cancel order");
+            Err(AppError::Internal("Failed to cancel order".to_string()))
+        }
+        Ok(orders::CancelOutcome::Cancelled(record)) => {
+            state.cache.invalidate("order", &id.to_string()).await;
+            state.events.publish(events::DomainEvent::OrderCancelled {
+                order_id: record.order_id,
+                user_id: record.user_id,
+            });
+            info_trace!(order_id = %id, "Order cancelled");
+            dogstatsd::count("orders.cancelled", 1, &[format!("currency:{}", record.currency.as_str())]);
+            Ok(OrderResponse::from(record))
+        }
+        Ok(orders::CancelOutcome::AlreadyCancelled) => {
+            warn_trace!(order_id = %id, "Order cancellation failed: already cancelled");
+            Err(AppError::Conflict("Order already cancelled".to_string()))
+        }
+        Ok(orders::CancelOutcome::NotFound) => {
+            warn_trace!(order_id = %id, "Order cancellation failed: not found");
+            Err(AppError::NotFound("Order not found".to_string()))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/export",
+    tag = "orders",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "All orders as a CSV or parquet file, streamed",
+            content((String = "text/csv"), (Vec<u8> = "application/vnd.apache.parquet"))),
+        (status = 400, description = "Unknown format", body = ErrorResponse),
+        (status = 500, description = "Export could not start", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state))]
+pub async fn export_orders(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let export_format = query.format.unwrap_or(export::ExportFormat::Csv);
+    let orders = state.orders.all().await;
+    info_trace!(export.format = export_format.as_str(), export.total_rows = orders.len(), "Exporting orders");
+
+    match export::export_stream(orders, export_format) {
+        Ok(stream) => (
+            [
+                (header::CONTENT_TYPE, export_format.content_type().to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"orders.{}\"", export_format.as_str()),
+                ),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response(),
+        Err(e) => {
+            error_trace!(error = %e, "Failed to start order export");
+            AppError::Internal("Failed to export orders".to_string()).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/orders",
+    tag = "orders",
+    params(AnalyticsQuery),
+    responses(
+        (status = 200, description = "Totals, averages and top products for orders in the window", body = analytics::OrderAnalytics),
+        (status = 400, description = "Invalid window", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, format))]
+pub async fn order_analytics(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AnalyticsQuery>,
+    format: ResponseFormat,
+) -> impl IntoResponse {
+    let window = query.window.unwrap_or_else(|| "1h".to_string());
+    let duration = match analytics::parse_window(&window) {
+        Ok(duration) => duration,
+        Err(message) => {
+            warn_trace!(window = %window, "Invalid analytics window");
+            return AppError::BadRequest(message).into_response();
+        }
+    };
+
+    let result = analytics::order_analytics(&state.orders, &window, duration).await;
+    info_trace!(
+        window = %window,
+        order_count = result.order_count,
+        "Order analytics computed"
+    );
+    format.body(result).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{seed_order, test_app};
+    use crate::user_api::User;
+    use std::sync::Mutex;
+    use std::time::Instant;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    /// A span's name, when it opened, and when it closed if it has
+    type Interval = (&'static str, Instant, Option<Instant>);
+
+    /// Records when each span opens and closes
+    #[derive(Clone, Default)]
+    struct SpanTimes(Arc<Mutex<Vec<Interval>>>);
+
+    /// Position of a span in [`SpanTimes`]
+    struct Recorded(usize);
+
+    impl SpanTimes {
+        /// Open and close times of the finished spans called `name`
+        fn of(&self, name: &str) -> Vec<(Instant, Instant)> {
+            let intervals = self.0.lock().unwrap();
+            intervals
+                .iter()
+                .filter(|interval| interval.0 == name)
+                .filter_map(|&(_, start, end)| Some((start, end?)))
+                .collect()
+        }
+    }
+
+    impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> tracing_subscriber::Layer<S> for SpanTimes {
+        fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, ctx: Context<'_, S>) {
+            let mut intervals = self.0.lock().unwrap();
+            intervals.push((attrs.metadata().name(), Instant::now(), None));
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(Recorded(intervals.len() - 1));
+            }
+        }
+
+        fn on_close(&self, id: tracing::span::Id, ctx: Context<'_, S>) {
+            if let Some(Recorded(index)) = ctx.span(&id).and_then(|span| span.extensions_mut().remove::<Recorded>()) {
+                self.0.lock().unwrap()[index].2 = Some(Instant::now());
+            }
+        }
+    }
+
+    fn item(product: &str, quantity: u32) -> OrderItem {
+        OrderItem {
+            product_id: ProductId::try_from(product.to_string()).unwrap(),
+            quantity,
+            price: rust_decimal::Decimal::ONE,
+        }
+    }
+
+    #[tokio::test]
+    async fn payment_and_inventory_spans_overlap() {
+        let times = SpanTimes::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(times.clone()));
+        let config = config::ServiceConfig::load(None).unwrap();
+        let amount = rust_decimal::Decimal::TEN;
+
+        pay_and_check_inventory(UserId::generate(), &[item("prod-001", 2)], amount, money::Currency::Usd, PaymentGateway::V2, &config)
+            .await
+            .unwrap();
+
+        let (payment_start, payment_end) = times.of("process_payment")[0];
+        let (inventory_start, inventory_end) = times.of("check_inventory")[0];
+        assert!(payment_start < inventory_end);
+        assert!(inventory_start < payment_end);
+    }
+
+    #[tokio::test]
+    async fn inventory_checks_products_a_few_at_a_time() {
+        let times = SpanTimes::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(times.clone()));
+        let mut items: Vec<_> = (0..10).map(|n| item(&format!("prod-{:03}", n), 1)).collect();
+        items.push(item("prod-003", 500));
+        items.push(item("prod-007", 501));
+
+        let error = check_inventory(&items).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+        assert!(error.to_string().contains("prod-003, prod-007"));
+
+        let checks = times.of("check_product_stock");
+        assert_eq!(checks.len(), 10);
+        let most_in_flight = checks
+            .iter()
+            .map(|&(start, _)| checks.iter().filter(|&&(other_start, other_end)| other_start <= start && start < other_end).count())
+            .max();
+        assert_eq!(most_in_flight, Some(INVENTORY_CONCURRENCY));
+    }
+
+    #[test]
+    fn client_types_match_the_api() {
+        let order = OrderResponse {
+            order_id: OrderId::generate(),
+            user_id: UserId::generate(),
+            total_amount: rust_decimal::Decimal::new(3998, 2),
+            currency: money::Currency::Eur,
+            status: "confirmed".to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let json = serde_json::to_value(&order).unwrap();
+        let client_order: rust_datadog_otel::client::Order = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(client_order.currency, "EUR");
+        assert_eq!(serde_json::to_value(&client_order).unwrap(), json);
+
+        let new_order = rust_datadog_otel::client::NewOrder {
+            user_id: order.user_id.to_string().parse().unwrap(),
+            currency: "GBP".to_string(),
+            items: vec![rust_datadog_otel::client::OrderLine {
+                product_id: "prod-001".to_string(),
+                quantity: 2,
+                price: rust_decimal::Decimal::new(1999, 2),
+            }],
+        };
+        let request: OrderRequest = serde_json::from_value(serde_json::to_value(&new_order).unwrap()).unwrap();
+        assert_eq!(request.currency, money::Currency::Gbp);
+        assert_eq!(request.items[0].product_id.to_string(), "prod-001");
+
+        let user = User {
+            id: UserId::generate(),
+            name: "Ada".to_string(),
+            email: "ada@example.com".to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let client_user: rust_datadog_otel::client::User = serde_json::from_value(serde_json::to_value(&user).unwrap()).unwrap();
+        assert_eq!(client_user.id.to_string(), user.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn first_failure_fails_the_order() {
+        let config = config::ServiceConfig::load(None).unwrap();
+        let settle = |items: Vec<OrderItem>, amount| {
+            let config = &config;
+            async move {
+                pay_and_check_inventory(UserId::generate(), &items, amount, money::Currency::Usd, PaymentGateway::V2, config)
+                    .await
+                    .map_err(|e| e.status())
+            }
+        };
+
+        assert_eq!(settle(vec![item("prod-001", 300), item("prod-001", 300)], rust_decimal::Decimal::TEN).await, Err(StatusCode::CONFLICT));
+        assert_eq!(
+            settle(vec![item("prod-001", 1)], PAYMENT_LIMIT + rust_decimal::Decimal::ONE).await,
+            Err(StatusCode::PAYMENT_REQUIRED)
+        );
+    }
+
+    #[tokio::test]
+    async fn orders_are_cancelled_by_their_owner_only() {
+        use tower::ServiceExt;
+
+        let (state, app) = test_app().await;
+        let owner = UserId::generate();
+        let order_id = seed_order(&state, owner).await;
+        let cancel = |token: Option<String>| {
+            let mut request = axum::http::Request::post(format!("/api/v1/orders/{}/cancel", order_id));
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        assert_eq!(cancel(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let stranger = state.auth.issue(UserId::generate(), auth::Role::User).unwrap();
+        assert_eq!(cancel(Some(stranger)).await.unwrap().status(), StatusCode::FORBIDDEN);
+        let token = state.auth.issue(owner, auth::Role::User).unwrap();
+        assert_eq!(cancel(Some(token.clone())).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(state.orders.find(order_id).await.unwrap().status, "cancelled");
+        assert_eq!(cancel(Some(token)).await.unwrap().status(), StatusCode::CONFLICT);
+    }
+}
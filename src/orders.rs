@@ -0,0 +1,260 @@
+use crate::concurrency::{IfMatch, UpdateOutcome};
+use crate::cost;
+use crate::ids::{OrderId, ProductId, UserId};
+use crate::money::Currency;
+use crate::repository::StoreError;
+use chrono::{DateTime, Utc};
+use rust_datadog_otel::error_trace;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+/// One line of a stored order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub product_id: ProductId,
+    pub quantity: u32,
+    /// Unit price in the order's currency
+    pub price: Decimal,
+}
+
+/// Order as persisted by `POST /orders`
+#[derive(Debug, Clone)]
+pub struct OrderRecord {
+    pub order_id: OrderId,
+    pub user_id: UserId,
+    pub lines: Vec<OrderLine>,
+    pub total_amount: Decimal,
+    pub currency: Currency,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    /// Bumped on every update; the order's ETag
+    pub version: u64,
+}
+
+/// Status of cancelled orders, which no update can change
+pub const CANCELLED: &str = "cancelled";
+
+/// Result of [`OrderRepository::cancel`]
+#[derive(Debug)]
+pub enum CancelOutcome {
+    Cancelled(OrderRecord),
+    AlreadyCancelled,
+    NotFound,
+}
+
+/// Where orders are kept; [`OrderRepository`] traces and meters calls around it
+///
+/// Listings are oldest first, by `(created_at, order_id)`.
+#[async_trait::async_trait]
+pub trait OrderStore: Send + Sync + Debug {
+    async fn insert(&self, order: OrderRecord) -> Result<(), StoreError>;
+    async fn get(&self, order_id: OrderId) -> Result<Option<OrderRecord>, StoreError>;
+    /// Mark an order cancelled, atomically with the check that it isn't already
+    async fn cancel(&self, order_id: OrderId) -> Result<CancelOutcome, StoreError>;
+    /// Set the status and bump the version if `if_match` still matches it, atomically
+    async fn update_status(
+        &self,
+        order_id: OrderId,
+        status: &str,
+        if_match: &IfMatch,
+    ) -> Result<UpdateOutcome<OrderRecord>, StoreError>;
+    async fn all(&self) -> Result<Vec<OrderRecord>, StoreError>;
+    /// Up to `limit + 1` of a user's orders after the `(created_at, order_id)` key `after`
+    async fn for_user(
+        &self,
+        user_id: UserId,
+        after: Option<(DateTime<Utc>, OrderId)>,
+        limit: usize,
+    ) -> Result<Vec<OrderRecord>, StoreError>;
+    async fn created_since(&self, since: DateTime<Utc>) -> Result<Vec<OrderRecord>, StoreError>;
+    /// Backend name for logs
+    fn backend(&self) -> &'static str;
+}
+
+/// Orders in process memory, kept in creation order and lost on restart; used without `DATABASE_URL`
+#[derive(Debug, Default)]
+pub struct MemoryOrderStore {
+    orders: RwLock<Vec<OrderRecord>>,
+}
+
+#[async_trait::async_trait]
+impl OrderStore for MemoryOrderStore {
+    async fn insert(&self, order: OrderRecord) -> Result<(), StoreError> {
+        self.orders.write().await.push(order);
+        Ok(())
+    }
+
+    async fn get(&self, order_id: OrderId) -> Result<Option<OrderRecord>, StoreError> {
+        let orders = self.orders.read().await;
+        Ok(orders.iter().find(|order| order.order_id == order_id).cloned())
+    }
+
+    async fn cancel(&self, order_id: OrderId) -> Result<CancelOutcome, StoreError> {
+        let mut orders = self.orders.write().await;
+        Ok(match orders.iter_mut().find(|order| order.order_id == order_id) {
+            Some(order) if order.status == CANCELLED => CancelOutcome::AlreadyCancelled,
+            Some(order) => {
+                order.status = CANCELLED.to_string();
+                order.version += 1;
+                CancelOutcome::Cancelled(order.clone())
+            }
+            None => CancelOutcome::NotFound,
+        })
+    }
+
+    async fn update_status(
+        &self,
+        order_id: OrderId,
+        status: &str,
+        if_match: &IfMatch,
+    ) -> Result<UpdateOutcome<OrderRecord>, StoreError> {
+        let mut orders = self.orders.write().await;
+        let Some(order) = orders.iter_mut().find(|order| order.order_id == order_id) else {
+            return Ok(UpdateOutcome::NotFound);
+        };
+        if let Some(outcome) = status_precondition(order, if_match) {
+            return Ok(outcome);
+        }
+        order.status = status.to_string();
+        order.version += 1;
+        Ok(UpdateOutcome::Updated(order.clone()))
+    }
+
+    async fn all(&self) -> Result<Vec<OrderRecord>, StoreError> {
+        Ok(self.orders.read().await.clone())
+    }
+
+    async fn for_user(
+        &self,
+        user_id: UserId,
+        after: Option<(DateTime<Utc>, OrderId)>,
+        limit: usize,
+    ) -> Result<Vec<OrderRecord>, StoreError> {
+        let orders = self.orders.read().await;
+        let mut page: Vec<OrderRecord> = orders
+            .iter()
+            .filter(|order| order.user_id == user_id)
+            .filter(|order| after.is_none_or(|after| (order.created_at, order.order_id) > after))
+            .cloned()
+            .collect();
+        page.sort_by_key(|order| (order.created_at, order.order_id));
+        page.truncate(limit + 1);
+        Ok(page)
+    }
+
+    async fn created_since(&self, since: DateTime<Utc>) -> Result<Vec<OrderRecord>, StoreError> {
+        let orders = self.orders.read().await;
+        // Sorted by creation time, so the window is a suffix
+        let start = orders.partition_point(|order| order.created_at < since);
+        Ok(orders[start..].to_vec())
+    }
+
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Why a status update can't apply to `order`, if it can't; shared by the stores
+pub fn status_precondition(order: &OrderRecord, if_match: &IfMatch) -> Option<UpdateOutcome<OrderRecord>> {
+    if !if_match.matches(order.version) {
+        return Some(UpdateOutcome::Stale {
+            current_version: order.version,
+        });
+    }
+    // Cancelled orders stay cancelled; cancelling goes through `cancel`
+    (order.status == CANCELLED).then(|| UpdateOutcome::Rejected("Order is cancelled".to_string()))
+}
+
+/// Order repository over an [`OrderStore`] backend
+///
+/// Reads that return no error type (`find`, `all`, ...) log a backend failure
+/// and answer as if nothing was found, as user lookups do.
+#[derive(Debug)]
+pub struct OrderRepository {
+    store: Arc<dyn OrderStore>,
+}
+
+impl OrderRepository {
+    pub fn new(store: Arc<dyn OrderStore>) -> Self {
+        Self { store }
+    }
+
+    pub fn backend(&self) -> &'static str {
+        self.store.backend()
+    }
+
+    #[instrument(skip(self, order), fields(order_id = %order.order_id))]
+    pub async fn insert(&self, order: OrderRecord) -> Result<(), StoreError> {
+        cost::record_db_call();
+        self.store.insert(order).await
+    }
+
+    /// Mark an order cancelled
+    #[instrument(skip(self), fields(order_id = %order_id))]
+    pub async fn cancel(&self, order_id: OrderId) -> Result<CancelOutcome, StoreError> {
+        cost::record_db_call();
+        self.store.cancel(order_id).await
+    }
+
+    /// Stored order by ID
+    #[instrument(skip(self), fields(order_id = %order_id))]
+    pub async fn find(&self, order_id: OrderId) -> Option<OrderRecord> {
+        cost::record_db_call();
+        logged(self.store.get(order_id).await)?
+    }
+
+    /// Move an order to `status` if `if_match` still matches its version
+    ///
+    /// Cancelled orders stay cancelled; cancelling goes through [`Self::cancel`].
+    #[instrument(skip(self, if_match), fields(order_id = %order_id))]
+    pub async fn update_status(
+        &self,
+        order_id: OrderId,
+        status: &str,
+        if_match: &IfMatch,
+    ) -> Result<UpdateOutcome<OrderRecord>, StoreError> {
+        cost::record_db_call();
+        self.store.update_status(order_id, status, if_match).await
+    }
+
+    /// Every order, oldest first
+    #[instrument(skip(self))]
+    pub async fn all(&self) -> Vec<OrderRecord> {
+        cost::record_db_call();
+        logged(self.store.all().await).unwrap_or_default()
+    }
+
+    /// Up to `limit` of a user's orders after the `(created_at, order_id)` key
+    /// `after`, oldest first, and whether more follow
+    #[instrument(skip(self, after), fields(user_id = %user_id))]
+    pub async fn page_for_user(
+        &self,
+        user_id: UserId,
+        after: Option<(DateTime<Utc>, OrderId)>,
+        limit: usize,
+    ) -> (Vec<OrderRecord>, bool) {
+        cost::record_db_call();
+        let mut page = logged(self.store.for_user(user_id, after, limit).await).unwrap_or_default();
+        let has_more = page.len() > limit;
+        page.truncate(limit);
+        (page, has_more)
+    }
+
+    /// Orders created at or after `since`, oldest first
+    #[instrument(skip(self))]
+    pub async fn created_since(&self, since: DateTime<Utc>) -> Vec<OrderRecord> {
+        cost::record_db_call();
+        logged(self.store.created_since(since).await).unwrap_or_default()
+    }
+}
+
+/// `result`'s value, logging the error of a failed backend call
+fn logged<T>(result: Result<T, StoreError>) -> Option<T> {
+    result
+        .map_err(|e| error_trace!(error = %e, "Order storage call failed"))
+        .ok()
+}
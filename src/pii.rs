@@ -0,0 +1,280 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use crate::secrets::{SecretString, Secrets};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use rust_datadog_otel::warn_trace;
+use sha2::Sha256;
+use std::fmt;
+
+const NONCE_LEN: usize = 12;
+
+/// Errors raised while encrypting or decrypting PII fields
+#[derive(Debug)]
+pub enum PiiError {
+    InvalidKey(String),
+    UnknownKeyId(String),
+    MalformedCiphertext,
+    Crypto,
+}
+
+impl fmt::Display for PiiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PiiError::InvalidKey(reason) => write!(f, "invalid encryption key: {}", reason),
+            PiiError::UnknownKeyId(id) => write!(f, "unknown encryption key id: {}", id),
+            PiiError::MalformedCiphertext => write!(f, "malformed ciphertext"),
+            PiiError::Crypto => write!(f, "encryption operation failed"),
+        }
+    }
+}
+
+impl std::error::Error for PiiError {}
+
+/// A named AES-256 data key
+struct DataKey {
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+/// Source of encryption key material
+///
+/// Implemented by the environment provider today; a KMS-backed provider can
+/// plug in here without touching the repository layer.
+pub trait KeyProvider {
+    /// Data keys as `(key_id, key_bytes)`, active key first
+    fn data_keys(&self) -> Result<Vec<(String, Vec<u8>)>, PiiError>;
+
+    /// Key used for deterministic lookup hashes
+    fn hash_key(&self) -> Result<Vec<u8>, PiiError>;
+}
+
+/// Reads keys through the [`Secrets`] chain (file, mounted volume, env)
+///
+/// - `PII_ENCRYPTION_KEYS`: comma-separated `key_id:base64_key` pairs, active key first.
+///   Older keys stay listed so existing records can be decrypted and rotated.
+/// - `PII_HASH_KEY`: base64 HMAC key for lookup hashes
+///
+/// When unset, an ephemeral key is generated (data won't survive a restart).
+pub struct SecretsKeyProvider<'a> {
+    secrets: &'a Secrets,
+}
+
+impl<'a> SecretsKeyProvider<'a> {
+    pub fn new(secrets: &'a Secrets) -> Self {
+        Self { secrets }
+    }
+
+    fn load(&self, key: &str) -> Result<Option<SecretString>, PiiError> {
+        self.secrets
+            .get(key)
+            .map_err(|e| PiiError::InvalidKey(e.to_string()))
+    }
+}
+
+impl KeyProvider for SecretsKeyProvider<'_> {
+    fn data_keys(&self) -> Result<Vec<(String, Vec<u8>)>, PiiError> {
+        let Some(raw) = self.load("PII_ENCRYPTION_KEYS")? else {
+            warn_trace!("PII_ENCRYPTION_KEYS not set, using an ephemeral encryption key");
+            let key = Aes256Gcm::generate_key(OsRng);
+            return Ok(vec![("ephemeral".to_string(), key.to_vec())]);
+        };
+
+        raw.expose_secret()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (id, encoded) = entry.split_once(':').ok_or_else(|| {
+                    PiiError::InvalidKey("expected key_id:base64_key entries".to_string())
+                })?;
+                let bytes = BASE64
+                    .decode(encoded)
+                    .map_err(|e| PiiError::InvalidKey(format!("key '{}': {}", id, e)))?;
+                Ok((id.to_string(), bytes))
+            })
+            .collect()
+    }
+
+    fn hash_key(&self) -> Result<Vec<u8>, PiiError> {
+        match self.load("PII_HASH_KEY")? {
+            Some(encoded) => BASE64
+                .decode(encoded.expose_secret())
+                .map_err(|e| PiiError::InvalidKey(format!("PII_HASH_KEY: {}", e))),
+            None => {
+                warn_trace!("PII_HASH_KEY not set, using an ephemeral lookup hash key");
+                Ok(Aes256Gcm::generate_key(OsRng).to_vec())
+            }
+        }
+    }
+}
+
+/// Field-level encryption for PII columns
+///
+/// Ciphertexts are stored as `key_id:base64(nonce || ciphertext)` so records
+/// encrypted under a previous key can still be read after rotation.
+pub struct FieldCipher {
+    keys: Vec<DataKey>,
+    hash_key: Vec<u8>,
+}
+
+impl fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldCipher")
+            .field("active_key_id", &self.active_key_id())
+            .field("key_count", &self.keys.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FieldCipher {
+    pub fn from_provider(provider: &dyn KeyProvider) -> Result<Self, PiiError> {
+        let keys = provider
+            .data_keys()?
+            .into_iter()
+            .map(|(id, bytes)| {
+                if bytes.len() != 32 {
+                    return Err(PiiError::InvalidKey(format!(
+                        "key '{}' must be 32 bytes, got {}",
+                        id,
+                        bytes.len()
+                    )));
+                }
+                let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes));
+                Ok(DataKey { id, cipher })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if keys.is_empty() {
+            return Err(PiiError::InvalidKey("no encryption keys configured".to_string()));
+        }
+
+        Ok(Self {
+            keys,
+            hash_key: provider.hash_key()?,
+        })
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.keys[0].id
+    }
+
+    /// Encrypt a value with the active key
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, PiiError> {
+        let key = &self.keys[0];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = key
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| PiiError::Crypto)?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(format!("{}:{}", key.id, BASE64.encode(payload)))
+    }
+
+    /// Decrypt a value produced by [`FieldCipher::encrypt`] under any configured key
+    pub fn decrypt(&self, stored: &str) -> Result<String, PiiError> {
+        let (key_id, encoded) = stored
+            .split_once(':')
+            .ok_or(PiiError::MalformedCiphertext)?;
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.id == key_id)
+            .ok_or_else(|| PiiError::UnknownKeyId(key_id.to_string()))?;
+
+        let payload = BASE64
+            .decode(encoded)
+            .map_err(|_| PiiError::MalformedCiphertext)?;
+        if payload.len() < NONCE_LEN {
+            return Err(PiiError::MalformedCiphertext);
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+
+        let plaintext = key
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| PiiError::Crypto)?;
+        String::from_utf8(plaintext).map_err(|_| PiiError::MalformedCiphertext)
+    }
+
+    /// Whether a stored value was encrypted with a key other than the active one
+    pub fn needs_rotation(&self, stored: &str) -> bool {
+        stored
+            .split_once(':')
+            .is_some_and(|(key_id, _)| key_id != self.active_key_id())
+    }
+
+    /// Deterministic keyed hash for equality lookups on encrypted fields
+    ///
+    /// Input is normalized (trimmed, lowercased) so lookups are case-insensitive.
+    pub fn lookup_hash(&self, value: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.hash_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(value.trim().to_lowercase().as_bytes());
+        BASE64.encode(mac.finalize().into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fixed keys, active first
+    struct StaticKeys(Vec<(&'static str, u8)>);
+
+    impl KeyProvider for StaticKeys {
+        fn data_keys(&self) -> Result<Vec<(String, Vec<u8>)>, PiiError> {
+            Ok(self.0.iter().map(|(id, byte)| (id.to_string(), vec![*byte; 32])).collect())
+        }
+
+        fn hash_key(&self) -> Result<Vec<u8>, PiiError> {
+            Ok(b"lookup-hash-key".to_vec())
+        }
+    }
+
+    fn cipher(keys: Vec<(&'static str, u8)>) -> FieldCipher {
+        FieldCipher::from_provider(&StaticKeys(keys)).unwrap()
+    }
+
+    #[test]
+    fn decrypts_under_active_and_retired_keys() {
+        let old = cipher(vec![("k1", 1)]);
+        let rotated = cipher(vec![("k2", 2), ("k1", 1)]);
+
+        let stored = old.encrypt("ada@example.com").unwrap();
+        assert!(stored.starts_with("k1:"));
+        assert_eq!(old.decrypt(&stored).unwrap(), "ada@example.com");
+        assert_eq!(rotated.decrypt(&stored).unwrap(), "ada@example.com");
+        assert!(rotated.needs_rotation(&stored));
+
+        let fresh = rotated.encrypt("ada@example.com").unwrap();
+        assert_ne!(fresh, rotated.encrypt("ada@example.com").unwrap(), "nonces are random");
+        assert!(!rotated.needs_rotation(&fresh));
+        assert!(matches!(old.decrypt(&fresh), Err(PiiError::UnknownKeyId(id)) if id == "k2"));
+    }
+
+    #[test]
+    fn rejects_tampered_and_malformed_ciphertexts() {
+        let cipher = cipher(vec![("k1", 1)]);
+        let stored = cipher.encrypt("ada@example.com").unwrap();
+        let (key_id, encoded) = stored.split_once(':').unwrap();
+        let mut payload = BASE64.decode(encoded).unwrap();
+        *payload.last_mut().unwrap() ^= 1;
+        let tampered = format!("{}:{}", key_id, BASE64.encode(payload));
+
+        assert!(matches!(cipher.decrypt(&tampered), Err(PiiError::Crypto)));
+        assert!(matches!(cipher.decrypt("no-key-id"), Err(PiiError::MalformedCiphertext)));
+        assert!(matches!(cipher.decrypt("k1:AAAA"), Err(PiiError::MalformedCiphertext)));
+    }
+
+    #[test]
+    fn lookup_hashes_ignore_case_and_whitespace() {
+        let cipher = cipher(vec![("k1", 1)]);
+        assert_eq!(cipher.lookup_hash(" Ada@Example.COM\n"), cipher.lookup_hash("ada@example.com"));
+        assert_ne!(cipher.lookup_hash("ada@example.com"), cipher.lookup_hash("bob@example.com"));
+    }
+}
@@ -0,0 +1,398 @@
+use crate::auth::Role;
+use crate::concurrency::{IfMatch, UpdateOutcome};
+use crate::ids::{OrderId, UserId};
+use crate::orders::{self, CancelOutcome, OrderRecord, OrderStore};
+use crate::repository::{Repository, StoreError, StoredUser};
+use crate::sql::{observe, DbmPropagation, MeteredPool, QueryTracer};
+use chrono::{DateTime, Utc};
+use rust_datadog_otel::info_trace;
+use sqlx::postgres::{PgPoolOptions, PgRow};
+use sqlx::{Connection, Postgres, Row};
+use std::collections::HashMap;
+
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
+/// Created at startup when missing, like [`ORDERS_SCHEMA`]; there are no migrations beyond these
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS users (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    email_ciphertext TEXT NOT NULL,
+    email_hash TEXT NOT NULL UNIQUE,
+    password_hash TEXT,
+    role TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    version BIGINT NOT NULL
+)";
+
+const INSERT: &str = "INSERT INTO users (id, name, email_ciphertext, email_hash, password_hash, role, created_at, version) \
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+const SELECT_BY_ID: &str = "SELECT id, name, email_ciphertext, email_hash, password_hash, role, created_at, version \
+    FROM users WHERE id = $1";
+const SELECT_BY_ID_FOR_UPDATE: &str = "SELECT id, name, email_ciphertext, email_hash, password_hash, role, created_at, version \
+    FROM users WHERE id = $1 FOR UPDATE";
+const SELECT_BY_EMAIL_HASH: &str = "SELECT id, name, email_ciphertext, email_hash, password_hash, role, created_at, version \
+    FROM users WHERE email_hash = $1";
+const UPDATE_EMAIL: &str = "UPDATE users SET email_ciphertext = $2 WHERE id = $1";
+const UPDATE_NAME: &str = "UPDATE users SET name = $2, version = $3 WHERE id = $1";
+const SELECT_CREATED_BEFORE: &str = "SELECT id FROM users WHERE created_at < $1";
+const DELETE_MANY: &str = "DELETE FROM users WHERE id = ANY($1)";
+const SELECT_NAMES: &str = "SELECT id, name FROM users";
+const COUNT: &str = "SELECT COUNT(*) FROM users";
+
+/// Lines are a JSON array of `product_id`, `quantity` and `price`; amounts are decimal strings
+const ORDERS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS orders (
+    order_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    lines TEXT NOT NULL,
+    total_amount TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    version BIGINT NOT NULL
+)";
+const ORDERS_USER_INDEX: &str = "CREATE INDEX IF NOT EXISTS orders_user_created ON orders (user_id, created_at, order_id)";
+const ORDERS_CREATED_INDEX: &str = "CREATE INDEX IF NOT EXISTS orders_created ON orders (created_at, order_id)";
+
+const INSERT_ORDER: &str = "INSERT INTO orders (order_id, user_id, lines, total_amount, currency, status, created_at, version) \
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+const SELECT_ORDER: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE order_id = $1";
+const SELECT_ORDER_FOR_UPDATE: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE order_id = $1 FOR UPDATE";
+const UPDATE_ORDER_STATUS: &str = "UPDATE orders SET status = $2, version = $3 WHERE order_id = $1";
+const SELECT_ORDERS: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders ORDER BY created_at, order_id";
+const SELECT_USER_ORDERS: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE user_id = $1 ORDER BY created_at, order_id LIMIT $2";
+const SELECT_USER_ORDERS_AFTER: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE user_id = $1 AND (created_at, order_id) > ($2, $3) ORDER BY created_at, order_id LIMIT $4";
+const SELECT_ORDERS_SINCE: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE created_at >= $1 ORDER BY created_at, order_id";
+
+/// Users and orders in Postgres, through one sqlx connection pool
+///
+/// Queries run in `postgres.query` spans from [`QueryTracer`], whose
+/// parameterized statements match Database Monitoring's normalized ones.
+///
+/// Configuration:
+/// - `DATABASE_URL`: Postgres connection URL, read through `Secrets` (so `DATABASE_URL_FILE` and
+///   `SECRETS_DIR` work); users and orders stay in memory when unset
+/// - `DATABASE_MAX_CONNECTIONS`: pool size (default 10)
+/// - `DD_DBM_PROPAGATION_MODE`: `disabled` (default), `service` or `full`
+#[derive(Debug)]
+pub struct PostgresRepository {
+    pool: MeteredPool<Postgres>,
+    queries: QueryTracer,
+}
+
+impl PostgresRepository {
+    pub const BACKEND: &'static str = "postgres";
+
+    /// Connect to `url` and create the users and orders tables if missing
+    pub async fn connect(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let max_connections = match std::env::var("DATABASE_MAX_CONNECTIONS") {
+            Ok(value) => value
+                .parse::<u32>()
+                .map_err(|e| format!("DATABASE_MAX_CONNECTIONS: {}", e))?,
+            Err(_) => DEFAULT_MAX_CONNECTIONS,
+        };
+        let dbm = DbmPropagation::from_env()?;
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections.max(1))
+            .connect(url)
+            .await
+            .map_err(|e| format!("DATABASE_URL: {}", e))?;
+        let database = pool.connect_options().get_database().unwrap_or("postgres").to_string();
+
+        let queries = QueryTracer::new("postgresql", database, dbm);
+        let repository = Self {
+            pool: MeteredPool::new(pool, &queries),
+            queries,
+        };
+        for statement in [SCHEMA, ORDERS_SCHEMA, ORDERS_USER_INDEX, ORDERS_CREATED_INDEX] {
+            let span = repository.queries.span("CREATE", statement);
+            let sql = repository.queries.sql(&span, statement);
+            let query = sqlx::query(&sql).persistent(false);
+            observe(span, async { query.execute(&mut *repository.pool.acquire().await?).await }).await?;
+        }
+        info_trace!(db.name = %repository.queries.database(), max_connections, dbm = ?dbm, "Postgres user and order storage ready");
+        Ok(repository)
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for PostgresRepository {
+    async fn insert(&self, user: StoredUser) -> Result<(), StoreError> {
+        let created_at = DateTime::parse_from_rfc3339(&user.created_at)?.with_timezone(&Utc);
+        let span = self.queries.span("INSERT", INSERT);
+        let sql = self.queries.sql(&span, INSERT);
+        let query = sqlx::query(&sql)
+            .persistent(self.queries.persistent())
+            .bind(user.id)
+            .bind(user.name)
+            .bind(user.email_ciphertext)
+            .bind(user.email_hash)
+            .bind(user.password_hash)
+            .bind(user.role.as_str())
+            .bind(created_at)
+            .bind(user.version as i64);
+        observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(())
+    }
+
+    async fn get(&self, id: UserId) -> Result<Option<StoredUser>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_BY_ID);
+        let sql = self.queries.sql(&span, SELECT_BY_ID);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(id);
+        let row = observe(span, async { query.fetch_optional(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(row.as_ref().map(stored_user).transpose()?)
+    }
+
+    async fn find_by_email_hash(&self, email_hash: &str) -> Result<Option<StoredUser>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_BY_EMAIL_HASH);
+        let sql = self.queries.sql(&span, SELECT_BY_EMAIL_HASH);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(email_hash);
+        let row = observe(span, async { query.fetch_optional(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(row.as_ref().map(stored_user).transpose()?)
+    }
+
+    async fn set_email_ciphertext(&self, id: UserId, ciphertext: &str) -> Result<(), StoreError> {
+        let span = self.queries.span("UPDATE", UPDATE_EMAIL);
+        let sql = self.queries.sql(&span, UPDATE_EMAIL);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(id).bind(ciphertext);
+        observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(())
+    }
+
+    async fn update_name(&self, id: UserId, name: &str, if_match: &IfMatch) -> Result<UpdateOutcome<StoredUser>, StoreError> {
+        // The row stays locked until commit, so two writers can't both match
+        let mut connection = self.pool.acquire().await?;
+        let mut transaction = connection.begin().await?;
+        let span = self.queries.span("SELECT", SELECT_BY_ID_FOR_UPDATE);
+        let sql = self.queries.sql(&span, SELECT_BY_ID_FOR_UPDATE);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(id);
+        let Some(row) = observe(span, query.fetch_optional(&mut *transaction)).await? else {
+            return Ok(UpdateOutcome::NotFound);
+        };
+        let mut stored = stored_user(&row)?;
+        if !if_match.matches(stored.version) {
+            return Ok(UpdateOutcome::Stale {
+                current_version: stored.version,
+            });
+        }
+        stored.name = name.to_string();
+        stored.version += 1;
+
+        let span = self.queries.span("UPDATE", UPDATE_NAME);
+        let sql = self.queries.sql(&span, UPDATE_NAME);
+        let query = sqlx::query(&sql)
+            .persistent(self.queries.persistent())
+            .bind(id)
+            .bind(&stored.name)
+            .bind(stored.version as i64);
+        observe(span, query.execute(&mut *transaction)).await?;
+        transaction.commit().await?;
+        Ok(UpdateOutcome::Updated(stored))
+    }
+
+    async fn created_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<UserId>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_CREATED_BEFORE);
+        let sql = self.queries.sql(&span, SELECT_CREATED_BEFORE);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(cutoff);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(rows.iter().map(|row| row.try_get("id")).collect::<Result<_, _>>()?)
+    }
+
+    async fn delete_many(&self, ids: &[UserId]) -> Result<usize, StoreError> {
+        let span = self.queries.span("DELETE", DELETE_MANY);
+        let sql = self.queries.sql(&span, DELETE_MANY);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(ids);
+        let result = observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(result.rows_affected() as usize)
+    }
+
+    async fn names(&self) -> Result<HashMap<UserId, String>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_NAMES);
+        let sql = self.queries.sql(&span, SELECT_NAMES);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent());
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(rows
+            .iter()
+            .map(|row| Ok((row.try_get("id")?, row.try_get("name")?)))
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+
+    async fn count(&self) -> Result<usize, StoreError> {
+        let span = self.queries.span("SELECT", COUNT);
+        let sql = self.queries.sql(&span, COUNT);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent());
+        let row = observe(span, async { query.fetch_one(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(row.try_get::<i64, _>(0)? as usize)
+    }
+
+    fn backend(&self) -> &'static str {
+        Self::BACKEND
+    }
+}
+
+#[async_trait::async_trait]
+impl OrderStore for PostgresRepository {
+    async fn insert(&self, order: OrderRecord) -> Result<(), StoreError> {
+        let span = self.queries.span("INSERT", INSERT_ORDER);
+        let sql = self.queries.sql(&span, INSERT_ORDER);
+        let query = sqlx::query(&sql)
+            .persistent(self.queries.persistent())
+            .bind(order.order_id)
+            .bind(order.user_id)
+            .bind(serde_json::to_string(&order.lines)?)
+            .bind(order.total_amount.to_string())
+            .bind(order.currency.as_str())
+            .bind(order.status)
+            .bind(order.created_at)
+            .bind(order.version as i64);
+        observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(())
+    }
+
+    async fn get(&self, order_id: OrderId) -> Result<Option<OrderRecord>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_ORDER);
+        let sql = self.queries.sql(&span, SELECT_ORDER);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(order_id);
+        let row = observe(span, async { query.fetch_optional(&mut *self.pool.acquire().await?).await }).await?;
+        row.as_ref().map(order_record).transpose()
+    }
+
+    async fn cancel(&self, order_id: OrderId) -> Result<CancelOutcome, StoreError> {
+        let mut connection = self.pool.acquire().await?;
+        let mut transaction = connection.begin().await?;
+        let Some(mut order) = self.lock_order(&mut transaction, order_id).await? else {
+            return Ok(CancelOutcome::NotFound);
+        };
+        if order.status == orders::CANCELLED {
+            return Ok(CancelOutcome::AlreadyCancelled);
+        }
+        order.status = orders::CANCELLED.to_string();
+        order.version += 1;
+        self.write_status(&mut transaction, &order).await?;
+        transaction.commit().await?;
+        Ok(CancelOutcome::Cancelled(order))
+    }
+
+    async fn update_status(
+        &self,
+        order_id: OrderId,
+        status: &str,
+        if_match: &IfMatch,
+    ) -> Result<UpdateOutcome<OrderRecord>, StoreError> {
+        // The row stays locked until commit, so two writers can't both match
+        let mut connection = self.pool.acquire().await?;
+        let mut transaction = connection.begin().await?;
+        let Some(mut order) = self.lock_order(&mut transaction, order_id).await? else {
+            return Ok(UpdateOutcome::NotFound);
+        };
+        if let Some(outcome) = orders::status_precondition(&order, if_match) {
+            return Ok(outcome);
+        }
+        order.status = status.to_string();
+        order.version += 1;
+        self.write_status(&mut transaction, &order).await?;
+        transaction.commit().await?;
+        Ok(UpdateOutcome::Updated(order))
+    }
+
+    async fn all(&self) -> Result<Vec<OrderRecord>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_ORDERS);
+        let sql = self.queries.sql(&span, SELECT_ORDERS);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent());
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        rows.iter().map(order_record).collect()
+    }
+
+    async fn for_user(
+        &self,
+        user_id: UserId,
+        after: Option<(DateTime<Utc>, OrderId)>,
+        limit: usize,
+    ) -> Result<Vec<OrderRecord>, StoreError> {
+        let statement = if after.is_some() { SELECT_USER_ORDERS_AFTER } else { SELECT_USER_ORDERS };
+        let span = self.queries.span("SELECT", statement);
+        let sql = self.queries.sql(&span, statement);
+        let mut query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(user_id);
+        if let Some((created_at, order_id)) = after {
+            query = query.bind(created_at).bind(order_id);
+        }
+        let query = query.bind(limit as i64 + 1);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        rows.iter().map(order_record).collect()
+    }
+
+    async fn created_since(&self, since: DateTime<Utc>) -> Result<Vec<OrderRecord>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_ORDERS_SINCE);
+        let sql = self.queries.sql(&span, SELECT_ORDERS_SINCE);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(since);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        rows.iter().map(order_record).collect()
+    }
+
+    fn backend(&self) -> &'static str {
+        Self::BACKEND
+    }
+}
+
+impl PostgresRepository {
+    /// An order, locked until `transaction` ends
+    async fn lock_order(
+        &self,
+        transaction: &mut sqlx::Transaction<'_, Postgres>,
+        order_id: OrderId,
+    ) -> Result<Option<OrderRecord>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_ORDER_FOR_UPDATE);
+        let sql = self.queries.sql(&span, SELECT_ORDER_FOR_UPDATE);
+        let query = sqlx::query(&sql).persistent(self.queries.persistent()).bind(order_id);
+        let row = observe(span, query.fetch_optional(&mut **transaction)).await?;
+        row.as_ref().map(order_record).transpose()
+    }
+
+    async fn write_status(
+        &self,
+        transaction: &mut sqlx::Transaction<'_, Postgres>,
+        order: &OrderRecord,
+    ) -> Result<(), StoreError> {
+        let span = self.queries.span("UPDATE", UPDATE_ORDER_STATUS);
+        let sql = self.queries.sql(&span, UPDATE_ORDER_STATUS);
+        let query = sqlx::query(&sql)
+            .persistent(self.queries.persistent())
+            .bind(order.order_id)
+            .bind(&order.status)
+            .bind(order.version as i64);
+        observe(span, query.execute(&mut **transaction)).await?;
+        Ok(())
+    }
+}
+
+fn order_record(row: &PgRow) -> Result<OrderRecord, StoreError> {
+    Ok(OrderRecord {
+        order_id: row.try_get("order_id")?,
+        user_id: row.try_get("user_id")?,
+        lines: serde_json::from_str(row.try_get("lines")?)?,
+        total_amount: row.try_get::<&str, _>("total_amount")?.parse()?,
+        currency: row.try_get::<&str, _>("currency")?.parse()?,
+        status: row.try_get("status")?,
+        created_at: row.try_get("created_at")?,
+        version: row.try_get::<i64, _>("version")? as u64,
+    })
+}
+
+fn stored_user(row: &PgRow) -> Result<StoredUser, sqlx::Error> {
+    let role: String = row.try_get("role")?;
+    Ok(StoredUser {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        email_ciphertext: row.try_get("email_ciphertext")?,
+        email_hash: row.try_get("email_hash")?,
+        password_hash: row.try_get("password_hash")?,
+        role: if role == Role::Admin.as_str() { Role::Admin } else { Role::User },
+        created_at: row.try_get::<DateTime<Utc>, _>("created_at")?.to_rfc3339(),
+        version: row.try_get::<i64, _>("version")? as u64,
+    })
+}
@@ -0,0 +1,207 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::{dogstatsd, warn_trace};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Request header choosing the priority class
+const PRIORITY_HEADER: &str = "x-priority";
+
+/// Request priority class, from the `x-priority` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    Normal,
+    Low,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|priority| value.trim().eq_ignore_ascii_case(priority.as_str()))
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// When an admitted request's priority class timeout expires, in request extensions
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(pub tokio::time::Instant);
+
+/// Admission and deadline settings for one priority class
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ClassLimits {
+    /// The class is shed once this many requests of any class are in flight
+    pub max_in_flight: usize,
+    /// Requests still running after this long are abandoned with 503
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct ClassCounters {
+    admitted: AtomicU64,
+    shed: AtomicU64,
+    timed_out: AtomicU64,
+}
+
+/// Priority classes sharing one in-flight budget
+///
+/// Low-priority requests are admitted only while the service is lightly
+/// loaded, so under load they are shed first and high-priority traffic keeps
+/// the remaining capacity.
+///
+/// Configuration:
+/// - `PRIORITY_CLASSES`: JSON map of `high`/`normal`/`low` to
+///   `{"max_in_flight": .., "timeout_ms": ..}`, overriding the defaults
+///   (high 200/30000, normal 150/10000, low 75/5000)
+#[derive(Debug)]
+pub struct PriorityClasses {
+    limits: [ClassLimits; 3],
+    counters: [ClassCounters; 3],
+    in_flight: AtomicUsize,
+}
+
+impl PriorityClasses {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut limits = [
+            ClassLimits { max_in_flight: 200, timeout_ms: 30_000 },
+            ClassLimits { max_in_flight: 150, timeout_ms: 10_000 },
+            ClassLimits { max_in_flight: 75, timeout_ms: 5_000 },
+        ];
+        if let Ok(json) = std::env::var("PRIORITY_CLASSES") {
+            let overrides: BTreeMap<Priority, ClassLimits> =
+                serde_json::from_str(&json).map_err(|e| format!("PRIORITY_CLASSES: {}", e))?;
+            for (priority, class) in overrides {
+                limits[priority.index()] = class;
+            }
+        }
+        Ok(Self {
+            limits,
+            counters: Default::default(),
+            in_flight: AtomicUsize::new(0),
+        })
+    }
+
+    /// Take an in-flight slot for `priority`, unless the class is being shed
+    fn admit(self: &Arc<Self>, priority: Priority) -> Option<InFlight> {
+        let limit = self.limits[priority.index()].max_in_flight;
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (current < limit).then_some(current + 1)
+            })
+            .ok()
+            .map(|_| InFlight(self.clone()))
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let classes: serde_json::Map<String, serde_json::Value> = Priority::ALL
+            .into_iter()
+            .map(|priority| {
+                let limits = self.limits[priority.index()];
+                let counters = &self.counters[priority.index()];
+                (
+                    priority.as_str().to_string(),
+                    serde_json::json!({
+                        "max_in_flight": limits.max_in_flight,
+                        "timeout_ms": limits.timeout_ms,
+                        "admitted": counters.admitted.load(Ordering::Relaxed),
+                        "shed": counters.shed.load(Ordering::Relaxed),
+                        "timed_out": counters.timed_out.load(Ordering::Relaxed),
+                    }),
+                )
+            })
+            .collect();
+        serde_json::json!({
+            "in_flight": self.in_flight.load(Ordering::Relaxed),
+            "classes": classes,
+        })
+    }
+}
+
+/// An admitted request's slot, released when the request finishes or is dropped
+struct InFlight(Arc<PriorityClasses>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Middleware applying the request's priority class
+///
+/// Tags the request span with `request.priority`, sheds requests whose class
+/// is over its limit with 503 and `Retry-After`, and enforces the class
+/// timeout. The class and its [`Deadline`] are stored in request extensions.
+/// Shed and timed-out requests are also sent as the DogStatsD counts
+/// `request.shed` and `request.timed_out`, tagged by priority.
+pub async fn enforce_priority(
+    State(classes): State<Arc<PriorityClasses>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let priority = match request.headers().get(PRIORITY_HEADER).map(|value| value.to_str()) {
+        None => Priority::Normal,
+        Some(value) => match value.ok().and_then(Priority::parse) {
+            Some(priority) => priority,
+            None => {
+                warn_trace!("Invalid x-priority header, treating the request as normal priority");
+                Priority::Normal
+            }
+        },
+    };
+    let span = tracing::Span::current();
+    span.set_attribute("request.priority", priority.as_str());
+    request.extensions_mut().insert(priority);
+
+    let counters = &classes.counters[priority.index()];
+    let Some(_slot) = classes.admit(priority) else {
+        counters.shed.fetch_add(1, Ordering::Relaxed);
+        dogstatsd::count("request.shed", 1, &[format!("priority:{}", priority.as_str())]);
+        span.set_attribute("request.shed", true);
+        warn_trace!(request.priority = priority.as_str(), "Request shed under load");
+        let message = format!("Overloaded; {} priority requests are being shed", priority.as_str());
+        return AppError::ServiceUnavailable(message).into_response();
+    };
+    counters.admitted.fetch_add(1, Ordering::Relaxed);
+
+    let timeout = Duration::from_millis(classes.limits[priority.index()].timeout_ms);
+    request
+        .extensions_mut()
+        .insert(Deadline(tokio::time::Instant::now() + timeout));
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            counters.timed_out.fetch_add(1, Ordering::Relaxed);
+            dogstatsd::count("request.timed_out", 1, &[format!("priority:{}", priority.as_str())]);
+            span.set_attribute("request.timed_out", true);
+            warn_trace!(
+                request.priority = priority.as_str(),
+                timeout_ms = timeout.as_millis() as u64,
+                "Request exceeded its priority class timeout"
+            );
+            AppError::ServiceUnavailable("Request timed out".to_string()).into_response()
+        }
+    }
+}
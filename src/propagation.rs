@@ -0,0 +1,962 @@
+use crate::attribute_filter::matches_pattern;
+use crate::debug_trace;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::context::FutureExt;
+use opentelemetry::propagation::text_map_propagator::FieldIter;
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::propagation::BaggagePropagator;
+use std::sync::{Arc, OnceLock};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const DATADOG_TRACE_ID_HEADER: &str = "x-datadog-trace-id";
+const DATADOG_PARENT_ID_HEADER: &str = "x-datadog-parent-id";
+const DATADOG_SAMPLING_PRIORITY_HEADER: &str = "x-datadog-sampling-priority";
+const DATADOG_ORIGIN_HEADER: &str = "x-datadog-origin";
+const DATADOG_TAGS_HEADER: &str = "x-datadog-tags";
+
+/// Propagation tag carrying the upper 64 bits of a 128-bit trace ID, in hex
+const TRACE_ID_HIGH_TAG: &str = "_dd.p.tid";
+
+const TRACEPARENT_HEADER: &str = "traceparent";
+const TRACESTATE_HEADER: &str = "tracestate";
+
+/// W3C Trace Context Level 2 flag: the trace ID's rightmost 56 bits are random
+///
+/// Only kept on sampled contexts: the Datadog sampler reads flags of exactly
+/// `0x02` as "decision deferred" and would sample an unsampled trace again.
+const RANDOM: TraceFlags = TraceFlags::new(0x02);
+
+/// Largest 56-bit value: consistent sampling thresholds and randomness are 56 bits
+const MAX_56_BIT: u64 = (1 << 56) - 1;
+
+/// Reads propagation fields from request headers
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// dd-trace's `x-datadog-*` headers
+///
+/// Trace and parent IDs are decimal; the trace ID header holds the lower 64
+/// bits, with the upper half in the `_dd.p.tid` tag of `x-datadog-tags`. The
+/// sampling priority and origin are kept in the `dd` tracestate entry
+/// (`s:<priority>;o:<origin>`) so they survive until the context is injected
+/// again. A missing priority leaves the trace sampled, deferring to our sampler.
+#[derive(Debug, Default)]
+pub struct DatadogPropagator;
+
+fn datadog_header_fields() -> &'static [String; 5] {
+    static FIELDS: OnceLock<[String; 5]> = OnceLock::new();
+    FIELDS.get_or_init(|| {
+        [
+            DATADOG_TRACE_ID_HEADER,
+            DATADOG_PARENT_ID_HEADER,
+            DATADOG_SAMPLING_PRIORITY_HEADER,
+            DATADOG_ORIGIN_HEADER,
+            DATADOG_TAGS_HEADER,
+        ]
+        .map(String::from)
+    })
+}
+
+impl DatadogPropagator {
+    fn extract_span_context(extractor: &dyn Extractor) -> Option<SpanContext> {
+        let trace_id_low: u64 = extractor.get(DATADOG_TRACE_ID_HEADER)?.trim().parse().ok()?;
+        let parent_id: u64 = extractor.get(DATADOG_PARENT_ID_HEADER)?.trim().parse().ok()?;
+        if trace_id_low == 0 || parent_id == 0 {
+            return None;
+        }
+        let trace_id_high = extractor
+            .get(DATADOG_TAGS_HEADER)
+            .into_iter()
+            .flat_map(|tags| tags.split(','))
+            .find_map(|tag| tag.trim().strip_prefix(TRACE_ID_HIGH_TAG)?.strip_prefix('='))
+            .and_then(|high| u64::from_str_radix(high, 16).ok())
+            .unwrap_or(0);
+        let trace_id = TraceId::from(u128::from(trace_id_high) << 64 | u128::from(trace_id_low));
+
+        let priority: Option<i8> = extractor
+            .get(DATADOG_SAMPLING_PRIORITY_HEADER)
+            .and_then(|priority| priority.trim().parse().ok());
+        // dd-trace generates the lower 64 bits at random, which is what the random flag promises
+        let flags = match priority {
+            Some(priority) if priority <= 0 => TraceFlags::default(),
+            _ => TraceFlags::SAMPLED | RANDOM,
+        };
+
+        // tracestate values can't contain ',', ';' or '='
+        let origin = extractor
+            .get(DATADOG_ORIGIN_HEADER)
+            .filter(|origin| !origin.is_empty() && origin.chars().all(|c| c.is_ascii_graphic() && !",;=".contains(c)));
+        let dd_state: Vec<String> = priority
+            .map(|priority| format!("s:{}", priority))
+            .into_iter()
+            .chain(origin.map(|origin| format!("o:{}", origin)))
+            .collect();
+        let trace_state = if dd_state.is_empty() {
+            TraceState::default()
+        } else {
+            TraceState::from_key_value([("dd", dd_state.join(";"))]).unwrap_or_default()
+        };
+
+        Some(SpanContext::new(trace_id, SpanId::from(parent_id), flags, true, trace_state))
+    }
+}
+
+impl TextMapPropagator for DatadogPropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let trace_id = u128::from_be_bytes(span_context.trace_id().to_bytes());
+        injector.set(DATADOG_TRACE_ID_HEADER, (trace_id as u64).to_string());
+        injector.set(
+            DATADOG_PARENT_ID_HEADER,
+            u64::from_be_bytes(span_context.span_id().to_bytes()).to_string(),
+        );
+
+        let dd_state = span_context.trace_state().get("dd").unwrap_or_default();
+        let dd_field = |name: &str| dd_state.split(';').find_map(|field| field.strip_prefix(name));
+        let priority = dd_field("s:")
+            .map(str::to_string)
+            .unwrap_or_else(|| if span_context.is_sampled() { "1" } else { "0" }.to_string());
+        injector.set(DATADOG_SAMPLING_PRIORITY_HEADER, priority);
+        if let Some(origin) = dd_field("o:") {
+            injector.set(DATADOG_ORIGIN_HEADER, origin.to_string());
+        }
+        let trace_id_high = (trace_id >> 64) as u64;
+        if trace_id_high != 0 {
+            injector.set(DATADOG_TAGS_HEADER, format!("{}={:016x}", TRACE_ID_HIGH_TAG, trace_id_high));
+        }
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        match Self::extract_span_context(extractor) {
+            Some(span_context) => cx.with_remote_span_context(span_context),
+            None => cx.clone(),
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(datadog_header_fields())
+    }
+}
+
+/// W3C `traceparent`/`tracestate`, including Trace Context Level 2
+///
+/// Beyond Level 1 this keeps the `random` trace flag, so services that derive
+/// sampling decisions from the trace ID can rely on it downstream, and it
+/// checks the OpenTelemetry consistent-probability sampling fields in the `ot`
+/// tracestate entry (`th:<threshold>;rv:<randomness>`) as the spec requires: a
+/// malformed field is dropped, and so is a threshold the sampled flag
+/// contradicts, since it no longer describes how the trace was sampled.
+/// Other tracestate entries pass through untouched.
+#[derive(Debug, Default)]
+pub struct W3cPropagator;
+
+fn w3c_header_fields() -> &'static [String; 2] {
+    static FIELDS: OnceLock<[String; 2]> = OnceLock::new();
+    FIELDS.get_or_init(|| [TRACEPARENT_HEADER, TRACESTATE_HEADER].map(String::from))
+}
+
+impl W3cPropagator {
+    /// Parse `version-traceid-parentid-flags`, keeping the `sampled` and `random` flags
+    ///
+    /// The Level 1 parser in `opentelemetry_sdk` rejects version `00` headers
+    /// with any other flag set, which Level 2 senders now send.
+    fn extract_span_context(extractor: &dyn Extractor) -> Option<SpanContext> {
+        let lower_hex = |field: &str, len: usize| {
+            field.len() == len && field.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        let traceparent = extractor.get(TRACEPARENT_HEADER)?.trim();
+        let parts: Vec<&str> = traceparent.split('-').collect();
+        let [version, trace_id, span_id, flags, ..] = parts[..] else {
+            return None;
+        };
+        // Version `00` has exactly four fields; later versions may append more
+        if !lower_hex(version, 2) || version == "ff" || (version == "00" && parts.len() != 4) {
+            return None;
+        }
+        if !lower_hex(trace_id, 32) || !lower_hex(span_id, 16) || !lower_hex(flags, 2) {
+            return None;
+        }
+        let trace_id = TraceId::from_hex(trace_id).ok().filter(|id| *id != TraceId::INVALID)?;
+        let span_id = SpanId::from_hex(span_id).ok().filter(|id| *id != SpanId::INVALID)?;
+        let flags = TraceFlags::new(u8::from_str_radix(flags, 16).ok()?);
+        let flags = if flags.is_sampled() {
+            flags & (TraceFlags::SAMPLED | RANDOM)
+        } else {
+            TraceFlags::default()
+        };
+
+        let mut trace_state = extractor
+            .get(TRACESTATE_HEADER)
+            .and_then(|trace_state| trace_state.parse::<TraceState>().ok())
+            .unwrap_or_default();
+        if let Some(ot) = trace_state.get("ot") {
+            trace_state = match sanitize_ot_state(ot, trace_id, flags) {
+                Some(ot) => trace_state.insert("ot", ot),
+                None => trace_state.delete("ot"),
+            }
+            .unwrap_or_default();
+        }
+        Some(SpanContext::new(trace_id, span_id, flags, true, trace_state))
+    }
+}
+
+/// `ot` tracestate entry with invalid or contradicted sampling fields removed
+fn sanitize_ot_state(ot: &str, trace_id: TraceId, flags: TraceFlags) -> Option<String> {
+    let hex = |value: &str, max_len: usize| {
+        (!value.is_empty() && value.len() <= max_len && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')))
+            .then(|| u64::from_str_radix(value, 16).ok())
+            .flatten()
+    };
+    // Thresholds drop trailing zeros; `th:8` is 8 followed by 13 zeros, i.e. 50%
+    let threshold = |th: &str| hex(th, 14).map(|value| value << (4 * (14 - th.len())));
+    let randomness = ot
+        .split(';')
+        .find_map(|field| field.strip_prefix("rv:"))
+        .filter(|rv| rv.len() == 14)
+        .and_then(|rv| hex(rv, 14))
+        .or_else(|| (flags & RANDOM == RANDOM).then(|| u128::from_be_bytes(trace_id.to_bytes()) as u64 & MAX_56_BIT));
+
+    let fields: Vec<&str> = ot
+        .split(';')
+        .filter(|field| match field.split_once(':') {
+            Some(("th", th)) => match threshold(th) {
+                // A sampled trace must have randomness at or above the rejection threshold
+                Some(threshold) => flags.is_sampled() && randomness.is_none_or(|randomness| randomness >= threshold),
+                None => false,
+            },
+            Some(("rv", rv)) => rv.len() == 14 && hex(rv, 14).is_some(),
+            Some(_) => true,
+            None => false,
+        })
+        .collect();
+    (!fields.is_empty()).then(|| fields.join(";"))
+}
+
+impl TextMapPropagator for W3cPropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let flags = span_context.trace_flags() & (TraceFlags::SAMPLED | RANDOM);
+        injector.set(
+            TRACEPARENT_HEADER,
+            format!("00-{}-{}-{:02x}", span_context.trace_id(), span_context.span_id(), flags),
+        );
+        injector.set(TRACESTATE_HEADER, span_context.trace_state().header());
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        match Self::extract_span_context(extractor) {
+            Some(span_context) => cx.with_remote_span_context(span_context),
+            None => cx.clone(),
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(w3c_header_fields())
+    }
+}
+
+const B3_SINGLE_HEADER: &str = "b3";
+const B3_TRACE_ID_HEADER: &str = "x-b3-traceid";
+const B3_SPAN_ID_HEADER: &str = "x-b3-spanid";
+const B3_SAMPLED_HEADER: &str = "x-b3-sampled";
+const B3_FLAGS_HEADER: &str = "x-b3-flags";
+
+/// Zipkin B3 headers, as sent by Istio/Envoy and Zipkin-instrumented services
+///
+/// Either the single `b3: {trace}-{span}-{sampled}-{parent}` header or the
+/// `x-b3-*` headers. Trace IDs may be 64 or 128 bits. A missing sampling
+/// decision leaves the trace sampled, deferring to our sampler; the debug flag
+/// (`d`, `x-b3-flags: 1`) samples it.
+#[derive(Debug)]
+pub struct B3Propagator {
+    single_header: bool,
+}
+
+fn b3_single_header_fields() -> &'static [String; 1] {
+    static FIELDS: OnceLock<[String; 1]> = OnceLock::new();
+    FIELDS.get_or_init(|| [B3_SINGLE_HEADER.to_string()])
+}
+
+fn b3_multi_header_fields() -> &'static [String; 4] {
+    static FIELDS: OnceLock<[String; 4]> = OnceLock::new();
+    FIELDS.get_or_init(|| [B3_TRACE_ID_HEADER, B3_SPAN_ID_HEADER, B3_SAMPLED_HEADER, B3_FLAGS_HEADER].map(String::from))
+}
+
+impl B3Propagator {
+    fn trace_id(hex: &str) -> Option<TraceId> {
+        if hex.len() != 16 && hex.len() != 32 {
+            return None;
+        }
+        u128::from_str_radix(hex, 16).ok().map(TraceId::from).filter(|id| *id != TraceId::INVALID)
+    }
+
+    fn span_id(hex: &str) -> Option<SpanId> {
+        if hex.len() != 16 {
+            return None;
+        }
+        u64::from_str_radix(hex, 16).ok().map(SpanId::from).filter(|id| *id != SpanId::INVALID)
+    }
+
+    /// `None` when the decision is deferred to us
+    fn sampled(value: &str) -> Option<bool> {
+        match value {
+            "1" | "d" | "true" => Some(true),
+            "0" | "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn span_context(trace_id: TraceId, span_id: SpanId, sampled: Option<bool>) -> SpanContext {
+        let flags = match sampled {
+            Some(false) => TraceFlags::default(),
+            _ => TraceFlags::SAMPLED,
+        };
+        SpanContext::new(trace_id, span_id, flags, true, TraceState::default())
+    }
+
+    fn extract_single(extractor: &dyn Extractor) -> Option<SpanContext> {
+        // A bare sampling decision (`b3: 0`) carries no context to continue
+        let mut fields = extractor.get(B3_SINGLE_HEADER)?.trim().split('-');
+        let trace_id = Self::trace_id(fields.next()?)?;
+        let span_id = Self::span_id(fields.next()?)?;
+        let sampled = match fields.next() {
+            Some(value) => Some(Self::sampled(value)?),
+            None => None,
+        };
+        Some(Self::span_context(trace_id, span_id, sampled))
+    }
+
+    fn extract_multi(extractor: &dyn Extractor) -> Option<SpanContext> {
+        let trace_id = Self::trace_id(extractor.get(B3_TRACE_ID_HEADER)?.trim())?;
+        let span_id = Self::span_id(extractor.get(B3_SPAN_ID_HEADER)?.trim())?;
+        let debug = extractor.get(B3_FLAGS_HEADER).is_some_and(|flags| flags.trim() == "1");
+        let sampled = if debug {
+            Some(true)
+        } else {
+            extractor.get(B3_SAMPLED_HEADER).and_then(|value| Self::sampled(value.trim()))
+        };
+        Some(Self::span_context(trace_id, span_id, sampled))
+    }
+}
+
+impl TextMapPropagator for B3Propagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let sampled = if span_context.is_sampled() { "1" } else { "0" };
+        if self.single_header {
+            injector.set(
+                B3_SINGLE_HEADER,
+                format!("{}-{}-{}", span_context.trace_id(), span_context.span_id(), sampled),
+            );
+        } else {
+            injector.set(B3_TRACE_ID_HEADER, span_context.trace_id().to_string());
+            injector.set(B3_SPAN_ID_HEADER, span_context.span_id().to_string());
+            injector.set(B3_SAMPLED_HEADER, sampled.to_string());
+        }
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        let span_context = if self.single_header {
+            Self::extract_single(extractor)
+        } else {
+            Self::extract_multi(extractor)
+        };
+        match span_context {
+            Some(span_context) => cx.with_remote_span_context(span_context),
+            None => cx.clone(),
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        if self.single_header {
+            FieldIter::new(b3_single_header_fields())
+        } else {
+            FieldIter::new(b3_multi_header_fields())
+        }
+    }
+}
+
+/// Writes propagation fields into outgoing request headers
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        // The W3C propagator writes an empty `tracestate` when there is none
+        if value.is_empty() {
+            return;
+        }
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value)) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// A trace context header format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// dd-trace's `x-datadog-*` headers
+    Datadog,
+    /// W3C `traceparent`/`tracestate`
+    TraceContext,
+    /// B3 `x-b3-*` headers
+    B3Multi,
+    /// B3 single `b3` header
+    B3,
+    /// W3C `baggage`, key-value pairs carried alongside whichever trace context
+    Baggage,
+}
+
+impl Style {
+    /// Names accepted in `DD_TRACE_PROPAGATION_STYLE*`, as in dd-trace
+    pub const NAMES: [&'static str; 6] = ["datadog", "tracecontext", "b3multi", "b3", "baggage", "none"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Style::Datadog => "datadog",
+            Style::TraceContext => "tracecontext",
+            Style::B3Multi => "b3multi",
+            Style::B3 => "b3",
+            Style::Baggage => "baggage",
+        }
+    }
+
+    /// Parse a style list, comma- or space-separated; `none` selects no style
+    fn parse_list(value: &str) -> Result<Vec<Style>, String> {
+        let mut styles = Vec::new();
+        for name in value.split([',', ' ']).map(str::trim).filter(|name| !name.is_empty()) {
+            let style = match name.to_ascii_lowercase().as_str() {
+                "datadog" => Style::Datadog,
+                "tracecontext" => Style::TraceContext,
+                "b3multi" => Style::B3Multi,
+                "b3" => Style::B3,
+                "baggage" => Style::Baggage,
+                "none" => continue,
+                _ => return Err(format!("unknown propagation style '{}'", name)),
+            };
+            if !styles.contains(&style) {
+                styles.push(style);
+            }
+        }
+        Ok(styles)
+    }
+
+    fn propagator(self) -> &'static (dyn TextMapPropagator + Send + Sync) {
+        static DATADOG: DatadogPropagator = DatadogPropagator;
+        static B3_MULTI: B3Propagator = B3Propagator { single_header: false };
+        static B3_SINGLE: B3Propagator = B3Propagator { single_header: true };
+        static TRACE_CONTEXT: W3cPropagator = W3cPropagator;
+        static BAGGAGE: OnceLock<BaggagePropagator> = OnceLock::new();
+        match self {
+            Style::Datadog => &DATADOG,
+            Style::TraceContext => &TRACE_CONTEXT,
+            Style::B3Multi => &B3_MULTI,
+            Style::B3 => &B3_SINGLE,
+            Style::Baggage => BAGGAGE.get_or_init(BaggagePropagator::new),
+        }
+    }
+}
+
+/// Which header formats are read from requests and written to outgoing calls
+///
+/// Extraction tries each style in order and continues the first trace found.
+/// If a later W3C `traceparent` names the same trace, its parent is used
+/// instead, since a W3C-aware hop in between updates `traceparent` but not the
+/// other headers. `baggage` is read on top of whichever trace was found, or
+/// alone. By default every style is read, Datadog first as in dd-trace, and
+/// Datadog, W3C and baggage headers are written.
+///
+/// Configuration (style lists as in dd-trace: `datadog`, `tracecontext`,
+/// `b3multi`, `b3`, `baggage`, or `none`):
+/// - `DD_TRACE_PROPAGATION_STYLE_EXTRACT`: styles read from requests, in order
+/// - `DD_TRACE_PROPAGATION_STYLE_INJECT`: styles written to outgoing calls
+/// - `DD_TRACE_PROPAGATION_STYLE`: both, where the specific variable isn't set
+#[derive(Debug, Clone)]
+pub struct Propagation {
+    extract: Vec<Style>,
+    inject: Vec<Style>,
+}
+
+impl Default for Propagation {
+    fn default() -> Self {
+        Self {
+            extract: vec![Style::Datadog, Style::TraceContext, Style::B3Multi, Style::B3, Style::Baggage],
+            inject: vec![Style::Datadog, Style::TraceContext, Style::Baggage],
+        }
+    }
+}
+
+impl Propagation {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let styles = |name: &str| -> Result<Option<Vec<Style>>, Box<dyn std::error::Error>> {
+            match std::env::var(name) {
+                Ok(value) => Ok(Some(Style::parse_list(&value).map_err(|e| format!("{}: {}", name, e))?)),
+                Err(_) => Ok(None),
+            }
+        };
+        let default = Self::default();
+        let both = styles("DD_TRACE_PROPAGATION_STYLE")?;
+        Ok(Self {
+            extract: styles("DD_TRACE_PROPAGATION_STYLE_EXTRACT")?
+                .or_else(|| both.clone())
+                .unwrap_or(default.extract),
+            inject: styles("DD_TRACE_PROPAGATION_STYLE_INJECT")?
+                .or(both)
+                .unwrap_or(default.inject),
+        })
+    }
+
+    pub fn extract_styles(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.extract.iter().map(|style| style.as_str())
+    }
+
+    pub fn inject_styles(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.inject.iter().map(|style| style.as_str())
+    }
+
+    /// Remote parent and baggage from the request headers, if the caller sent either
+    fn extract(&self, headers: &HeaderMap) -> Option<Context> {
+        let extractor = HeaderExtractor(headers);
+        let trace = self.extract_trace(&extractor);
+        if !self.extract.contains(&Style::Baggage) {
+            return trace;
+        }
+        let cx = Style::Baggage
+            .propagator()
+            .extract_with_context(trace.as_ref().unwrap_or(&Context::new()), &extractor);
+        if cx.baggage().is_empty() {
+            trace
+        } else {
+            Some(cx)
+        }
+    }
+
+    fn extract_trace(&self, extractor: &HeaderExtractor) -> Option<Context> {
+        let remote = |style: Style| {
+            let cx = style.propagator().extract_with_context(&Context::new(), extractor);
+            cx.span().span_context().is_remote().then_some(cx)
+        };
+        let (index, first) = self
+            .extract
+            .iter()
+            .enumerate()
+            .filter(|(_, style)| **style != Style::Baggage)
+            .find_map(|(index, style)| Some((index, remote(*style)?)))?;
+
+        let first_trace = first.span().span_context().trace_id();
+        if self.extract[index + 1..].contains(&Style::TraceContext) {
+            if let Some(w3c) = remote(Style::TraceContext) {
+                let w3c_trace = w3c.span().span_context().trace_id();
+                if w3c_trace == first_trace {
+                    return Some(w3c);
+                }
+                debug_trace!(
+                    trace_id = %first_trace,
+                    w3c.trace_id = %w3c_trace,
+                    "Propagation headers name different traces, continuing the first"
+                );
+            }
+        }
+        Some(first)
+    }
+
+    fn inject(&self, cx: &Context, headers: &mut HeaderMap) {
+        let mut injector = HeaderInjector(headers);
+        for style in &self.inject {
+            style.propagator().inject_context(cx, &mut injector);
+        }
+    }
+}
+
+static PROPAGATION: OnceLock<Propagation> = OnceLock::new();
+
+/// Use `propagation` for every request and outgoing call from now on
+///
+/// Call once at startup, before serving; later calls are ignored.
+pub fn install(propagation: Propagation) {
+    let _ = PROPAGATION.set(propagation);
+}
+
+fn propagation() -> &'static Propagation {
+    PROPAGATION.get_or_init(Propagation::default)
+}
+
+/// Add the current span's trace context to the headers of an outgoing call
+pub fn inject_current(headers: &mut HeaderMap) {
+    propagation().inject(&tracing::Span::current().context(), headers);
+}
+
+/// Trace context and baggage carried by `headers`, such as a consumed message's
+///
+/// Reads the formats [`extract_trace_context`] reads for requests; `None` when
+/// `headers` hold neither.
+pub fn extract(headers: &HeaderMap) -> Option<Context> {
+    propagation().extract(headers)
+}
+
+/// Value of the baggage entry `key` the current request carries
+///
+/// Baggage comes from the caller's `baggage` header and is passed on to
+/// outgoing calls; handlers read it here, e.g. `propagation::baggage("tenant.id")`.
+pub fn baggage(key: &str) -> Option<String> {
+    let cx = tracing::Span::current().context();
+    cx.baggage().get(key).map(|value| value.as_str().to_string())
+}
+
+/// Baggage entries copied onto every span as attributes
+///
+/// Entries such as `customer.tier` or `tenant.id` then become searchable and
+/// facetable on each span of the request, including downstream and database
+/// spans, without handlers tagging them. Only listed keys are copied, since
+/// callers control baggage; a span's own attribute of the same key wins.
+///
+/// Configuration:
+/// - `TELEMETRY_BAGGAGE_ATTRIBUTES`: baggage keys to copy, comma-separated, `prefix.*`
+///   wildcards (default none)
+#[derive(Debug, Clone, Default)]
+pub struct BaggageAttributes {
+    keys: Vec<String>,
+}
+
+impl BaggageAttributes {
+    pub fn from_env() -> Self {
+        std::env::var("TELEMETRY_BAGGAGE_ATTRIBUTES")
+            .map(|keys| Self::parse(&keys))
+            .unwrap_or_default()
+    }
+
+    fn parse(keys: &str) -> Self {
+        Self {
+            keys: keys.split(',').map(str::trim).filter(|key| !key.is_empty()).map(str::to_string).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Listed entries of `cx`'s baggage, as attributes
+    pub fn attributes(&self, cx: &Context) -> Vec<KeyValue> {
+        if self.keys.is_empty() {
+            return Vec::new();
+        }
+        cx.baggage()
+            .iter()
+            .filter(|(key, _)| self.keys.iter().any(|pattern| matches_pattern(pattern, key.as_str())))
+            .map(|(key, (value, _))| KeyValue::new(key.clone(), value.as_str().to_string()))
+            .collect()
+    }
+}
+
+/// Middleware continuing the caller's trace from its propagation headers
+///
+/// Reads the header formats chosen in [`Propagation`]. The extracted context
+/// is current while the rest of the request runs, so spans opened without a
+/// tracing parent, such as `api.request`, become children of the upstream
+/// span instead of starting a new trace. The caller's sampling decision
+/// carries over. Missing or malformed headers leave the request as a new trace.
+pub async fn extract_trace_context(request: Request, next: Next) -> Response {
+    match propagation().extract(request.headers()) {
+        Some(parent) => next.run(request).with_context(parent).await,
+        None => next.run(request).await,
+    }
+}
+
+/// Trace headers added to responses, for finding a request in Datadog
+///
+/// Frontend developers and support engineers can copy the ID of a failed call
+/// from the browser's network tab straight into Datadog's trace search.
+/// `x-datadog-trace-id` holds the trace ID's lower 64 bits in decimal, as
+/// dd-trace sends it; `traceparent` names the request span in W3C form, with
+/// its sampled flag telling whether the trace was kept.
+///
+/// Configuration:
+/// - `TRACE_RESPONSE_HEADERS`: headers written, comma-separated: `datadog`
+///   (`x-datadog-trace-id`), `tracecontext` (`traceparent`), or `none` (default `datadog`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseHeaders {
+    styles: Vec<Style>,
+}
+
+impl Default for ResponseHeaders {
+    fn default() -> Self {
+        Self {
+            styles: vec![Style::Datadog],
+        }
+    }
+}
+
+impl ResponseHeaders {
+    /// Names accepted in `TRACE_RESPONSE_HEADERS`
+    pub const NAMES: [&'static str; 3] = ["datadog", "tracecontext", "none"];
+
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        match std::env::var("TRACE_RESPONSE_HEADERS") {
+            Ok(value) => Ok(Self::parse(&value).map_err(|e| format!("TRACE_RESPONSE_HEADERS: {}", e))?),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, String> {
+        let styles = Style::parse_list(value)?;
+        if let Some(style) = styles.iter().find(|style| !matches!(style, Style::Datadog | Style::TraceContext)) {
+            return Err(format!("{} headers aren't written to responses", style.as_str()));
+        }
+        Ok(Self { styles })
+    }
+
+    /// Header names written, for CORS `Access-Control-Expose-Headers`
+    pub fn header_names(&self) -> Vec<HeaderName> {
+        self.styles
+            .iter()
+            .map(|style| match style {
+                Style::TraceContext => HeaderName::from_static(TRACEPARENT_HEADER),
+                _ => HeaderName::from_static(DATADOG_TRACE_ID_HEADER),
+            })
+            .collect()
+    }
+
+    fn write(&self, cx: &Context, headers: &mut HeaderMap) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        for style in &self.styles {
+            let (name, value) = match style {
+                Style::TraceContext => {
+                    let flags = span_context.trace_flags() & TraceFlags::SAMPLED;
+                    let traceparent =
+                        format!("00-{}-{}-{:02x}", span_context.trace_id(), span_context.span_id(), flags);
+                    (TRACEPARENT_HEADER, traceparent)
+                }
+                _ => {
+                    let trace_id = u128::from_be_bytes(span_context.trace_id().to_bytes());
+                    (DATADOG_TRACE_ID_HEADER, (trace_id as u64).to_string())
+                }
+            };
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+    }
+}
+
+/// Middleware adding the request's trace headers to its response
+///
+/// Runs inside the `api.request` span, whose IDs it writes.
+pub async fn add_response_headers(
+    State(config): State<Arc<ResponseHeaders>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    config.write(&tracing::Span::current().context(), response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    fn parent(pairs: &[(&'static str, &'static str)]) -> Option<SpanContext> {
+        Propagation::default()
+            .extract(&headers(pairs))
+            .map(|cx| cx.span().span_context().clone())
+    }
+
+    #[test]
+    fn datadog_headers_round_trip() {
+        let span_context = parent(&[
+            ("x-datadog-trace-id", "7277407061855694839"),
+            ("x-datadog-parent-id", "5208512171318403364"),
+            ("x-datadog-sampling-priority", "2"),
+            ("x-datadog-origin", "rum"),
+            ("x-datadog-tags", "_dd.p.dm=-4,_dd.p.tid=640cfd8d00000000"),
+        ])
+        .unwrap();
+        assert_eq!(span_context.trace_id().to_string(), "640cfd8d0000000064fe8b2a57d3eff7");
+        assert_eq!(span_context.span_id().to_string(), "48485a3953bb6124");
+        assert!(span_context.is_sampled());
+
+        let mut injected = HashMap::new();
+        DatadogPropagator.inject_context(&Context::new().with_remote_span_context(span_context), &mut injected);
+        assert_eq!(injected["x-datadog-trace-id"], "7277407061855694839");
+        assert_eq!(injected["x-datadog-parent-id"], "5208512171318403364");
+        assert_eq!(injected["x-datadog-sampling-priority"], "2");
+        assert_eq!(injected["x-datadog-origin"], "rum");
+        assert_eq!(injected["x-datadog-tags"], "_dd.p.tid=640cfd8d00000000");
+    }
+
+    #[test]
+    fn rejected_priorities_are_not_sampled() {
+        let span_context = parent(&[
+            ("x-datadog-trace-id", "1"),
+            ("x-datadog-parent-id", "2"),
+            ("x-datadog-sampling-priority", "-1"),
+        ])
+        .unwrap();
+        assert!(!span_context.is_sampled());
+        assert!(parent(&[("x-datadog-trace-id", "1"), ("x-datadog-parent-id", "0")]).is_none());
+    }
+
+    #[test]
+    fn w3c_parent_wins_within_the_same_trace() {
+        let traceparent = "00-0000000000000000000000000000002a-00000000000000ff-01";
+        let same = parent(&[
+            ("x-datadog-trace-id", "42"),
+            ("x-datadog-parent-id", "7"),
+            ("traceparent", traceparent),
+        ])
+        .unwrap();
+        assert_eq!(same.span_id(), SpanId::from(0xff));
+
+        let different = parent(&[
+            ("x-datadog-trace-id", "43"),
+            ("x-datadog-parent-id", "7"),
+            ("traceparent", traceparent),
+        ])
+        .unwrap();
+        assert_eq!(different.span_id(), SpanId::from(7));
+    }
+
+    #[test]
+    fn w3c_level2_random_flag_and_sampling_fields() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-03";
+        let consistent = parent(&[("traceparent", traceparent), ("tracestate", "ot=th:8;rv:9b8233f7e3a151,vendor=x")]).unwrap();
+        assert_eq!(consistent.trace_flags(), TraceFlags::SAMPLED | RANDOM);
+        assert_eq!(consistent.trace_state().get("ot"), Some("th:8;rv:9b8233f7e3a151"));
+
+        let mut injected = HashMap::new();
+        W3cPropagator.inject_context(&Context::new().with_remote_span_context(consistent), &mut injected);
+        assert_eq!(injected["traceparent"], traceparent);
+        assert_eq!(injected["tracestate"], "ot=th:8;rv:9b8233f7e3a151,vendor=x");
+
+        // Randomness below the threshold contradicts the sampled flag
+        let contradicted = parent(&[("traceparent", traceparent), ("tracestate", "ot=th:c;rv:9b8233f7e3a151")]).unwrap();
+        assert_eq!(contradicted.trace_state().get("ot"), Some("rv:9b8233f7e3a151"));
+        let malformed = parent(&[("traceparent", traceparent), ("tracestate", "ot=th:zz,vendor=x")]).unwrap();
+        assert_eq!(malformed.trace_state().header(), "vendor=x");
+
+        let unsampled = parent(&[("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-02")]).unwrap();
+        assert_eq!(unsampled.trace_flags(), TraceFlags::default());
+    }
+
+    #[test]
+    fn b3_single_and_multi_headers() {
+        let single = parent(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0-05e3ac9a4f6e3b90")]).unwrap();
+        assert_eq!(single.trace_id().to_string(), "80f198ee56343ba864fe8b2a57d3eff7");
+        assert_eq!(single.span_id().to_string(), "e457b5a2e4d86bd1");
+        assert!(!single.is_sampled());
+        assert!(parent(&[("b3", "0")]).is_none());
+
+        let multi = parent(&[
+            ("x-b3-traceid", "64fe8b2a57d3eff7"),
+            ("x-b3-spanid", "e457b5a2e4d86bd1"),
+            ("x-b3-flags", "1"),
+        ])
+        .unwrap();
+        assert_eq!(multi.trace_id().to_string(), "000000000000000064fe8b2a57d3eff7");
+        assert!(multi.is_sampled());
+
+        let mut injected = HashMap::new();
+        let cx = Context::new().with_remote_span_context(single);
+        Style::B3.propagator().inject_context(&cx, &mut injected);
+        assert_eq!(injected["b3"], "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0");
+    }
+
+    #[test]
+    fn baggage_rides_along_and_allow_listed_keys_become_attributes() {
+        let cx = Propagation::default()
+            .extract(&headers(&[
+                ("x-datadog-trace-id", "42"),
+                ("x-datadog-parent-id", "7"),
+                ("baggage", "customer.tier=gold,tenant.id=acme,session=abc"),
+            ]))
+            .unwrap();
+        assert_eq!(cx.span().span_context().span_id(), SpanId::from(7));
+        assert_eq!(cx.baggage().len(), 3);
+
+        let attributes = BaggageAttributes::parse("customer.*, tenant.id").attributes(&cx);
+        let mut keys: Vec<&str> = attributes.iter().map(|attribute| attribute.key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, ["customer.tier", "tenant.id"]);
+        assert!(BaggageAttributes::default().attributes(&cx).is_empty());
+
+        // Baggage alone still reaches handlers, under a new trace
+        let alone = Propagation::default().extract(&headers(&[("baggage", "tenant.id=acme")])).unwrap();
+        assert!(!alone.span().span_context().is_valid());
+        assert_eq!(alone.baggage().get("tenant.id").map(|value| value.as_str()), Some("acme"));
+    }
+
+    #[test]
+    fn parses_style_lists() {
+        assert_eq!(
+            Style::parse_list("tracecontext, Datadog b3").unwrap(),
+            vec![Style::TraceContext, Style::Datadog, Style::B3]
+        );
+        assert_eq!(Style::parse_list("none").unwrap(), vec![]);
+        assert!(Style::parse_list("datadog,jaeger").is_err());
+    }
+
+    #[test]
+    fn writes_trace_ids_to_responses() {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("640cfd8d0000000064fe8b2a57d3eff7").unwrap(),
+            SpanId::from_hex("48485a3953bb6124").unwrap(),
+            TraceFlags::SAMPLED | RANDOM,
+            false,
+            TraceState::default(),
+        );
+        let cx = Context::new().with_remote_span_context(span_context);
+        let mut headers = HeaderMap::new();
+        ResponseHeaders::parse("datadog,tracecontext").unwrap().write(&cx, &mut headers);
+        assert_eq!(headers["x-datadog-trace-id"], "7277407061855694839");
+        assert_eq!(headers["traceparent"], "00-640cfd8d0000000064fe8b2a57d3eff7-48485a3953bb6124-01");
+
+        let mut headers = HeaderMap::new();
+        ResponseHeaders::parse("none").unwrap().write(&cx, &mut headers);
+        assert!(headers.is_empty());
+        assert!(ResponseHeaders::parse("b3").is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use axum::{
+    extract::{Request, State},
+    http::Version,
+    middleware::Next,
+    response::Response,
+};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Protocol versions we count, as recorded in `network.protocol.version`
+const PROTOCOLS: [&str; 4] = ["1.0", "1.1", "2", "3"];
+
+/// OpenTelemetry `network.protocol.version` value for an HTTP version
+pub fn protocol_version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "1.1",
+    }
+}
+
+/// Requests served per HTTP protocol version
+#[derive(Debug, Default)]
+pub struct ProtocolStats {
+    requests: [AtomicU64; PROTOCOLS.len()],
+}
+
+impl ProtocolStats {
+    fn record(&self, version: Version) {
+        let protocol = protocol_version(version);
+        if let Some(index) = PROTOCOLS.iter().position(|p| *p == protocol) {
+            self.requests[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, u64> {
+        PROTOCOLS
+            .iter()
+            .zip(&self.requests)
+            .map(|(protocol, count)| (*protocol, count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// Middleware counting requests by protocol version
+pub async fn count_protocol(
+    State(stats): State<Arc<ProtocolStats>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    stats.record(request.version());
+    next.run(request).await
+}
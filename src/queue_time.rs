@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Headers carrying the time a proxy first saw the request, in order of preference
+const QUEUE_HEADERS: [&str; 2] = ["x-request-start", "x-queue-start"];
+
+/// Upper bounds of the histogram buckets, in milliseconds; the last bucket is unbounded
+const BUCKETS_MS: [u64; 11] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// Larger values come from clock skew or a misconfigured proxy, not real queueing
+const MAX_QUEUE_TIME: Duration = Duration::from_secs(60 * 60);
+
+/// Parse a proxy timestamp (`t=1700000000.123`, `1700000000123`, ...) as time since the epoch
+///
+/// nginx sends seconds with a fractional part; other proxies send whole
+/// milliseconds or microseconds, told apart here by magnitude.
+fn parse_timestamp(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let raw = value.strip_prefix("t=").unwrap_or(value);
+    let number: f64 = raw.parse().ok().filter(|n: &f64| n.is_finite() && *n > 0.0)?;
+
+    let seconds = if number >= 1e15 {
+        number / 1e6
+    } else if number >= 1e12 {
+        number / 1e3
+    } else {
+        number
+    };
+    // Values too large for a `Duration` are garbage, not timestamps
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Time the request spent before reaching the app, and the header it came from
+fn queue_time(headers: &HeaderMap, now: Duration) -> Option<(Duration, &'static str)> {
+    QUEUE_HEADERS.iter().find_map(|name| {
+        let start = parse_timestamp(headers.get(*name)?.to_str().ok()?)?;
+        // A proxy clock slightly ahead of ours means no measurable queueing
+        let waited = now.saturating_sub(start);
+        (waited <= MAX_QUEUE_TIME).then_some((waited, *name))
+    })
+}
+
+/// Histogram bucket counts for `/admin/queue-time`
+#[derive(Debug, Serialize)]
+pub struct QueueTimeSnapshot {
+    count: u64,
+    sum_ms: f64,
+    /// Upper bound in ms (`"+Inf"` for the last) to number of requests in that bucket
+    buckets: Vec<(String, u64)>,
+}
+
+/// Histogram of upstream queue time, in milliseconds
+#[derive(Debug, Default)]
+pub struct QueueTimeStats {
+    buckets: [AtomicU64; BUCKETS_MS.len() + 1],
+    count: AtomicU64,
+    sum_us: AtomicU64,
+}
+
+impl QueueTimeStats {
+    fn record(&self, waited: Duration) {
+        let ms = waited.as_millis() as u64;
+        let index = BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us
+            .fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> QueueTimeSnapshot {
+        let bounds = BUCKETS_MS
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(std::iter::once("+Inf".to_string()));
+        QueueTimeSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            sum_ms: self.sum_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            buckets: bounds
+                .zip(&self.buckets)
+                .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+                .collect(),
+        }
+    }
+}
+
+/// Middleware recording upstream queue time from `X-Request-Start`/`X-Queue-Start`
+///
+/// Runs inside the `api.request` span and tags it with `http.queue_time_ms`
+/// and `http.queue_time.source`, so time spent in the load balancer isn't
+/// mistaken for handler latency.
+pub async fn record_queue_time(
+    State(stats): State<Arc<QueueTimeStats>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    if let Some((waited, source)) = queue_time(request.headers(), now) {
+        stats.record(waited);
+        let span = tracing::Span::current();
+        span.set_attribute("http.queue_time_ms", waited.as_secs_f64() * 1000.0);
+        span.set_attribute("http.queue_time.source", source);
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proxy_timestamp_formats() {
+        let expected = Duration::from_millis(1_700_000_000_123);
+        let close = |parsed: Option<Duration>| parsed.unwrap().abs_diff(expected) < Duration::from_micros(1);
+        assert!(close(parse_timestamp("t=1700000000.123")));
+        assert!(close(parse_timestamp(" 1700000000.123 ")));
+        assert!(close(parse_timestamp("1700000000123")));
+        assert!(close(parse_timestamp("t=1700000000123000")));
+    }
+
+    #[test]
+    fn rejects_garbage_and_huge_timestamps() {
+        for value in ["", "t=", "soon", "-5", "0", "NaN", "inf", "1e30", "t=1e300"] {
+            assert_eq!(parse_timestamp(value), None, "{:?}", value);
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-start", "1e30".parse().unwrap());
+        assert_eq!(queue_time(&headers, Duration::from_secs(1_700_000_000)), None);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::auth::Principal;
+use crate::dependency_health;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use redis::aio::ConnectionManager;
+use rust_datadog_otel::client_ip::ClientIp;
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::{info_trace, warn_trace};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_LIMIT: u64 = 600;
+const DEFAULT_WINDOW_SECS: u64 = 60;
+
+/// Longest wait for the store before counting locally instead
+const STORE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Windows kept by the in-memory store before expired ones are swept
+const MEMORY_SWEEP_THRESHOLD: usize = 10_000;
+
+/// A key's usage of its current fixed window
+#[derive(Debug, Clone, Copy)]
+pub struct WindowUsage {
+    /// Requests counted in the window, including this one
+    pub count: u64,
+    /// Time until the window resets
+    pub reset: Duration,
+}
+
+/// Fixed-window request counters
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync + Debug {
+    /// Count a request against `key`, starting a new `window` if none is open
+    async fn hit(&self, key: &str, window: Duration) -> Result<WindowUsage, StoreError>;
+
+    /// Backend name recorded on request spans
+    fn backend(&self) -> &'static str;
+}
+
+/// Increment and start the window's expiry in one round trip
+const HIT_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[1])
+end
+return {count, redis.call('PTTL', KEYS[1])}
+"#;
+
+/// Redis-backed counters, so the limit holds across replicas
+pub struct RedisRateLimitStore {
+    connection: ConnectionManager,
+}
+
+impl Debug for RedisRateLimitStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisRateLimitStore").finish_non_exhaustive()
+    }
+}
+
+impl RedisRateLimitStore {
+    pub fn new(connection: ConnectionManager) -> Self {
+        Self { connection }
+    }
+
+    fn key(key: &str) -> String {
+        format!("ratelimit:{}", key)
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn hit(&self, key: &str, window: Duration) -> Result<WindowUsage, StoreError> {
+        let (count, ttl_ms): (u64, i64) = dependency_health::observe("redis", async {
+            redis::Script::new(HIT_SCRIPT)
+                .key(Self::key(key))
+                .arg(window.as_millis() as u64)
+                .invoke_async(&mut self.connection.clone())
+                .await
+        })
+        .await?;
+        Ok(WindowUsage {
+            count,
+            reset: Duration::from_millis(ttl_ms.max(0) as u64),
+        })
+    }
+
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+}
+
+/// Process-local counters, so each replica enforces the limit on its own
+#[derive(Debug, Default)]
+pub struct MemoryRateLimitStore {
+    windows: Mutex<HashMap<String, (u64, Instant)>>,
+}
+
+impl MemoryRateLimitStore {
+    fn count(&self, key: &str, window: Duration) -> WindowUsage {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if windows.len() >= MEMORY_SWEEP_THRESHOLD {
+            windows.retain(|_, (_, resets_at)| *resets_at > now);
+        }
+        let (count, resets_at) = windows.entry(key.to_string()).or_insert((0, now + window));
+        if *resets_at <= now {
+            *count = 0;
+            *resets_at = now + window;
+        }
+        *count += 1;
+        WindowUsage {
+            count: *count,
+            reset: *resets_at - now,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn hit(&self, key: &str, window: Duration) -> Result<WindowUsage, StoreError> {
+        Ok(self.count(key, window))
+    }
+
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Per-caller request limit for the API
+///
+/// Callers are keyed by user when authenticated, else by client IP. When the
+/// store can't be reached, requests are counted by a process-local fallback
+/// instead, so the limit still applies per replica rather than failing open
+/// or rejecting everything.
+///
+/// Configuration:
+/// - `RATE_LIMIT_REQUESTS`: requests allowed per caller per window (default 600; `0` disables)
+/// - `RATE_LIMIT_WINDOW_SECS`: window length (default 60)
+#[derive(Debug)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    fallback: MemoryRateLimitStore,
+    limit: u64,
+    window: Duration,
+    degraded: AtomicBool,
+    allowed: AtomicU64,
+    limited: AtomicU64,
+    store_errors: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn from_env(store: Arc<dyn RateLimitStore>) -> Result<Self, Box<dyn std::error::Error>> {
+        let setting = |name: &str, default: u64| -> Result<u64, Box<dyn std::error::Error>> {
+            match std::env::var(name) {
+                Ok(value) => Ok(value.parse().map_err(|e| format!("{}: {}", name, e))?),
+                Err(_) => Ok(default),
+            }
+        };
+        let window = setting("RATE_LIMIT_WINDOW_SECS", DEFAULT_WINDOW_SECS)?;
+        if window == 0 {
+            return Err("RATE_LIMIT_WINDOW_SECS must be at least 1".into());
+        }
+        Ok(Self {
+            store,
+            fallback: MemoryRateLimitStore::default(),
+            limit: setting("RATE_LIMIT_REQUESTS", DEFAULT_LIMIT)?,
+            window: Duration::from_secs(window),
+            degraded: AtomicBool::new(false),
+            allowed: AtomicU64::new(0),
+            limited: AtomicU64::new(0),
+            store_errors: AtomicU64::new(0),
+        })
+    }
+
+    pub fn backend(&self) -> &'static str {
+        self.store.backend()
+    }
+
+    /// Count a request for `key`, falling back to local counters if the store fails
+    async fn hit(&self, key: &str) -> (WindowUsage, bool) {
+        let span = tracing::Span::current();
+        let started = Instant::now();
+        let result = tokio::time::timeout(STORE_TIMEOUT, self.store.hit(key, self.window))
+            .await
+            .unwrap_or_else(|_| Err("rate limit store timed out".into()));
+        span.set_attribute("rate_limit.store_latency_ms", started.elapsed().as_secs_f64() * 1000.0);
+
+        match result {
+            Ok(usage) => {
+                if self.degraded.swap(false, Ordering::Relaxed) {
+                    info_trace!(rate_limit.store = self.store.backend(), "Rate limit store recovered");
+                }
+                (usage, false)
+            }
+            Err(e) => {
+                self.store_errors.fetch_add(1, Ordering::Relaxed);
+                if !self.degraded.swap(true, Ordering::Relaxed) {
+                    warn_trace!(
+                        rate_limit.store = self.store.backend(),
+                        error = %e,
+                        "Rate limit store unavailable, counting locally"
+                    );
+                }
+                (self.fallback.count(key, self.window), true)
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "store": self.store.backend(),
+            "limit": self.limit,
+            "window_secs": self.window.as_secs(),
+            "degraded": self.degraded.load(Ordering::Relaxed),
+            "allowed": self.allowed.load(Ordering::Relaxed),
+            "limited": self.limited.load(Ordering::Relaxed),
+            "store_errors": self.store_errors.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// `RateLimit-*` headers (IETF draft) describing the caller's window
+fn rate_limit_headers(limit: u64, usage: WindowUsage) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("ratelimit-limit", HeaderValue::from(limit));
+    headers.insert("ratelimit-remaining", HeaderValue::from(limit.saturating_sub(usage.count)));
+    headers.insert("ratelimit-reset", HeaderValue::from(usage.reset.as_secs().max(1)));
+    headers
+}
+
+/// Middleware enforcing the per-caller request limit
+///
+/// Runs inside authentication so signed-in callers are limited per user. The
+/// request span is tagged with `rate_limit.key_type`, `rate_limit.store`,
+/// `rate_limit.store_latency_ms`, `rate_limit.remaining` and, when the local
+/// fallback counted the request, `rate_limit.fallback`. Limited requests get
+/// 429 with `Retry-After`.
+pub async fn enforce_rate_limit(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    if limiter.limit == 0 {
+        return next.run(request).await;
+    }
+    let (key_type, key) = match (request.extensions().get::<Principal>(), request.extensions().get::<ClientIp>()) {
+        (Some(principal), _) => ("user", format!("user:{}", principal.user_id)),
+        (None, Some(ClientIp(ip))) => ("ip", format!("ip:{}", ip)),
+        (None, None) => ("unknown", "unknown".to_string()),
+    };
+
+    let (usage, fallback) = limiter.hit(&key).await;
+    let span = tracing::Span::current();
+    span.set_attribute("rate_limit.key_type", key_type);
+    span.set_attribute("rate_limit.store", if fallback { "memory" } else { limiter.store.backend() });
+    span.set_attribute("rate_limit.remaining", limiter.limit.saturating_sub(usage.count) as i64);
+    if fallback {
+        span.set_attribute("rate_limit.fallback", true);
+    }
+    let headers = rate_limit_headers(limiter.limit, usage);
+
+    if usage.count > limiter.limit {
+        limiter.limited.fetch_add(1, Ordering::Relaxed);
+        span.set_attribute("rate_limit.limited", true);
+        warn_trace!(rate_limit.key_type = key_type, rate_limit.limit = limiter.limit, "Rate limit exceeded");
+        let mut response = AppError::TooManyRequests("Rate limit exceeded".into()).into_response();
+        response.headers_mut().extend(headers);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(usage.reset.as_secs().max(1)));
+        return response;
+    }
+
+    limiter.allowed.fetch_add(1, Ordering::Relaxed);
+    let mut response = next.run(request).await;
+    response.headers_mut().extend(headers);
+    response
+}
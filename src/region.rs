@@ -0,0 +1,186 @@
+use crate::{cost, dependency_health};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use rust_datadog_otel::{downstream, warn_trace};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Request header choosing the simulated region
+const REGION_HEADER: &str = "x-region";
+
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+/// Simulated latency added to each downstream call made from a region
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LatencyProfile {
+    /// Added to every call
+    pub base_ms: u64,
+    /// Up to this much more, chosen uniformly per call
+    #[serde(default)]
+    pub jitter_ms: u64,
+}
+
+impl LatencyProfile {
+    fn sample(self) -> Duration {
+        let jitter = if self.jitter_ms == 0 {
+            0
+        } else {
+            OsRng.next_u64() % (self.jitter_ms + 1)
+        };
+        Duration::from_millis(self.base_ms + jitter)
+    }
+}
+
+/// Rough round-trip distances from a `us-east-1` backend
+fn default_profiles() -> BTreeMap<String, LatencyProfile> {
+    [
+        ("us-east-1", 0, 2),
+        ("us-west-2", 35, 10),
+        ("eu-west-1", 80, 15),
+        ("ap-southeast-1", 180, 30),
+    ]
+    .into_iter()
+    .map(|(region, base_ms, jitter_ms)| (region.to_string(), LatencyProfile { base_ms, jitter_ms }))
+    .collect()
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+struct RegionStats {
+    requests: u64,
+    downstream_calls: u64,
+    added_latency_ms: u64,
+}
+
+/// Region latency profiles and per-region counters for `/admin/regions`
+///
+/// Lets one deployment stand in for several: a request's region (the
+/// `x-region` header, else the home region) sets how slow its downstream calls are,
+/// and spans are tagged with `region` so the regions can be compared.
+///
+/// Configuration:
+/// - `home`: region for requests without a known `x-region` header (`ServiceConfig::region`)
+/// - `REGION_LATENCY_PROFILES`: JSON map of region to `{"base_ms": .., "jitter_ms": ..}`,
+///   replacing the built-in `us-east-1`, `us-west-2`, `eu-west-1` and `ap-southeast-1` profiles
+#[derive(Debug)]
+pub struct Regions {
+    home: String,
+    profiles: BTreeMap<String, LatencyProfile>,
+    stats: Mutex<BTreeMap<String, RegionStats>>,
+}
+
+impl Regions {
+    pub fn from_env(home: String) -> Result<Self, Box<dyn std::error::Error>> {
+        let profiles = match std::env::var("REGION_LATENCY_PROFILES") {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("REGION_LATENCY_PROFILES: {}", e))?,
+            Err(_) => default_profiles(),
+        };
+        if !profiles.contains_key(&home) {
+            return Err(format!("REGION '{}' has no latency profile", home).into());
+        }
+        Ok(Self {
+            home,
+            profiles,
+            stats: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn home(&self) -> &str {
+        &self.home
+    }
+
+    fn record(&self, region: &str, apply: impl FnOnce(&mut RegionStats)) {
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        apply(stats.entry(region.to_string()).or_default());
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::json!({
+            "home": self.home,
+            "profiles": self.profiles,
+            "stats": *stats,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RequestRegion {
+    regions: Arc<Regions>,
+    name: String,
+}
+
+tokio::task_local! {
+    static CURRENT: RequestRegion;
+}
+
+/// Middleware choosing the request's region and tagging the request span
+///
+/// The region applies to downstream calls made while handling the request,
+/// via [`simulate_downstream`].
+pub async fn apply_region(State(regions): State<Arc<Regions>>, request: Request, next: Next) -> Response {
+    let requested = request
+        .headers()
+        .get(REGION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_ascii_lowercase);
+    let name = match requested {
+        Some(region) if regions.profiles.contains_key(&region) => region,
+        Some(region) => {
+            warn_trace!(region.requested = %region, "Unknown region, using the default");
+            regions.home.clone()
+        }
+        None => regions.home.clone(),
+    };
+
+    tracing::Span::current().set_attribute("region", name.clone());
+    regions.record(&name, |stats| stats.requests += 1);
+    CURRENT.scope(RequestRegion { regions, name }, next.run(request)).await
+}
+
+/// Run `future` in the current request's region, for work spawned off the request task
+pub fn in_current_region<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let current = CURRENT.try_with(RequestRegion::clone).ok();
+    async move {
+        match current {
+            Some(current) => CURRENT.scope(current, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Wait out the current request's regional latency before calling `service`
+///
+/// Tags the current span with `region`, `peer.service` and
+/// `region.added_latency_ms`, and counts as a call to `service` for its
+/// dependency health. Does nothing outside a request.
+pub async fn simulate_downstream(service: &'static str) {
+    let Ok(current) = CURRENT.try_with(RequestRegion::clone) else {
+        return;
+    };
+    let delay = current.regions.profiles[&current.name].sample();
+
+    let span = tracing::Span::current();
+    span.set_attribute("region", current.name.clone());
+    downstream::set_peer_service(&span, service);
+    span.set_attribute("region.added_latency_ms", delay.as_millis() as i64);
+    if service == "database" {
+        cost::record_db_call();
+    } else {
+        cost::record_external_call();
+    }
+    current.regions.record(&current.name, |stats| {
+        stats.downstream_calls += 1;
+        stats.added_latency_ms += delay.as_millis() as u64;
+    });
+
+    tokio::time::sleep(delay).await;
+    dependency_health::record(service, true, delay);
+}
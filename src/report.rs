@@ -0,0 +1,92 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Products the synthetic order dataset draws from
+const PRODUCTS: [&str; 8] = [
+    "keyboard", "mouse", "monitor", "laptop", "headset", "webcam", "dock", "cable",
+];
+
+/// Revenue and volume for one product
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ProductSummary {
+    pub orders: u64,
+    pub units: u64,
+    pub revenue: f64,
+}
+
+/// Result of [`generate`]
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Report {
+    pub rows: usize,
+    pub total_revenue: f64,
+    pub average_order_value: f64,
+    /// 95th percentile order value
+    pub p95_order_value: f64,
+    pub products: BTreeMap<String, ProductSummary>,
+    /// SHA-256 over every row, so two reports over the same data can be compared
+    pub digest: String,
+}
+
+/// Deterministic xorshift generator, so a given `rows` always yields the same report
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Aggregate `rows` synthetic orders: per-product totals, order value
+/// percentiles and a digest of the dataset
+///
+/// Deliberately CPU-bound; call it through [`crate::compute::compute`].
+pub fn generate(rows: usize) -> Report {
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    let mut hasher = Sha256::new();
+    let mut products: BTreeMap<String, ProductSummary> = BTreeMap::new();
+    let mut order_values = Vec::with_capacity(rows);
+
+    for row in 0..rows {
+        let product = PRODUCTS[(rng.next() % PRODUCTS.len() as u64) as usize];
+        let quantity = rng.next() % 5 + 1;
+        let price = (rng.next() % 50_000) as f64 / 100.0 + 1.0;
+        let value = price * quantity as f64;
+
+        hasher.update(format!("{}:{}:{}:{:.2}\n", row, product, quantity, price));
+
+        let summary = products.entry(product.to_string()).or_default();
+        summary.orders += 1;
+        summary.units += quantity;
+        summary.revenue += value;
+        order_values.push(value);
+    }
+
+    order_values.sort_by(f64::total_cmp);
+    let total_revenue: f64 = order_values.iter().sum();
+    let p95_order_value = order_values
+        .get((rows * 95 / 100).min(rows.saturating_sub(1)))
+        .copied()
+        .unwrap_or_default();
+
+    Report {
+        rows,
+        total_revenue,
+        average_order_value: if rows == 0 {
+            0.0
+        } else {
+            total_revenue / rows as f64
+        },
+        p95_order_value,
+        products,
+        digest: hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    }
+}
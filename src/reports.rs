@@ -0,0 +1,247 @@
+use crate::analytics::{self, OrderAnalytics};
+use crate::object_store::ObjectStorage;
+use crate::orders::OrderRepository;
+use opentelemetry::trace::SpanContext;
+use rust_datadog_otel::{dogstatsd, info_trace, trace_context};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use utoipa::ToSchema;
+
+const DEFAULT_INTERVAL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_DIR: &str = "reports";
+
+/// Artifact keys are `orders-summary-<timestamp>.json`, so the newest sorts last
+const KEY_PREFIX: &str = "orders-summary-";
+
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where report artifacts are written
+#[async_trait::async_trait]
+pub trait ArtifactStore: Send + Sync + Debug {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), StorageError>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+    /// Keys starting with `prefix`, in any order
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError>;
+    /// Backend name recorded on report spans
+    fn backend(&self) -> &'static str;
+}
+
+/// Artifacts as files in a local directory (a mounted volume in Kubernetes)
+#[derive(Debug)]
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl ArtifactStore for DirectoryStore {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), StorageError> {
+        tokio::fs::create_dir_all(&self.root).await?;
+        // Write then rename, so readers never see a half-written artifact
+        let path = self.root.join(key);
+        let partial = self.root.join(format!(".{}.partial", key));
+        tokio::fs::write(&partial, body).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match tokio::fs::read(self.root.join(key)).await {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut keys = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(name) = entry.file_name().to_str() {
+                if name.starts_with(prefix) {
+                    keys.push(name.to_string());
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    fn backend(&self) -> &'static str {
+        "directory"
+    }
+}
+
+/// Directory under which artifacts are kept in object storage
+const OBJECT_PREFIX: &str = "reports";
+
+#[async_trait::async_trait]
+impl ArtifactStore for ObjectStorage {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), StorageError> {
+        ObjectStorage::put(self, &format!("{}/{}", OBJECT_PREFIX, key), body).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        ObjectStorage::get(self, &format!("{}/{}", OBJECT_PREFIX, key)).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let keys = ObjectStorage::list(self, OBJECT_PREFIX).await?;
+        Ok(keys
+            .into_iter()
+            .filter_map(|key| {
+                key.strip_prefix(OBJECT_PREFIX)?
+                    .strip_prefix('/')
+                    .map(str::to_string)
+            })
+            .filter(|key| key.starts_with(prefix))
+            .collect())
+    }
+
+    fn backend(&self) -> &'static str {
+        ObjectStorage::backend(self)
+    }
+}
+
+/// A generated orders summary, as stored and served by `/reports/latest`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReportArtifact {
+    pub key: String,
+    pub generated_at: String,
+    /// Trace that generated the report (hex), linked from requests that read it
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub summary: OrderAnalytics,
+}
+
+impl ReportArtifact {
+    /// Span context of the generating trace, for a span link
+    fn span_context(&self) -> Option<SpanContext> {
+        trace_context::remote_span_context(self.trace_id.as_deref()?, self.span_id.as_deref()?)
+    }
+}
+
+/// Periodic orders summary reports
+///
+/// Artifacts go to object storage when `OBJECT_STORE_URL` points at a
+/// persistent backend, and to a local directory otherwise.
+///
+/// Configuration:
+/// - `REPORTS_DIR`: directory artifacts are written to without object storage (default `reports`)
+/// - `REPORTS_INTERVAL_SECS`: time between reports (default 24 hours); each
+///   report covers the same length of history, capped at the analytics limit
+#[derive(Debug)]
+pub struct Reports {
+    store: Arc<dyn ArtifactStore>,
+    orders: Arc<OrderRepository>,
+    interval: Duration,
+    generated: AtomicU64,
+}
+
+impl Reports {
+    pub fn from_env(orders: Arc<OrderRepository>, objects: Arc<ObjectStorage>) -> Self {
+        // The in-memory object store would lose reports on restart; a directory can be a volume
+        let store: Arc<dyn ArtifactStore> = if objects.backend() == "memory" {
+            let dir = std::env::var("REPORTS_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string());
+            Arc::new(DirectoryStore::new(dir))
+        } else {
+            objects
+        };
+
+        let interval = std::env::var("REPORTS_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_INTERVAL_SECS);
+
+        Self {
+            store,
+            orders,
+            interval: Duration::from_secs(interval),
+            generated: AtomicU64::new(0),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// `reports.generated` counter value
+    pub fn generated(&self) -> u64 {
+        self.generated.load(Ordering::Relaxed)
+    }
+
+    /// Summarize recent orders and store the artifact; returns its key
+    #[instrument(
+        name = "reports.generate",
+        skip(self),
+        fields(
+            report.backend = self.store.backend(),
+            report.key = tracing::field::Empty,
+            report.size = tracing::field::Empty,
+        )
+    )]
+    pub async fn generate(&self) -> Result<String, StorageError> {
+        let window_secs = self.interval.as_secs().min(analytics::MAX_WINDOW_DAYS as u64 * 86_400);
+        let window = format!("{}m", window_secs.div_ceil(60));
+        let duration = chrono::Duration::minutes(window_secs.div_ceil(60) as i64);
+        let summary = analytics::order_analytics(&self.orders, &window, duration).await;
+
+        let now = chrono::Utc::now();
+        let span = tracing::Span::current();
+        let ids = trace_context::trace_ids(&span);
+        let artifact = ReportArtifact {
+            key: format!("{}{}.json", KEY_PREFIX, now.format("%Y%m%dT%H%M%SZ")),
+            generated_at: now.to_rfc3339(),
+            trace_id: ids.as_ref().map(|ids| ids.trace_id_hex.clone()),
+            span_id: ids.map(|ids| ids.span_id_hex),
+            summary,
+        };
+
+        let body = serde_json::to_vec_pretty(&artifact)?;
+        span.record("report.key", artifact.key.as_str());
+        span.record("report.size", body.len());
+        self.store.put(&artifact.key, body).await?;
+
+        let generated = self.generated.fetch_add(1, Ordering::Relaxed) + 1;
+        dogstatsd::count("reports.generated", 1, &[format!("backend:{}", self.store.backend())]);
+        info_trace!(
+            report.key = %artifact.key,
+            order_count = artifact.summary.order_count,
+            reports.generated = generated,
+            "Report generated"
+        );
+        Ok(artifact.key)
+    }
+
+    /// Most recent artifact, linking the current span to the trace that generated it
+    #[instrument(name = "reports.latest", skip(self), fields(report.backend = self.store.backend()))]
+    pub async fn latest(&self) -> Result<Option<ReportArtifact>, StorageError> {
+        let Some(key) = self.store.list(KEY_PREFIX).await?.into_iter().max() else {
+            return Ok(None);
+        };
+        let Some(body) = self.store.get(&key).await? else {
+            return Ok(None);
+        };
+        let artifact: ReportArtifact = serde_json::from_slice(&body)?;
+
+        if let Some(context) = artifact.span_context() {
+            tracing::Span::current().add_link(context);
+        }
+        Ok(Some(artifact))
+    }
+}
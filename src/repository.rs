@@ -0,0 +1,334 @@
+use crate::auth::Role;
+use crate::concurrency::{IfMatch, UpdateOutcome};
+use crate::cost;
+use crate::ids::UserId;
+use crate::pii::FieldCipher;
+use chrono::{DateTime, Utc};
+use rust_datadog_otel::{debug_trace, error_trace};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// User record as persisted
+///
+/// `email_ciphertext` is AES-GCM encrypted; `email_hash` is a keyed hash used
+/// for lookups so the plaintext never needs to be stored.
+#[derive(Debug, Clone)]
+pub struct StoredUser {
+    pub id: UserId,
+    pub name: String,
+    pub email_ciphertext: String,
+    pub email_hash: String,
+    /// argon2 PHC hash; `None` for users created without a password
+    pub password_hash: Option<String>,
+    pub role: Role,
+    /// RFC 3339
+    pub created_at: String,
+    /// Bumped on every update; the record's ETag
+    pub version: u64,
+}
+
+/// Decrypted user returned to callers
+#[derive(Debug, Clone)]
+pub struct UserRecord {
+    pub id: UserId,
+    pub name: String,
+    pub email: String,
+    pub created_at: String,
+    pub version: u64,
+}
+
+/// User kept outside the database, e.g. in a cache, with the email still encrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedUser {
+    pub id: UserId,
+    pub name: String,
+    pub email_ciphertext: String,
+    pub created_at: String,
+    pub version: u64,
+}
+
+/// Login credentials looked up by email
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub user_id: UserId,
+    pub password_hash: String,
+    pub role: Role,
+}
+
+/// Where user rows are kept; [`UserRepository`] encrypts and decrypts around it
+#[async_trait::async_trait]
+pub trait Repository: Send + Sync + Debug {
+    async fn insert(&self, user: StoredUser) -> Result<(), StoreError>;
+    async fn get(&self, id: UserId) -> Result<Option<StoredUser>, StoreError>;
+    async fn find_by_email_hash(&self, email_hash: &str) -> Result<Option<StoredUser>, StoreError>;
+    /// Replace the email ciphertext, e.g. after re-encrypting under a new key
+    async fn set_email_ciphertext(&self, id: UserId, ciphertext: &str) -> Result<(), StoreError>;
+    /// Rename and bump the version if `if_match` still matches it, atomically
+    async fn update_name(&self, id: UserId, name: &str, if_match: &IfMatch) -> Result<UpdateOutcome<StoredUser>, StoreError>;
+    async fn created_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<UserId>, StoreError>;
+    /// Delete by ID; returns how many existed
+    async fn delete_many(&self, ids: &[UserId]) -> Result<usize, StoreError>;
+    /// Every user's name by ID
+    async fn names(&self) -> Result<HashMap<UserId, String>, StoreError>;
+    async fn count(&self) -> Result<usize, StoreError>;
+    /// Backend name for logs
+    fn backend(&self) -> &'static str;
+}
+
+/// Users in process memory, lost on restart; used without `DATABASE_URL`
+#[derive(Debug, Default)]
+pub struct MemoryRepository {
+    users: RwLock<HashMap<UserId, StoredUser>>,
+}
+
+impl MemoryRepository {
+    pub const BACKEND: &'static str = "memory";
+}
+
+#[async_trait::async_trait]
+impl Repository for MemoryRepository {
+    async fn insert(&self, user: StoredUser) -> Result<(), StoreError> {
+        self.users.write().await.insert(user.id, user);
+        Ok(())
+    }
+
+    async fn get(&self, id: UserId) -> Result<Option<StoredUser>, StoreError> {
+        Ok(self.users.read().await.get(&id).cloned())
+    }
+
+    async fn find_by_email_hash(&self, email_hash: &str) -> Result<Option<StoredUser>, StoreError> {
+        Ok(self.users.read().await.values().find(|user| user.email_hash == email_hash).cloned())
+    }
+
+    async fn set_email_ciphertext(&self, id: UserId, ciphertext: &str) -> Result<(), StoreError> {
+        if let Some(user) = self.users.write().await.get_mut(&id) {
+            user.email_ciphertext = ciphertext.to_string();
+        }
+        Ok(())
+    }
+
+    async fn update_name(&self, id: UserId, name: &str, if_match: &IfMatch) -> Result<UpdateOutcome<StoredUser>, StoreError> {
+        // Check and write under one lock, so two writers can't both match
+        let mut users = self.users.write().await;
+        let Some(stored) = users.get_mut(&id) else {
+            return Ok(UpdateOutcome::NotFound);
+        };
+        if !if_match.matches(stored.version) {
+            return Ok(UpdateOutcome::Stale {
+                current_version: stored.version,
+            });
+        }
+        stored.name = name.to_string();
+        stored.version += 1;
+        Ok(UpdateOutcome::Updated(stored.clone()))
+    }
+
+    async fn created_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<UserId>, StoreError> {
+        Ok(self
+            .users
+            .read()
+            .await
+            .values()
+            .filter(|user| {
+                DateTime::parse_from_rfc3339(&user.created_at).is_ok_and(|created| created < cutoff)
+            })
+            .map(|user| user.id)
+            .collect())
+    }
+
+    async fn delete_many(&self, ids: &[UserId]) -> Result<usize, StoreError> {
+        let mut users = self.users.write().await;
+        Ok(ids.iter().filter(|id| users.remove(id).is_some()).count())
+    }
+
+    async fn names(&self) -> Result<HashMap<UserId, String>, StoreError> {
+        Ok(self.users.read().await.values().map(|user| (user.id, user.name.clone())).collect())
+    }
+
+    async fn count(&self) -> Result<usize, StoreError> {
+        Ok(self.users.read().await.len())
+    }
+
+    fn backend(&self) -> &'static str {
+        Self::BACKEND
+    }
+}
+
+/// User repository with encrypted PII columns, over a [`Repository`] backend
+///
+/// Lookups that return no error type (`email_exists`, `names`, ...) log a
+/// backend failure and answer as if nothing was found.
+#[derive(Debug)]
+pub struct UserRepository {
+    cipher: FieldCipher,
+    store: Arc<dyn Repository>,
+}
+
+impl UserRepository {
+    pub fn new(cipher: FieldCipher, store: Arc<dyn Repository>) -> Self {
+        Self { cipher, store }
+    }
+
+    pub fn backend(&self) -> &'static str {
+        self.store.backend()
+    }
+
+    /// Whether users live in memory only, so `/users/{id}` v1 may simulate missing ones
+    pub fn is_simulated(&self) -> bool {
+        self.store.backend() == MemoryRepository::BACKEND
+    }
+
+    #[instrument(skip(self, record, password_hash), fields(user_id = %record.id, role = role.as_str(), key_id = %self.cipher.active_key_id()))]
+    pub async fn insert(
+        &self,
+        record: &UserRecord,
+        password_hash: Option<String>,
+        role: Role,
+    ) -> Result<(), StoreError> {
+        cost::record_db_call();
+        let stored = StoredUser {
+            id: record.id,
+            name: record.name.clone(),
+            email_ciphertext: self.cipher.encrypt(&record.email)?,
+            email_hash: self.cipher.lookup_hash(&record.email),
+            password_hash,
+            role,
+            created_at: record.created_at.clone(),
+            version: record.version,
+        };
+
+        self.store.insert(stored).await
+    }
+
+    /// Fetch a user by ID, re-encrypting the email under the active key if needed
+    #[instrument(skip(self), fields(user_id = %id))]
+    pub async fn find_by_id(&self, id: UserId) -> Result<Option<UserRecord>, StoreError> {
+        cost::record_db_call();
+        let Some(stored) = self.store.get(id).await? else {
+            return Ok(None);
+        };
+
+        let email = self.cipher.decrypt(&stored.email_ciphertext)?;
+
+        if self.cipher.needs_rotation(&stored.email_ciphertext) {
+            let rotated = self.cipher.encrypt(&email)?;
+            self.store.set_email_ciphertext(id, &rotated).await?;
+            debug_trace!(user_id = %id, key_id = %self.cipher.active_key_id(), "Rotated user email encryption key");
+        }
+
+        Ok(Some(UserRecord {
+            id: stored.id,
+            name: stored.name,
+            email,
+            created_at: stored.created_at,
+            version: stored.version,
+        }))
+    }
+
+    /// `record` for a cache, encrypting its email
+    pub fn to_cached(&self, record: &UserRecord) -> Result<CachedUser, StoreError> {
+        Ok(CachedUser {
+            id: record.id,
+            name: record.name.clone(),
+            email_ciphertext: self.cipher.encrypt(&record.email)?,
+            created_at: record.created_at.clone(),
+            version: record.version,
+        })
+    }
+
+    /// A user read back from a cache
+    pub fn read_cached(&self, cached: CachedUser) -> Result<UserRecord, StoreError> {
+        Ok(UserRecord {
+            id: cached.id,
+            name: cached.name,
+            email: self.cipher.decrypt(&cached.email_ciphertext)?,
+            created_at: cached.created_at,
+            version: cached.version,
+        })
+    }
+
+    /// Rename a user if `if_match` still matches its version
+    #[instrument(skip(self, name, if_match), fields(user_id = %id))]
+    pub async fn update_name(
+        &self,
+        id: UserId,
+        name: String,
+        if_match: &IfMatch,
+    ) -> Result<UpdateOutcome<UserRecord>, StoreError> {
+        cost::record_db_call();
+        let updated = match self.store.update_name(id, &name, if_match).await? {
+            UpdateOutcome::Updated(updated) => updated,
+            UpdateOutcome::Stale { current_version } => return Ok(UpdateOutcome::Stale { current_version }),
+            UpdateOutcome::NotFound => return Ok(UpdateOutcome::NotFound),
+            UpdateOutcome::Rejected(reason) => return Ok(UpdateOutcome::Rejected(reason)),
+        };
+
+        Ok(UpdateOutcome::Updated(UserRecord {
+            id: updated.id,
+            name: updated.name,
+            email: self.cipher.decrypt(&updated.email_ciphertext)?,
+            created_at: updated.created_at,
+            version: updated.version,
+        }))
+    }
+
+    /// Whether a user with this email exists, using the deterministic lookup hash
+    #[instrument(skip_all)]
+    pub async fn email_exists(&self, email: &str) -> bool {
+        cost::record_db_call();
+        let hash = self.cipher.lookup_hash(email);
+        logged(self.store.find_by_email_hash(&hash).await).is_some_and(|user| user.is_some())
+    }
+
+    /// Password login credentials for an email, if that user has a password
+    #[instrument(skip_all)]
+    pub async fn credentials(&self, email: &str) -> Option<Credentials> {
+        cost::record_db_call();
+        let hash = self.cipher.lookup_hash(email);
+        let user = logged(self.store.find_by_email_hash(&hash).await)??;
+        Some(Credentials {
+            user_id: user.id,
+            password_hash: user.password_hash?,
+            role: user.role,
+        })
+    }
+
+    /// IDs of users created before `cutoff`
+    #[instrument(skip(self))]
+    pub async fn created_before(&self, cutoff: DateTime<Utc>) -> Vec<UserId> {
+        cost::record_db_call();
+        logged(self.store.created_before(cutoff).await).unwrap_or_default()
+    }
+
+    /// Delete users by ID; returns how many existed
+    #[instrument(skip_all, fields(batch.size = ids.len()))]
+    pub async fn delete_many(&self, ids: &[UserId]) -> usize {
+        cost::record_db_call();
+        logged(self.store.delete_many(ids).await).unwrap_or_default()
+    }
+
+    /// Every user's name by ID, without decrypting PII
+    #[instrument(skip(self))]
+    pub async fn names(&self) -> HashMap<UserId, String> {
+        cost::record_db_call();
+        logged(self.store.names().await).unwrap_or_default()
+    }
+
+    pub async fn count(&self) -> usize {
+        cost::record_db_call();
+        logged(self.store.count().await).unwrap_or_default()
+    }
+}
+
+/// `result`'s value, logging the error of a failed backend call
+fn logged<T>(result: Result<T, StoreError>) -> Option<T> {
+    result
+        .map_err(|e| error_trace!(error = %e, "User storage call failed"))
+        .ok()
+}
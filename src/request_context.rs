@@ -0,0 +1,173 @@
+use crate::auth::{Principal, Role};
+use crate::feature_flags::FeatureFlags;
+use crate::ids::UserId;
+use crate::priority::{Deadline, Priority};
+use crate::session::Session;
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use rust_datadog_otel::error::AppError;
+use rust_datadog_otel::warn_trace;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+/// Request (and response) header carrying the request ID
+const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Request header naming the caller's tenant
+const TENANT_HEADER: &str = "x-tenant-id";
+/// Longest accepted request or tenant ID; longer values are replaced or dropped
+const MAX_ID_LEN: usize = 128;
+
+const DEFAULT_FLAGS: &str = "new_checkout";
+
+/// Who and what a request is for, built once per request by [`build_context`]
+///
+/// Handlers extract it instead of re-reading headers and re-tagging the span
+/// themselves; every field is already on the request span.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// `X-Request-ID` from the caller, or a generated UUID; echoed on the response
+    pub request_id: String,
+    /// `X-Tenant-ID` from the caller
+    pub tenant: Option<String>,
+    /// The caller, from a bearer token or else a session
+    pub user_id: Option<UserId>,
+    pub role: Option<Role>,
+    /// Flags evaluated for the caller; empty for anonymous requests
+    pub flags: BTreeMap<String, bool>,
+    pub priority: Priority,
+    /// When the request's priority class timeout expires
+    pub deadline: Instant,
+}
+
+impl RequestContext {
+    /// Time left before the deadline, zero once it has passed
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// Tag the current span with every field of the context
+    ///
+    /// Individual flags are already tagged as `feature_flag.<key>` when
+    /// evaluated; `request.flags` lists the enabled ones.
+    fn record(&self) {
+        let span = tracing::Span::current();
+        span.set_attribute("request.id", self.request_id.clone());
+        if let Some(tenant) = &self.tenant {
+            span.set_attribute("tenant.id", tenant.clone());
+        }
+        if let Some(user_id) = self.user_id {
+            span.set_attribute("usr.id", user_id.to_string());
+        }
+        if let Some(role) = self.role {
+            span.set_attribute("usr.role", role.as_str());
+        }
+        let enabled: Vec<&str> = self
+            .flags
+            .iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(key, _)| key.as_str())
+            .collect();
+        span.set_attribute("request.flags", enabled.join(","));
+        span.set_attribute("request.priority", self.priority.as_str());
+        span.set_attribute("request.deadline_ms", self.remaining().as_millis() as i64);
+    }
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<RequestContext>().cloned().ok_or_else(|| {
+            // Only API routes run `build_context`
+            warn_trace!(path = %parts.uri.path(), "Request context requested outside the API");
+            AppError::Internal("No request context for this route".to_string())
+        })
+    }
+}
+
+/// Flags evaluated into each [`RequestContext`]
+///
+/// Configuration:
+/// - `REQUEST_CONTEXT_FLAGS`: comma-separated flag keys (default `new_checkout`)
+#[derive(Debug)]
+pub struct ContextConfig {
+    flags: Arc<FeatureFlags>,
+    flag_keys: Vec<String>,
+}
+
+impl ContextConfig {
+    pub fn from_env(flags: Arc<FeatureFlags>) -> Self {
+        let flag_keys = std::env::var("REQUEST_CONTEXT_FLAGS")
+            .unwrap_or_else(|_| DEFAULT_FLAGS.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self { flags, flag_keys }
+    }
+}
+
+/// A caller-supplied ID, if it is short, printable ASCII
+fn header_id(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty() && value.len() <= MAX_ID_LEN)
+        .filter(|value| value.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+}
+
+/// Middleware building the [`RequestContext`] and flattening it onto the request span
+///
+/// Runs inside authentication, sessions and priority classes, whose results
+/// it collects. The request ID is echoed as `X-Request-ID` on the response.
+pub async fn build_context(State(config): State<Arc<ContextConfig>>, mut request: Request, next: Next) -> Response {
+    let request_id = header_id(&request, REQUEST_ID_HEADER).unwrap_or_else(|| Uuid::new_v4().to_string());
+    let extensions = request.extensions();
+    let (user_id, role) = match (extensions.get::<Principal>(), extensions.get::<Session>()) {
+        (Some(principal), _) => (Some(principal.user_id), Some(principal.role)),
+        (None, Some(session)) => (Some(session.data.user_id), None),
+        (None, None) => (None, None),
+    };
+    let priority = extensions.get::<Priority>().copied().unwrap_or(Priority::Normal);
+    let deadline = extensions.get::<Deadline>().map_or_else(Instant::now, |deadline| deadline.0);
+
+    let mut flags = BTreeMap::new();
+    if let Some(user_id) = user_id {
+        let targeting_key = user_id.to_string();
+        for key in &config.flag_keys {
+            flags.insert(key.clone(), config.flags.is_enabled(key, &targeting_key).await);
+        }
+    }
+
+    let context = RequestContext {
+        request_id,
+        tenant: header_id(&request, TENANT_HEADER),
+        user_id,
+        role,
+        flags,
+        priority,
+        deadline,
+    };
+    context.record();
+    let request_id = HeaderValue::from_str(&context.request_id).ok();
+    request.extensions_mut().insert(context);
+
+    let mut response = next.run(request).await;
+    if let Some(request_id) = request_id {
+        response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+    }
+    response
+}
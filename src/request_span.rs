@@ -0,0 +1,140 @@
+use crate::protocol::protocol_version;
+use crate::span_dedup::{self, Source};
+use axum::{
+    extract::MatchedPath,
+    http::{header, Request, Response},
+};
+use opentelemetry::{trace::Status, KeyValue};
+use std::time::Duration;
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::trace::{MakeSpan, OnResponse, TraceLayer};
+use tracing::{field::Empty, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Name of the server span opened for every request
+pub const SPAN_NAME: &str = "api.request";
+
+/// [`layer`]'s type, for routers that name it
+pub type RequestSpanLayer =
+    TraceLayer<SharedClassifier<ServerErrorsAsFailures>, MakeRequestSpan, (), RecordResponse, (), (), ()>;
+
+/// Tracing layer opening one server span per request
+///
+/// The `api.request` span follows the OpenTelemetry HTTP conventions:
+/// `span.kind=server` with `http.method`, `http.route` (the route template),
+/// `url.path`, `user_agent.original` and `network.protocol.version`, plus
+/// `http.status_code` once the response is ready. Its `resource.name` is the
+/// method and route template, such as `GET /api/users/{id}`, so Datadog
+/// groups requests per endpoint rather than per path. It stays open until the
+/// response body is sent. Middleware and handlers run inside it, so tags they
+/// set land on the request span. The layer must run after routing, so add it
+/// with `Router::layer`. It must also sit inside `propagation::extract_trace_context`
+/// so the span continues the caller's trace.
+///
+/// On 5xx responses the span also gets an error status, `error.type` set
+/// to the status code, and an `exception` event, which Datadog shows as
+/// the span's error. This covers answers from handlers and middleware alike.
+pub fn layer() -> RequestSpanLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(MakeRequestSpan)
+        .on_request(())
+        .on_response(RecordResponse)
+        .on_body_chunk(())
+        .on_eos(())
+        .on_failure(())
+}
+
+/// Opens the `api.request` span from the routed request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MakeRequestSpan;
+
+impl<B> MakeSpan<B> for MakeRequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let route = request.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+        let user_agent = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok());
+        let resource = match route {
+            Some(route) => format!("{} {}", request.method(), route_template(route)),
+            None => request.method().to_string(),
+        };
+        // Unmatched paths have no route, so they are never request spans to dedup
+        let suppressed = route.is_some_and(|route| span_dedup::suppressed(route) == Some(Source::Middleware));
+
+        if suppressed {
+            tracing::info_span!(
+                target: span_dedup::SUPPRESSED_TARGET,
+                SPAN_NAME,
+                otel.kind = "server",
+                resource.name = %resource,
+                http.method = %request.method(),
+                http.route = route,
+                url.path = request.uri().path(),
+                user_agent.original = user_agent,
+                network.protocol.version = protocol_version(request.version()),
+                http.status_code = Empty,
+            )
+        } else {
+            tracing::info_span!(
+                SPAN_NAME,
+                otel.kind = "server",
+                resource.name = %resource,
+                http.method = %request.method(),
+                http.route = route,
+                url.path = request.uri().path(),
+                user_agent.original = user_agent,
+                network.protocol.version = protocol_version(request.version()),
+                http.status_code = Empty,
+            )
+        }
+    }
+}
+
+/// `route` with axum's `:param` and `*rest` captures as OpenAPI-style `{param}`
+fn route_template(route: &str) -> String {
+    route
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':').or_else(|| segment.strip_prefix('*')) {
+            Some(name) => format!("{{{}}}", name),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Records the response status on the request span, marking 5xx as errors
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecordResponse;
+
+impl<B> OnResponse<B> for RecordResponse {
+    fn on_response(self, response: &Response<B>, _latency: Duration, span: &Span) {
+        let status = response.status();
+        span.record("http.status_code", status.as_u16());
+        if status.is_server_error() {
+            let reason = status.canonical_reason().unwrap_or("Server error");
+            span.set_status(Status::error(reason));
+            span.set_attribute("error.type", status.as_str().to_string());
+            span.add_event(
+                "exception",
+                vec![
+                    KeyValue::new("exception.type", format!("HTTP {}", status.as_u16())),
+                    KeyValue::new("exception.message", reason),
+                ],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_templates_use_braces_for_captures() {
+        assert_eq!(route_template("/api/users/:id"), "/api/users/{id}");
+        assert_eq!(route_template("/api/orders/:id/items/:item"), "/api/orders/{id}/items/{item}");
+        assert_eq!(route_template("/static/*path"), "/static/{path}");
+        assert_eq!(route_template("/health"), "/health");
+    }
+}
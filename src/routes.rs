@@ -0,0 +1,290 @@
+use crate::feature_flags::FeatureFlags;
+use crate::versioning::{self, ApiVersion};
+use crate::{
+    auth, cost, csrf, decompression, disconnect, experiments, ip_filter, openapi, priority, queue_time, rate_limit,
+    region, request_context, security, session, static_assets, AppState,
+};
+// Handlers
+use crate::admin_api::{
+    auth_stats, concurrency_stats, cost_stats, dependency_stats, disconnect_stats, effective_config, email_stats,
+    event_log, event_schema, experiment_stats, job_stats, job_status, priority_stats, protocol_stats, purge_users,
+    queue_time_stats, rate_limit_stats, region_stats,
+};
+use crate::auth_api::{auth_login, current_session, current_user, login, logout, register};
+use crate::demo_api::{
+    database_query, generate_report, latest_report, search_entities, simulate_error, slow_operation, upload_file,
+};
+use crate::order_api::{
+    cancel_order, create_order, export_orders, get_order, list_user_orders, order_analytics, update_order,
+};
+use crate::service_api::{demo, demo_config, health, ready, root, span_stream, telemetry_status};
+use crate::user_api::{create_user, get_user, import_users, update_user};
+use axum::{
+    middleware,
+    routing::{get, post, put, MethodRouter},
+    Router,
+};
+use rust_datadog_otel::info_trace;
+use rust_datadog_otel::layers::ServiceLayers;
+use rust_datadog_otel::metrics;
+use std::sync::Arc;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+/// The service's HTTP app: the route tables, the API middleware and the library's [`ServiceLayers`]
+///
+/// Service-level routes are mounted at the root, admin-only ones behind
+/// [`admin_only`], and the versioned API under each of [`API_MOUNTS`] with its
+/// own middleware. IP filtering, security auditing and CSRF checks wrap
+/// everything, inside the outer layers of [`ServiceLayers::apply`].
+pub fn app(state: Arc<AppState>, flags: Arc<FeatureFlags>) -> Result<Router, Box<dyn std::error::Error>> {
+    let layers = ServiceLayers::from_env(&state.config.cors_origins)?.with_protocols(state.protocols.clone());
+    let ip_policy = Arc::new(ip_filter::IpPolicy::from_env()?);
+    let csrf_config = csrf::CsrfConfig::from_env();
+    let decompression = Arc::new(decompression::DecompressionConfig::from_env());
+    let request_context = Arc::new(request_context::ContextConfig::from_env(flags));
+    let experiments = state.experiments.clone();
+    let queue_times = state.queue_times.clone();
+    let regions = state.regions.clone();
+    let priorities = state.priorities.clone();
+    let costs = state.costs.clone();
+    let disconnects = state.disconnects.clone();
+    let sessions = state.sessions.clone();
+    let rate_limiter = state.rate_limiter.clone();
+    let auth = state.auth.clone();
+
+    let service_routes = build_routes(meta_routes()).merge(admin_only(build_routes(admin_routes()), state.auth.clone()));
+    let mut app = API_MOUNTS
+        .into_iter()
+        .fold(service_routes, |router, (prefix, version)| {
+            router.nest(
+                prefix,
+                build_routes(api_routes())
+                    .route_layer(middleware::from_fn_with_state(costs.clone(), cost::meter_cost))
+                    .route_layer(middleware::from_fn_with_state(
+                        disconnects.clone(),
+                        disconnect::detect_disconnect,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        request_context.clone(),
+                        request_context::build_context,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        experiments.clone(),
+                        experiments::tag_experiments,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        sessions.clone(),
+                        session::load_session,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        rate_limiter.clone(),
+                        rate_limit::enforce_rate_limit,
+                    ))
+                    .layer(middleware::from_fn_with_state(auth.clone(), auth::authenticate))
+                    .layer(middleware::from_fn_with_state(
+                        decompression.clone(),
+                        decompression::decompress_request,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        queue_times.clone(),
+                        queue_time::record_queue_time,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        regions.clone(),
+                        region::apply_region,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        priorities.clone(),
+                        priority::enforce_priority,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        version,
+                        versioning::tag_api_version,
+                    )),
+            )
+        })
+        .nest("/static", static_assets::routes())
+        .merge(
+            SwaggerUi::new("/swagger-ui")
+                .url("/api-docs/openapi.json", openapi::ApiDoc::openapi()),
+        )
+        // Prometheus scrape endpoint, on its own router so it skips the API middleware
+        .merge(metrics::routes(layers.metrics()));
+
+    if csrf_config.enabled() {
+        info_trace!("CSRF protection enabled");
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::new(csrf_config),
+            csrf::enforce_csrf,
+        ));
+    }
+
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            ip_policy,
+            ip_filter::enforce_ip_policy,
+        ))
+        .layer(middleware::from_fn(security::audit_security_events));
+    Ok(layers.apply(app).with_state(state))
+}
+
+/// Service-level routes outside the versioned API
+///
+/// Route tables are shared with the OpenAPI coverage test so every route stays documented.
+pub fn meta_routes() -> Vec<(&'static str, MethodRouter<Arc<AppState>>)> {
+    vec![
+        ("/", get(root)),
+        ("/health", get(health)),
+        ("/ready", get(ready)),
+        ("/demo", get(demo)),
+        ("/demo/config", get(demo_config)),
+        ("/debug/span-stream", get(span_stream)),
+        ("/debug/telemetry", get(telemetry_status)),
+        ("/admin/protocols", get(protocol_stats)),
+        ("/admin/queue-time", get(queue_time_stats)),
+        ("/admin/regions", get(region_stats)),
+        ("/admin/priority", get(priority_stats)),
+        ("/admin/costs", get(cost_stats)),
+        ("/admin/disconnects", get(disconnect_stats)),
+        ("/admin/rate-limit", get(rate_limit_stats)),
+        ("/admin/dependencies", get(dependency_stats)),
+        ("/admin/experiments", get(experiment_stats)),
+        ("/admin/jobs", get(job_stats)),
+        ("/admin/auth", get(auth_stats)),
+        ("/admin/email", get(email_stats)),
+        ("/admin/events/schema", get(event_schema)),
+        ("/admin/concurrency", get(concurrency_stats)),
+    ]
+}
+
+/// Operator routes that act on or expose user data, mounted with [`admin_only`]
+pub fn admin_routes() -> Vec<(&'static str, MethodRouter<Arc<AppState>>)> {
+    vec![
+        ("/admin/jobs/:id", get(job_status)),
+        ("/admin/events", get(event_log)),
+        ("/admin/config", get(effective_config)),
+        ("/admin/users/purge", post(purge_users)),
+    ]
+}
+
+/// Require a valid token carrying the admin role on every route of `router`
+pub fn admin_only<S: Clone + Send + Sync + 'static>(router: Router<S>, auth: Arc<auth::Auth>) -> Router<S> {
+    router
+        .route_layer(middleware::from_fn(auth::require_role(auth::Role::Admin)))
+        .layer(middleware::from_fn_with_state(auth, auth::authenticate))
+}
+
+/// Versioned API routes, relative to the version prefix
+pub fn api_routes() -> Vec<(&'static str, MethodRouter<Arc<AppState>>)> {
+    vec![
+        ("/users", post(create_user)),
+        (
+            "/users/import",
+            post(import_users).route_layer(middleware::from_fn(auth::require_role(auth::Role::Admin))),
+        ),
+        ("/auth/register", post(register)),
+        ("/auth/login", post(auth_login)),
+        ("/auth/me", get(current_user)),
+        ("/session", get(current_session)),
+        ("/session/login", post(login)),
+        ("/session/logout", post(logout)),
+        ("/users/:id", get(get_user).put(update_user)),
+        ("/users/:id/orders", get(list_user_orders)),
+        ("/orders", post(create_order)),
+        ("/orders/export", get(export_orders)),
+        ("/orders/:id", get(get_order).put(update_order)),
+        ("/orders/:id/cancel", post(cancel_order)),
+        ("/analytics/orders", get(order_analytics)),
+        ("/search", get(search_entities)),
+        ("/reports/latest", get(latest_report)),
+        ("/uploads/:name", put(upload_file)),
+        ("/simulate-error", get(simulate_error)),
+        ("/slow-operation", get(slow_operation)),
+        ("/database-query", get(database_query)),
+        ("/report", get(generate_report)),
+    ]
+}
+
+/// Prefixes the versioned API is mounted under
+///
+/// The unversioned `/api` prefix is the original surface; it is served as v1
+/// so existing clients and route-keyed dashboards keep working.
+pub const API_MOUNTS: [(&str, ApiVersion); 3] = [
+    ("/api/v1", ApiVersion::V1),
+    ("/api/v2", ApiVersion::V2),
+    ("/api", ApiVersion::V1),
+];
+
+pub fn build_routes(routes: Vec<(&'static str, MethodRouter<Arc<AppState>>)>) -> Router<Arc<AppState>> {
+    routes
+        .into_iter()
+        .fold(Router::new(), |router, (path, route)| router.route(path, route))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, StatusCode};
+    use crate::ids::UserId;
+    use crate::secrets;
+
+    #[tokio::test]
+    async fn admin_routes_reject_anonymous_callers() {
+        use tower::ServiceExt;
+
+        assert!(admin_routes().iter().any(|(route, _)| *route == "/admin/users/purge"));
+        let auth = Arc::new(auth::Auth::from_env(&secrets::Secrets::from_env()).unwrap());
+        let app = admin_only(Router::new().route("/admin/users/purge", post(|| async { StatusCode::ACCEPTED })), auth.clone());
+        let purge = |token: Option<String>| {
+            let mut request = axum::http::Request::post("/admin/users/purge");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap())
+        };
+
+        assert_eq!(purge(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let user = auth.issue(UserId::generate(), auth::Role::User).unwrap();
+        assert_eq!(purge(Some(user)).await.unwrap().status(), StatusCode::FORBIDDEN);
+        let admin = auth.issue(UserId::generate(), auth::Role::Admin).unwrap();
+        assert_eq!(purge(Some(admin)).await.unwrap().status(), StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn admin_routes_are_mounted_behind_admin_only() {
+        use tower::ServiceExt;
+
+        let (state, app) = crate::tests::test_app().await;
+        let get = |path: &str, token: Option<String>| {
+            // From loopback, so the private-network restriction on /admin lets it through
+            let loopback = std::net::SocketAddr::from(([127, 0, 0, 1], 40000));
+            let mut request = axum::http::Request::get(path).extension(axum::extract::ConnectInfo(loopback));
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap())
+        };
+
+        let admin = state.auth.issue(UserId::generate(), auth::Role::Admin).unwrap();
+        for path in ["/admin/events", "/admin/config"] {
+            assert_eq!(get(path, None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(get(path, Some(admin.clone())).await.unwrap().status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_ids_with_400() {
+        use tower::ServiceExt;
+
+        let (_, app) = crate::tests::test_app().await;
+        for path in ["/api/v1/users/not-a-uuid", "/api/v1/orders/not-a-uuid"] {
+            let request = axum::http::Request::get(path).body(axum::body::Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let error: rust_datadog_otel::error::ErrorResponse = serde_json::from_slice(&body).unwrap();
+            assert!(error.error.contains("expected a UUID"), "{}", error.error);
+        }
+    }
+}
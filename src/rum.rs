@@ -0,0 +1,56 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+const DEFAULT_SDK_URL: &str = "https://www.datadoghq-browser-agent.com/us1/v5/datadog-rum.js";
+
+/// Browser RUM settings handed to the demo page
+///
+/// Configuration:
+/// - `DD_RUM_APPLICATION_ID` and `DD_RUM_CLIENT_TOKEN`: RUM is enabled when both are set
+/// - `DD_SITE` (default `datadoghq.com`)
+/// - `DD_RUM_SERVICE` (default `<DD_SERVICE>-frontend`)
+/// - `DD_RUM_SDK_URL`: browser SDK bundle (default the US1 v5 CDN bundle)
+///
+/// The client token is public by design, so this is safe to serve to browsers.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RumConfig {
+    enabled: bool,
+    application_id: Option<String>,
+    client_token: Option<String>,
+    site: String,
+    service: String,
+    env: String,
+    version: String,
+    sdk_url: String,
+}
+
+impl RumConfig {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+        let application_id = var("DD_RUM_APPLICATION_ID");
+        let client_token = var("DD_RUM_CLIENT_TOKEN");
+        let service = var("DD_RUM_SERVICE").unwrap_or_else(|| {
+            format!(
+                "{}-frontend",
+                var("DD_SERVICE").unwrap_or_else(|| "rust-datadog-otel".to_string())
+            )
+        });
+
+        Self {
+            enabled: application_id.is_some() && client_token.is_some(),
+            application_id,
+            client_token,
+            site: var("DD_SITE").unwrap_or_else(|| "datadoghq.com".to_string()),
+            service,
+            env: var("DD_ENV").unwrap_or_else(|| "development".to_string()),
+            version: var("DD_VERSION").unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string()),
+            sdk_url: var("DD_RUM_SDK_URL").unwrap_or_else(|| DEFAULT_SDK_URL.to_string()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
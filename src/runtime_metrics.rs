@@ -0,0 +1,163 @@
+use crate::{dogstatsd, info_trace};
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, RuntimeMetrics};
+
+const DEFAULT_INTERVAL_SECS: u64 = 10;
+
+/// Busy share of an interval at or above which a worker counts as saturated
+const SATURATED_BUSY_RATIO: f64 = 0.95;
+
+/// Periodically publishes Tokio runtime gauges through [`dogstatsd`]
+///
+/// Sent every interval, so latency spikes in traces can be lined up with
+/// runtime pressure:
+/// - `runtime.tokio.workers`, `runtime.tokio.alive_tasks` and
+///   `runtime.tokio.global_queue_depth`
+/// - `runtime.tokio.worker.busy_ratio` (tagged `worker`), the share of the
+///   interval each worker spent running tasks, and
+///   `runtime.tokio.saturated_workers`, how many were busy nearly all of it: a
+///   worker stuck on blocking code shows up here
+/// - built with `RUSTFLAGS="--cfg tokio_unstable"`, also
+///   `runtime.tokio.worker.mean_poll_time_us` and
+///   `runtime.tokio.worker.local_queue_depth` (tagged `worker`),
+///   `runtime.tokio.blocking_threads`, `runtime.tokio.idle_blocking_threads`
+///   and `runtime.tokio.blocking_queue_depth`
+///
+/// Configuration:
+/// - `DD_RUNTIME_METRICS_ENABLED`: publish runtime metrics (default true)
+/// - `RUNTIME_METRICS_INTERVAL_SECS`: seconds between samples (default 10)
+#[derive(Debug, Clone)]
+pub struct RuntimeReporter {
+    enabled: bool,
+    interval: Duration,
+}
+
+impl RuntimeReporter {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let enabled = match std::env::var("DD_RUNTIME_METRICS_ENABLED") {
+            Ok(value) => value
+                .parse::<bool>()
+                .map_err(|e| format!("DD_RUNTIME_METRICS_ENABLED: {}", e))?,
+            Err(_) => true,
+        };
+        let interval_secs = match std::env::var("RUNTIME_METRICS_INTERVAL_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|e| format!("RUNTIME_METRICS_INTERVAL_SECS: {}", e))?,
+            Err(_) => DEFAULT_INTERVAL_SECS,
+        };
+        Ok(Self {
+            enabled,
+            interval: Duration::from_secs(interval_secs.max(1)),
+        })
+    }
+
+    /// Sample the current runtime every interval until the process exits
+    pub fn start(self) {
+        if !self.enabled {
+            return;
+        }
+        let handle = Handle::current();
+        info_trace!(interval_secs = self.interval.as_secs(), "Runtime metrics publishing");
+        tokio::spawn(async move {
+            let mut sampler = Sampler::new(&handle.metrics());
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                for (metric, value, tags) in sampler.sample(&handle.metrics()) {
+                    dogstatsd::gauge(metric, value, &tags);
+                }
+            }
+        });
+    }
+}
+
+/// Turns the runtime's cumulative counters into per-interval gauges
+#[derive(Debug)]
+struct Sampler {
+    at: Instant,
+    busy: Vec<Duration>,
+}
+
+impl Sampler {
+    fn new(metrics: &RuntimeMetrics) -> Self {
+        Self {
+            at: Instant::now(),
+            busy: (0..metrics.num_workers()).map(|worker| metrics.worker_total_busy_duration(worker)).collect(),
+        }
+    }
+
+    fn sample(&mut self, metrics: &RuntimeMetrics) -> Vec<(&'static str, f64, Vec<String>)> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.at).as_secs_f64();
+        self.at = now;
+
+        let workers = metrics.num_workers();
+        let mut gauges = vec![
+            ("runtime.tokio.workers", workers as f64, Vec::new()),
+            ("runtime.tokio.alive_tasks", metrics.num_alive_tasks() as f64, Vec::new()),
+            ("runtime.tokio.global_queue_depth", metrics.global_queue_depth() as f64, Vec::new()),
+        ];
+        let mut saturated = 0;
+        for worker in 0..workers {
+            let tags = vec![format!("worker:{}", worker)];
+            let busy = metrics.worker_total_busy_duration(worker);
+            let previous = self.busy.get(worker).copied().unwrap_or_default();
+            let ratio = if elapsed > 0.0 {
+                (busy.saturating_sub(previous).as_secs_f64() / elapsed).min(1.0)
+            } else {
+                0.0
+            };
+            if ratio >= SATURATED_BUSY_RATIO {
+                saturated += 1;
+            }
+            gauges.push(("runtime.tokio.worker.busy_ratio", ratio, tags.clone()));
+            #[cfg(tokio_unstable)]
+            {
+                let poll_us = metrics.worker_mean_poll_time(worker).as_secs_f64() * 1_000_000.0;
+                gauges.push(("runtime.tokio.worker.mean_poll_time_us", poll_us, tags.clone()));
+                let depth = metrics.worker_local_queue_depth(worker) as f64;
+                gauges.push(("runtime.tokio.worker.local_queue_depth", depth, tags));
+            }
+            if let Some(slot) = self.busy.get_mut(worker) {
+                *slot = busy;
+            }
+        }
+        gauges.push(("runtime.tokio.saturated_workers", saturated as f64, Vec::new()));
+        #[cfg(tokio_unstable)]
+        gauges.extend([
+            ("runtime.tokio.blocking_threads", metrics.num_blocking_threads() as f64, Vec::new()),
+            ("runtime.tokio.idle_blocking_threads", metrics.num_idle_blocking_threads() as f64, Vec::new()),
+            ("runtime.tokio.blocking_queue_depth", metrics.blocking_queue_depth() as f64, Vec::new()),
+        ]);
+        gauges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_workers_and_busy_ratios() {
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(2).build().unwrap();
+        let metrics = runtime.metrics();
+        let mut sampler = Sampler::new(&metrics);
+        runtime.block_on(async {
+            tokio::spawn(async { std::thread::sleep(Duration::from_millis(50)) }).await.unwrap();
+        });
+
+        let gauges = sampler.sample(&metrics);
+        let value = |name: &str| gauges.iter().find(|(metric, ..)| *metric == name).map(|(_, value, _)| *value);
+        assert_eq!(value("runtime.tokio.workers"), Some(2.0));
+        let ratios: Vec<f64> = gauges
+            .iter()
+            .filter(|(metric, ..)| *metric == "runtime.tokio.worker.busy_ratio")
+            .map(|(_, value, _)| *value)
+            .collect();
+        assert_eq!(ratios.len(), 2);
+        assert!(ratios.iter().all(|ratio| (0.0..=1.0).contains(ratio)));
+        assert!(ratios.iter().any(|ratio| *ratio > 0.0));
+    }
+}
@@ -0,0 +1,305 @@
+use crate::attribute_filter::matches_pattern;
+use datadog_opentelemetry::configuration::SamplingRuleConfig;
+use opentelemetry::trace::{Link, SamplingDecision, SamplingResult, SpanBuilder, SpanKind, TraceContextExt, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::trace::{IdGenerator, RandomIdGenerator, Sampler, ShouldSample};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// `OTEL_TRACES_SAMPLER` values, as in the OpenTelemetry SDKs
+pub const NAMES: [&str; 6] = [
+    "always_on",
+    "always_off",
+    "traceidratio",
+    "parentbased_always_on",
+    "parentbased_always_off",
+    "parentbased_traceidratio",
+];
+
+/// The sampler `OTEL_TRACES_SAMPLER` and `OTEL_TRACES_SAMPLER_ARG` configure, if any
+///
+/// The argument is the ratio for the `traceidratio` samplers (default 1.0).
+///
+/// Configuration:
+/// - `OTEL_TRACES_SAMPLER`: one of [`NAMES`]; unset leaves sampling to the Datadog SDK
+/// - `OTEL_TRACES_SAMPLER_ARG`: share of traces kept, from 0.0 to 1.0
+pub fn from_env() -> Result<Option<Sampler>, Box<dyn std::error::Error>> {
+    let Ok(name) = std::env::var("OTEL_TRACES_SAMPLER") else {
+        return Ok(None);
+    };
+    let arg = std::env::var("OTEL_TRACES_SAMPLER_ARG").ok();
+    Ok(Some(parse(&name, arg.as_deref()).map_err(|e| format!("OTEL_TRACES_SAMPLER: {}", e))?))
+}
+
+fn parse(name: &str, arg: Option<&str>) -> Result<Sampler, String> {
+    let ratio = || -> Result<f64, String> {
+        let Some(arg) = arg else {
+            return Ok(1.0);
+        };
+        match arg.trim().parse::<f64>() {
+            Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+            _ => Err(format!("ratio must be between 0 and 1, got '{}'", arg)),
+        }
+    };
+    Ok(match name.trim().to_ascii_lowercase().as_str() {
+        "always_on" => Sampler::AlwaysOn,
+        "always_off" => Sampler::AlwaysOff,
+        "traceidratio" => Sampler::TraceIdRatioBased(ratio()?),
+        "parentbased_always_on" => Sampler::ParentBased(Box::new(Sampler::AlwaysOn)),
+        "parentbased_always_off" => Sampler::ParentBased(Box::new(Sampler::AlwaysOff)),
+        "parentbased_traceidratio" => Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio()?))),
+        other => return Err(format!("unknown sampler '{}', expected one of {}", other, NAMES.join(", "))),
+    })
+}
+
+/// Root span attribute holding the route template, set on request spans
+const ROUTE_ATTRIBUTE: &str = "http.route";
+
+/// Sample rates per route, for traces starting at a request span
+///
+/// Rules are `pattern=rate` pairs, checked in order. A pattern matches a root
+/// span's `http.route` (`/api/orders*`), or, on spans without one such as a
+/// background job's, its name; `*` at the end matches any suffix. The rate samples by trace ID,
+/// so the decision is stable for a trace. Child spans follow their parent,
+/// local or from the caller; root spans no rule matches go to the fallback
+/// sampler, which keeps them by default.
+///
+/// Configuration:
+/// - `TRACE_ROUTE_SAMPLE_RATES`: rules, comma-separated, e.g. `/api/orders*=1,/health=0.01`
+#[derive(Debug, Clone)]
+pub struct RouteSampler {
+    rules: Vec<(String, f64)>,
+    fallback: Box<dyn ShouldSample>,
+}
+
+impl RouteSampler {
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::env::var("TRACE_ROUTE_SAMPLE_RATES") {
+            Ok(rules) => Ok(Some(Self::parse(&rules).map_err(|e| format!("TRACE_ROUTE_SAMPLE_RATES: {}", e))?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Rules from `pattern=rate` pairs, comma- or space-separated
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let rules = rules
+            .split([',', ' '])
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| {
+                let (pattern, rate) = rule
+                    .split_once('=')
+                    .ok_or_else(|| format!("'{}': expected pattern=rate", rule))?;
+                match rate.parse::<f64>() {
+                    Ok(rate) if (0.0..=1.0).contains(&rate) => Ok((pattern.to_string(), rate)),
+                    _ => Err(format!("'{}': rate must be between 0 and 1", rule)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            rules,
+            fallback: Box::new(Sampler::AlwaysOn),
+        })
+    }
+
+    /// Sample root spans no rule matches with `fallback` instead of keeping them
+    pub fn with_fallback(mut self, fallback: Box<dyn ShouldSample>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl ShouldSample for RouteSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        if let Some(parent) = parent_context.filter(|cx| cx.has_active_span()) {
+            let parent = parent.span().span_context().clone();
+            return SamplingResult {
+                decision: if parent.is_sampled() {
+                    SamplingDecision::RecordAndSample
+                } else {
+                    SamplingDecision::Drop
+                },
+                attributes: Vec::new(),
+                trace_state: parent.trace_state().clone(),
+            };
+        }
+        let route = attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == ROUTE_ATTRIBUTE)
+            .map(|kv| kv.value.as_str());
+        let key = route.as_deref().unwrap_or(name);
+        match self.rules.iter().find(|(pattern, _)| matches_pattern(pattern, key)) {
+            Some((_, rate)) => Sampler::TraceIdRatioBased(*rate)
+                .should_sample(parent_context, trace_id, name, span_kind, attributes, links),
+            None => self
+                .fallback
+                .should_sample(parent_context, trace_id, name, span_kind, attributes, links),
+        }
+    }
+}
+
+/// One entry of `DD_TRACE_SAMPLING_RULES`, as the other Datadog SDKs read it
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleJson {
+    sample_rate: f64,
+    service: Option<String>,
+    /// Operation (span) name
+    name: Option<String>,
+    resource: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// `DD_TRACE_SAMPLE_RATE`, the share of traces kept when no sampling rule matches
+pub fn sample_rate_from_env() -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    match std::env::var("DD_TRACE_SAMPLE_RATE") {
+        Ok(rate) => Ok(Some(rate.trim().parse::<f64>().map_err(|e| format!("DD_TRACE_SAMPLE_RATE: {}", e))?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Datadog sampling rules from `DD_TRACE_SAMPLING_RULES`, in order; none when unset
+///
+/// A JSON array of rules, each with a `sample_rate` and any of `service`,
+/// `name`, `resource` (glob patterns, `*` and `?`) and `tags`, e.g.
+/// `[{"service": "checkout", "resource": "GET /health", "sample_rate": 0.01}]`.
+/// The first rule matching a trace's root span sets its rate.
+pub fn datadog_rules_from_env() -> Result<Vec<SamplingRuleConfig>, Box<dyn std::error::Error>> {
+    match std::env::var("DD_TRACE_SAMPLING_RULES") {
+        Ok(json) => Ok(parse_datadog_rules(&json).map_err(|e| format!("DD_TRACE_SAMPLING_RULES: {}", e))?),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+fn parse_datadog_rules(json: &str) -> Result<Vec<SamplingRuleConfig>, String> {
+    let rules: Vec<RuleJson> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            if !(0.0..=1.0).contains(&rule.sample_rate) {
+                return Err(format!(
+                    "rule {}: sample_rate must be between 0 and 1, got {}",
+                    index + 1,
+                    rule.sample_rate
+                ));
+            }
+            Ok(SamplingRuleConfig {
+                sample_rate: rule.sample_rate,
+                service: rule.service,
+                name: rule.name,
+                resource: rule.resource,
+                tags: rule.tags,
+                provenance: "customer".to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Run `sampler` on a span about to start, ahead of the Datadog SDK's own sampler
+///
+/// Spans it drops (or only records) are started with that decision; spans it
+/// keeps are left to the SDK, so Datadog sampling rules and rate limits still
+/// apply on top. Root spans get their trace ID here, since ratio samplers
+/// decide by it.
+pub(crate) fn presample(sampler: &dyn ShouldSample, builder: &mut SpanBuilder, parent_cx: &Context) {
+    let trace_id = if parent_cx.has_active_span() {
+        parent_cx.span().span_context().trace_id()
+    } else {
+        *builder
+            .trace_id
+            .get_or_insert_with(|| RandomIdGenerator::default().new_trace_id())
+    };
+    let result = sampler.should_sample(
+        Some(parent_cx),
+        trace_id,
+        &builder.name,
+        builder.span_kind.as_ref().unwrap_or(&SpanKind::Internal),
+        builder.attributes.as_deref().unwrap_or(&[]),
+        builder.links.as_deref().unwrap_or(&[]),
+    );
+    if result.decision != SamplingDecision::RecordAndSample {
+        builder.sampling_result = Some(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_otel_sampler_names() {
+        assert!(matches!(parse("always_off", None), Ok(Sampler::AlwaysOff)));
+        assert!(matches!(parse("TraceIdRatio", Some("0.25")), Ok(Sampler::TraceIdRatioBased(r)) if r == 0.25));
+        assert!(matches!(parse("parentbased_traceidratio", None), Ok(Sampler::ParentBased(_))));
+        assert_eq!(
+            parse("traceidratio", Some("2")).unwrap_err(),
+            "ratio must be between 0 and 1, got '2'"
+        );
+        assert!(parse("sometimes", None).unwrap_err().starts_with("unknown sampler 'sometimes'"));
+    }
+
+    #[test]
+    fn parses_datadog_sampling_rules() {
+        let rules = parse_datadog_rules(
+            r#"[{"service": "checkout", "resource": "GET /health", "sample_rate": 0.01},
+                {"name": "api.request", "tags": {"http.route": "/api/orders*"}, "sample_rate": 1}]"#,
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].resource.as_deref(), Some("GET /health"));
+        assert_eq!(rules[1].tags["http.route"], "/api/orders*");
+        assert_eq!(
+            parse_datadog_rules(r#"[{"sample_rate": 2}]"#).unwrap_err(),
+            "rule 1: sample_rate must be between 0 and 1, got 2"
+        );
+        assert!(parse_datadog_rules(r#"[{"service": "checkout"}]"#).is_err());
+    }
+
+    #[test]
+    fn samples_root_spans_by_route() {
+        let sampler = RouteSampler::parse("/api/orders*=1, health=0").unwrap().with_fallback(Box::new(Sampler::AlwaysOff));
+        let decide = |name: &str, route: Option<&'static str>| {
+            let attributes: Vec<KeyValue> = route.map(|route| KeyValue::new(ROUTE_ATTRIBUTE, route)).into_iter().collect();
+            sampler
+                .should_sample(None, TraceId::from(7u128), name, &SpanKind::Server, &attributes, &[])
+                .decision
+        };
+        assert_eq!(decide("api.request", Some("/api/orders/:id")), SamplingDecision::RecordAndSample);
+        assert_eq!(decide("health", None), SamplingDecision::Drop);
+        // Unmatched roots go to the fallback
+        assert_eq!(decide("api.request", Some("/api/users/:id")), SamplingDecision::Drop);
+        assert_eq!(
+            RouteSampler::parse("/health=2").unwrap_err(),
+            "'/health=2': rate must be between 0 and 1"
+        );
+    }
+
+    #[test]
+    fn leaves_kept_spans_to_the_sdk() {
+        let mut kept = SpanBuilder::from_name("kept");
+        presample(&Sampler::AlwaysOn, &mut kept, &Context::new());
+        assert!(kept.sampling_result.is_none());
+        assert!(kept.trace_id.is_some());
+
+        let mut dropped = SpanBuilder::from_name("dropped");
+        presample(&Sampler::AlwaysOff, &mut dropped, &Context::new());
+        assert_eq!(dropped.sampling_result.map(|result| result.decision), Some(SamplingDecision::Drop));
+    }
+}
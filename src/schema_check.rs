@@ -0,0 +1,328 @@
+use crate::events;
+use serde_json::Value;
+use std::fmt;
+
+/// Golden copy of the event envelope schema that consumers were built against
+pub const GOLDEN_PATH: &str = "schemas/events.avsc";
+
+const PRIMITIVES: [&str; 8] = ["null", "boolean", "int", "long", "float", "double", "bytes", "string"];
+
+/// A schema change that would break a reader on one side of the pipeline
+#[derive(Debug, PartialEq)]
+pub struct Incompatibility {
+    /// `backward` (new readers, old events) or `forward` (old readers, new events)
+    pub direction: &'static str,
+    /// Location in the schema, e.g. `EventEnvelope.data.OrderConfirmed.currency`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.direction, self.path, self.message)
+    }
+}
+
+/// Check `current` against `golden` for full compatibility
+///
+/// Follows Avro schema resolution: readers skip fields they don't know, fill
+/// fields the writer lacks from their default, and accept numeric promotions.
+/// Both directions are checked, since producers and consumers deploy separately.
+pub fn check(golden: &Value, current: &Value) -> Vec<Incompatibility> {
+    let mut problems = Vec::new();
+    let root = type_name(current).unwrap_or("schema").to_string();
+    compare(current, golden, "backward", &root, &mut problems);
+    compare(golden, current, "forward", &root, &mut problems);
+    problems
+}
+
+/// Name of a named type, or the primitive type name
+fn type_name(schema: &Value) -> Option<&str> {
+    match schema {
+        Value::String(name) => Some(name),
+        Value::Object(object) => object
+            .get("name")
+            .or_else(|| object.get("type"))
+            .and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+/// `record`, `enum`, `array`, `map`, `fixed`, `union` or the primitive type
+fn kind(schema: &Value) -> &str {
+    match schema {
+        Value::Array(_) => "union",
+        Value::String(name) => name,
+        Value::Object(object) => object.get("type").and_then(Value::as_str).unwrap_or("invalid"),
+        _ => "invalid",
+    }
+}
+
+/// Whether a reader of type `reader` accepts a value written as `writer`
+fn promotes(writer: &str, reader: &str) -> bool {
+    writer == reader
+        || matches!(
+            (writer, reader),
+            ("int", "long" | "float" | "double")
+                | ("long", "float" | "double")
+                | ("float", "double")
+                | ("string", "bytes")
+                | ("bytes", "string")
+        )
+}
+
+/// Whether two union branches describe the same type, so one resolves to the other
+fn same_branch(a: &Value, b: &Value) -> bool {
+    let (kind_a, kind_b) = (kind(a), kind(b));
+    if PRIMITIVES.contains(&kind_a) || PRIMITIVES.contains(&kind_b) {
+        return promotes(kind_b, kind_a) || promotes(kind_a, kind_b);
+    }
+    kind_a == kind_b && type_name(a) == type_name(b)
+}
+
+/// Path into a union branch, naming the branch when it's a named type
+fn branch_path(path: &str, branch: &Value) -> String {
+    match branch.get("name").and_then(Value::as_str) {
+        Some(name) => format!("{}.{}", path, name),
+        None => path.to_string(),
+    }
+}
+
+/// Record problems reading data written with `writer` using `reader`
+fn compare(
+    reader: &Value,
+    writer: &Value,
+    direction: &'static str,
+    path: &str,
+    problems: &mut Vec<Incompatibility>,
+) {
+    let mut problem = |message: String| {
+        problems.push(Incompatibility {
+            direction,
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    match (reader, writer) {
+        (_, Value::Array(branches)) => {
+            for branch in branches {
+                let target = match reader {
+                    Value::Array(readers) => readers.iter().find(|r| same_branch(r, branch)),
+                    _ => Some(reader).filter(|r| same_branch(r, branch)),
+                };
+                match target {
+                    Some(target) => {
+                        compare(target, branch, direction, &branch_path(path, branch), problems)
+                    }
+                    None => problems.push(Incompatibility {
+                        direction,
+                        path: path.to_string(),
+                        message: format!(
+                            "writer union branch `{}` has no matching reader type",
+                            type_name(branch).unwrap_or("?")
+                        ),
+                    }),
+                }
+            }
+        }
+        (Value::Array(readers), _) => match readers.iter().find(|r| same_branch(r, writer)) {
+            Some(target) => compare(target, writer, direction, &branch_path(path, writer), problems),
+            None => problem(format!(
+                "writer type `{}` is not in the reader union",
+                type_name(writer).unwrap_or("?")
+            )),
+        },
+        _ => {
+            let (reader_kind, writer_kind) = (kind(reader), kind(writer));
+            match (reader_kind, writer_kind) {
+                ("record", "record") => compare_records(reader, writer, direction, path, problems),
+                ("enum", "enum") => {
+                    let symbols = |schema: &Value| -> Vec<String> {
+                        schema["symbols"]
+                            .as_array()
+                            .map(|symbols| {
+                                symbols
+                                    .iter()
+                                    .filter_map(|s| s.as_str().map(str::to_string))
+                                    .collect()
+                            })
+                            .unwrap_or_default()
+                    };
+                    let known = symbols(reader);
+                    if reader.get("default").is_none() {
+                        for symbol in symbols(writer).iter().filter(|s| !known.contains(s)) {
+                            problem(format!(
+                                "symbol `{}` is unknown to the reader, which has no default",
+                                symbol
+                            ));
+                        }
+                    }
+                }
+                ("array", "array") => compare(
+                    &reader["items"],
+                    &writer["items"],
+                    direction,
+                    &format!("{}[]", path),
+                    problems,
+                ),
+                ("map", "map") => compare(
+                    &reader["values"],
+                    &writer["values"],
+                    direction,
+                    &format!("{}{{}}", path),
+                    problems,
+                ),
+                (reader_kind, writer_kind) if promotes(writer_kind, reader_kind) => {
+                    let logical = |schema: &Value| schema.get("logicalType").cloned();
+                    if logical(reader) != logical(writer) {
+                        problem("logical type changed".to_string());
+                    }
+                }
+                (reader_kind, writer_kind) => problem(format!(
+                    "type changed from `{}` to `{}`",
+                    writer_kind, reader_kind
+                )),
+            }
+        }
+    }
+}
+
+fn compare_records(
+    reader: &Value,
+    writer: &Value,
+    direction: &'static str,
+    path: &str,
+    problems: &mut Vec<Incompatibility>,
+) {
+    let fields = |schema: &Value| schema["fields"].as_array().cloned().unwrap_or_default();
+    let writer_fields = fields(writer);
+
+    for field in fields(reader) {
+        let name = field["name"].as_str().unwrap_or("?");
+        let field_path = format!("{}.{}", path, name);
+        match writer_fields.iter().find(|w| w["name"] == field["name"]) {
+            Some(written) => compare(&field["type"], &written["type"], direction, &field_path, problems),
+            None if field.get("default").is_some() => {}
+            None => problems.push(Incompatibility {
+                direction,
+                path: field_path,
+                message: "field is missing on the writer side and the reader has no default".to_string(),
+            }),
+        }
+    }
+}
+
+/// `check-schemas` subcommand: compare the event schema with the golden copy
+///
+/// Returns the process exit code. With `update`, a compatible schema is
+/// written back as the new golden copy.
+pub fn run(update: bool) -> i32 {
+    let current = events::avro_schema();
+    let write_golden = || {
+        let text = serde_json::to_string_pretty(&current).expect("schema serializes") + "\n";
+        std::fs::write(GOLDEN_PATH, text).map_err(|e| eprintln!("{}: {}", GOLDEN_PATH, e))
+    };
+
+    let golden = match std::fs::read_to_string(GOLDEN_PATH) {
+        Ok(text) => match serde_json::from_str::<Value>(&text) {
+            Ok(golden) => golden,
+            Err(e) => {
+                eprintln!("{}: invalid JSON: {}", GOLDEN_PATH, e);
+                return 2;
+            }
+        },
+        // The first golden copy has nothing to be compatible with
+        Err(e) if update && e.kind() == std::io::ErrorKind::NotFound => {
+            return match write_golden() {
+                Ok(()) => {
+                    println!("Created {}", GOLDEN_PATH);
+                    0
+                }
+                Err(()) => 2,
+            };
+        }
+        Err(e) => {
+            eprintln!("{}: {}", GOLDEN_PATH, e);
+            return 2;
+        }
+    };
+
+    let problems = check(&golden, &current);
+    if !problems.is_empty() {
+        eprintln!("Event schema is incompatible with {}:", GOLDEN_PATH);
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        return 1;
+    }
+
+    if golden == current {
+        println!("Event schema matches {}", GOLDEN_PATH);
+    } else if update {
+        if write_golden().is_err() {
+            return 2;
+        }
+        println!("Event schema is compatible; updated {}", GOLDEN_PATH);
+    } else {
+        println!(
+            "Event schema changed compatibly; run `check-schemas --update` to refresh {}",
+            GOLDEN_PATH
+        );
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(fields: Value) -> Value {
+        json!({"type": "record", "name": "Event", "fields": fields})
+    }
+
+    #[test]
+    fn current_schema_is_compatible_with_golden() {
+        let golden: Value = serde_json::from_str(include_str!("../schemas/events.avsc")).unwrap();
+        let problems = check(&golden, &events::avro_schema());
+        assert!(problems.is_empty(), "incompatible: {:?}", problems);
+    }
+
+    #[test]
+    fn new_fields_need_defaults() {
+        let golden = record(json!([{"name": "id", "type": "string"}]));
+        let required = record(json!([
+            {"name": "id", "type": "string"},
+            {"name": "region", "type": "string"}
+        ]));
+        let optional = record(json!([
+            {"name": "id", "type": "string"},
+            {"name": "region", "type": ["null", "string"], "default": null}
+        ]));
+
+        let problems = check(&golden, &required);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].direction, "backward");
+        assert_eq!(problems[0].path, "Event.region");
+        assert!(check(&golden, &optional).is_empty());
+    }
+
+    #[test]
+    fn type_changes_and_new_symbols_are_flagged() {
+        let golden = record(json!([
+            {"name": "count", "type": "int"},
+            {"name": "kind", "type": {"type": "enum", "name": "Kind", "symbols": ["a"]}}
+        ]));
+        let current = record(json!([
+            {"name": "count", "type": "string"},
+            {"name": "kind", "type": {"type": "enum", "name": "Kind", "symbols": ["a", "b"]}}
+        ]));
+
+        let problems: Vec<_> = check(&golden, &current).iter().map(ToString::to_string).collect();
+        assert!(problems.contains(&"backward Event.count: type changed from `int` to `string`".to_string()));
+        assert!(problems.contains(&"forward Event.count: type changed from `string` to `int`".to_string()));
+        assert!(problems
+            .contains(&"forward Event.kind: symbol `b` is unknown to the reader, which has no default".to_string()));
+    }
+}
@@ -0,0 +1,463 @@
+use crate::cost;
+use crate::secrets::Secrets;
+#[cfg(feature = "meilisearch")]
+use crate::secrets::SecretString;
+use rust_datadog_otel::{debug_trace, warn_trace};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use tracing::Instrument;
+use utoipa::ToSchema;
+
+pub type SearchError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Most hits a search returns
+pub const MAX_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentKind {
+    User,
+    Product,
+}
+
+impl DocumentKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DocumentKind::User => "user",
+            DocumentKind::Product => "product",
+        }
+    }
+}
+
+/// A searchable entity: user names and product SKUs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchDocument {
+    pub kind: DocumentKind,
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SearchHit {
+    pub kind: DocumentKind,
+    pub id: String,
+    pub title: String,
+    /// Relevance, higher is better; only comparable within one response
+    pub score: f64,
+}
+
+/// A full-text index
+#[async_trait::async_trait]
+pub trait SearchBackend: Send + Sync + Debug {
+    /// Add or replace documents
+    async fn index(&self, documents: &[SearchDocument]) -> Result<(), SearchError>;
+    async fn delete(&self, kind: DocumentKind, ids: &[String]) -> Result<(), SearchError>;
+    async fn search(
+        &self,
+        query: &str,
+        kind: Option<DocumentKind>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, SearchError>;
+    /// Backend name recorded on spans
+    fn name(&self) -> &'static str;
+}
+
+/// Lowercased alphanumeric terms, so `Prod-001` matches `prod 001`
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+type DocumentKey = (DocumentKind, String);
+
+#[derive(Debug, Default)]
+struct IndexData {
+    documents: HashMap<DocumentKey, SearchDocument>,
+    /// Term to the documents containing it; ordered for prefix lookups
+    postings: BTreeMap<String, HashSet<DocumentKey>>,
+}
+
+impl IndexData {
+    fn remove(&mut self, key: &DocumentKey) {
+        let Some(document) = self.documents.remove(key) else {
+            return;
+        };
+        for term in tokenize(&document.title) {
+            if let Some(keys) = self.postings.get_mut(&term) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+}
+
+/// In-process inverted index, the default backend
+///
+/// Every query term must match a document term exactly or as a prefix, so
+/// results narrow as the user types; exact matches score higher.
+#[derive(Debug, Default)]
+pub struct EmbeddedIndex {
+    data: RwLock<IndexData>,
+}
+
+#[async_trait::async_trait]
+impl SearchBackend for EmbeddedIndex {
+    async fn index(&self, documents: &[SearchDocument]) -> Result<(), SearchError> {
+        let mut data = self.data.write().await;
+        for document in documents {
+            let key = (document.kind, document.id.clone());
+            data.remove(&key);
+            for term in tokenize(&document.title) {
+                data.postings.entry(term).or_default().insert(key.clone());
+            }
+            data.documents.insert(key, document.clone());
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, kind: DocumentKind, ids: &[String]) -> Result<(), SearchError> {
+        let mut data = self.data.write().await;
+        for id in ids {
+            data.remove(&(kind, id.clone()));
+        }
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        kind: Option<DocumentKind>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, SearchError> {
+        let terms = tokenize(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let data = self.data.read().await;
+        let mut scores: Option<HashMap<&DocumentKey, f64>> = None;
+        for term in &terms {
+            let mut term_scores: HashMap<&DocumentKey, f64> = HashMap::new();
+            for (indexed, keys) in data.postings.range(term.clone()..) {
+                if !indexed.starts_with(term.as_str()) {
+                    break;
+                }
+                let weight = if indexed == term { 1.0 } else { 0.5 };
+                for key in keys {
+                    let score = term_scores.entry(key).or_default();
+                    *score = score.max(weight);
+                }
+            }
+            // Every term must match
+            scores = Some(match scores {
+                None => term_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(key, score)| term_scores.get(key).map(|term| (key, score + term)))
+                    .collect(),
+            });
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(key, _)| kind.is_none_or(|kind| key.0 == kind))
+            .map(|(key, score)| {
+                let document = &data.documents[key];
+                SearchHit {
+                    kind: document.kind,
+                    id: document.id.clone(),
+                    title: document.title.clone(),
+                    score: score / terms.len() as f64,
+                }
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.title.cmp(&b.title)));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    fn name(&self) -> &'static str {
+        "embedded"
+    }
+}
+
+/// External Meilisearch index over its REST API
+///
+/// Documents share one index, keyed `<kind>-<id>` and filterable by `kind`.
+#[cfg(feature = "meilisearch")]
+#[derive(Debug)]
+pub struct MeilisearchBackend {
+    client: rust_datadog_otel::http_client::HttpClient,
+    url: String,
+    index: String,
+    api_key: Option<SecretString>,
+}
+
+#[cfg(feature = "meilisearch")]
+impl MeilisearchBackend {
+    /// Connect and make `kind` filterable
+    pub async fn connect(url: &str, index: &str, api_key: Option<SecretString>) -> Result<Self, SearchError> {
+        let backend = Self {
+            client: rust_datadog_otel::http_client::HttpClient::new().with_peer_service("meilisearch"),
+            url: url.trim_end_matches('/').to_string(),
+            index: index.to_string(),
+            api_key,
+        };
+        let request = backend
+            .request(reqwest::Method::PATCH, "settings")
+            .json(&serde_json::json!({ "filterableAttributes": ["kind"] }));
+        backend.client.send(request).await?.error_for_status()?;
+        Ok(backend)
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/indexes/{}/{}", self.url, self.index, path);
+        let request = self.client.request(method, url);
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key.expose_secret()),
+            None => request,
+        }
+    }
+
+    fn document_id(kind: DocumentKind, id: &str) -> String {
+        format!("{}-{}", kind.as_str(), id)
+    }
+}
+
+#[cfg(feature = "meilisearch")]
+#[async_trait::async_trait]
+impl SearchBackend for MeilisearchBackend {
+    async fn index(&self, documents: &[SearchDocument]) -> Result<(), SearchError> {
+        let body: Vec<_> = documents
+            .iter()
+            .map(|document| {
+                serde_json::json!({
+                    "doc_id": Self::document_id(document.kind, &document.id),
+                    "kind": document.kind,
+                    "id": document.id,
+                    "title": document.title,
+                })
+            })
+            .collect();
+        // Meilisearch applies writes asynchronously; a 202 means the task was queued
+        let request = self.request(reqwest::Method::POST, "documents?primaryKey=doc_id").json(&body);
+        self.client.send(request).await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn delete(&self, kind: DocumentKind, ids: &[String]) -> Result<(), SearchError> {
+        let body: Vec<String> = ids.iter().map(|id| Self::document_id(kind, id)).collect();
+        let request = self.request(reqwest::Method::POST, "documents/delete-batch").json(&body);
+        self.client.send(request).await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        kind: Option<DocumentKind>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, SearchError> {
+        #[derive(Deserialize)]
+        struct Hit {
+            kind: DocumentKind,
+            id: String,
+            title: String,
+            #[serde(rename = "_rankingScore", default)]
+            score: f64,
+        }
+        #[derive(Deserialize)]
+        struct Response {
+            hits: Vec<Hit>,
+        }
+
+        let mut body = serde_json::json!({ "q": query, "limit": limit, "showRankingScore": true });
+        if let Some(kind) = kind {
+            body["filter"] = serde_json::json!(format!("kind = {}", kind.as_str()));
+        }
+        let request = self.request(reqwest::Method::POST, "search").json(&body);
+        let response: Response = self.client.send(request).await?.error_for_status()?.json().await?;
+        Ok(response
+            .hits
+            .into_iter()
+            .map(|hit| SearchHit {
+                kind: hit.kind,
+                id: hit.id,
+                title: hit.title,
+                score: hit.score,
+            })
+            .collect())
+    }
+
+    fn name(&self) -> &'static str {
+        "meilisearch"
+    }
+}
+
+/// Full-text search over users and products
+///
+/// Writes index as they happen; indexing is best-effort, so a failure is
+/// logged on the write's trace without failing the write.
+///
+/// Configuration:
+/// - `SEARCH_BACKEND`: `embedded` (default) or `meilisearch` (needs the `meilisearch` cargo feature)
+/// - `MEILISEARCH_URL`: server URL (default `http://localhost:7700`)
+/// - `MEILISEARCH_API_KEY`: API key, if the server requires one, read through [`Secrets`]
+/// - `MEILISEARCH_INDEX`: index name (default `rust-datadog-otel`)
+#[derive(Debug)]
+pub struct Search {
+    backend: Arc<dyn SearchBackend>,
+}
+
+impl Search {
+    pub async fn from_env(secrets: &Secrets) -> Result<Self, Box<dyn std::error::Error>> {
+        let backend = std::env::var("SEARCH_BACKEND").unwrap_or_else(|_| "embedded".to_string());
+        let backend: Arc<dyn SearchBackend> = match backend.as_str() {
+            "embedded" => Arc::new(EmbeddedIndex::default()),
+            "meilisearch" => Self::meilisearch(secrets).await?,
+            other => return Err(format!("unknown SEARCH_BACKEND '{}', expected embedded or meilisearch", other).into()),
+        };
+        Ok(Self { backend })
+    }
+
+    #[cfg(feature = "meilisearch")]
+    async fn meilisearch(secrets: &Secrets) -> Result<Arc<dyn SearchBackend>, Box<dyn std::error::Error>> {
+        let url = std::env::var("MEILISEARCH_URL").unwrap_or_else(|_| "http://localhost:7700".to_string());
+        let index = std::env::var("MEILISEARCH_INDEX").unwrap_or_else(|_| "rust-datadog-otel".to_string());
+        let api_key = secrets.get("MEILISEARCH_API_KEY")?;
+        let backend = MeilisearchBackend::connect(&url, &index, api_key)
+            .await
+            .map_err(|e| format!("Meilisearch at {}: {}", url, e))?;
+        Ok(Arc::new(backend))
+    }
+
+    #[cfg(not(feature = "meilisearch"))]
+    async fn meilisearch(_secrets: &Secrets) -> Result<Arc<dyn SearchBackend>, Box<dyn std::error::Error>> {
+        Err("SEARCH_BACKEND=meilisearch needs a build with the meilisearch feature".into())
+    }
+
+    pub fn backend(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// Charge the current request: the embedded index is local, other backends are remote
+    fn charge(&self) {
+        if self.backend.name() == "embedded" {
+            cost::record_db_call();
+        } else {
+            cost::record_external_call();
+        }
+    }
+
+    /// Index documents in a `search.index` span
+    pub async fn index(&self, documents: Vec<SearchDocument>) {
+        let span = tracing::info_span!(
+            "search.index",
+            otel.kind = "client",
+            search.backend = self.backend.name(),
+            search.operation = "index",
+            search.documents = documents.len(),
+        );
+        self.charge();
+        if let Err(e) = self.backend.index(&documents).instrument(span.clone()).await {
+            let _guard = span.enter();
+            warn_trace!(error = %e, "Search indexing failed");
+        }
+    }
+
+    /// Remove documents in a `search.index` span
+    pub async fn remove(&self, kind: DocumentKind, ids: Vec<String>) {
+        let span = tracing::info_span!(
+            "search.index",
+            otel.kind = "client",
+            search.backend = self.backend.name(),
+            search.operation = "delete",
+            search.documents = ids.len(),
+        );
+        self.charge();
+        if let Err(e) = self.backend.delete(kind, &ids).instrument(span.clone()).await {
+            let _guard = span.enter();
+            warn_trace!(error = %e, "Search index delete failed");
+        }
+    }
+
+    /// Run a query in a `search.query` span with its latency and hit count
+    ///
+    /// The query text may contain names, so only its length is recorded.
+    pub async fn search(
+        &self,
+        query: &str,
+        kind: Option<DocumentKind>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, SearchError> {
+        let span = tracing::info_span!(
+            "search.query",
+            otel.kind = "client",
+            search.backend = self.backend.name(),
+            search.query_length = query.chars().count(),
+            search.kind = kind.map(DocumentKind::as_str),
+            search.hits = tracing::field::Empty,
+            search.latency_ms = tracing::field::Empty,
+        );
+
+        self.charge();
+        let started = Instant::now();
+        let result = self.backend.search(query, kind, limit).instrument(span.clone()).await;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        span.record("search.latency_ms", latency_ms);
+        if let Ok(hits) = &result {
+            span.record("search.hits", hits.len());
+            let _guard = span.enter();
+            debug_trace!(search.hits = hits.len(), search.latency_ms = latency_ms, "Search completed");
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(kind: DocumentKind, id: &str, title: &str) -> SearchDocument {
+        SearchDocument {
+            kind,
+            id: id.to_string(),
+            title: title.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn embedded_index_matches_all_terms_by_prefix() {
+        let index = EmbeddedIndex::default();
+        index
+            .index(&[
+                document(DocumentKind::User, "1", "Ada Lovelace"),
+                document(DocumentKind::User, "2", "Ada Byron"),
+                document(DocumentKind::Product, "prod-001", "prod-001"),
+            ])
+            .await
+            .unwrap();
+
+        let ids = |hits: Vec<SearchHit>| hits.into_iter().map(|hit| hit.id).collect::<Vec<_>>();
+        assert_eq!(ids(index.search("ada love", None, 10).await.unwrap()), ["1"]);
+        assert_eq!(ids(index.search("PROD 001", None, 10).await.unwrap()), ["prod-001"]);
+        assert!(index.search("ad", Some(DocumentKind::Product), 10).await.unwrap().is_empty());
+
+        // Exact matches outrank prefix matches, and renames drop old terms
+        index.index(&[document(DocumentKind::User, "2", "Adam")]).await.unwrap();
+        assert_eq!(ids(index.search("ada", None, 10).await.unwrap()), ["1", "2"]);
+        assert!(index.search("byron", None, 10).await.unwrap().is_empty());
+
+        index.delete(DocumentKind::User, &["1".to_string()]).await.unwrap();
+        assert_eq!(ids(index.search("ada", None, 10).await.unwrap()), ["2"]);
+    }
+}
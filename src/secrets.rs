@@ -0,0 +1,178 @@
+use rust_datadog_otel::debug_trace;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Default mount point for Kubernetes secret volumes
+const DEFAULT_SECRETS_DIR: &str = "/var/run/secrets/rust-datadog-otel";
+
+/// A secret value that never prints its contents
+///
+/// `Debug` and `Display` both render `[REDACTED]`, so a `SecretString` can be
+/// passed through `#[instrument]` or structured logs without leaking.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// Access the underlying value; keep the result out of logs and spans
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString([REDACTED])")
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+/// Errors raised while loading secrets
+#[derive(Debug)]
+pub enum SecretError {
+    Io { source: &'static str, path: PathBuf, error: std::io::Error },
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::Io { source, path, error } => {
+                write!(f, "{} secret provider failed to read {}: {}", source, path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// A source of secret values looked up by name (e.g. `DD_API_KEY`)
+pub trait SecretProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns `Ok(None)` when this provider doesn't hold the secret
+    fn get(&self, key: &str) -> Result<Option<SecretString>, SecretError>;
+}
+
+/// Reads secrets from environment variables
+pub struct EnvSecretProvider;
+
+impl SecretProvider for EnvSecretProvider {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<SecretString>, SecretError> {
+        Ok(std::env::var(key)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(SecretString::new))
+    }
+}
+
+/// Reads secrets from files referenced by `<KEY>_FILE` environment variables
+///
+/// This is the Docker secrets convention, e.g. `DB_PASSWORD_FILE=/run/secrets/db_password`.
+pub struct FileSecretProvider;
+
+impl SecretProvider for FileSecretProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<SecretString>, SecretError> {
+        let Ok(path) = std::env::var(format!("{}_FILE", key)) else {
+            return Ok(None);
+        };
+        read_secret_file(self.name(), PathBuf::from(path)).map(Some)
+    }
+}
+
+/// Reads secrets from a mounted volume where each file is named after its key
+///
+/// Matches how Kubernetes projects a Secret into a directory. The directory is
+/// `SECRETS_DIR` (default `/var/run/secrets/rust-datadog-otel`).
+pub struct MountedVolumeSecretProvider {
+    dir: PathBuf,
+}
+
+impl MountedVolumeSecretProvider {
+    pub fn from_env() -> Self {
+        let dir = std::env::var("SECRETS_DIR").unwrap_or_else(|_| DEFAULT_SECRETS_DIR.to_string());
+        Self { dir: PathBuf::from(dir) }
+    }
+}
+
+impl SecretProvider for MountedVolumeSecretProvider {
+    fn name(&self) -> &'static str {
+        "mounted-volume"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<SecretString>, SecretError> {
+        let path = self.dir.join(key);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        read_secret_file(self.name(), path).map(Some)
+    }
+}
+
+fn read_secret_file(source: &'static str, path: PathBuf) -> Result<SecretString, SecretError> {
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(SecretString::new(contents.trim_end_matches(['\r', '\n']))),
+        Err(error) => Err(SecretError::Io { source, path, error }),
+    }
+}
+
+/// Ordered chain of secret providers; the first provider holding a key wins
+///
+/// Default order: `<KEY>_FILE` files, mounted volume, then plain environment variables.
+pub struct Secrets {
+    providers: Vec<Box<dyn SecretProvider>>,
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.providers.iter().map(|p| p.name()).collect();
+        f.debug_struct("Secrets").field("providers", &names).finish()
+    }
+}
+
+impl Secrets {
+    pub fn from_env() -> Self {
+        Self {
+            providers: vec![
+                Box::new(FileSecretProvider),
+                Box::new(MountedVolumeSecretProvider::from_env()),
+                Box::new(EnvSecretProvider),
+            ],
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<SecretString>, SecretError> {
+        for provider in &self.providers {
+            if let Some(secret) = provider.get(key)? {
+                debug_trace!(secret = %key, provider = provider.name(), "Loaded secret");
+                return Ok(Some(secret));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Name of the first provider holding `key`, without handing out the value
+    pub fn source(&self, key: &str) -> Result<Option<&'static str>, SecretError> {
+        for provider in &self.providers {
+            if provider.get(key)?.is_some() {
+                return Ok(Some(provider.name()));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,164 @@
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use rust_datadog_otel::client_ip::ClientIp;
+
+/// Log target for security events
+///
+/// Datadog Cloud SIEM pipelines can route on `logger.name:security` to pick
+/// these up without scanning application logs.
+pub const SECURITY_TARGET: &str = "security";
+
+/// Classification of a response that should produce a security event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SecurityEvent {
+    AuthenticationFailure,
+    AuthorizationFailure,
+    RateLimited,
+}
+
+impl SecurityEvent {
+    fn from_status(status: StatusCode) -> Option<Self> {
+        match status {
+            StatusCode::UNAUTHORIZED => Some(Self::AuthenticationFailure),
+            StatusCode::FORBIDDEN => Some(Self::AuthorizationFailure),
+            StatusCode::TOO_MANY_REQUESTS => Some(Self::RateLimited),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::AuthenticationFailure => "authentication_failure",
+            Self::AuthorizationFailure => "authorization_failure",
+            Self::RateLimited => "rate_limit_exceeded",
+        }
+    }
+
+    fn outcome(self) -> &'static str {
+        match self {
+            Self::AuthenticationFailure | Self::AuthorizationFailure => "failure",
+            Self::RateLimited => "throttled",
+        }
+    }
+}
+
+/// Middleware emitting structured security events for 401/403/429 responses
+///
+/// Events are written to the `security` log target with `event.category`
+/// and Datadog standard attributes (`evt.name`, `evt.outcome`,
+/// `network.client.ip`, `http.useragent`) so they can be routed into Cloud
+/// SIEM. The category is also sent as `evt.category`, Datadog's reserved
+/// name, so SIEM rules match it without a remapper.
+pub async fn audit_security_events(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let client_ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .map_or_else(|| "unknown".to_string(), |ClientIp(ip)| ip.to_string());
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let response = next.run(request).await;
+
+    if let Some(event) = SecurityEvent::from_status(response.status()) {
+        let (trace_id, span_id) = rust_datadog_otel::trace_context::current_trace_context()
+            .map_or_else(|| (String::new(), String::new()), |ids| (ids.trace_id, ids.span_id));
+
+        tracing::event!(
+            target: SECURITY_TARGET,
+            tracing::Level::WARN,
+            dd.trace_id = %trace_id,
+            dd.span_id = %span_id,
+            event.category = "authentication",
+            evt.name = event.name(),
+            evt.category = "authentication",
+            evt.outcome = event.outcome(),
+            network.client.ip = %client_ip,
+            http.useragent = %user_agent,
+            http.method = %method,
+            http.url_details.path = %path,
+            http.status_code = response.status().as_u16(),
+            "Security event: {}",
+            event.name()
+        );
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+    use tracing::field::{Field, Visit};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    /// `evt.name`, `event.category` and `evt.category` of each event on the security target
+    #[derive(Clone, Default)]
+    struct SecurityEvents(Arc<Mutex<Vec<EventFields>>>);
+
+    #[derive(Default)]
+    struct EventFields {
+        name: String,
+        category: String,
+        reserved_category: String,
+    }
+
+    impl Visit for EventFields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            match field.name() {
+                "evt.name" => self.name = value.to_string(),
+                "event.category" => self.category = value.to_string(),
+                "evt.category" => self.reserved_category = value.to_string(),
+                _ => {}
+            }
+        }
+
+        fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for SecurityEvents {
+        fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+            if event.metadata().target() == SECURITY_TARGET {
+                let mut fields = EventFields::default();
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn emits_events_for_denied_and_throttled_requests() {
+        let events = SecurityEvents::default();
+        let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(events.clone()));
+        let app = Router::new()
+            .route("/status/:code", get(|axum::extract::Path(code): axum::extract::Path<u16>| async move {
+                StatusCode::from_u16(code).unwrap()
+            }))
+            .layer(middleware::from_fn(audit_security_events));
+
+        for code in [200, 401, 403, 404, 429] {
+            let request = axum::http::Request::get(format!("/status/{}", code)).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let events = events.0.lock().unwrap();
+        let names: Vec<&str> = events.iter().map(|fields| fields.name.as_str()).collect();
+        assert_eq!(names, ["authentication_failure", "authorization_failure", "rate_limit_exceeded"]);
+        assert!(events
+            .iter()
+            .all(|fields| fields.category == "authentication" && fields.reserved_category == "authentication"));
+    }
+}
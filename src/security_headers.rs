@@ -0,0 +1,118 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+const DEFAULT_HSTS: &str = "max-age=31536000; includeSubDomains";
+const DEFAULT_CSP: &str = "default-src 'none'; frame-ancestors 'none'";
+const DEFAULT_REFERRER_POLICY: &str = "no-referrer";
+const DEFAULT_DEBUG_UI_CSP: &str = "default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; connect-src 'self'; frame-ancestors 'none'";
+const DEFAULT_DEMO_CSP: &str = "default-src 'self'; script-src 'self' https://www.datadoghq-browser-agent.com; connect-src 'self' https://*.browser-intake-datadoghq.com https://*.browser-intake-us3-datadoghq.com https://*.browser-intake-us5-datadoghq.com https://*.browser-intake-datadoghq.eu https://*.browser-intake-ap1-datadoghq.com https://*.browser-intake-ddog-gov.com; worker-src blob:; img-src 'self' data:; frame-ancestors 'none'";
+
+/// RUM demo page, which loads the Datadog browser SDK and sends it data
+const DEMO_PREFIX: &str = "/static/demo";
+
+/// Browser UIs that need a CSP allowing their own scripts and styles
+const UI_PREFIXES: &[&str] = &["/debug", "/swagger-ui"];
+
+/// Standard security response headers
+///
+/// Configuration (an empty value disables that header):
+/// - `SECURITY_HEADER_HSTS` (default `max-age=31536000; includeSubDomains`)
+/// - `SECURITY_HEADER_CSP` (default `default-src 'none'; frame-ancestors 'none'`)
+/// - `SECURITY_HEADER_REFERRER_POLICY` (default `no-referrer`)
+/// - `SECURITY_HEADER_DEBUG_UI_CSP`: CSP override for `/debug` and `/swagger-ui`
+/// - `SECURITY_HEADER_DEMO_CSP`: CSP override for the RUM demo page, allowing the Datadog browser SDK
+///
+/// `X-Content-Type-Options: nosniff` is always sent. Headers already set by a
+/// handler are left untouched, so individual routes can override them too.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    overrides: Vec<(String, Vec<(HeaderName, HeaderValue)>)>,
+}
+
+impl SecurityHeaders {
+    pub fn from_env() -> Result<Self, header::InvalidHeaderValue> {
+        let mut headers = vec![(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        )];
+
+        for (name, var, default) in [
+            (header::STRICT_TRANSPORT_SECURITY, "SECURITY_HEADER_HSTS", DEFAULT_HSTS),
+            (header::CONTENT_SECURITY_POLICY, "SECURITY_HEADER_CSP", DEFAULT_CSP),
+            (header::REFERRER_POLICY, "SECURITY_HEADER_REFERRER_POLICY", DEFAULT_REFERRER_POLICY),
+        ] {
+            if let Some(value) = header_from_env(var, default)? {
+                headers.push((name, value));
+            }
+        }
+
+        let mut overrides = Vec::new();
+        if let Some(csp) = header_from_env("SECURITY_HEADER_DEBUG_UI_CSP", DEFAULT_DEBUG_UI_CSP)? {
+            for prefix in UI_PREFIXES {
+                overrides.push((
+                    prefix.to_string(),
+                    vec![(header::CONTENT_SECURITY_POLICY, csp.clone())],
+                ));
+            }
+        }
+
+        if let Some(csp) = header_from_env("SECURITY_HEADER_DEMO_CSP", DEFAULT_DEMO_CSP)? {
+            overrides.push((
+                DEMO_PREFIX.to_string(),
+                vec![(header::CONTENT_SECURITY_POLICY, csp)],
+            ));
+        }
+
+        Ok(Self { headers, overrides })
+    }
+
+    /// Headers for a path, with the most specific route override applied
+    fn headers_for(&self, path: &str) -> Vec<(HeaderName, HeaderValue)> {
+        let mut headers = self.headers.clone();
+        let matching = self
+            .overrides
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len());
+
+        if let Some((_, overrides)) = matching {
+            for (name, value) in overrides {
+                headers.retain(|(existing, _)| existing != name);
+                headers.push((name.clone(), value.clone()));
+            }
+        }
+        headers
+    }
+}
+
+fn header_from_env(
+    var: &str,
+    default: &str,
+) -> Result<Option<HeaderValue>, header::InvalidHeaderValue> {
+    let value = std::env::var(var).unwrap_or_else(|_| default.to_string());
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    HeaderValue::from_str(&value).map(Some)
+}
+
+/// Middleware adding [`SecurityHeaders`] to every response
+pub async fn apply_security_headers(
+    State(config): State<Arc<SecurityHeaders>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = config.headers_for(request.uri().path());
+    let mut response = next.run(request).await;
+
+    for (name, value) in headers {
+        response.headers_mut().entry(name).or_insert(value);
+    }
+    response
+}
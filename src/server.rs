@@ -0,0 +1,96 @@
+use axum::{
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto::Builder, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use rust_datadog_otel::{debug_trace, info_trace, warn_trace};
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+/// How long in-flight connections get to finish after a shutdown signal
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// HTTP protocol settings for the listener
+///
+/// Configuration:
+/// - `HTTP2_CLEARTEXT`: `true` to accept HTTP/2 over plain TCP (h2c, prior
+///   knowledge) alongside HTTP/1.1 (default `false`)
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    h2c: bool,
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        let h2c = std::env::var("HTTP2_CLEARTEXT")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        Self { h2c }
+    }
+}
+
+/// Serve `app` until `shutdown` resolves, then drain open connections
+///
+/// Replaces `axum::serve` so the hyper connection builder can be configured
+/// for HTTP/2. `ConnectInfo<SocketAddr>` is still provided to handlers.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    config: ServerConfig,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()> {
+    let mut builder = Builder::new(TokioExecutor::new());
+    if !config.h2c {
+        builder = builder.http1_only();
+    }
+    info_trace!(h2c = config.h2c, "HTTP protocols configured");
+
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, remote_addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    // Usually fd exhaustion; back off instead of spinning
+                    warn_trace!(error = %e, "Failed to accept connection");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let service = app.clone().map_request(move |mut request: Request<Incoming>| {
+            request.extensions_mut().insert(ConnectInfo(remote_addr));
+            request
+        });
+        let connection = builder
+            .serve_connection(TokioIo::new(stream), TowerToHyperService::new(service))
+            .into_owned();
+        let connection = graceful.watch(connection);
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                debug_trace!(error = %e, client = %remote_addr, "Connection closed with error");
+            }
+        });
+    }
+
+    drop(listener);
+    tokio::select! {
+        _ = graceful.shutdown() => {}
+        _ = tokio::time::sleep(SHUTDOWN_GRACE_PERIOD) => {
+            warn_trace!("Timed out waiting for connections to close");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use crate::negotiation::ResponseFormat;
+use crate::{dependency_health, rum, AppState};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Redirect},
+};
+use rust_datadog_otel::{
+    cardinality_guard, export_failover, export_fallback, export_mirror, info_trace, span_dedup, span_rules, tail_sampling,
+    warn_trace,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    status: String,
+    version: String,
+    timestamp: String,
+    /// Where spans are exported: `agent`, `collector`, `otlp`, `console`, or `none`
+    exporter: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "meta",
+    responses((status = 200, description = "API information and endpoint list", body = serde_json::Value))
+)]
+#[instrument]
+pub async fn root(format: ResponseFormat) -> impl IntoResponse {
+    info_trace!("Root endpoint called");
+    format.body(serde_json::json!({
+        "message": "Rust Datadog OpenTelemetry Demo API",
+        "version": env!("CARGO_PKG_VERSION"),
+        "endpoints": [
+            "GET /health",
+            "GET /ready",
+            "GET /metrics",
+            "GET /demo",
+            "POST /api/users",
+            "POST /api/users/import",
+            "GET /api/users/:id",
+            "POST /api/orders",
+            "GET /api/orders/:id",
+            "GET /api/simulate-error?error_type=<type>",
+            "GET /api/slow-operation",
+            "GET /api/database-query"
+        ],
+        "api_versions": {
+            "v1": "/api/v1 (deprecated; /api is an alias)",
+            "v2": "/api/v2"
+        }
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "meta",
+    responses((status = 200, description = "Service is healthy", body = HealthResponse))
+)]
+#[instrument]
+pub async fn health(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    info_trace!("Health check called");
+    
+    format.body(HealthResponse {
+        status: "healthy".to_string(),
+        version: state.version.clone(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        exporter: export_fallback::active_exporter().to_string(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "meta",
+    responses(
+        (status = 200, description = "Serving and no dependency is down", body = serde_json::Value),
+        (status = 503, description = "Shutting down or a dependency is down", body = serde_json::Value)
+    )
+)]
+#[instrument(skip(state))]
+pub async fn ready(State(state): State<Arc<AppState>>, format: ResponseFormat) -> impl IntoResponse {
+    let down = dependency_health::down();
+    let serving = state.health.is_serving();
+    let status = if serving && down.is_empty() {
+        StatusCode::OK
+    } else {
+        warn_trace!(serving = serving, down = ?down, "Readiness check failed");
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        format.body(serde_json::json!({
+            "ready": status == StatusCode::OK,
+            "serving": serving,
+            "dependencies_down": down,
+        })),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/demo",
+    tag = "meta",
+    responses((status = 303, description = "Redirect to the RUM → APM demo page"))
+)]
+pub async fn demo() -> Redirect {
+    Redirect::to("/static/demo/index.html")
+}
+
+#[utoipa::path(
+    get,
+    path = "/debug/span-stream",
+    tag = "meta",
+    responses((status = 200, description = "Server-sent `span` events summarizing each finished span: name, trace and span IDs, kind, status and duration", content_type = "text/event-stream", body = String))
+)]
+#[instrument(skip(state))]
+pub async fn span_stream(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    info_trace!("Span stream subscriber connected");
+    state.span_tap.stream()
+}
+
+#[utoipa::path(
+    get,
+    path = "/debug/telemetry",
+    tag = "meta",
+    responses((status = 200, description = "Trace export targets, the active one, failover and recovery counts, the fallback exporter, the OTLP mirror, cardinality guard rewrites, routes with redundant request spans, and how often each span rule applied", body = serde_json::Value))
+)]
+#[instrument]
+pub async fn telemetry_status(format: ResponseFormat) -> impl IntoResponse {
+    let mut status = export_failover::snapshot();
+    status["fallback"] = export_fallback::snapshot();
+    status["mirror"] = export_mirror::snapshot();
+    status["cardinality_guard"] = cardinality_guard::snapshot();
+    status["span_dedup"] = span_dedup::snapshot();
+    status["span_rules"] = span_rules::snapshot();
+    status["tail_sampling"] = tail_sampling::snapshot();
+    format.body(status)
+}
+
+#[utoipa::path(
+    get,
+    path = "/demo/config",
+    tag = "meta",
+    responses((status = 200, description = "Browser RUM settings for the demo page", body = rum::RumConfig))
+)]
+#[instrument(skip(state))]
+pub async fn demo_config(State(state): State<Arc<AppState>>) -> Json<rum::RumConfig> {
+    Json(state.rum.clone())
+}
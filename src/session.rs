@@ -0,0 +1,289 @@
+use crate::ids::UserId;
+use crate::secrets::Secrets;
+use crate::dependency_health;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use base64::{
+    engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL},
+    Engine,
+};
+use hmac::{Hmac, Mac};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use rust_datadog_otel::warn_trace;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const SESSION_COOKIE: &str = "session";
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Data kept server-side for a logged-in session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionData {
+    pub user_id: UserId,
+    pub created_at: String,
+}
+
+/// The session attached to the current request
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub data: SessionData,
+}
+
+impl Session {
+    /// Hash of the session ID, safe to put on spans and logs
+    pub fn id_hash(&self) -> String {
+        hash_id(&self.id)
+    }
+}
+
+fn hash_id(id: &str) -> String {
+    Sha256::digest(id.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Server-side session storage
+#[async_trait::async_trait]
+pub trait SessionStore: Send + Sync + Debug {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>, StoreError>;
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<(), StoreError>;
+    async fn delete(&self, id: &str) -> Result<(), StoreError>;
+}
+
+/// Process-local sessions, lost on restart and not shared between replicas
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, (SessionData, Instant)>>,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>, StoreError> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        match sessions.get(id) {
+            Some((data, expires)) if *expires > Instant::now() => Ok(Some(data.clone())),
+            Some(_) => {
+                sessions.remove(id);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<(), StoreError> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id.to_string(), (data.clone(), Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), StoreError> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id);
+        Ok(())
+    }
+}
+
+/// Redis-backed sessions, shared between replicas
+pub struct RedisSessionStore {
+    connection: ConnectionManager,
+}
+
+impl Debug for RedisSessionStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisSessionStore").finish_non_exhaustive()
+    }
+}
+
+impl RedisSessionStore {
+    pub fn new(connection: ConnectionManager) -> Self {
+        Self { connection }
+    }
+
+    fn key(id: &str) -> String {
+        format!("session:{}", id)
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>, StoreError> {
+        let raw: Option<String> =
+            dependency_health::observe("redis", self.connection.clone().get(Self::key(id))).await?;
+        Ok(raw.map(|raw| serde_json::from_str(&raw)).transpose()?)
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<(), StoreError> {
+        let raw = serde_json::to_string(data)?;
+        let mut connection = self.connection.clone();
+        let _: () =
+            dependency_health::observe("redis", connection.set_ex(Self::key(id), raw, ttl.as_secs())).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), StoreError> {
+        let _: () = dependency_health::observe("redis", self.connection.clone().del(Self::key(id))).await?;
+        Ok(())
+    }
+}
+
+/// Cookie-based sessions
+///
+/// The cookie holds only a random session ID signed with HMAC-SHA256, so it
+/// can't be forged or guessed; session data stays in the [`SessionStore`].
+///
+/// Configuration:
+/// - `SESSION_SECRET`: base64 signing key, read through [`Secrets`]
+///   (ephemeral when unset, which logs everyone out on restart)
+/// - `SESSION_TTL_SECS`: session lifetime (default 24 hours)
+/// - `SESSION_COOKIE_SECURE`: set the `Secure` cookie attribute (default `true`)
+pub struct SessionManager {
+    store: Arc<dyn SessionStore>,
+    key: Vec<u8>,
+    ttl: Duration,
+    secure_cookie: bool,
+}
+
+impl Debug for SessionManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionManager")
+            .field("store", &self.store)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionManager {
+    pub fn from_env(
+        store: Arc<dyn SessionStore>,
+        secrets: &Secrets,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let key = match secrets.get("SESSION_SECRET")? {
+            Some(secret) => BASE64
+                .decode(secret.expose_secret())
+                .map_err(|e| format!("SESSION_SECRET: {}", e))?,
+            None => {
+                warn_trace!("SESSION_SECRET not set, using an ephemeral session signing key");
+                let mut key = vec![0u8; 32];
+                OsRng.fill_bytes(&mut key);
+                key
+            }
+        };
+        let ttl = std::env::var("SESSION_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        let secure_cookie = std::env::var("SESSION_COOKIE_SECURE")
+            .map(|v| !v.eq_ignore_ascii_case("false"))
+            .unwrap_or(true);
+
+        Ok(Self {
+            store,
+            key,
+            ttl: Duration::from_secs(ttl),
+            secure_cookie,
+        })
+    }
+
+    fn sign(&self, id: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(id.as_bytes());
+        BASE64_URL.encode(mac.finalize().into_bytes())
+    }
+
+    /// Session ID from a cookie value, if its signature checks out
+    fn verify(&self, cookie: &str) -> Option<String> {
+        let (id, signature) = cookie.split_once('.')?;
+        let signature = BASE64_URL.decode(signature).ok()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).ok()?;
+        mac.update(id.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+        Some(id.to_string())
+    }
+
+    fn cookie(&self, value: &str, max_age: u64) -> String {
+        let secure = if self.secure_cookie { "; Secure" } else { "" };
+        format!(
+            "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+            SESSION_COOKIE, value, max_age, secure
+        )
+    }
+
+    async fn load(&self, headers: &HeaderMap) -> Option<Session> {
+        let cookie = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == SESSION_COOKIE)
+            .map(|(_, value)| value)?;
+
+        let Some(id) = self.verify(cookie) else {
+            warn_trace!("Session cookie with invalid signature");
+            return None;
+        };
+
+        match self.store.load(&id).await {
+            Ok(data) => data.map(|data| Session { id, data }),
+            Err(e) => {
+                warn_trace!(error = %e, session.id_hash = %hash_id(&id), "Failed to load session");
+                None
+            }
+        }
+    }
+
+    /// Start a session for `user_id`; returns it with its `Set-Cookie` value
+    pub async fn create(&self, user_id: UserId) -> Result<(Session, String), StoreError> {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let data = SessionData {
+            user_id,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        self.store.save(&id, &data, self.ttl).await?;
+
+        let cookie = self.cookie(&format!("{}.{}", id, self.sign(&id)), self.ttl.as_secs());
+        Ok((Session { id, data }, cookie))
+    }
+
+    /// End a session; returns the `Set-Cookie` value clearing the cookie
+    pub async fn destroy(&self, session: &Session) -> Result<String, StoreError> {
+        self.store.delete(&session.id).await?;
+        Ok(self.cookie("", 0))
+    }
+}
+
+/// Middleware attaching the request's [`Session`] (if any) as an extension
+///
+/// Runs inside the `api.request` span and tags it with `session.id_hash`;
+/// the raw session ID never reaches telemetry. The session's user is tagged
+/// as part of the request context.
+pub async fn load_session(
+    State(sessions): State<Arc<SessionManager>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(session) = sessions.load(request.headers()).await {
+        tracing::Span::current().set_attribute("session.id_hash", session.id_hash());
+        request.extensions_mut().insert(session);
+    }
+    next.run(request).await
+}
@@ -0,0 +1,363 @@
+use crate::debug_trace;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::subscriber::Interest;
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Filter, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Field marking a span as a request span, holding the route template
+pub const ROUTE_FIELD: &str = "http.route";
+
+/// Target of request spans that are opened, for logs, but kept out of traces
+pub const SUPPRESSED_TARGET: &str = "span_dedup::suppressed";
+
+/// A child ending this close to its request span's end wraps the rest of the request
+const END_TOLERANCE: Duration = Duration::from_millis(1);
+
+/// Which of two request spans to keep out of traces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The HTTP tracing layer's request span, `api.request`
+    Middleware,
+    /// The span the route's handler opens with `#[instrument]`
+    Handler,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Source::Middleware => "middleware",
+            Source::Handler => "handler",
+        }
+    }
+}
+
+/// Extension on request spans
+#[derive(Debug)]
+struct RequestSpan {
+    route: String,
+    opened: Instant,
+    suppress_handler: bool,
+    /// The last direct child opened, and when it closed
+    last_child: Option<(Id, &'static str, Option<Instant>)>,
+}
+
+#[derive(Debug, Default)]
+struct RouteReport {
+    requests: u64,
+    redundant: u64,
+    /// Names of the request span and of the child wrapping it
+    spans: Option<(&'static str, &'static str)>,
+}
+
+/// Detects request spans nested in request spans, such as a handler's
+/// `#[instrument]` span inside the HTTP tracing layer's `api.request`
+///
+/// A request span is one with an `http.route` field. When its last direct
+/// child ends within a millisecond (or a tenth of the request) of the request
+/// span itself, the child wraps all the remaining work: the trace shows the
+/// request twice. Such routes are listed on `/debug/telemetry` and in a debug
+/// log as they are found.
+///
+/// Either source can be kept out of traces per route, and spans stay in logs.
+/// Suppressing the middleware makes the handler span the request's root; tags
+/// other middleware set on the request span are lost with it. Suppressing the
+/// handler drops its span, and attributes it sets on it, from the route's
+/// first redundant request on.
+///
+/// Configuration:
+/// - `TELEMETRY_SPAN_DEDUP`: comma-separated `route=source` pairs, source `middleware` or `handler`,
+///   route a template such as `/api/users/:id` or `*` for every route (default none)
+#[derive(Debug, Default)]
+pub struct SpanDedup {
+    suppress: Vec<(String, Source)>,
+    routes: Mutex<BTreeMap<String, RouteReport>>,
+}
+
+impl SpanDedup {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        match std::env::var("TELEMETRY_SPAN_DEDUP") {
+            Ok(spec) => Self::parse(&spec).map_err(|e| format!("TELEMETRY_SPAN_DEDUP: {}", e).into()),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut suppress = Vec::new();
+        for pair in spec.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (route, source) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected route=source, got '{}'", pair))?;
+            let source = match source.trim() {
+                "middleware" => Source::Middleware,
+                "handler" => Source::Handler,
+                other => return Err(format!("expected middleware or handler, got '{}'", other)),
+            };
+            suppress.push((route.trim().to_string(), source));
+        }
+        Ok(Self {
+            suppress,
+            ..Self::default()
+        })
+    }
+
+    /// The source kept out of traces on `route`; a rule for the route beats `*`
+    pub fn suppressed(&self, route: &str) -> Option<Source> {
+        let rule = |pattern: &str| self.suppress.iter().find(|(r, _)| r == pattern).map(|(_, source)| *source);
+        rule(route).or_else(|| rule("*"))
+    }
+
+    /// Name of the child span found wrapping `route`'s request span
+    fn redundant_child(&self, route: &str) -> Option<&'static str> {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        Some(routes.get(route)?.spans?.1)
+    }
+
+    fn record(&self, route: &str, redundant: Option<(&'static str, &'static str)>) {
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let report = routes.entry(route.to_string()).or_default();
+        report.requests += 1;
+        let Some(spans) = redundant else {
+            return;
+        };
+        report.redundant += 1;
+        if report.spans.replace(spans).is_none() {
+            let flagged: Vec<String> = routes
+                .iter()
+                .filter_map(|(route, report)| Some(format!("{} ({} > {})", route, report.spans?.0, report.spans?.1)))
+                .collect();
+            debug_trace!(
+                http.route = %route,
+                routes = ?flagged,
+                "Routes with redundant request spans; TELEMETRY_SPAN_DEDUP can drop one source"
+            );
+        }
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let redundant: Vec<_> = routes
+            .iter()
+            .filter_map(|(route, report)| {
+                let (request_span, child_span) = report.spans?;
+                Some(serde_json::json!({
+                    "route": route,
+                    "request_span": request_span,
+                    "child_span": child_span,
+                    "redundant": report.redundant,
+                    "requests": report.requests,
+                    "suppressed": self.suppressed(route).map(Source::as_str),
+                }))
+            })
+            .collect();
+        serde_json::json!({
+            "suppress": self
+                .suppress
+                .iter()
+                .map(|(route, source)| serde_json::json!({ "route": route, "source": source.as_str() }))
+                .collect::<Vec<_>>(),
+            "redundant_routes": redundant,
+        })
+    }
+}
+
+/// Reads the route off a request span's fields
+#[derive(Default)]
+struct RouteVisitor(Option<String>);
+
+impl Visit for RouteVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == ROUTE_FIELD {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == ROUTE_FIELD {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+/// The [`Layer`] watching request spans and their children
+#[derive(Debug, Clone)]
+pub struct DedupLayer(Arc<SpanDedup>);
+
+impl DedupLayer {
+    pub fn new(dedup: Arc<SpanDedup>) -> Self {
+        Self(dedup)
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for DedupLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if let Some(parent) = span.parent() {
+            if let Some(request) = parent.extensions_mut().get_mut::<RequestSpan>() {
+                request.last_child = Some((id.clone(), span.name(), None));
+            }
+        }
+        let mut route = RouteVisitor::default();
+        attrs.record(&mut route);
+        if let Some(route) = route.0 {
+            let suppress_handler = self.0.suppressed(&route) == Some(Source::Handler);
+            span.extensions_mut().insert(RequestSpan {
+                route,
+                opened: Instant::now(),
+                suppress_handler,
+                last_child: None,
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let now = Instant::now();
+        if let Some(request) = span.extensions().get::<RequestSpan>() {
+            let duration = now.duration_since(request.opened);
+            let wraps_the_rest = |closed: Instant| now.duration_since(closed) <= END_TOLERANCE.max(duration / 10);
+            let redundant = match &request.last_child {
+                Some((_, child, Some(closed))) if wraps_the_rest(*closed) => Some((span.name(), *child)),
+                _ => None,
+            };
+            self.0.record(&request.route, redundant);
+        }
+        if let Some(parent) = span.parent() {
+            if let Some(request) = parent.extensions_mut().get_mut::<RequestSpan>() {
+                if let Some((child, _, closed)) = &mut request.last_child {
+                    if *child == id {
+                        *closed = Some(now);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Per-layer [`Filter`] keeping suppressed request spans away from the layer it wraps
+#[derive(Debug, Clone)]
+pub struct DedupFilter(Arc<SpanDedup>);
+
+impl DedupFilter {
+    pub fn new(dedup: Arc<SpanDedup>) -> Self {
+        Self(dedup)
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Filter<S> for DedupFilter {
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        if meta.target() == SUPPRESSED_TARGET {
+            return false;
+        }
+        if !meta.is_span() {
+            return true;
+        }
+        let Some(parent) = cx.lookup_current() else {
+            return true;
+        };
+        let extensions = parent.extensions();
+        match extensions.get::<RequestSpan>() {
+            Some(request) if request.suppress_handler => self.0.redundant_child(&request.route) != Some(meta.name()),
+            _ => true,
+        }
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        if meta.target() == SUPPRESSED_TARGET {
+            Interest::never()
+        } else if meta.is_span() {
+            // Decided per span, from its parent
+            Interest::sometimes()
+        } else {
+            Interest::always()
+        }
+    }
+}
+
+static DEDUP: OnceLock<Arc<SpanDedup>> = OnceLock::new();
+
+/// Use `dedup` for [`suppressed`] and [`snapshot`]; call once at startup
+pub fn install(dedup: SpanDedup) -> Arc<SpanDedup> {
+    DEDUP.get_or_init(|| Arc::new(dedup)).clone()
+}
+
+/// The source kept out of traces on `route`, for middleware opening request spans
+pub fn suppressed(route: &str) -> Option<Source> {
+    DEDUP.get().and_then(|dedup| dedup.suppressed(route))
+}
+
+/// Suppression rules and redundant routes for `/debug/telemetry`
+pub fn snapshot() -> serde_json::Value {
+    match DEDUP.get() {
+        Some(dedup) => dedup.snapshot(),
+        None => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Names of the spans a filtered layer was shown
+    #[derive(Clone, Default)]
+    struct Seen(Arc<Mutex<Vec<&'static str>>>);
+
+    impl<S: Subscriber> Layer<S> for Seen {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            self.0.lock().unwrap().push(attrs.metadata().name());
+        }
+    }
+
+    fn request(route: &str) {
+        let request = tracing::info_span!("api.request", http.route = %route);
+        let _request = request.enter();
+        tracing::info_span!("decompress").in_scope(|| {});
+        std::thread::sleep(Duration::from_millis(5));
+        tracing::info_span!("get_user").in_scope(|| std::thread::sleep(Duration::from_millis(5)));
+    }
+
+    #[test]
+    fn flags_wrapping_children_and_drops_the_suppressed_source() {
+        let dedup = Arc::new(SpanDedup::parse("/api/users/:id=handler").unwrap());
+        let seen = Seen::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(seen.clone().with_filter(DedupFilter::new(dedup.clone())))
+            .with(DedupLayer::new(dedup.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            request("/api/users/:id");
+            request("/api/users/:id");
+            request("/api/orders/:id");
+        });
+
+        let snapshot = dedup.snapshot();
+        let routes = snapshot["redundant_routes"].as_array().unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[1]["route"], "/api/users/:id");
+        assert_eq!(routes[1]["child_span"], "get_user");
+        assert_eq!(routes[1]["redundant"], 2);
+        assert_eq!(routes[1]["suppressed"], "handler");
+        assert_eq!(routes[0]["suppressed"], serde_json::Value::Null);
+        // Found on the first request, dropped from the second; other routes keep theirs
+        let seen = seen.0.lock().unwrap();
+        assert_eq!(seen.iter().filter(|name| **name == "get_user").count(), 2);
+        assert_eq!(seen.iter().filter(|name| **name == "decompress").count(), 3);
+    }
+
+    #[test]
+    fn route_rules_beat_the_wildcard() {
+        let dedup = SpanDedup::parse("*=middleware, /health=handler").unwrap();
+        assert_eq!(dedup.suppressed("/health"), Some(Source::Handler));
+        assert_eq!(dedup.suppressed("/api/orders"), Some(Source::Middleware));
+        assert!(SpanDedup::parse("/health=both").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::attribute_filter::matches_pattern;
+use opentelemetry::{KeyValue, Value};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// What a rule does to the span name, or to the attributes it matches
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Rename(String),
+    Set(Value),
+    Drop,
+}
+
+#[derive(Debug)]
+struct Rule {
+    /// Span names the rule applies to, `prefix*` wildcards allowed; every span when unset
+    span: Option<String>,
+    /// Attribute keys the rule applies to; the span name itself when unset
+    attribute: Option<String>,
+    action: Action,
+    applied: AtomicU64,
+}
+
+impl Rule {
+    fn covers(&self, span: &str) -> bool {
+        self.span.as_deref().is_none_or(|pattern| matches_pattern(pattern, span))
+    }
+}
+
+/// Operator rules renaming spans and rewriting or dropping attributes before export
+///
+/// Fixes instrumentation naming without a code change, e.g. showing
+/// `fetch_user_from_database` as `postgres.query`. Rules live in a TOML file,
+/// apply in order, and run before the attribute filter and cardinality guard:
+///
+/// ```toml
+/// [[rule]]
+/// span = "fetch_user_from_database"    # span name; `prefix*` wildcards, every span when omitted
+/// rename = "postgres.query"
+///
+/// [[rule]]
+/// attribute = "user_id"                # attribute key; `prefix.*` wildcards
+/// rename = "usr.id"                    # or `value = …` to replace the value, or `drop = true`
+/// ```
+///
+/// Span patterns match the name the code gives the span, before any rename.
+///
+/// Configuration:
+/// - `TELEMETRY_SPAN_RULES`: path of the rules file; no rules when unset
+#[derive(Debug, Default)]
+pub struct SpanRules {
+    rules: Vec<Rule>,
+}
+
+impl SpanRules {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let Ok(path) = std::env::var("TELEMETRY_SPAN_RULES") else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("TELEMETRY_SPAN_RULES {}: {}", path, e))?;
+        Ok(Self::parse(&text).map_err(|e| format!("TELEMETRY_SPAN_RULES {}: {}", path, e))?)
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let document: toml_edit::DocumentMut = text.parse().map_err(|e| format!("invalid TOML: {}", e))?;
+        if let Some(key) = document.iter().map(|(key, _)| key).find(|key| *key != "rule") {
+            return Err(format!("{}: expected only [[rule]] tables", key));
+        }
+        let Some(tables) = document.get("rule") else {
+            return Ok(Self::default());
+        };
+        let tables = tables.as_array_of_tables().ok_or("rule: expected [[rule]] tables")?;
+        let rules = tables
+            .iter()
+            .enumerate()
+            .map(|(index, table)| parse_rule(table).map_err(|e| format!("rule {}: {}", index + 1, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `name` after the span rename rules matching it
+    pub fn span_name(&self, name: Cow<'static, str>) -> Cow<'static, str> {
+        let mut renamed = name.clone();
+        for rule in self.rules.iter().filter(|rule| rule.attribute.is_none() && rule.covers(&name)) {
+            if let Action::Rename(to) = &rule.action {
+                rule.applied.fetch_add(1, Ordering::Relaxed);
+                renamed = Cow::Owned(to.clone());
+            }
+        }
+        renamed
+    }
+
+    /// Apply the attribute rules covering span `span` to `attributes`
+    pub fn attributes(&self, span: &str, attributes: &mut Vec<KeyValue>) {
+        for rule in &self.rules {
+            let Some(pattern) = &rule.attribute else {
+                continue;
+            };
+            if !rule.covers(span) {
+                continue;
+            }
+            let before = attributes.len();
+            let mut applied = 0;
+            match &rule.action {
+                Action::Drop => {
+                    attributes.retain(|kv| !matches_pattern(pattern, kv.key.as_str()));
+                    applied = before - attributes.len();
+                }
+                Action::Rename(to) => {
+                    for kv in attributes.iter_mut().filter(|kv| matches_pattern(pattern, kv.key.as_str())) {
+                        *kv = KeyValue::new(to.clone(), kv.value.clone());
+                        applied += 1;
+                    }
+                }
+                Action::Set(value) => {
+                    for kv in attributes.iter_mut().filter(|kv| matches_pattern(pattern, kv.key.as_str())) {
+                        kv.value = value.clone();
+                        applied += 1;
+                    }
+                }
+            }
+            rule.applied.fetch_add(applied as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Each rule and how often it applied, for `/debug/telemetry`
+    pub fn snapshot(&self) -> serde_json::Value {
+        let rules: Vec<_> = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let action = match &rule.action {
+                    Action::Rename(to) => serde_json::json!({ "rename": to }),
+                    Action::Set(value) => serde_json::json!({ "value": value.to_string() }),
+                    Action::Drop => serde_json::json!({ "drop": true }),
+                };
+                serde_json::json!({
+                    "span": rule.span,
+                    "attribute": rule.attribute,
+                    "action": action,
+                    "applied": rule.applied.load(Ordering::Relaxed),
+                })
+            })
+            .collect();
+        serde_json::json!({ "rules": rules })
+    }
+}
+
+fn parse_rule(table: &toml_edit::Table) -> Result<Rule, String> {
+    let text = |key: &str| -> Result<Option<String>, String> {
+        match table.get(key) {
+            Some(item) => item
+                .as_str()
+                .map(|value| Some(value.to_string()))
+                .ok_or_else(|| format!("{}: expected a string", key)),
+            None => Ok(None),
+        }
+    };
+    if let Some(key) = table
+        .iter()
+        .map(|(key, _)| key)
+        .find(|key| !["span", "attribute", "rename", "value", "drop"].contains(key))
+    {
+        return Err(format!("unknown key {}", key));
+    }
+    let span = text("span")?;
+    let attribute = text("attribute")?;
+
+    let mut actions = Vec::new();
+    if let Some(to) = text("rename")? {
+        actions.push(Action::Rename(to));
+    }
+    if let Some(item) = table.get("value") {
+        actions.push(Action::Set(toml_value(item).ok_or("value: expected a string, number or boolean")?));
+    }
+    match table.get("drop").map(|item| item.as_bool()) {
+        Some(Some(true)) => actions.push(Action::Drop),
+        Some(Some(false)) | None => {}
+        Some(None) => return Err("drop: expected a boolean".to_string()),
+    }
+    let action = match actions.len() {
+        1 => actions.remove(0),
+        0 => return Err("expected one of rename, value or drop".to_string()),
+        _ => return Err("expected only one of rename, value or drop".to_string()),
+    };
+    if attribute.is_none() && !matches!(action, Action::Rename(_)) {
+        return Err("value and drop need an attribute".to_string());
+    }
+    if attribute.is_none() && span.is_none() {
+        return Err("renaming every span needs a span pattern".to_string());
+    }
+    Ok(Rule {
+        span,
+        attribute,
+        action,
+        applied: AtomicU64::new(0),
+    })
+}
+
+fn toml_value(item: &toml_edit::Item) -> Option<Value> {
+    let value = item.as_value()?;
+    value
+        .as_str()
+        .map(|s| Value::from(s.to_string()))
+        .or_else(|| value.as_integer().map(Value::from))
+        .or_else(|| value.as_float().map(Value::from))
+        .or_else(|| value.as_bool().map(Value::from))
+}
+
+static RULES: OnceLock<Arc<SpanRules>> = OnceLock::new();
+
+/// Use `rules` for [`snapshot`]; returns the shared rules for the tracer
+pub fn install(rules: SpanRules) -> Arc<SpanRules> {
+    RULES.get_or_init(|| Arc::new(rules)).clone()
+}
+
+/// Rules and how often each applied, for `/debug/telemetry`
+pub fn snapshot() -> serde_json::Value {
+    match RULES.get() {
+        Some(rules) => rules.snapshot(),
+        None => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+        [[rule]]
+        span = "fetch_user_from_database"
+        rename = "postgres.query"
+
+        [[rule]]
+        span = "fetch_*"
+        attribute = "user_id"
+        rename = "usr.id"
+
+        [[rule]]
+        attribute = "db.system"
+        value = "postgresql"
+
+        [[rule]]
+        attribute = "http.request.header.*"
+        drop = true
+    "#;
+
+    #[test]
+    fn renames_spans_and_rewrites_attributes_in_order() {
+        let rules = SpanRules::parse(RULES).unwrap();
+        assert_eq!(rules.span_name("fetch_user_from_database".into()), "postgres.query");
+        assert_eq!(rules.span_name("get_user".into()), "get_user");
+
+        let mut attributes = vec![
+            KeyValue::new("user_id", "42"),
+            KeyValue::new("db.system", "in-memory"),
+            KeyValue::new("http.request.header.cookie", "secret"),
+        ];
+        rules.attributes("fetch_user_from_database", &mut attributes);
+        assert_eq!(
+            attributes,
+            vec![KeyValue::new("usr.id", "42"), KeyValue::new("db.system", "postgresql")]
+        );
+
+        // Span patterns scope attribute rules
+        let mut attributes = vec![KeyValue::new("user_id", "42")];
+        rules.attributes("get_user", &mut attributes);
+        assert_eq!(attributes, vec![KeyValue::new("user_id", "42")]);
+        assert_eq!(rules.snapshot()["rules"][0]["applied"], 1);
+    }
+
+    #[test]
+    fn example_file_is_valid() {
+        let rules = SpanRules::parse(include_str!("../span-rules.example.toml")).unwrap();
+        assert!(!rules.is_empty());
+    }
+
+    #[test]
+    fn rejects_ambiguous_rules() {
+        let error = |text: &str| SpanRules::parse(text).unwrap_err();
+        assert_eq!(error("[[rule]]\nspan = \"a\""), "rule 1: expected one of rename, value or drop");
+        assert_eq!(
+            error("[[rule]]\nattribute = \"a\"\nrename = \"b\"\ndrop = true"),
+            "rule 1: expected only one of rename, value or drop"
+        );
+        assert_eq!(error("[[rule]]\nspan = \"a\"\ndrop = true"), "rule 1: value and drop need an attribute");
+        assert_eq!(error("[[rule]]\nspan = \"a\"\nto = \"b\""), "rule 1: unknown key to");
+    }
+}
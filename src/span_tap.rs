@@ -0,0 +1,111 @@
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::Stream;
+use opentelemetry::trace::{SpanId, Status};
+use opentelemetry::Context;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+
+/// Finished spans buffered per subscriber; slower subscribers skip ahead
+const BUFFER: usize = 1024;
+
+/// What `/debug/span-stream` sends for each finished span
+#[derive(Debug, Clone, Serialize)]
+pub struct SpanSummary {
+    pub name: String,
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub kind: String,
+    /// `unset`, `ok` or `error`
+    pub status: &'static str,
+    pub error: Option<String>,
+    pub duration_ms: f64,
+}
+
+impl From<&SpanData> for SpanSummary {
+    fn from(span: &SpanData) -> Self {
+        let (status, error) = match &span.status {
+            Status::Unset => ("unset", None),
+            Status::Ok => ("ok", None),
+            Status::Error { description } => ("error", Some(description.to_string())),
+        };
+        let duration = span.end_time.duration_since(span.start_time).unwrap_or_default();
+        Self {
+            name: span.name.to_string(),
+            trace_id: span.span_context.trace_id().to_string(),
+            span_id: span.span_context.span_id().to_string(),
+            parent_span_id: (span.parent_span_id != SpanId::INVALID).then(|| span.parent_span_id.to_string()),
+            kind: format!("{:?}", span.span_kind).to_lowercase(),
+            status,
+            error,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Span processor copying finished spans to live `/debug/span-stream` subscribers
+///
+/// Registered alongside the exporter, so it sees every span the tracer
+/// records whether or not it is exported. With no subscribers it does nothing.
+#[derive(Debug, Clone)]
+pub struct SpanTap {
+    sender: broadcast::Sender<SpanSummary>,
+}
+
+impl SpanTap {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(BUFFER).0,
+        }
+    }
+
+    /// Server-sent `span` events, one per finished span, until the client leaves
+    ///
+    /// A subscriber that falls more than the buffer behind gets a `lagged`
+    /// event with the number of spans it missed.
+    pub fn stream(&self) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let receiver = self.sender.subscribe();
+        let events = futures_util::stream::unfold(receiver, |mut receiver| async move {
+            let event = match receiver.recv().await {
+                Ok(summary) => Event::default()
+                    .event("span")
+                    .json_data(summary)
+                    .unwrap_or_else(|_| Event::default().comment("unserializable span")),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    Event::default().event("lagged").data(missed.to_string())
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            Some((Ok(event), receiver))
+        });
+        Sse::new(events).keep_alive(KeepAlive::default())
+    }
+}
+
+impl Default for SpanTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpanProcessor for SpanTap {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        if self.sender.receiver_count() > 0 {
+            // Only fails when the last subscriber left in the meantime
+            let _ = self.sender.send(SpanSummary::from(&span));
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: std::time::Duration) -> OTelSdkResult {
+        Ok(())
+    }
+}
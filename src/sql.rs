@@ -0,0 +1,232 @@
+use crate::dependency_health;
+use opentelemetry::trace::{Status, TraceContextExt};
+use rust_datadog_otel::{downstream, dogstatsd, telemetry};
+use sqlx::pool::PoolConnection;
+use sqlx::{Database, Pool};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Between `db.pool.*` gauge samples
+const POOL_METRICS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How much trace context is written into SQL comments, as dd-trace's `DD_DBM_PROPAGATION_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbmPropagation {
+    Disabled,
+    /// Service, environment and version tags, linking query metrics to the service
+    Service,
+    /// Also the query span's `traceparent`, linking each sampled query to its trace
+    Full,
+}
+
+impl DbmPropagation {
+    pub const NAMES: [&'static str; 3] = ["disabled", "service", "full"];
+
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        match std::env::var("DD_DBM_PROPAGATION_MODE").as_deref() {
+            Err(_) | Ok("disabled") => Ok(DbmPropagation::Disabled),
+            Ok("service") => Ok(DbmPropagation::Service),
+            Ok("full") => Ok(DbmPropagation::Full),
+            Ok(other) => Err(format!("DD_DBM_PROPAGATION_MODE: unknown mode '{}'", other).into()),
+        }
+    }
+}
+
+/// Spans and DBM comments for the SQL repositories
+///
+/// Every query runs in a `<system>.query` client span (`postgres.query`,
+/// `sqlite.query`) with `db.system`, `db.name`, `db.operation` and the
+/// parameterized `db.statement` as its resource, and `peer.service` set to
+/// the database name, so Datadog shows the database in the service map.
+/// With DBM propagation on, each query also starts with a
+/// `/*dddbs=…,dde=…,ddps=…*/` comment naming the service, plus the span's
+/// `traceparent` in `full` mode, which Database Monitoring uses to link
+/// query samples to the service and trace.
+#[derive(Debug, Clone)]
+pub struct QueryTracer {
+    system: &'static str,
+    database: String,
+    dbm: DbmPropagation,
+}
+
+impl QueryTracer {
+    pub fn new(system: &'static str, database: impl Into<String>, dbm: DbmPropagation) -> Self {
+        Self {
+            system,
+            database: database.into(),
+            dbm,
+        }
+    }
+
+    pub fn database(&self) -> &str {
+        &self.database
+    }
+
+    /// A span for one run of `statement`
+    pub fn span(&self, operation: &'static str, statement: &'static str) -> Span {
+        let span = tracing::info_span!(
+            "sql.query",
+            otel.name = %format!("{}.query", self.span_prefix()),
+            otel.kind = "client",
+            resource.name = statement,
+            span.type = "sql",
+            db.system = self.system,
+            db.name = %self.database,
+            db.operation = operation,
+            db.statement = statement,
+        );
+        downstream::set_peer_service(&span, self.database.clone());
+        span
+    }
+
+    /// `statement` with the DBM comment in front, when propagation is on
+    pub fn sql(&self, span: &Span, statement: &str) -> String {
+        match dbm_comment(self.dbm, &self.database, span) {
+            Some(comment) => format!("{} {}", comment, statement),
+            None => statement.to_string(),
+        }
+    }
+
+    /// Comments differ per query in `full` mode, so those can't be cached as prepared statements
+    pub fn persistent(&self) -> bool {
+        self.dbm != DbmPropagation::Full
+    }
+
+    /// `postgresql` spans keep the shorter name Datadog's integrations use
+    fn span_prefix(&self) -> &'static str {
+        match self.system {
+            "postgresql" => "postgres",
+            other => other,
+        }
+    }
+}
+
+/// A sqlx pool that reports its saturation
+///
+/// Connections are acquired explicitly so the wait can be measured: each
+/// acquire records `db.pool.size`, `db.pool.idle`, `db.pool.max` and
+/// `db.pool.wait_ms` on the current span, which is the query span inside
+/// [`observe`], sends the wait as the `db.pool.wait_ms` histogram and counts
+/// `db.pool.acquire_timeouts` when none frees up in time. The
+/// `db.pool.size`, `db.pool.idle`, `db.pool.in_use` and `db.pool.max` gauges
+/// are sampled every 10 seconds. Metrics are tagged `db.system` and `db.name`.
+#[derive(Debug)]
+pub struct MeteredPool<DB: Database> {
+    pool: Pool<DB>,
+    tags: Vec<String>,
+}
+
+impl<DB: Database> MeteredPool<DB> {
+    /// Wrap `pool` for the database `queries` traces, and start sampling its gauges
+    pub fn new(pool: Pool<DB>, queries: &QueryTracer) -> Self {
+        let tags = vec![format!("db.system:{}", queries.system), format!("db.name:{}", queries.database)];
+        let sampled = pool.clone();
+        let gauge_tags = tags.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(POOL_METRICS_INTERVAL);
+            while !sampled.is_closed() {
+                ticker.tick().await;
+                for (metric, value) in pool_gauges(&sampled) {
+                    dogstatsd::gauge(metric, value, &gauge_tags);
+                }
+            }
+        });
+        Self { pool, tags }
+    }
+
+    /// A connection from the pool, timing the wait for one
+    pub async fn acquire(&self) -> Result<PoolConnection<DB>, sqlx::Error> {
+        let started = Instant::now();
+        let result = self.pool.acquire().await;
+        let wait_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let span = Span::current();
+        for (key, value) in pool_gauges(&self.pool) {
+            if key != "db.pool.in_use" {
+                span.set_attribute(key, value as i64);
+            }
+        }
+        span.set_attribute("db.pool.wait_ms", wait_ms);
+        dogstatsd::histogram("db.pool.wait_ms", wait_ms, &self.tags);
+        if let Err(sqlx::Error::PoolTimedOut) = result {
+            dogstatsd::count("db.pool.acquire_timeouts", 1, &self.tags);
+        }
+        result
+    }
+}
+
+fn pool_gauges<DB: Database>(pool: &Pool<DB>) -> [(&'static str, f64); 4] {
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+    [
+        ("db.pool.size", size as f64),
+        ("db.pool.idle", idle as f64),
+        ("db.pool.in_use", size.saturating_sub(idle) as f64),
+        ("db.pool.max", pool.options().get_max_connections() as f64),
+    ]
+}
+
+/// Run `query` in `span`, failing the span on error and tracking database health
+pub async fn observe<T>(span: Span, query: impl Future<Output = Result<T, sqlx::Error>>) -> Result<T, sqlx::Error> {
+    let result = dependency_health::observe("database", query).instrument(span.clone()).await;
+    if let Err(e) = &result {
+        span.set_status(Status::error(e.to_string()));
+    }
+    result
+}
+
+/// SQL comment tagging a query for Database Monitoring, in dd-trace's format
+fn dbm_comment(mode: DbmPropagation, database: &str, span: &Span) -> Option<String> {
+    if mode == DbmPropagation::Disabled {
+        return None;
+    }
+    let identity = telemetry::config();
+    let mut tags = vec![
+        ("dddbs", database.to_string()),
+        ("dde", identity.env.clone()),
+        ("ddps", identity.service.clone()),
+        ("ddpv", identity.version.clone()),
+    ];
+    if mode == DbmPropagation::Full {
+        let cx = span.context();
+        let span_context = cx.span().span_context().clone();
+        if span_context.is_valid() {
+            let flags = if span_context.is_sampled() { "01" } else { "00" };
+            let traceparent = format!("00-{}-{}-{}", span_context.trace_id(), span_context.span_id(), flags);
+            tags.push(("traceparent", traceparent));
+        }
+    }
+    let tags: Vec<String> = tags
+        .into_iter()
+        .map(|(key, value)| format!("{}='{}'", key, comment_escape(&value)))
+        .collect();
+    Some(format!("/*{}*/", tags.join(",")))
+}
+
+fn comment_escape(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dbm_comments_tag_the_service() {
+        assert_eq!(dbm_comment(DbmPropagation::Disabled, "app", &Span::none()), None);
+        let comment = dbm_comment(DbmPropagation::Full, "app db", &Span::none()).unwrap();
+        assert!(comment.starts_with("/*dddbs='app%20db',dde='"), "{}", comment);
+        assert!(comment.ends_with("'*/"));
+        // No trace outside a span, so no traceparent
+        assert!(!comment.contains("traceparent"));
+        assert_eq!(comment_escape("it's*/"), "it%27s%2A%2F");
+    }
+}
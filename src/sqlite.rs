@@ -0,0 +1,461 @@
+use crate::auth::Role;
+use crate::concurrency::{IfMatch, UpdateOutcome};
+use crate::ids::{OrderId, UserId};
+use crate::orders::{self, CancelOutcome, OrderRecord, OrderStore};
+use crate::repository::{Repository, StoreError, StoredUser};
+use crate::sql::{observe, DbmPropagation, MeteredPool, QueryTracer};
+use chrono::{DateTime, Utc};
+use rust_datadog_otel::info_trace;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow};
+use sqlx::{Connection, Row, Sqlite};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const DEFAULT_MAX_CONNECTIONS: u32 = 4;
+
+/// Created at startup when missing, like [`ORDERS_SCHEMA`]; there are no migrations beyond these
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS users (
+    id BLOB PRIMARY KEY,
+    name TEXT NOT NULL,
+    email_ciphertext TEXT NOT NULL,
+    email_hash TEXT NOT NULL UNIQUE,
+    password_hash TEXT,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    version INTEGER NOT NULL
+)";
+
+const INSERT: &str = "INSERT INTO users (id, name, email_ciphertext, email_hash, password_hash, role, created_at, version) \
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+const SELECT_BY_ID: &str = "SELECT id, name, email_ciphertext, email_hash, password_hash, role, created_at, version \
+    FROM users WHERE id = ?";
+const SELECT_BY_EMAIL_HASH: &str = "SELECT id, name, email_ciphertext, email_hash, password_hash, role, created_at, version \
+    FROM users WHERE email_hash = ?";
+const UPDATE_EMAIL: &str = "UPDATE users SET email_ciphertext = ? WHERE id = ?";
+const UPDATE_NAME: &str = "UPDATE users SET name = ?, version = ? WHERE id = ? AND version = ?";
+const SELECT_CREATED_BEFORE: &str = "SELECT id FROM users WHERE created_at < ?";
+const DELETE: &str = "DELETE FROM users WHERE id = ?";
+const SELECT_NAMES: &str = "SELECT id, name FROM users";
+const COUNT: &str = "SELECT COUNT(*) FROM users";
+
+/// Lines are a JSON array of `product_id`, `quantity` and `price`; amounts are decimal strings
+const ORDERS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS orders (
+    order_id BLOB PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    lines TEXT NOT NULL,
+    total_amount TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    version INTEGER NOT NULL
+)";
+const ORDERS_USER_INDEX: &str = "CREATE INDEX IF NOT EXISTS orders_user_created ON orders (user_id, created_at, order_id)";
+const ORDERS_CREATED_INDEX: &str = "CREATE INDEX IF NOT EXISTS orders_created ON orders (created_at, order_id)";
+
+const INSERT_ORDER: &str = "INSERT INTO orders (order_id, user_id, lines, total_amount, currency, status, created_at, version) \
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+const SELECT_ORDER: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE order_id = ?";
+const UPDATE_ORDER_STATUS: &str = "UPDATE orders SET status = ?, version = ? WHERE order_id = ? AND version = ?";
+const SELECT_ORDERS: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders ORDER BY created_at, order_id";
+const SELECT_USER_ORDERS: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE user_id = ? ORDER BY created_at, order_id LIMIT ?";
+const SELECT_USER_ORDERS_AFTER: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE user_id = ? AND (created_at, order_id) > (?, ?) ORDER BY created_at, order_id LIMIT ?";
+const SELECT_ORDERS_SINCE: &str = "SELECT order_id, user_id, lines, total_amount, currency, status, created_at, version \
+    FROM orders WHERE created_at >= ? ORDER BY created_at, order_id";
+
+/// Users and orders in an embedded SQLite file, for demos without a database server
+///
+/// Selected with a `sqlite:` `DATABASE_URL` such as `sqlite://users.db`; the
+/// file is created when missing. Queries run in `sqlite.query` spans from
+/// [`QueryTracer`], named after the file, with no DBM comments since
+/// Database Monitoring doesn't cover SQLite.
+///
+/// Configuration:
+/// - `DATABASE_URL`: `sqlite://<path>` (or `sqlite::memory:`, lost on restart)
+/// - `DATABASE_MAX_CONNECTIONS`: pool size (default 4); writes are serialized by SQLite either way
+#[derive(Debug)]
+pub struct SqliteRepository {
+    pool: MeteredPool<Sqlite>,
+    queries: QueryTracer,
+}
+
+impl SqliteRepository {
+    pub const BACKEND: &'static str = "sqlite";
+
+    /// Whether `url` names a SQLite database rather than a Postgres one
+    pub fn handles(url: &str) -> bool {
+        url.starts_with("sqlite:")
+    }
+
+    /// Open the database at `url`, creating the file and the users and orders tables if missing
+    pub async fn connect(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let max_connections = match std::env::var("DATABASE_MAX_CONNECTIONS") {
+            Ok(value) => value
+                .parse::<u32>()
+                .map_err(|e| format!("DATABASE_MAX_CONNECTIONS: {}", e))?,
+            Err(_) => DEFAULT_MAX_CONNECTIONS,
+        };
+        let options = SqliteConnectOptions::from_str(url)
+            .map_err(|e| format!("DATABASE_URL: {}", e))?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let database = options
+            .get_filename()
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "sqlite".to_string());
+        // An idle pool would drop an in-memory database with its last connection
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections.max(1))
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+            .map_err(|e| format!("DATABASE_URL: {}", e))?;
+
+        let queries = QueryTracer::new("sqlite", database, DbmPropagation::Disabled);
+        let repository = Self {
+            pool: MeteredPool::new(pool, &queries),
+            queries,
+        };
+        for statement in [SCHEMA, ORDERS_SCHEMA, ORDERS_USER_INDEX, ORDERS_CREATED_INDEX] {
+            let span = repository.queries.span("CREATE", statement);
+            observe(span, async { sqlx::query(statement).execute(&mut *repository.pool.acquire().await?).await }).await?;
+        }
+        info_trace!(db.name = %repository.queries.database(), max_connections, "SQLite user and order storage ready");
+        Ok(repository)
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for SqliteRepository {
+    async fn insert(&self, user: StoredUser) -> Result<(), StoreError> {
+        let created_at = DateTime::parse_from_rfc3339(&user.created_at)?.with_timezone(&Utc);
+        let query = sqlx::query(INSERT)
+            .bind(user.id)
+            .bind(user.name)
+            .bind(user.email_ciphertext)
+            .bind(user.email_hash)
+            .bind(user.password_hash)
+            .bind(user.role.as_str())
+            .bind(created_at)
+            .bind(user.version as i64);
+        let span = self.queries.span("INSERT", INSERT);
+        observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(())
+    }
+
+    async fn get(&self, id: UserId) -> Result<Option<StoredUser>, StoreError> {
+        let query = sqlx::query(SELECT_BY_ID).bind(id);
+        let span = self.queries.span("SELECT", SELECT_BY_ID);
+        let row = observe(span, async { query.fetch_optional(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(row.as_ref().map(stored_user).transpose()?)
+    }
+
+    async fn find_by_email_hash(&self, email_hash: &str) -> Result<Option<StoredUser>, StoreError> {
+        let query = sqlx::query(SELECT_BY_EMAIL_HASH).bind(email_hash);
+        let span = self.queries.span("SELECT", SELECT_BY_EMAIL_HASH);
+        let row = observe(span, async { query.fetch_optional(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(row.as_ref().map(stored_user).transpose()?)
+    }
+
+    async fn set_email_ciphertext(&self, id: UserId, ciphertext: &str) -> Result<(), StoreError> {
+        let query = sqlx::query(UPDATE_EMAIL).bind(ciphertext).bind(id);
+        let span = self.queries.span("UPDATE", UPDATE_EMAIL);
+        observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(())
+    }
+
+    async fn update_name(&self, id: UserId, name: &str, if_match: &IfMatch) -> Result<UpdateOutcome<StoredUser>, StoreError> {
+        let Some(mut stored) = Repository::get(self, id).await? else {
+            return Ok(UpdateOutcome::NotFound);
+        };
+        if !if_match.matches(stored.version) {
+            return Ok(UpdateOutcome::Stale {
+                current_version: stored.version,
+            });
+        }
+        let read_version = stored.version;
+        stored.name = name.to_string();
+        stored.version += 1;
+
+        // No row locks in SQLite, so the update only applies if nobody wrote since the read
+        let query = sqlx::query(UPDATE_NAME)
+            .bind(&stored.name)
+            .bind(stored.version as i64)
+            .bind(id)
+            .bind(read_version as i64);
+        let span = self.queries.span("UPDATE", UPDATE_NAME);
+        let result = observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        if result.rows_affected() == 0 {
+            return Ok(match Repository::get(self, id).await? {
+                Some(current) => UpdateOutcome::Stale {
+                    current_version: current.version,
+                },
+                None => UpdateOutcome::NotFound,
+            });
+        }
+        Ok(UpdateOutcome::Updated(stored))
+    }
+
+    async fn created_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<UserId>, StoreError> {
+        let query = sqlx::query(SELECT_CREATED_BEFORE).bind(cutoff);
+        let span = self.queries.span("SELECT", SELECT_CREATED_BEFORE);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(rows.iter().map(|row| row.try_get("id")).collect::<Result<_, _>>()?)
+    }
+
+    async fn delete_many(&self, ids: &[UserId]) -> Result<usize, StoreError> {
+        // No array parameters in SQLite; one statement per ID, committed together
+        let mut connection = self.pool.acquire().await?;
+        let mut transaction = connection.begin().await?;
+        let mut deleted = 0;
+        for id in ids {
+            let query = sqlx::query(DELETE).bind(*id);
+            let span = self.queries.span("DELETE", DELETE);
+            let result = observe(span, query.execute(&mut *transaction)).await?;
+            deleted += result.rows_affected() as usize;
+        }
+        transaction.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn names(&self) -> Result<HashMap<UserId, String>, StoreError> {
+        let span = self.queries.span("SELECT", SELECT_NAMES);
+        let query = sqlx::query(SELECT_NAMES);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(rows
+            .iter()
+            .map(|row| Ok((row.try_get("id")?, row.try_get("name")?)))
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+
+    async fn count(&self) -> Result<usize, StoreError> {
+        let span = self.queries.span("SELECT", COUNT);
+        let query = sqlx::query(COUNT);
+        let row = observe(span, async { query.fetch_one(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(row.try_get::<i64, _>(0)? as usize)
+    }
+
+    fn backend(&self) -> &'static str {
+        Self::BACKEND
+    }
+}
+
+#[async_trait::async_trait]
+impl OrderStore for SqliteRepository {
+    async fn insert(&self, order: OrderRecord) -> Result<(), StoreError> {
+        let query = sqlx::query(INSERT_ORDER)
+            .bind(order.order_id)
+            .bind(order.user_id)
+            .bind(serde_json::to_string(&order.lines)?)
+            .bind(order.total_amount.to_string())
+            .bind(order.currency.as_str())
+            .bind(order.status)
+            .bind(order.created_at)
+            .bind(order.version as i64);
+        let span = self.queries.span("INSERT", INSERT_ORDER);
+        observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        Ok(())
+    }
+
+    async fn get(&self, order_id: OrderId) -> Result<Option<OrderRecord>, StoreError> {
+        let query = sqlx::query(SELECT_ORDER).bind(order_id);
+        let span = self.queries.span("SELECT", SELECT_ORDER);
+        let row = observe(span, async { query.fetch_optional(&mut *self.pool.acquire().await?).await }).await?;
+        row.as_ref().map(order_record).transpose()
+    }
+
+    async fn cancel(&self, order_id: OrderId) -> Result<CancelOutcome, StoreError> {
+        // Re-read after losing a race, until the order is cancelled by us or someone else
+        loop {
+            let Some(mut order) = OrderStore::get(self, order_id).await? else {
+                return Ok(CancelOutcome::NotFound);
+            };
+            if order.status == orders::CANCELLED {
+                return Ok(CancelOutcome::AlreadyCancelled);
+            }
+            order.status = orders::CANCELLED.to_string();
+            if self.write_status(&mut order).await? {
+                return Ok(CancelOutcome::Cancelled(order));
+            }
+        }
+    }
+
+    async fn update_status(
+        &self,
+        order_id: OrderId,
+        status: &str,
+        if_match: &IfMatch,
+    ) -> Result<UpdateOutcome<OrderRecord>, StoreError> {
+        loop {
+            let Some(mut order) = OrderStore::get(self, order_id).await? else {
+                return Ok(UpdateOutcome::NotFound);
+            };
+            if let Some(outcome) = orders::status_precondition(&order, if_match) {
+                return Ok(outcome);
+            }
+            order.status = status.to_string();
+            if self.write_status(&mut order).await? {
+                return Ok(UpdateOutcome::Updated(order));
+            }
+        }
+    }
+
+    async fn all(&self) -> Result<Vec<OrderRecord>, StoreError> {
+        let query = sqlx::query(SELECT_ORDERS);
+        let span = self.queries.span("SELECT", SELECT_ORDERS);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        rows.iter().map(order_record).collect()
+    }
+
+    async fn for_user(
+        &self,
+        user_id: UserId,
+        after: Option<(DateTime<Utc>, OrderId)>,
+        limit: usize,
+    ) -> Result<Vec<OrderRecord>, StoreError> {
+        let statement = if after.is_some() { SELECT_USER_ORDERS_AFTER } else { SELECT_USER_ORDERS };
+        let mut query = sqlx::query(statement).bind(user_id);
+        if let Some((created_at, order_id)) = after {
+            query = query.bind(created_at).bind(order_id);
+        }
+        let query = query.bind(limit as i64 + 1);
+        let span = self.queries.span("SELECT", statement);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        rows.iter().map(order_record).collect()
+    }
+
+    async fn created_since(&self, since: DateTime<Utc>) -> Result<Vec<OrderRecord>, StoreError> {
+        let query = sqlx::query(SELECT_ORDERS_SINCE).bind(since);
+        let span = self.queries.span("SELECT", SELECT_ORDERS_SINCE);
+        let rows = observe(span, async { query.fetch_all(&mut *self.pool.acquire().await?).await }).await?;
+        rows.iter().map(order_record).collect()
+    }
+
+    fn backend(&self) -> &'static str {
+        Self::BACKEND
+    }
+}
+
+impl SqliteRepository {
+    /// Save `order`'s status under the next version, unless someone wrote since it was read
+    ///
+    /// No row locks in SQLite, so the version in the `WHERE` clause stands in for one.
+    async fn write_status(&self, order: &mut OrderRecord) -> Result<bool, StoreError> {
+        let query = sqlx::query(UPDATE_ORDER_STATUS)
+            .bind(&order.status)
+            .bind(order.version as i64 + 1)
+            .bind(order.order_id)
+            .bind(order.version as i64);
+        let span = self.queries.span("UPDATE", UPDATE_ORDER_STATUS);
+        let result = observe(span, async { query.execute(&mut *self.pool.acquire().await?).await }).await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        order.version += 1;
+        Ok(true)
+    }
+}
+
+fn order_record(row: &SqliteRow) -> Result<OrderRecord, StoreError> {
+    Ok(OrderRecord {
+        order_id: row.try_get("order_id")?,
+        user_id: row.try_get("user_id")?,
+        lines: serde_json::from_str(row.try_get("lines")?)?,
+        total_amount: row.try_get::<&str, _>("total_amount")?.parse()?,
+        currency: row.try_get::<&str, _>("currency")?.parse()?,
+        status: row.try_get("status")?,
+        created_at: row.try_get("created_at")?,
+        version: row.try_get::<i64, _>("version")? as u64,
+    })
+}
+
+fn stored_user(row: &SqliteRow) -> Result<StoredUser, sqlx::Error> {
+    let role: String = row.try_get("role")?;
+    Ok(StoredUser {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        email_ciphertext: row.try_get("email_ciphertext")?,
+        email_hash: row.try_get("email_hash")?,
+        password_hash: row.try_get("password_hash")?,
+        role: if role == Role::Admin.as_str() { Role::Admin } else { Role::User },
+        created_at: row.try_get::<DateTime<Utc>, _>("created_at")?.to_rfc3339(),
+        version: row.try_get::<i64, _>("version")? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_and_updates_users_in_memory() {
+        let repository = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        let id = UserId::generate();
+        let user = StoredUser {
+            id,
+            name: "Ada".to_string(),
+            email_ciphertext: "ciphertext".to_string(),
+            email_hash: "hash".to_string(),
+            password_hash: None,
+            role: Role::User,
+            created_at: Utc::now().to_rfc3339(),
+            version: 1,
+        };
+        Repository::insert(&repository, user).await.unwrap();
+        assert_eq!(repository.find_by_email_hash("hash").await.unwrap().map(|user| user.id), Some(id));
+
+        let updated = repository.update_name(id, "Grace", &IfMatch::default()).await.unwrap();
+        assert!(matches!(updated, UpdateOutcome::Updated(ref user) if user.name == "Grace" && user.version == 2));
+        assert_eq!(repository.created_before(Utc::now()).await.unwrap(), vec![id]);
+        assert_eq!(repository.delete_many(&[id]).await.unwrap(), 1);
+        assert_eq!(repository.count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn stores_pages_and_cancels_orders() {
+        let repository = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        let user_id = UserId::generate();
+        let started = Utc::now();
+        let mut ids = Vec::new();
+        for minute in 0..3 {
+            let order = OrderRecord {
+                order_id: OrderId::generate(),
+                user_id,
+                lines: vec![orders::OrderLine {
+                    product_id: "prod-001".to_string().try_into().unwrap(),
+                    quantity: 2,
+                    price: rust_decimal::Decimal::new(1999, 2),
+                }],
+                total_amount: rust_decimal::Decimal::new(3998, 2),
+                currency: crate::money::Currency::Eur,
+                status: "confirmed".to_string(),
+                created_at: started + chrono::Duration::minutes(minute),
+                version: 1,
+            };
+            ids.push(order.order_id);
+            OrderStore::insert(&repository, order).await.unwrap();
+        }
+
+        let stored = OrderStore::get(&repository, ids[0]).await.unwrap().unwrap();
+        assert_eq!(stored.lines[0].price, rust_decimal::Decimal::new(1999, 2));
+        assert_eq!(stored.currency, crate::money::Currency::Eur);
+        let first_page = repository.for_user(user_id, None, 2).await.unwrap();
+        assert_eq!(first_page.iter().map(|order| order.order_id).collect::<Vec<_>>(), ids);
+        let after = (first_page[1].created_at, first_page[1].order_id);
+        let rest = repository.for_user(user_id, Some(after), 2).await.unwrap();
+        assert_eq!(rest.iter().map(|order| order.order_id).collect::<Vec<_>>(), ids[2..]);
+        assert_eq!(repository.created_since(started + chrono::Duration::minutes(1)).await.unwrap().len(), 2);
+
+        assert!(matches!(repository.cancel(ids[0]).await.unwrap(), CancelOutcome::Cancelled(ref order) if order.version == 2));
+        assert!(matches!(repository.cancel(ids[0]).await.unwrap(), CancelOutcome::AlreadyCancelled));
+        let updated = repository.update_status(ids[1], "shipped", &IfMatch::default()).await.unwrap();
+        assert!(matches!(updated, UpdateOutcome::Updated(ref order) if order.status == "shipped" && order.version == 2));
+        assert!(matches!(repository.update_status(ids[0], "shipped", &IfMatch::default()).await.unwrap(), UpdateOutcome::Rejected(_)));
+    }
+}
@@ -0,0 +1,128 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use include_dir::{include_dir, Dir};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tower_http::services::ServeDir;
+use tracing::Instrument;
+
+/// Assets compiled into the binary, used when no asset directory is on disk
+static EMBEDDED_ASSETS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/static");
+
+const CACHE_CONTROL: &str = "public, max-age=300";
+
+/// Where static files are being served from
+#[derive(Debug, Clone, Copy)]
+enum AssetSource {
+    Disk,
+    Embedded,
+}
+
+impl AssetSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            AssetSource::Disk => "disk",
+            AssetSource::Embedded => "embedded",
+        }
+    }
+}
+
+/// Static file routes, to be nested under `/static`
+///
+/// Files come from `STATIC_DIR` (default `static`) when that directory exists,
+/// so assets can be edited without a rebuild; otherwise the copy embedded at
+/// build time is served.
+pub fn routes<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    let dir = PathBuf::from(std::env::var("STATIC_DIR").unwrap_or_else(|_| "static".to_string()));
+
+    let (router, source) = if dir.is_dir() {
+        (
+            Router::new().fallback_service(ServeDir::new(dir)),
+            AssetSource::Disk,
+        )
+    } else {
+        (Router::new().fallback(serve_embedded), AssetSource::Embedded)
+    };
+
+    router.layer(middleware::from_fn_with_state(source, trace_static))
+}
+
+/// Serve a file from [`EMBEDDED_ASSETS`], answering `If-None-Match` with 304
+async fn serve_embedded(uri: Uri, headers: HeaderMap) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else {
+        path.to_string()
+    };
+
+    let Some(file) = EMBEDDED_ASSETS.get_file(&path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let digest = Sha256::digest(file.contents());
+    let etag = format!("\"{}\"", hex_prefix(&digest));
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        (
+            [(header::CONTENT_TYPE, mime.as_ref().to_string())],
+            file.contents(),
+        )
+            .into_response()
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+fn hex_prefix(digest: &[u8]) -> String {
+    digest.iter().take(8).map(|b| format!("{:02x}", b)).collect()
+}
+
+/// How a static request was satisfied, recorded as `http.cache_status`
+fn cache_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::NOT_MODIFIED => "revalidated",
+        StatusCode::NOT_FOUND => "not_found",
+        status if status.is_success() => "miss",
+        _ => "error",
+    }
+}
+
+/// Wrap static requests in a `static.serve` span and set `Cache-Control`
+async fn trace_static(State(source): State<AssetSource>, request: Request, next: Next) -> Response {
+    let span = tracing::info_span!(
+        "static.serve",
+        file.path = %request.uri().path(),
+        static.source = source.as_str(),
+        http.cache_status = tracing::field::Empty,
+        http.status_code = tracing::field::Empty,
+    );
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+
+    span.record("http.cache_status", cache_status(response.status()));
+    span.record("http.status_code", response.status().as_u16());
+
+    if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+        response
+            .headers_mut()
+            .entry(header::CACHE_CONTROL)
+            .or_insert(HeaderValue::from_static(CACHE_CONTROL));
+    }
+    response
+}
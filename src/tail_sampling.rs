@@ -0,0 +1,227 @@
+use axum::body::Bytes;
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+const DEFAULT_LATENCY_MS: u64 = 1000;
+
+/// Span metric holding the trace's sampling priority, as the Agent reads it
+const PRIORITY_KEY: &str = "_sampling_priority_v1";
+
+/// Sampling priority of traces the service decided to drop
+const AUTO_REJECT: f64 = 0.0;
+
+/// Sampling priority of traces the sampler kept; higher means a user decision, left alone
+const AUTO_KEEP: f64 = 1.0;
+
+/// Trace payload endpoint whose format is understood; others pass through untouched
+const TRACES_PATH: &str = "/v0.4/traces";
+
+/// What happened to one trace chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Error,
+    Slow,
+    Rejected,
+    /// Already dropped, or kept by a user decision
+    Untouched,
+}
+
+/// Tail-based sampling: keep only traces with an error or a slow span
+///
+/// Runs in the export relay, on the payloads the SDK sends to the Agent. The
+/// SDK holds every span of a trace in memory until its local root ends, then
+/// sends them together, so each decision sees the whole trace. Traces with
+/// an error span, or a span lasting at least the latency threshold, keep
+/// their priority; the rest are marked rejected. The Agent still counts
+/// rejected traces in APM stats, so request and error rates stay right, but
+/// doesn't ingest them. Traces a user rule or caller explicitly kept are left
+/// alone.
+///
+/// Configuration:
+/// - `TELEMETRY_TAIL_SAMPLING`: keep only error and slow traces (default false)
+/// - `TELEMETRY_TAIL_LATENCY_MS`: span duration that makes a trace slow (default 1000)
+#[derive(Debug)]
+pub struct TailSampler {
+    latency_ns: u64,
+    errors: AtomicU64,
+    slow: AtomicU64,
+    rejected: AtomicU64,
+    untouched: AtomicU64,
+}
+
+impl TailSampler {
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let enabled = match std::env::var("TELEMETRY_TAIL_SAMPLING") {
+            Ok(value) => value
+                .parse::<bool>()
+                .map_err(|e| format!("TELEMETRY_TAIL_SAMPLING: {}", e))?,
+            Err(_) => false,
+        };
+        if !enabled {
+            return Ok(None);
+        }
+        let latency_ms = match std::env::var("TELEMETRY_TAIL_LATENCY_MS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|e| format!("TELEMETRY_TAIL_LATENCY_MS: {}", e))?,
+            Err(_) => DEFAULT_LATENCY_MS,
+        };
+        Ok(Some(Self::new(Duration::from_millis(latency_ms))))
+    }
+
+    fn new(latency: Duration) -> Self {
+        Self {
+            latency_ns: latency.as_nanos().try_into().unwrap_or(u64::MAX),
+            errors: AtomicU64::new(0),
+            slow: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            untouched: AtomicU64::new(0),
+        }
+    }
+
+    pub fn latency(&self) -> Duration {
+        Duration::from_nanos(self.latency_ns)
+    }
+
+    /// `body` with traces that are neither failing nor slow marked rejected
+    ///
+    /// Payloads for other endpoints, or that don't decode, are returned as is.
+    pub fn apply(&self, path: &str, body: Bytes) -> Bytes {
+        if path != TRACES_PATH {
+            return body;
+        }
+        let Ok(Value::Array(mut traces)) = rmp_serde::from_slice::<Value>(&body) else {
+            return body;
+        };
+        for trace in &mut traces {
+            if let Value::Array(spans) = trace {
+                let counter = match self.decide(spans) {
+                    Verdict::Error => &self.errors,
+                    Verdict::Slow => &self.slow,
+                    Verdict::Rejected => &self.rejected,
+                    Verdict::Untouched => &self.untouched,
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        match rmp_serde::to_vec(&Value::Array(traces)) {
+            Ok(encoded) => Bytes::from(encoded),
+            Err(_) => body,
+        }
+    }
+
+    fn decide(&self, spans: &mut [Value]) -> Verdict {
+        let priority = spans.iter().find_map(|span| metric(span, PRIORITY_KEY));
+        if priority.is_some_and(|priority| priority != AUTO_KEEP) {
+            return Verdict::Untouched;
+        }
+        if spans.iter().any(|span| span["error"].as_i64().unwrap_or(0) != 0) {
+            return Verdict::Error;
+        }
+        if spans.iter().any(|span| span["duration"].as_u64().unwrap_or(0) >= self.latency_ns) {
+            return Verdict::Slow;
+        }
+        for span in spans.iter_mut() {
+            set_metric(span, PRIORITY_KEY, AUTO_REJECT);
+        }
+        Verdict::Rejected
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "latency_ms": self.latency().as_millis() as u64,
+            "kept_errors": self.errors.load(Ordering::Relaxed),
+            "kept_slow": self.slow.load(Ordering::Relaxed),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+            "untouched": self.untouched.load(Ordering::Relaxed),
+        })
+    }
+}
+
+fn metric(span: &Value, key: &str) -> Option<f64> {
+    span["metrics"][key].as_f64()
+}
+
+fn set_metric(span: &mut Value, key: &str, value: f64) {
+    let Some(fields) = span.as_object_mut() else {
+        return;
+    };
+    let metrics = fields.entry("metrics").or_insert_with(|| Value::Object(Map::new()));
+    if let Some(metrics) = metrics.as_object_mut() {
+        metrics.insert(key.to_string(), Value::from(value));
+    }
+}
+
+static SAMPLER: OnceLock<Arc<TailSampler>> = OnceLock::new();
+
+/// Apply `sampler` to trace payloads in the export relay from now on
+pub fn install(sampler: TailSampler) {
+    let _ = SAMPLER.set(Arc::new(sampler));
+}
+
+/// `body` after the installed sampler, or unchanged without one
+pub fn apply(path: &str, body: Bytes) -> Bytes {
+    match SAMPLER.get() {
+        Some(sampler) => sampler.apply(path, body),
+        None => body,
+    }
+}
+
+/// Tail sampling decisions for `/debug/telemetry`
+pub fn snapshot() -> serde_json::Value {
+    match SAMPLER.get() {
+        Some(sampler) => sampler.snapshot(),
+        None => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(duration_ns: u64, error: i64, priority: Option<f64>) -> Value {
+        let mut metrics = Map::new();
+        if let Some(priority) = priority {
+            metrics.insert(PRIORITY_KEY.to_string(), Value::from(priority));
+        }
+        serde_json::json!({
+            "name": "api.request",
+            "span_id": u64::MAX,
+            "duration": duration_ns,
+            "error": error,
+            "metrics": metrics,
+        })
+    }
+
+    #[test]
+    fn keeps_error_and_slow_traces_and_rejects_the_rest() {
+        let sampler = TailSampler::new(Duration::from_millis(100));
+        let traces = serde_json::json!([
+            [span(5_000_000, 0, Some(AUTO_KEEP)), span(1_000_000, 1, None)],
+            [span(250_000_000, 0, Some(AUTO_KEEP))],
+            [span(5_000_000, 0, Some(AUTO_KEEP)), span(1_000_000, 0, None)],
+            [span(5_000_000, 0, Some(2.0))],
+        ]);
+        let body = rmp_serde::to_vec(&traces).unwrap();
+
+        let sampled = sampler.apply(TRACES_PATH, Bytes::from(body));
+        let traces: Value = rmp_serde::from_slice(&sampled).unwrap();
+        assert_eq!(traces[0][0]["span_id"], u64::MAX);
+        let priorities = |trace: &Value| -> Vec<Option<f64>> {
+            trace.as_array().unwrap().iter().map(|span| metric(span, PRIORITY_KEY)).collect()
+        };
+        assert_eq!(priorities(&traces[0]), vec![Some(AUTO_KEEP), None]);
+        assert_eq!(priorities(&traces[1]), vec![Some(AUTO_KEEP)]);
+        assert_eq!(priorities(&traces[2]), vec![Some(AUTO_REJECT), Some(AUTO_REJECT)]);
+        assert_eq!(priorities(&traces[3]), vec![Some(2.0)]);
+        assert_eq!(
+            sampler.snapshot(),
+            serde_json::json!({ "latency_ms": 100, "kept_errors": 1, "kept_slow": 1, "rejected": 1, "untouched": 1 })
+        );
+
+        // Other endpoints pass through byte for byte
+        assert_eq!(sampler.apply("/v0.6/stats", Bytes::from_static(b"\x90")), Bytes::from_static(b"\x90"));
+    }
+}
@@ -1,86 +1,441 @@
-use datadog_opentelemetry;
+use crate::attribute_filter::{AttributeFilter, FilteringTracer};
+use crate::cardinality_guard::{self, CardinalityGuard};
+use crate::console_exporter::{self, ConsoleExporter};
+use crate::export_failover::{self, ExportFailover};
+use crate::export_fallback::{self, ExportFallback};
+use crate::export_mirror::{self, ExportMirror};
+use crate::propagation::{self, BaggageAttributes, Propagation};
+use crate::sampling::{self, RouteSampler};
+use crate::span_dedup::{self, DedupFilter, DedupLayer, SpanDedup};
+use crate::span_rules::{self, SpanRules};
+use crate::span_tap::SpanTap;
+use crate::tail_sampling::{self, TailSampler};
+use datadog_opentelemetry::configuration::{Config, SamplingRuleConfig};
 use opentelemetry::global;
-use opentelemetry_sdk::trace::SdkTracerProvider;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{SdkTracerProvider, ShouldSample, SimpleSpanProcessor};
+use std::sync::OnceLock;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
-/// Initialize Datadog APM with OpenTelemetry
+/// Service identity and Agent location the telemetry was set up with
 ///
-/// This function uses Datadog's official OpenTelemetry SDK for Rust.
-/// Configuration is done via DD_* environment variables.
+/// Logs, Datadog events and the export relay read these, so they always
+/// agree with what the tracer reports.
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub service: String,
+    pub version: String,
+    pub env: String,
+    pub agent_host: String,
+    pub agent_port: u16,
+    /// Where traces go: `DD_TRACE_AGENT_URL`, else `http://agent_host:agent_port`
+    pub agent_url: String,
+}
+
+impl TelemetryConfig {
+    fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok();
+        let mut config = Self {
+            service: var("DD_SERVICE").unwrap_or_else(|| "rust-datadog-otel".to_string()),
+            version: var("DD_VERSION").unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string()),
+            env: var("DD_ENV").unwrap_or_else(|| "development".to_string()),
+            agent_host: var("DD_AGENT_HOST")
+                .or_else(|| var("HOST_IP"))
+                .unwrap_or_else(|| "localhost".to_string()),
+            agent_port: var("DD_TRACE_AGENT_PORT")
+                .or_else(|| var("DD_AGENT_PORT"))
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(DEFAULT_AGENT_PORT),
+            agent_url: String::new(),
+        };
+        config.agent_url = var("DD_TRACE_AGENT_URL").unwrap_or_else(|| config.host_url());
+        config
+    }
+
+    fn host_url(&self) -> String {
+        format!("http://{}:{}", self.agent_host, self.agent_port)
+    }
+}
+
+const DEFAULT_AGENT_PORT: u16 = 8126;
+
+static CONFIG: OnceLock<TelemetryConfig> = OnceLock::new();
+
+/// The configuration telemetry was initialized with, or the env defaults before that
+pub fn config() -> &'static TelemetryConfig {
+    CONFIG.get_or_init(TelemetryConfig::from_env)
+}
+
+/// Configure telemetry in code
+///
+/// Anything not set falls back to its environment variable, then to the
+/// default: `DD_SERVICE` (`rust-datadog-otel`), `DD_VERSION` (the crate
+/// version), `DD_ENV` (`development`), `RUST_LOG`, `DD_AGENT_HOST` or `HOST_IP`
+/// (`localhost`), `DD_TRACE_AGENT_PORT` or `DD_AGENT_PORT` (8126). An Agent
+/// host or port set in code also overrides `DD_TRACE_AGENT_URL`. The sample
+/// rate falls back to `DD_TRACE_SAMPLE_RATE`, and applies to traces no
+/// `DD_TRACE_SAMPLING_RULES` rule matches. A sampler set in code
+/// replaces `OTEL_TRACES_SAMPLER`. An OTLP mirror set in code is used whatever
+/// `TELEMETRY_OTLP_MIRROR` says.
 ///
-/// Returns the tracer provider which must be shutdown before exit to flush traces.
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use opentelemetry_sdk::trace::Sampler;
+///
+/// let tracer_provider = rust_datadog_otel::telemetry::TelemetryBuilder::new()
+///     .service("checkout")
+///     .version("1.4.2")
+///     .agent_host("datadog-agent.monitoring")
+///     .sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(0.2))))
+///     .init()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct TelemetryBuilder {
+    service: Option<String>,
+    version: Option<String>,
+    env: Option<String>,
+    agent_host: Option<String>,
+    agent_port: Option<u16>,
+    sample_rate: Option<f64>,
+    sampler: Option<Box<dyn ShouldSample>>,
+    route_sample_rates: Option<String>,
+    log_level: Option<String>,
+    otlp_mirror: Option<String>,
+    console_exporter: Option<bool>,
+    span_tap: Option<SpanTap>,
+}
+
+impl TelemetryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn service(mut self, service: impl Into<String>) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn env(mut self, env: impl Into<String>) -> Self {
+        self.env = Some(env.into());
+        self
+    }
+
+    pub fn agent_host(mut self, host: impl Into<String>) -> Self {
+        self.agent_host = Some(host.into());
+        self
+    }
+
+    pub fn agent_port(mut self, port: u16) -> Self {
+        self.agent_port = Some(port);
+        self
+    }
+
+    /// Keep this share of traces, from 0.0 to 1.0, as a catch-all sampling rule
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    /// Decide which spans to keep with `sampler`, before the Datadog SDK's sampling
+    ///
+    /// Any OpenTelemetry [`ShouldSample`]: a built-in `Sampler` (always on,
+    /// always off, trace ID ratio, parent based) or a custom implementation.
+    /// It sees each span's exported name and attributes. Spans it keeps still
+    /// go through Datadog's sampling rules and rate limits.
+    pub fn sampler(mut self, sampler: impl ShouldSample + 'static) -> Self {
+        self.sampler = Some(Box::new(sampler));
+        self
+    }
+
+    /// Per-route sample rates, as in `TRACE_ROUTE_SAMPLE_RATES` (see [`RouteSampler`])
+    ///
+    /// Root spans no rule matches go to the [`sampler`](Self::sampler), if any.
+    pub fn route_sample_rates(mut self, rules: impl Into<String>) -> Self {
+        self.route_sample_rates = Some(rules.into());
+        self
+    }
+
+    /// Log filter directives, as in `RUST_LOG` (default `info,rust_datadog_otel=debug`)
+    pub fn log_level(mut self, directives: impl Into<String>) -> Self {
+        self.log_level = Some(directives.into());
+        self
+    }
+
+    /// Also export every span to the OTLP/HTTP collector at `endpoint`, e.g. `http://otel-collector:4318`
+    pub fn otlp_mirror(mut self, endpoint: impl Into<String>) -> Self {
+        self.otlp_mirror = Some(endpoint.into());
+        self
+    }
+
+    /// Print finished spans to stdout instead of sending them to the Agent, as `DD_TRACE_DEBUG_EXPORTER=console` does
+    pub fn console_exporter(mut self, enabled: bool) -> Self {
+        self.console_exporter = Some(enabled);
+        self
+    }
+
+    /// Copy finished spans to `span_tap`, for a live span feed
+    pub fn span_tap(mut self, span_tap: SpanTap) -> Self {
+        self.span_tap = Some(span_tap);
+        self
+    }
+
+    /// Initialize Datadog APM with OpenTelemetry
+    ///
+    /// This uses Datadog's official OpenTelemetry SDK for Rust, which reads
+    /// its other `DD_*` environment variables itself. Traces go through
+    /// `export_failover`'s relay, which reconnects after an Agent restart,
+    /// unless `TELEMETRY_RECONNECT=false` and no failover, fallback or tail
+    /// sampling is set.
+    ///
+    /// Returns the tracer provider which must be shutdown before exit to flush traces.
+    ///
+    /// Reference: https://docs.datadoghq.com/tracing/trace_collection/custom_instrumentation/rust
+    pub fn init(self) -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+        let defaults = TelemetryConfig::from_env();
+        let agent_in_code = self.agent_host.is_some() || self.agent_port.is_some();
+        let mut config = TelemetryConfig {
+            service: self.service.unwrap_or(defaults.service),
+            version: self.version.unwrap_or(defaults.version),
+            env: self.env.unwrap_or(defaults.env),
+            agent_host: self.agent_host.unwrap_or(defaults.agent_host),
+            agent_port: self.agent_port.unwrap_or(defaults.agent_port),
+            agent_url: defaults.agent_url,
+        };
+        // An Agent set in code beats DD_TRACE_AGENT_URL too
+        if agent_in_code {
+            config.agent_url = config.host_url();
+        }
+        let sample_rate = match self.sample_rate {
+            Some(rate) => Some(rate),
+            None => sampling::sample_rate_from_env()?,
+        };
+        if let Some(rate) = sample_rate {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(format!("sample rate must be between 0 and 1, got {}", rate).into());
+            }
+        }
+        let mut sampling_rules = sampling::datadog_rules_from_env()?;
+        let console = match self.console_exporter {
+            Some(console) => console,
+            None => console_exporter::from_env()?,
+        };
+        let sampler = match self.sampler {
+            Some(sampler) => Some(sampler),
+            None => sampling::from_env()?.map(|sampler| Box::new(sampler) as Box<dyn ShouldSample>),
+        };
+        let route_sampler = match self.route_sample_rates {
+            Some(rules) => Some(RouteSampler::parse(&rules).map_err(|e| format!("route sample rates: {}", e))?),
+            None => RouteSampler::from_env()?,
+        };
+        let route_rules = route_sampler.as_ref().map_or(0, RouteSampler::len);
+        let sampler = match route_sampler {
+            Some(routes) => {
+                let routes = match sampler {
+                    Some(fallback) => routes.with_fallback(fallback),
+                    None => routes,
+                };
+                Some(Box::new(routes) as Box<dyn ShouldSample>)
+            }
+            None => sampler,
+        };
+        let cardinality_guard = CardinalityGuard::from_env()?;
+        let span_dedup = span_dedup::install(SpanDedup::from_env()?);
+        let span_rules = span_rules::install(SpanRules::from_env()?);
+        let log_level = self
+            .log_level
+            .or_else(|| std::env::var("RUST_LOG").ok())
+            .unwrap_or_else(|| "info,rust_datadog_otel=debug".to_string());
+        let env_filter = EnvFilter::try_new(&log_level).map_err(|e| format!("log level '{}': {}", log_level, e))?;
+
+        println!("Initializing Datadog APM");
+        println!("  Service: {}", config.service);
+        println!("  Version: {}", config.version);
+        println!("  Environment: {}", config.env);
+        println!("  Agent: {}", config.agent_url);
+        if let Some(rate) = sample_rate {
+            println!("  Sample rate: {}", rate);
+        }
+        if !sampling_rules.is_empty() {
+            println!("  Sampling rules: {}", sampling_rules.len());
+        }
+        if route_rules > 0 {
+            println!("  Route sample rates: {}", route_rules);
+        } else if let Some(sampler) = &sampler {
+            println!("  Sampler: {:?}", sampler);
+        }
+        println!("  Using: datadog-opentelemetry SDK v0.2.1");
+        if CONFIG.get().is_some() {
+            return Err("telemetry is already initialized".into());
+        }
+
+        let propagation = Propagation::from_env()?;
+        println!(
+            "  Propagation: extract [{}], inject [{}]",
+            propagation.extract_styles().collect::<Vec<_>>().join(", "),
+            propagation.inject_styles().collect::<Vec<_>>().join(", ")
+        );
+        propagation::install(propagation);
+
+        // Code settings override what the SDK reads from DD_* env vars
+        let mut sdk_config = Config::builder();
+        sdk_config
+            .set_service(config.service.clone())
+            .set_version(config.version.clone())
+            .set_env(config.env.clone())
+            .set_trace_agent_url(config.agent_url.clone());
+        // Rules first; the sample rate is the catch-all after them, as in the other Datadog SDKs
+        if let Some(sample_rate) = sample_rate {
+            sampling_rules.push(SamplingRuleConfig {
+                sample_rate,
+                provenance: "customer".to_string(),
+                ..Default::default()
+            });
+        }
+        if !sampling_rules.is_empty() {
+            sdk_config.set_trace_sampling_rules(sampling_rules);
+        }
+        let mirror = match self.otlp_mirror {
+            Some(endpoint) => Some(ExportMirror::new(export_fallback::traces_url(&endpoint))?),
+            None => ExportMirror::from_env()?,
+        };
+        let (fallback, failover) = if console {
+            // Nothing goes to the Agent, so there is nothing to fail over or fall back from
+            let null_agent_url = console_exporter::start_null_agent()?;
+            console_exporter::enable();
+            println!("  Exporter: console, spans print to stdout (no Agent needed)");
+            sdk_config.set_trace_agent_url(null_agent_url);
+            (None, None)
+        } else {
+            let fallback = ExportFallback::from_env()?;
+            let tail_sampler = TailSampler::from_env()?;
+            let failover = match ExportFailover::from_env(&config.agent_url)? {
+                Some(failover) => Some(failover),
+                // The fallback needs the relay to tell when exports fail, tail sampling to see payloads
+                None if fallback.is_some() || tail_sampler.is_some() => Some(ExportFailover::agent_only(&config.agent_url)?),
+                None => None,
+            };
+            if let Some(tail_sampler) = tail_sampler {
+                println!("  Tail sampling: errors and spans over {:?}", tail_sampler.latency());
+                tail_sampling::install(tail_sampler);
+            }
+            (fallback, failover)
+        };
+        if let Some(failover) = failover {
+            // The SDK exports to a local relay, which picks the Agent or the collector
+            let relay_url = export_failover::install(failover)?;
+            println!("  Export relay: {}", relay_url);
+            sdk_config.set_trace_agent_url(relay_url);
+        }
+        // Only once nothing can fail, so a rejected configuration can be fixed and retried
+        if CONFIG.set(config).is_err() {
+            return Err("telemetry is already initialized".into());
+        }
+
+        // Initializes the global tracer provider
+        let mut tracing = datadog_opentelemetry::tracing().with_config(sdk_config.build());
+        if let Some(span_tap) = self.span_tap {
+            tracing = tracing.with_span_processor(span_tap);
+        }
+        if console {
+            // Exported as each span ends, so output follows the requests
+            tracing = tracing.with_span_processor(SimpleSpanProcessor::new(ConsoleExporter));
+        }
+        if let Some(mirror) = mirror {
+            // Its own batch queue, so either backend can fail without holding up the other
+            println!("  OTLP mirror: {}", mirror.url());
+            tracing = tracing.with_span_processor(export_mirror::install(mirror));
+        }
+        if let Some(fallback) = fallback {
+            println!("  Export fallback: {}", fallback.target().name());
+            tracing = tracing.with_span_processor(export_fallback::install(fallback));
+        }
+        let tracer_provider = tracing.init();
+
+        // Get tracer from the global provider (official pattern)
+        // Wrapped so span rules, attribute allow/deny lists and cardinality limits are enforced before export
+        let attribute_filter = AttributeFilter::from_env();
+        if attribute_filter.is_active() {
+            println!("  Span attribute filtering: enabled");
+        }
+        if !span_rules.is_empty() {
+            println!("  Span rules: {}", span_rules.len());
+        }
+        let mut tracer = FilteringTracer::new(
+            global::tracer("rust-datadog-otel"),
+            span_rules,
+            attribute_filter,
+            cardinality_guard::install(cardinality_guard),
+        );
+        if let Some(sampler) = sampler {
+            tracer = tracer.with_sampler(sampler);
+        }
+        let baggage_attributes = BaggageAttributes::from_env();
+        if !baggage_attributes.is_empty() {
+            println!("  Baggage attributes: {}", baggage_attributes.len());
+            tracer = tracer.with_baggage_attributes(baggage_attributes);
+        }
+
+        // Create tracing layer with OpenTelemetry
+        // Filtered so request spans suppressed by TELEMETRY_SPAN_DEDUP stay out of traces, not logs
+        let telemetry_layer = tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(DedupFilter::new(span_dedup.clone()));
+
+        // Create logging layer with JSON formatting for Datadog log correlation
+        // Initialize tracing subscriber with both layers
+        tracing_subscriber::registry()
+            .with(env_filter)
+            .with(telemetry_layer)
+            .with(DedupLayer::new(span_dedup))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)  // ✅ Flatten fields to root level for Datadog
+                    .with_current_span(true)
+                    .with_span_list(true)
+                    .with_target(true)
+                    .with_thread_ids(true)
+                    .with_thread_names(true)
+            )
+            .init();
+
+        println!("Datadog APM initialized successfully");
+
+        Ok(tracer_provider)
+    }
+}
+
+/// Initialize telemetry from the environment alone
 ///
-/// Reference: https://docs.datadoghq.com/tracing/trace_collection/custom_instrumentation/rust
+/// Shorthand for `TelemetryBuilder::new().init()`; use [`TelemetryBuilder::span_tap`]
+/// to also feed a live span stream.
 pub fn init_telemetry() -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
-    // Get configuration from environment variables
-    let service_name = std::env::var("DD_SERVICE")
-        .unwrap_or_else(|_| "rust-datadog-otel".to_string());
-    
-    let service_version = std::env::var("DD_VERSION")
-        .unwrap_or_else(|_| env!("CARGO_PKG_VERSION").to_string());
-    
-    let deployment_environment = std::env::var("DD_ENV")
-        .unwrap_or_else(|_| "development".to_string());
-
-    let dd_agent_host = std::env::var("DD_AGENT_HOST")
-        .or_else(|_| std::env::var("HOST_IP"))
-        .unwrap_or_else(|_| "localhost".to_string());
-
-    println!("Initializing Datadog APM");
-    println!("  Service: {}", service_name);
-    println!("  Version: {}", service_version);
-    println!("  Environment: {}", deployment_environment);
-    println!("  Agent Host: {}", dd_agent_host);
-    println!("  Using: datadog-opentelemetry SDK v0.2.1");
-
-    // Initialize the Datadog tracer provider using the official SDK
-    // This picks up DD_* env var configuration and initializes the global tracer provider
-    let tracer_provider = datadog_opentelemetry::tracing()
-        .init();
-
-    // Get tracer from the global provider (official pattern)
-    let tracer = global::tracer("rust-datadog-otel");
-
-    // Create tracing layer with OpenTelemetry
-    let telemetry_layer = tracing_opentelemetry::layer().with_tracer(tracer);
-
-    // Create logging layer with JSON formatting for Datadog log correlation
-    let log_level = std::env::var("RUST_LOG")
-        .unwrap_or_else(|_| "info,rust_datadog_otel=debug".to_string());
-    
-    let env_filter = EnvFilter::try_from_default_env()
-        .or_else(|_| EnvFilter::try_new(&log_level))
-        .unwrap();
-
-    // Initialize tracing subscriber with both layers
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(telemetry_layer)
-        .with(
-            tracing_subscriber::fmt::layer()
-                .json()
-                .flatten_event(true)  // ✅ Flatten fields to root level for Datadog
-                .with_current_span(true)
-                .with_span_list(true)
-                .with_target(true)
-                .with_thread_ids(true)
-                .with_thread_names(true)
-        )
-        .init();
-
-    println!("Datadog APM initialized successfully");
-
-    Ok(tracer_provider)
+    TelemetryBuilder::new().init()
 }
 
 /// Shutdown OpenTelemetry gracefully
 ///
-/// This ensures all pending traces are flushed to the Datadog Agent before exit
-pub fn shutdown_telemetry(tracer_provider: SdkTracerProvider) {
-    println!("Shutting down telemetry...");
-    match tracer_provider.shutdown() {
-        Ok(_) => println!("Telemetry shutdown complete"),
-        Err(e) => eprintln!("Error shutting down telemetry: {:?}", e),
-    }
+/// This ensures all pending traces are flushed to the Datadog Agent before exit.
+/// Blocks until the flush finishes, so call it off the async runtime.
+pub fn shutdown_telemetry(tracer_provider: SdkTracerProvider) -> OTelSdkResult {
+    tracer_provider.shutdown()
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_out_of_range_sample_rates() {
+        let error = TelemetryBuilder::new().sample_rate(1.5).init().unwrap_err();
+        assert_eq!(error.to_string(), "sample rate must be between 0 and 1, got 1.5");
+    }
+}
@@ -1,13 +1,43 @@
-use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::KeyValue;
+use std::sync::OnceLock;
 use tracing::Span;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+/// A span's IDs in the formats Datadog and other tools expect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceIds {
+    /// Datadog log correlation format: the lower 64 bits in decimal, or 32 hex
+    /// digits for 128-bit IDs when 128-bit logging is on
+    pub trace_id: String,
+    /// Datadog format: decimal
+    pub span_id: String,
+    /// OpenTelemetry format, as Jaeger and Grafana Tempo show it: 32 hex digits
+    pub trace_id_hex: String,
+    /// OpenTelemetry format: 16 hex digits
+    pub span_id_hex: String,
+    /// Whether the trace is sampled, so its spans are exported
+    pub sampled: bool,
+}
+
 /// Extract current trace context for Datadog correlation
 ///
-/// Returns (trace_id, span_id) in Datadog-compatible decimal format
-pub fn current_trace_context() -> Option<(String, String)> {
-    let current_span = Span::current();
-    let context = current_span.context();
+/// The Datadog IDs default to the trace ID's lower 64 bits in decimal. With
+/// 128-bit logging on, trace IDs with a non-zero upper half are 32 lowercase
+/// hex digits, which Datadog matches against the full ID (its upper half
+/// travels as `_dd.p.tid` between services); 64-bit IDs stay decimal either
+/// way. The hex IDs are always the full OpenTelemetry ones, for links to
+/// other tools.
+///
+/// Configuration:
+/// - `DD_TRACE_128_BIT_TRACEID_LOGGING_ENABLED`: log full 128-bit trace IDs (default false)
+pub fn current_trace_context() -> Option<TraceIds> {
+    trace_ids(&Span::current())
+}
+
+/// IDs of `span`, or `None` when it isn't traced
+pub fn trace_ids(span: &Span) -> Option<TraceIds> {
+    let context = span.context();
     let otel_context = context.span();
     let span_context = otel_context.span_context();
 
@@ -15,24 +45,134 @@ pub fn current_trace_context() -> Option<(String, String)> {
         return None;
     }
 
-    // Convert OpenTelemetry trace_id (128-bit) to Datadog format (lower 64-bit decimal)
-    let trace_id_bytes = span_context.trace_id().to_bytes();
-    let trace_id_lower = u64::from_be_bytes([
-        trace_id_bytes[8],
-        trace_id_bytes[9],
-        trace_id_bytes[10],
-        trace_id_bytes[11],
-        trace_id_bytes[12],
-        trace_id_bytes[13],
-        trace_id_bytes[14],
-        trace_id_bytes[15],
-    ]);
+    Some(TraceIds {
+        trace_id: format_trace_id(span_context.trace_id(), log_128_bit_trace_ids()),
+        span_id: u64::from_be_bytes(span_context.span_id().to_bytes()).to_string(),
+        trace_id_hex: span_context.trace_id().to_string(),
+        span_id_hex: span_context.span_id().to_string(),
+        sampled: span_context.is_sampled(),
+    })
+}
 
-    // Convert span_id to decimal
-    let span_id_bytes = span_context.span_id().to_bytes();
-    let span_id_decimal = u64::from_be_bytes(span_id_bytes);
+fn log_128_bit_trace_ids() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var("DD_TRACE_128_BIT_TRACEID_LOGGING_ENABLED")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(false)
+    })
+}
 
-    Some((trace_id_lower.to_string(), span_id_decimal.to_string()))
+/// `trace_id` as Datadog logs expect it: 32 hex digits when `full` and the
+/// upper half is set, else the lower 64 bits in decimal
+fn format_trace_id(trace_id: TraceId, full: bool) -> String {
+    let id = u128::from_be_bytes(trace_id.to_bytes());
+    if full && id >> 64 != 0 {
+        format!("{:032x}", id)
+    } else {
+        (id as u64).to_string()
+    }
+}
+
+/// Span context naming another trace's span, for a span link
+///
+/// Takes IDs as either tool shows them: Datadog's decimal IDs (the trace ID's
+/// lower 64 bits, as logged by default) or OpenTelemetry's hex IDs, 32 digits
+/// for a trace and 16 for a span. Each ID is read on its own, so a 128-bit hex
+/// trace ID with a decimal span ID works too. A span ID of exactly 16 digits is
+/// read as hex. `None` when either ID doesn't parse or is zero.
+pub fn remote_span_context(trace_id: &str, span_id: &str) -> Option<SpanContext> {
+    let trace_id = parse_trace_id(trace_id.trim())?;
+    let span_id = parse_span_id(span_id.trim())?;
+    let span_context = SpanContext::new(trace_id, span_id, TraceFlags::SAMPLED, true, TraceState::default());
+    span_context.is_valid().then_some(span_context)
+}
+
+fn parse_trace_id(id: &str) -> Option<TraceId> {
+    if id.len() == 32 {
+        TraceId::from_hex(id).ok()
+    } else {
+        id.parse::<u64>().ok().map(|id| TraceId::from(id as u128))
+    }
+}
+
+fn parse_span_id(id: &str) -> Option<SpanId> {
+    if id.len() == 16 {
+        SpanId::from_hex(id).ok()
+    } else {
+        id.parse::<u64>().ok().map(SpanId::from)
+    }
+}
+
+/// Link `span` to another trace's span, e.g. a job back to the request that queued it
+///
+/// The IDs are parsed as in [`remote_span_context`]; returns whether they
+/// did. Links can be added at any time before the span closes, and show in
+/// Datadog as a span's "Span Links".
+pub fn link(span: &Span, trace_id: &str, span_id: &str, attributes: Vec<KeyValue>) -> bool {
+    match remote_span_context(trace_id, span_id) {
+        Some(span_context) => {
+            span.add_link_with_attributes(span_context, attributes);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Link a span to another trace by IDs in Datadog decimal or OpenTelemetry hex form
+///
+/// Links the current span unless one is given first, with optional link
+/// attributes, and evaluates to whether the IDs parsed:
+///
+/// ```
+/// use rust_datadog_otel::link_trace;
+///
+/// let span = tracing::info_span!("job.run");
+/// link_trace!("7277407061855694839", "5208512171318403364");
+/// link_trace!(span, "640cfd8d0000000064fe8b2a57d3eff7", "48485a3953bb6124", "link.reason" = "queued_by");
+/// ```
+#[macro_export]
+macro_rules! link_trace {
+    ($trace_id:expr, $span_id:expr $(, $key:literal = $value:expr)* $(,)?) => {
+        $crate::link_trace!(&$crate::__tracing::Span::current(), $trace_id, $span_id $(, $key = $value)*)
+    };
+    ($span:expr, $trace_id:expr, $span_id:expr $(, $key:literal = $value:expr)* $(,)?) => {
+        $crate::trace_context::link(
+            &$span,
+            &$trace_id.to_string(),
+            &$span_id.to_string(),
+            vec![$($crate::__opentelemetry::KeyValue::new($key, $value)),*],
+        )
+    };
+}
+
+/// Open a span named the Datadog way: operation, resource and span type
+///
+/// Sets `operation.name`, `resource.name` and `span.type`, which Datadog maps
+/// to the span's operation, its resource (the row in the service's resource
+/// list) and its type (`web`, `http`, `sql`, `cache`, `queue`, `worker` or
+/// `custom`). Extra fields follow as in `tracing::info_span!`. The span is a
+/// child of the current one; enter it or `instrument` a future with it:
+///
+/// ```
+/// use rust_datadog_otel::start_span;
+///
+/// let order_id = 42;
+/// let span = start_span!("checkout.charge", "POST /v1/charges", "http", order.id = order_id);
+/// let _guard = span.enter();
+/// ```
+#[macro_export]
+macro_rules! start_span {
+    ($name:expr, $resource:expr, $span_type:expr $(, $($fields:tt)+)?) => {
+        $crate::__tracing::info_span!(
+            $name,
+            operation.name = $name,
+            resource.name = %$resource,
+            span.type = $span_type,
+            $($($fields)+)?
+        )
+    };
 }
 
 /// Macro to add Datadog trace context to logs
@@ -40,17 +180,17 @@ pub fn current_trace_context() -> Option<(String, String)> {
 macro_rules! log_with_trace {
     // Pass through all arguments to tracing, but add Datadog fields
     ($level:ident, $($arg:tt)+) => {
-        if let Some((trace_id, span_id)) = $crate::trace_context::current_trace_context() {
-            tracing::$level!(
-                dd.trace_id = %trace_id,
-                dd.span_id = %span_id,
-                dd.service = %std::env::var("DD_SERVICE").unwrap_or_else(|_| "rust-datadog-otel".to_string()),
-                dd.env = %std::env::var("DD_ENV").unwrap_or_else(|_| "development".to_string()),
-                dd.version = %std::env::var("DD_VERSION").unwrap_or_else(|_| "0.1.0".to_string()),
+        if let Some(ids) = $crate::trace_context::current_trace_context() {
+            $crate::__tracing::$level!(
+                dd.trace_id = %ids.trace_id,
+                dd.span_id = %ids.span_id,
+                dd.service = %$crate::telemetry::config().service,
+                dd.env = %$crate::telemetry::config().env,
+                dd.version = %$crate::telemetry::config().version,
                 $($arg)+
             );
         } else {
-            tracing::$level!($($arg)+);
+            $crate::__tracing::$level!($($arg)+);
         }
     };
 }
@@ -76,3 +216,51 @@ macro_rules! debug_trace {
     ($($arg:tt)+) => { $crate::log_with_trace!(debug, $($arg)+) };
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_without_trace_context_outside_spans() {
+        assert_eq!(current_trace_context(), None);
+        // The macros expand without `tracing` in scope at the call site
+        crate::info_trace!(answer = 42, "Logged outside any span");
+    }
+
+    #[test]
+    fn formats_128_bit_trace_ids_as_hex_when_enabled() {
+        let trace_id = TraceId::from_hex("640cfd8d0000000064fe8b2a57d3eff7").unwrap();
+        assert_eq!(format_trace_id(trace_id, false), "7277407061855694839");
+        assert_eq!(format_trace_id(trace_id, true), "640cfd8d0000000064fe8b2a57d3eff7");
+
+        let trace_id = TraceId::from_hex("64fe8b2a57d3eff7").unwrap();
+        assert_eq!(format_trace_id(trace_id, true), "7277407061855694839");
+    }
+
+    #[test]
+    fn start_span_sets_datadog_fields() {
+        let span = crate::start_span!("orders.price", format!("price {}", "order"), "custom", order.id = 7);
+        let span = span.metadata().map(|metadata| (metadata.name(), metadata.fields().iter().map(|field| field.name()).collect::<Vec<_>>()));
+        let fields = ["operation.name", "resource.name", "span.type", "order.id"];
+        assert_eq!(span, Some(("orders.price", fields.to_vec())));
+    }
+
+    #[test]
+    fn links_accept_datadog_and_opentelemetry_ids() {
+        let datadog = remote_span_context("7277407061855694839", "5208512171318403364").unwrap();
+        assert_eq!(datadog.trace_id().to_string(), "000000000000000064fe8b2a57d3eff7");
+        assert_eq!(datadog.span_id().to_string(), "48485a3953bb6124");
+        assert!(datadog.is_remote());
+
+        let otel = remote_span_context("640cfd8d0000000064fe8b2a57d3eff7", "48485a3953bb6124").unwrap();
+        assert_eq!(otel.trace_id().to_string(), "640cfd8d0000000064fe8b2a57d3eff7");
+        assert_eq!(otel.span_id(), datadog.span_id());
+        let mixed = remote_span_context("640cfd8d0000000064fe8b2a57d3eff7", "5208512171318403364").unwrap();
+        assert_eq!(mixed, otel);
+
+        assert_eq!(remote_span_context("0", "1"), None);
+        assert_eq!(remote_span_context("abc", "1"), None);
+        assert!(!crate::link_trace!(tracing::Span::none(), "1", "not-an-id"));
+        assert!(crate::link_trace!("1", 2u64, "link.reason" = "queued_by"));
+    }
+}
@@ -0,0 +1,472 @@
+use crate::ids::{IdPath, UserId};
+use crate::negotiation::ResponseFormat;
+use crate::versioning::ApiVersion;
+use crate::{auth, concurrency, events, ndjson, region, repository, search, AppState};
+use axum::{
+    body::Body,
+    extract::{Extension, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use futures_util::StreamExt;
+use rust_datadog_otel::error::{AppError, ErrorResponse};
+use rust_datadog_otel::{
+    debug_trace, error_trace, info_trace, warn_trace,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct User {
+    pub id: UserId,
+    pub name: String,
+    pub email: String,
+    pub created_at: String,
+}
+
+impl From<repository::UserRecord> for User {
+    fn from(record: repository::UserRecord) -> Self {
+        Self {
+            id: record.id,
+            name: record.name,
+            email: record.email,
+            created_at: record.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateUserRequest {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateUserRequest {
+    name: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body = CreateUserRequest,
+    responses(
+        (status = 201, description = "User created", body = User),
+        (status = 400, description = "Invalid user", body = ErrorResponse),
+        (status = 409, description = "Email already registered", body = ErrorResponse),
+        (status = 500, description = "Storage failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state))]
+pub async fn create_user(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    Json(payload): Json<CreateUserRequest>,
+) -> impl IntoResponse {
+    info_trace!(
+        user_name = %payload.name,
+        user_email = %payload.email,
+        "Creating new user"
+    );
+
+    match register_user(&state, payload, None, auth::Role::User).await {
+        Ok(user) => {
+            info_trace!(user_id = %user.id, "User created successfully");
+            (StatusCode::CREATED, format.body(user)).into_response()
+        }
+        Err(e) => {
+            e.log();
+            AppError::from(e).into_response()
+        }
+    }
+}
+
+/// Why a user could not be created
+#[derive(Debug)]
+pub enum CreateUserError {
+    EmptyName,
+    EmailTaken,
+    Storage(repository::StoreError),
+}
+
+impl CreateUserError {
+    fn message(&self) -> &'static str {
+        match self {
+            CreateUserError::EmptyName => "Name cannot be empty",
+            CreateUserError::EmailTaken => "Email already registered",
+            CreateUserError::Storage(_) => "Failed to store user",
+        }
+    }
+
+    pub fn log(&self) {
+        match self {
+            CreateUserError::EmptyName => warn_trace!("User creation failed: empty name"),
+            CreateUserError::EmailTaken => {
+                warn_trace!("User creation failed: email already registered")
+            }
+            CreateUserError::Storage(e) => error_trace!(error = %e, "Failed to store user"),
+        }
+    }
+}
+
+impl From<CreateUserError> for AppError {
+    fn from(e: CreateUserError) -> Self {
+        let message = e.message().to_string();
+        match e {
+            CreateUserError::EmptyName => AppError::BadRequest(message),
+            CreateUserError::EmailTaken => AppError::Conflict(message),
+            CreateUserError::Storage(_) => AppError::Internal(message),
+        }
+    }
+}
+
+/// Validate and store a new user; shared by single and bulk creation
+pub async fn register_user(
+    state: &AppState,
+    payload: CreateUserRequest,
+    password_hash: Option<String>,
+    role: auth::Role,
+) -> Result<User, CreateUserError> {
+    // Simulate validation
+    if payload.name.is_empty() {
+        return Err(CreateUserError::EmptyName);
+    }
+
+    if state.users.email_exists(&payload.email).await {
+        return Err(CreateUserError::EmailTaken);
+    }
+
+    let record = repository::UserRecord {
+        id: UserId::generate(),
+        name: payload.name,
+        email: payload.email,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        version: 1,
+    };
+
+    state
+        .users
+        .insert(&record, password_hash, role)
+        .await
+        .map_err(CreateUserError::Storage)?;
+
+    state.events.publish(events::DomainEvent::UserCreated { user_id: record.id });
+    state.search.index(vec![user_document(&record.id, &record.name)]).await;
+    state.notifier.send_welcome(&record.email, &record.name);
+    Ok(User::from(record))
+}
+
+/// Create the operator's admin account unless its email is already registered
+#[instrument(skip_all)]
+pub async fn seed_admin(state: &AppState, account: auth::AdminAccount) -> Result<(), Box<dyn std::error::Error>> {
+    if state.users.email_exists(&account.email).await {
+        debug_trace!("Admin account already exists");
+        return Ok(());
+    }
+    let password_hash =
+        auth::hash_password(account.password.expose_secret()).map_err(|e| format!("Failed to hash admin password: {}", e))?;
+    let request = CreateUserRequest {
+        name: "Administrator".to_string(),
+        email: account.email,
+    };
+    let user = register_user(state, request, Some(password_hash), auth::Role::Admin)
+        .await
+        .map_err(|e| {
+            e.log();
+            format!("Failed to create admin account: {}", e.message())
+        })?;
+    info_trace!(user_id = %user.id, "Admin account created");
+    Ok(())
+}
+
+/// A progress event is added to the import span every this many lines
+const IMPORT_PROGRESS_INTERVAL: usize = 100;
+/// Longest accepted NDJSON line, so a missing newline can't buffer the whole body
+const IMPORT_MAX_LINE_BYTES: usize = 64 * 1024;
+/// Failures listed individually in the summary; `failed` still counts all of them
+const IMPORT_MAX_REPORTED_FAILURES: usize = 100;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportFailure {
+    /// 1-based line number in the uploaded file
+    line: usize,
+    error: String,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ImportSummary {
+    lines: usize,
+    inserted: usize,
+    failed: usize,
+    failures: Vec<ImportFailure>,
+    /// Set when the upload was cut short; lines before it were still imported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl ImportSummary {
+    fn record_failure(&mut self, line: usize, error: impl Into<String>) {
+        self.failed += 1;
+        if self.failures.len() < IMPORT_MAX_REPORTED_FAILURES {
+            self.failures.push(ImportFailure {
+                line,
+                error: error.into(),
+            });
+        }
+    }
+
+    async fn import_line(&mut self, state: &AppState, line: ndjson::Line) {
+        self.lines += 1;
+        let line_number = self.lines;
+
+        let bytes = match line {
+            ndjson::Line::Complete(bytes) => bytes,
+            ndjson::Line::TooLong => {
+                self.record_failure(
+                    line_number,
+                    format!("Line exceeds {} bytes", IMPORT_MAX_LINE_BYTES),
+                );
+                return;
+            }
+        };
+
+        // Blank lines (including a trailing newline) are not records
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+
+        match serde_json::from_slice::<CreateUserRequest>(&bytes) {
+            Ok(payload) => match register_user(state, payload, None, auth::Role::User).await {
+                Ok(_) => self.inserted += 1,
+                Err(e) => {
+                    debug_trace!(line = line_number, reason = e.message(), "Import line rejected");
+                    self.record_failure(line_number, e.message());
+                }
+            },
+            Err(e) => self.record_failure(line_number, format!("Invalid JSON: {}", e)),
+        }
+
+        if line_number % IMPORT_PROGRESS_INTERVAL == 0 {
+            info_trace!(
+                import.lines = self.lines,
+                import.inserted = self.inserted,
+                import.failed = self.failed,
+                "Import progress"
+            );
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/import",
+    tag = "users",
+    request_body(
+        content = String,
+        content_type = "application/x-ndjson",
+        description = "One `CreateUserRequest` JSON object per line"
+    ),
+    responses(
+        (status = 200, description = "Import finished; per-line failures are listed", body = ImportSummary),
+        (status = 400, description = "Upload interrupted; earlier lines were imported", body = ImportSummary),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the admin role", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, body))]
+pub async fn import_users(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    body: Body,
+) -> impl IntoResponse {
+    info_trace!("Starting bulk user import");
+
+    // Records are inserted as lines arrive rather than after buffering the upload
+    let mut summary = ImportSummary::default();
+    let mut splitter = ndjson::LineSplitter::new(IMPORT_MAX_LINE_BYTES);
+    let mut stream = body.into_data_stream();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                error_trace!(error = %e, lines = summary.lines, "Import upload interrupted");
+                summary.error = Some(format!("Failed to read request body: {}", e));
+                return (StatusCode::BAD_REQUEST, format.body(summary)).into_response();
+            }
+        };
+        for line in splitter.push(&chunk) {
+            summary.import_line(&state, line).await;
+        }
+    }
+    if let Some(line) = splitter.finish() {
+        summary.import_line(&state, line).await;
+    }
+
+    info_trace!(
+        import.lines = summary.lines,
+        import.inserted = summary.inserted,
+        import.failed = summary.failed,
+        "Bulk user import completed"
+    );
+
+    (StatusCode::OK, format.body(summary)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, format = Uuid, description = "User ID")),
+    responses(
+        (status = 200, description = "User found", body = User,
+            headers(("ETag" = String, description = "Version of a stored user, for `If-Match` on updates"))),
+        (status = 400, description = "Malformed user ID", body = ErrorResponse),
+        (status = 404, description = "User not found (v2, or v1 with DATABASE_URL set; otherwise v1 returns a simulated user)", body = ErrorResponse),
+        (status = 500, description = "Storage failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, format), fields(user_id = %id))]
+pub async fn get_user(
+    State(state): State<Arc<AppState>>,
+    Extension(version): Extension<ApiVersion>,
+    IdPath(id): IdPath<UserId>,
+    format: ResponseFormat,
+) -> impl IntoResponse {
+    info_trace!(user_id = %id, "Fetching user");
+
+    // Look up stored users first; without a real database v1 falls back to
+    // the simulated one, v2 only returns users that actually exist
+    let mut etag = None;
+    let user = match find_user(&state, id).await {
+        Ok(Some(record)) => {
+            etag = Some(concurrency::etag(record.version));
+            Some(User::from(record))
+        }
+        Ok(None) if version == ApiVersion::V1 && state.users.is_simulated() => fetch_user_from_database(id).await,
+        Ok(None) => None,
+        Err(e) => {
+            error_trace!(user_id = %id, error = %e, "Failed to decrypt stored user");
+            return AppError::Internal("Failed to load user".to_string()).into_response();
+        }
+    };
+
+    match user {
+        Some(user) => {
+            debug_trace!(user_id = %id, "User found");
+            let mut response = (StatusCode::OK, format.body(user)).into_response();
+            if let Some(etag) = etag {
+                response.headers_mut().insert(header::ETAG, etag);
+            }
+            response
+        }
+        None => {
+            warn_trace!(user_id = %id, "User not found");
+            AppError::NotFound("User not found".to_string()).into_response()
+        }
+    }
+}
+
+/// A stored user, read through the cache
+pub async fn find_user(state: &AppState, id: UserId) -> Result<Option<repository::UserRecord>, repository::StoreError> {
+    let key = id.to_string();
+    if let Some(cached) = state.cache.get("user", &key).await {
+        return Ok(Some(state.users.read_cached(cached)?));
+    }
+    let record = state.users.find_by_id(id).await?;
+    if let Some(record) = &record {
+        state.cache.set("user", &key, &state.users.to_cached(record)?).await;
+    }
+    Ok(record)
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    params(
+        ("id" = String, Path, format = Uuid, description = "User ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag from a previous read; fails with 412 if the user changed since")
+    ),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "User updated", body = User,
+            headers(("ETag" = String, description = "New version of the user"))),
+        (status = 400, description = "Malformed user ID or empty name", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 403, description = "Not the user or an admin", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 412, description = "User was modified since the `If-Match` version", body = ErrorResponse),
+        (status = 500, description = "Storage failure", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(state, principal, if_match, format, payload), fields(user_id = %id))]
+pub async fn update_user(
+    State(state): State<Arc<AppState>>,
+    IdPath(id): IdPath<UserId>,
+    principal: auth::Principal,
+    if_match: concurrency::IfMatch,
+    format: ResponseFormat,
+    Json(payload): Json<UpdateUserRequest>,
+) -> impl IntoResponse {
+    if principal.user_id != id && principal.role != auth::Role::Admin {
+        return AppError::Forbidden("Users can only update themselves".to_string()).into_response();
+    }
+    if payload.name.trim().is_empty() {
+        return AppError::BadRequest("Name must not be empty".to_string()).into_response();
+    }
+
+    match state.users.update_name(id, payload.name, &if_match).await {
+        Ok(concurrency::UpdateOutcome::Updated(record)) => {
+            state.concurrency.record_update("user");
+            state.cache.invalidate("user", &id.to_string()).await;
+            state.search.index(vec![user_document(&record.id, &record.name)]).await;
+            info_trace!(user_id = %id, version = record.version, "User updated");
+            let etag = concurrency::etag(record.version);
+            ([(header::ETAG, etag)], format.body(User::from(record))).into_response()
+        }
+        Ok(concurrency::UpdateOutcome::Stale { current_version }) => state
+            .concurrency
+            .record_conflict("user", &if_match, current_version)
+            .into_response(),
+        Ok(concurrency::UpdateOutcome::NotFound) => AppError::NotFound("User not found".to_string()).into_response(),
+        Ok(concurrency::UpdateOutcome::Rejected(message)) => {
+            AppError::BadRequest(message).into_response()
+        }
+        Err(e) => {
+            error_trace!(user_id = %id, error = %e, "Failed to decrypt updated user");
+            AppError::Internal("Failed to load user".to_string()).into_response()
+        }
+    }
+}
+
+/// Simulated user for v1 lookups when users are only kept in memory
+#[instrument(fields(user_id = %id))]
+pub async fn fetch_user_from_database(id: UserId) -> Option<User> {
+    // Simulate database query delay
+    region::simulate_downstream("database").await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    
+    debug_trace!(user_id = %id, "Querying database for user");
+
+    // Mock user data
+    Some(User {
+        id,
+        name: "John Doe".to_string(),
+        email: "john.doe@example.com".to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+/// Search index entry for a user; only the name is indexed, never the email
+fn user_document(id: &UserId, name: &str) -> search::SearchDocument {
+    search::SearchDocument {
+        kind: search::DocumentKind::User,
+        id: id.to_string(),
+        title: name.to_string(),
+    }
+}
@@ -0,0 +1,94 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::fmt;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// API version a request was routed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+        }
+    }
+
+    /// Route prefix for this version
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/api/v1",
+            ApiVersion::V2 => "/api/v2",
+        }
+    }
+
+    fn is_deprecated(self) -> bool {
+        matches!(self, ApiVersion::V1)
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Add RFC 8594 deprecation headers pointing callers at the successor version
+///
+/// `route` is the path relative to the version prefix. The sunset date comes
+/// from `API_V1_SUNSET` (an HTTP-date) when set.
+fn add_deprecation_headers(response: &mut Response, route: &str) {
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+
+    if let Some(sunset) = std::env::var("API_V1_SUNSET")
+        .ok()
+        .and_then(|value| HeaderValue::from_str(&value).ok())
+    {
+        headers.insert("sunset", sunset);
+    }
+
+    let link = format!(
+        "<{}{}>; rel=\"successor-version\"",
+        ApiVersion::V2.prefix(),
+        route
+    );
+    if let Ok(value) = HeaderValue::from_str(&link) {
+        headers.insert(header::LINK, value);
+    }
+}
+
+/// Middleware tagging requests with their API version
+///
+/// The version is stored in request extensions for handlers, recorded as the
+/// `api.version` tag on the `api.request` span, and deprecated versions get
+/// deprecation headers. Routes keep their unversioned names, so dashboards can
+/// split by the tag instead of being re-keyed per version.
+pub async fn tag_api_version(
+    State(version): State<ApiVersion>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // Nested routers see the path without the version prefix
+    let route = request.uri().path().to_string();
+    request.extensions_mut().insert(version);
+
+    let span = tracing::Span::current();
+    span.set_attribute("api.version", version.as_str());
+    span.set_attribute("api.deprecated", version.is_deprecated());
+
+    let mut response = next.run(request).await;
+
+    if version.is_deprecated() {
+        add_deprecation_headers(&mut response, &route);
+    }
+    response
+}
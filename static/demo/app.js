@@ -0,0 +1,151 @@
+// Demo frontend: calls the API with W3C trace context so browser sessions
+// and backend traces line up. When RUM is configured the SDK injects the
+// headers itself; otherwise a traceparent is generated per request.
+
+let rumEnabled = false;
+
+function randomHex(bytes) {
+  const buffer = new Uint8Array(bytes);
+  crypto.getRandomValues(buffer);
+  return Array.from(buffer, (b) => b.toString(16).padStart(2, "0")).join("");
+}
+
+function newTraceparent() {
+  return `00-${randomHex(16)}-${randomHex(8)}-01`;
+}
+
+function loadScript(src) {
+  return new Promise((resolve, reject) => {
+    const script = document.createElement("script");
+    script.src = src;
+    script.onload = resolve;
+    script.onerror = reject;
+    document.head.appendChild(script);
+  });
+}
+
+async function initRum() {
+  const status = document.getElementById("rum-status");
+  const config = await fetch("/demo/config").then((r) => r.json());
+
+  if (!config.enabled) {
+    status.textContent =
+      "RUM disabled (set DD_RUM_APPLICATION_ID and DD_RUM_CLIENT_TOKEN). Requests still carry a generated traceparent.";
+    return;
+  }
+
+  try {
+    await loadScript(config.sdkUrl);
+    window.DD_RUM.init({
+      applicationId: config.applicationId,
+      clientToken: config.clientToken,
+      site: config.site,
+      service: config.service,
+      env: config.env,
+      version: config.version,
+      sessionSampleRate: 100,
+      traceSampleRate: 100,
+      trackResources: true,
+      trackUserInteractions: true,
+      allowedTracingUrls: [
+        { match: window.location.origin, propagatorTypes: ["tracecontext", "datadog"] },
+      ],
+    });
+    rumEnabled = true;
+    status.textContent = `RUM enabled for ${config.service} (${config.env}) on ${config.site}.`;
+  } catch (e) {
+    status.textContent = `Failed to load the RUM SDK from ${config.sdkUrl}.`;
+  }
+}
+
+async function call(label, path, options = {}) {
+  const headers = new Headers(options.headers || {});
+  const traceparent = newTraceparent();
+  if (!rumEnabled) {
+    headers.set("traceparent", traceparent);
+  }
+
+  const started = performance.now();
+  let status = "network error";
+  let body = null;
+  try {
+    const response = await fetch(path, { ...options, headers });
+    status = response.status;
+    body = await response.json().catch(() => null);
+  } finally {
+    const traceId = rumEnabled ? "(injected by RUM)" : traceparent.split("-")[1];
+    appendLog(label, status, performance.now() - started, traceId);
+  }
+  return body;
+}
+
+function appendLog(label, status, durationMs, traceId) {
+  const row = document.createElement("tr");
+  const ok = typeof status === "number" && status < 400;
+  for (const [text, cls] of [
+    [label, ""],
+    [String(status), ok ? "ok" : "fail"],
+    [`${durationMs.toFixed(0)} ms`, ""],
+    [traceId, "trace"],
+  ]) {
+    const cell = document.createElement("td");
+    cell.textContent = text;
+    if (cls) cell.className = cls;
+    row.appendChild(cell);
+  }
+  document.getElementById("log").prepend(row);
+}
+
+function postJson(body) {
+  return {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body),
+  };
+}
+
+function createUser() {
+  const id = randomHex(4);
+  return call(
+    "POST /api/users",
+    "/api/v2/users",
+    postJson({ name: `Demo ${id}`, email: `demo-${id}@example.com` }),
+  );
+}
+
+// Orders without a created user go to a fixed demo user ID
+function createOrder(userId = "00000000-0000-4000-8000-000000000001") {
+  return call(
+    "POST /api/orders",
+    "/api/v2/orders",
+    postJson({
+      user_id: userId,
+      items: [
+        { product_id: "prod-1", quantity: 2, price: "19.99" },
+        { product_id: "prod-2", quantity: 1, price: "5.50" },
+      ],
+    }),
+  );
+}
+
+const actions = {
+  health: () => call("GET /health", "/health"),
+  "create-user": createUser,
+  "create-order": () => createOrder(),
+  slow: () => call("GET /api/slow-operation", "/api/v2/slow-operation"),
+  error: () => call("GET /api/simulate-error", "/api/v2/simulate-error?error_type=server"),
+  journey: async () => {
+    const user = await createUser();
+    const order = await createOrder(user && user.id);
+    if (order && order.order_id) {
+      await call("GET /api/orders/:id", `/api/v2/orders/${order.order_id}`);
+    }
+  },
+};
+
+document.addEventListener("DOMContentLoaded", () => {
+  document.querySelectorAll("button[data-action]").forEach((button) => {
+    button.addEventListener("click", () => actions[button.dataset.action]());
+  });
+  initRum();
+});